@@ -0,0 +1,107 @@
+// Benchmarks of the search's hot path on canned positions (see src/bench.rs):
+//
+// cargo bench --bench search
+// cargo bench --bench search -- minimax   (just the searches)
+//
+// The split searches only get faster with more than one core, see src/parallel.rs.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use starter_snake_rust::bench;
+
+// Plies of the fixed-depth searches, about what a turn of a standard game reaches
+const SEARCH_DEPTH: i32 = 5;
+
+fn simulate_move(c: &mut Criterion) {
+    let mut group = c.benchmark_group("simulate_move");
+    for mut position in bench::positions() {
+        let name = position.name.clone();
+        group.bench_function(&name, |b| {
+            b.iter(|| bench::simulate_moves(black_box(&mut position)))
+        });
+    }
+    group.finish();
+}
+
+fn flood_fill(c: &mut Criterion) {
+    let mut group = c.benchmark_group("flood_fill");
+    for position in bench::positions() {
+        group.bench_function(format!("{}/queue", position.name), |b| {
+            b.iter(|| bench::flood_fill(black_box(&position)))
+        });
+        if position.has_bitboard() {
+            group.bench_function(format!("{}/bitboard", position.name), |b| {
+                b.iter(|| bench::flood_fill_bits(black_box(&position)))
+            });
+        }
+    }
+    group.finish();
+}
+
+fn evaluate_board(c: &mut Criterion) {
+    let mut group = c.benchmark_group("evaluate_board");
+    for position in bench::positions() {
+        group.bench_function(&position.name, |b| {
+            b.iter(|| bench::evaluate(black_box(&position)))
+        });
+    }
+    group.finish();
+}
+
+fn partition_ordering(c: &mut Criterion) {
+    let mut group = c.benchmark_group("partition_ordering");
+    for mut position in bench::positions() {
+        let name = position.name.clone();
+        group.bench_function(&name, |b| {
+            b.iter(|| bench::partition_ordering(black_box(&mut position)))
+        });
+    }
+    group.finish();
+}
+
+// Prints how well the search to CUTOFF_DEPTH prunes every position before timing it
+const CUTOFF_DEPTH: i32 = 9;
+
+fn minimax(c: &mut Criterion) {
+    let mut group = c.benchmark_group("minimax");
+    group.sample_size(10);
+    for mut position in bench::positions() {
+        let stats = bench::cutoff_stats(&mut position, CUTOFF_DEPTH);
+        println!(
+            "{}: {} nodes to depth {}, {:.1}% cut off, {:.1}% of those by the first move",
+            position.name,
+            stats.nodes,
+            CUTOFF_DEPTH,
+            100.0 * stats.cutoffs as f64 / stats.nodes.max(1) as f64,
+            100.0 * stats.first_move as f64 / stats.cutoffs.max(1) as f64
+        );
+        let name = format!("{}/depth-{}", position.name, SEARCH_DEPTH);
+        group.bench_function(&name, |b| {
+            b.iter(|| bench::search(black_box(&mut position), SEARCH_DEPTH))
+        });
+    }
+    group.finish();
+}
+
+fn split_minimax(c: &mut Criterion) {
+    let mut group = c.benchmark_group("split_minimax");
+    group.sample_size(10);
+    for mut position in bench::positions() {
+        let name = format!("{}/depth-{}", position.name, SEARCH_DEPTH);
+        group.bench_function(&name, |b| {
+            b.iter(|| bench::split_search(black_box(&mut position), SEARCH_DEPTH))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    simulate_move,
+    flood_fill,
+    evaluate_board,
+    partition_ordering,
+    minimax,
+    split_minimax
+);
+criterion_main!(benches);
@@ -0,0 +1,606 @@
+// Plays whole games locally between strategies, using the rules in `rules.rs`, and lets a
+// person play one of the snakes at the terminal (`play`).
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::io::{self, BufRead, IsTerminal};
+use std::thread;
+use std::time::Duration;
+
+use crate::direction::Direction;
+use crate::render::print_board;
+use crate::rules::{advance_turn, shrink_safe_area, RulesConfig};
+use crate::strategy::{baseline, EngineStrategy, HumanStrategy, Strategy, BASELINES};
+use crate::{Battlesnake, Board, Coord, Game, GameState, RoyaleSettings, Ruleset, RulesetSettings};
+
+#[derive(Debug, Clone)]
+pub struct ArenaConfig {
+    pub width: i32,
+    pub height: i32,
+    // Games still going after this many turns are draws
+    pub max_turns: i32,
+    pub rules: RulesConfig,
+}
+
+impl Default for ArenaConfig {
+    fn default() -> ArenaConfig {
+        ArenaConfig {
+            width: 11,
+            height: 11,
+            max_turns: 500,
+            rules: RulesConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GameOutcome {
+    // Index of the winning strategy, None when everyone died on the same turn or time ran out
+    pub winner: Option<usize>,
+    pub turns: i32,
+}
+
+// Spawn points one cell in from the corners and the middle of the edges, like the official
+// standard maps
+fn spawn_points(width: i32, height: i32) -> Vec<Coord> {
+    let (left, right, bottom, top) = (1, width - 2, 1, height - 2);
+    let (middle_x, middle_y) = (width / 2, height / 2);
+    vec![
+        Coord { x: left, y: bottom },
+        Coord { x: right, y: top },
+        Coord { x: left, y: top },
+        Coord {
+            x: right,
+            y: bottom,
+        },
+        Coord {
+            x: left,
+            y: middle_y,
+        },
+        Coord {
+            x: right,
+            y: middle_y,
+        },
+        Coord {
+            x: middle_x,
+            y: bottom,
+        },
+        Coord {
+            x: middle_x,
+            y: top,
+        },
+    ]
+}
+
+pub fn new_board(snake_ids: &[String], config: &ArenaConfig, rng: &mut impl Rng) -> Board {
+    let mut points = spawn_points(config.width, config.height);
+    points.shuffle(rng);
+    let center = Coord {
+        x: config.width / 2,
+        y: config.height / 2,
+    };
+
+    let mut food = vec![center];
+    let mut snakes = Vec::new();
+    for (id, start) in snake_ids.iter().zip(points) {
+        snakes.push(Battlesnake {
+            id: id.clone(),
+            name: id.clone(),
+            health: 100,
+            body: vec![start; 3].into(),
+            head: start,
+            length: 3,
+            latency: "0".to_string(),
+            shout: None,
+            squad: None,
+        });
+        // One piece of food next to every snake, on the side of the center
+        let towards = |from: i32, to: i32| from + (to - from).signum();
+        let near = Coord {
+            x: towards(start.x, center.x),
+            y: towards(start.y, center.y),
+        };
+        if !food.contains(&near) {
+            food.push(near);
+        }
+    }
+
+    // Nobody eats in constrictor games
+    if config.rules.constrictor {
+        food.clear();
+    }
+
+    Board {
+        height: config.height as u32,
+        width: config.width,
+        food,
+        snakes,
+        hazards: Vec::new(),
+        wrapped: config.rules.wrapped,
+    }
+}
+
+pub fn new_game(id: &str, config: &ArenaConfig) -> Game {
+    let rules = &config.rules;
+    let name = match rules.shrink_every {
+        Some(_) => "royale",
+        None if rules.wrapped && rules.constrictor => "wrapped_constrictor",
+        None if rules.wrapped => "wrapped",
+        None if rules.constrictor => "constrictor",
+        None => "standard",
+    };
+    Game {
+        id: id.to_string(),
+        source: "arena".to_string(),
+        ruleset: Ruleset {
+            name: name.to_string(),
+            version: String::new(),
+            settings: RulesetSettings {
+                food_spawn_chance: Some(rules.food_spawn_chance),
+                minimum_food: Some(rules.minimum_food),
+                hazard_damage_per_turn: Some(rules.hazard_damage_per_turn),
+                royale: RoyaleSettings {
+                    shrink_every_n_turns: rules.shrink_every,
+                },
+                ..RulesetSettings::default()
+            },
+        },
+        timeout: 500,
+    }
+}
+
+// A game in progress, played one turn at a time by whoever picks the moves. Player i is the
+// snake `snake-i`.
+pub struct ArenaGame {
+    pub game: Game,
+    pub board: Board,
+    pub turn: i32,
+    ids: Vec<String>,
+}
+
+impl ArenaGame {
+    pub fn new(
+        game_id: &str,
+        players: usize,
+        config: &ArenaConfig,
+        rng: &mut impl Rng,
+    ) -> ArenaGame {
+        let ids: Vec<String> = (0..players).map(|i| format!("snake-{}", i)).collect();
+        ArenaGame {
+            game: new_game(game_id, config),
+            board: new_board(&ids, config, rng),
+            turn: 0,
+            ids,
+        }
+    }
+
+    pub fn is_over(&self, config: &ArenaConfig) -> bool {
+        self.board.snakes.len() <= 1 || self.turn >= config.max_turns
+    }
+
+    // The player of the snake at `board_index` on the board
+    pub fn player(&self, board_index: usize) -> usize {
+        let id = &self.board.snakes[board_index].id;
+        self.ids.iter().position(|i| i == id).unwrap()
+    }
+
+    // The request `player` gets this turn, None once it's eliminated
+    pub fn state(&self, player: usize) -> Option<GameState> {
+        let you = self
+            .board
+            .snakes
+            .iter()
+            .find(|s| s.id == self.ids[player])?;
+        Some(GameState {
+            game: self.game.clone(),
+            turn: self.turn,
+            board: self.board.clone(),
+            you: you.clone(),
+        })
+    }
+
+    // Plays one turn, `moves` has a move for every snake on the board, in board order
+    pub fn advance(&mut self, moves: &[Direction], config: &ArenaConfig, rng: &mut impl Rng) {
+        advance_turn(&mut self.board, moves, &config.rules, rng);
+        self.turn += 1;
+        if let Some(every) = config.rules.shrink_every {
+            if self.turn % every == 0 {
+                shrink_safe_area(&mut self.board, rng);
+            }
+        }
+    }
+
+    pub fn outcome(&self) -> GameOutcome {
+        // In a solo game (or when time runs out) nobody wins
+        let winner = match self.board.snakes.as_slice() {
+            [last] if self.ids.len() > 1 => self.ids.iter().position(|id| *id == last.id),
+            _ => None,
+        };
+        GameOutcome {
+            winner,
+            turns: self.turn,
+        }
+    }
+}
+
+// Plays one game, snake i is played by strategies[i]
+pub fn play_game(
+    game_id: &str,
+    strategies: &mut [Box<dyn Strategy>],
+    config: &ArenaConfig,
+    rng: &mut impl Rng,
+) -> GameOutcome {
+    play_game_watched(game_id, strategies, config, rng, |_| true)
+}
+
+// Plays one game like `play_game`, showing `watch` the game before every turn and once more
+// at the end. The game stops where it is when `watch` returns false.
+pub fn play_game_watched(
+    game_id: &str,
+    strategies: &mut [Box<dyn Strategy>],
+    config: &ArenaConfig,
+    rng: &mut impl Rng,
+    mut watch: impl FnMut(&ArenaGame) -> bool,
+) -> GameOutcome {
+    let mut arena = ArenaGame::new(game_id, strategies.len(), config, rng);
+    for (strategy, snake) in strategies.iter_mut().zip(&arena.board.snakes) {
+        strategy.start(&arena.game, &arena.board, snake);
+    }
+
+    let mut watching = true;
+    while !arena.is_over(config) {
+        if !watch(&arena) {
+            watching = false;
+            break;
+        }
+        let moves: Vec<Direction> = arena
+            .board
+            .snakes
+            .iter()
+            .enumerate()
+            .map(|(i, snake)| {
+                strategies[arena.player(i)].choose_move(
+                    &arena.game,
+                    arena.turn,
+                    &arena.board,
+                    snake,
+                )
+            })
+            .collect();
+        arena.advance(&moves, config, rng);
+    }
+    if watching {
+        watch(&arena);
+    }
+
+    for (i, strategy) in strategies.iter_mut().enumerate() {
+        strategy.end(&arena.game, arena.turn, &arena.board, &arena.ids[i]);
+    }
+    arena.outcome()
+}
+
+// Any size, custom community boards go beyond the standard 25x25
+pub(crate) fn parse_size(value: &str) -> Result<(i32, i32), String> {
+    value
+        .split_once('x')
+        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+        .filter(|&(w, h)| w >= 3 && h >= 3)
+        .ok_or_else(|| format!("--size is WIDTHxHEIGHT of at least 3x3, not '{}'", value))
+}
+
+// Turns between two shrinks of the safe area in royale games, like on the official ladder
+const ROYALE_SHRINK_EVERY: i32 = 25;
+
+pub(crate) fn set_ruleset(rules: &mut RulesConfig, value: &str) -> Result<(), String> {
+    (rules.wrapped, rules.constrictor, rules.shrink_every) = match value {
+        "standard" => (false, false, None),
+        "wrapped" => (true, false, None),
+        "constrictor" => (false, true, None),
+        "royale" => (false, false, Some(ROYALE_SHRINK_EVERY)),
+        _ => {
+            return Err(format!(
+                "--ruleset is 'standard', 'royale', 'wrapped' or 'constrictor', not '{}'",
+                value
+            ))
+        }
+    };
+    Ok(())
+}
+
+struct Options {
+    opponents: Vec<String>,
+    together: bool,
+    games: u32,
+    depth: i32,
+    seed: u64,
+    width: i32,
+    height: i32,
+    rules: RulesConfig,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        opponents: BASELINES.iter().map(|name| name.to_string()).collect(),
+        together: false,
+        games: 20,
+        depth: 4,
+        seed: 1,
+        width: ArenaConfig::default().width,
+        height: ArenaConfig::default().height,
+        rules: RulesConfig::default(),
+    };
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", flag))?;
+        let invalid = |_| format!("invalid value '{}' for {}", value, flag);
+        match flag.as_str() {
+            "--opponents" => {
+                options.opponents = value.split(',').map(str::to_string).collect();
+            }
+            "--mode" => {
+                options.together = match value.as_str() {
+                    "duel" => false,
+                    "ffa" => true,
+                    _ => return Err(format!("--mode is 'duel' or 'ffa', not '{}'", value)),
+                }
+            }
+            "--ruleset" => set_ruleset(&mut options.rules, value)?,
+            "--games" => options.games = value.parse().map_err(invalid)?,
+            "--depth" => options.depth = value.parse().map_err(invalid)?,
+            "--seed" => options.seed = value.parse().map_err(invalid)?,
+            "--size" => (options.width, options.height) = parse_size(value)?,
+            _ => return Err(format!("unknown option '{}'", flag)),
+        }
+    }
+    if let Some(unknown) = options
+        .opponents
+        .iter()
+        .find(|name| !BASELINES.contains(&name.as_str()))
+    {
+        return Err(format!(
+            "unknown opponent '{}', expected some of {}",
+            unknown,
+            BASELINES.join(", ")
+        ));
+    }
+    Ok(options)
+}
+
+// Plays `options.games` games of the engine against `opponents` all on the same board and
+// prints how the engine did
+fn play_series(opponents: &[String], options: &Options, config: &ArenaConfig, rng: &mut StdRng) {
+    let engine = EngineStrategy::new(options.depth).name();
+    let (mut wins, mut losses, mut turns) = (0, 0, 0);
+    for game in 0..options.games {
+        let mut strategies: Vec<Box<dyn Strategy>> =
+            vec![Box::new(EngineStrategy::new(options.depth))];
+        for (i, name) in opponents.iter().enumerate() {
+            let seed = options.seed + (game as u64) * 16 + i as u64;
+            strategies.push(baseline(name, seed).unwrap());
+        }
+        let game_id = format!("arena-{}-{}", opponents.join("-"), game);
+        let outcome = play_game(&game_id, &mut strategies, config, rng);
+        match outcome.winner {
+            Some(0) => wins += 1,
+            Some(_) => losses += 1,
+            None => {}
+        }
+        turns += outcome.turns;
+    }
+    println!(
+        "{} against {:<12} won {:>3} lost {:>3} of {} games, {:.0} turns on average",
+        engine,
+        opponents.join(", "),
+        wins,
+        losses,
+        options.games,
+        turns as f64 / options.games.max(1) as f64
+    );
+}
+
+// Entry point for `starter-snake-rust arena [--opponents a,b,...] [--mode duel|ffa]
+// [--ruleset standard|royale|wrapped|constrictor] [--games N] [--depth D] [--seed S]
+// [--size WxH]`: the engine against the baseline bots, in 1v1 games against each of them or
+// all of them on one board
+pub fn run(args: &[String]) -> Result<(), String> {
+    let options = parse_options(args)?;
+    let config = ArenaConfig {
+        width: options.width,
+        height: options.height,
+        rules: options.rules.clone(),
+        ..ArenaConfig::default()
+    };
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    if options.together {
+        play_series(&options.opponents, &options, &config, &mut rng);
+    } else {
+        for opponent in &options.opponents {
+            play_series(std::slice::from_ref(opponent), &options, &config, &mut rng);
+        }
+    }
+    Ok(())
+}
+
+// Entry point for `starter-snake-rust play [--engines N]
+// [--ruleset standard|royale|wrapped|constrictor] [--depth D] [--seed S] [--size WxH]`: a
+// game at the terminal, the person playing snake-0 and the engine the other `--engines`
+// snakes (1 by default)
+pub fn play(args: &[String]) -> Result<(), String> {
+    let mut config = ArenaConfig::default();
+    let (mut engines, mut depth, mut seed) = (1, 4, rand::random());
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", flag))?;
+        let invalid = |_| format!("invalid value '{}' for {}", value, flag);
+        match flag.as_str() {
+            "--engines" => engines = value.parse().map_err(invalid)?,
+            "--depth" => depth = value.parse().map_err(invalid)?,
+            "--seed" => seed = value.parse().map_err(invalid)?,
+            "--size" => (config.width, config.height) = parse_size(value)?,
+            "--ruleset" => set_ruleset(&mut config.rules, value)?,
+            _ => return Err(format!("unknown option '{}'", flag)),
+        }
+    }
+    if !(1..=7).contains(&engines) {
+        return Err(format!("--engines is 1 to 7, not {}", engines));
+    }
+
+    let mut strategies: Vec<Box<dyn Strategy>> =
+        vec![Box::new(HumanStrategy::new(Box::new(io::stdin().lock())))];
+    for _ in 0..engines {
+        strategies.push(Box::new(EngineStrategy::new(depth)));
+    }
+    println!(
+        "You are snake-0 (S), against {} engine snake(s) at depth {}. Seed {}.",
+        engines, depth, seed
+    );
+    let mut rng = StdRng::seed_from_u64(seed);
+    play_game(
+        &format!("play-{}", seed),
+        &mut strategies,
+        &config,
+        &mut rng,
+    );
+    Ok(())
+}
+
+// How `simulate` goes from one turn to the next
+enum Pace {
+    // Waits for Enter after every turn
+    Step,
+    // Shows the next turn after this long
+    Delay(Duration),
+}
+
+// Prints the board of `arena` with every head drawn as the first letter of its player's
+// strategy, snake-0's in uppercase, and every snake's health and length below it
+fn show_turn(arena: &ArenaGame, names: &[String], clear: bool) {
+    let mut board = arena.board.clone();
+    for (i, snake) in board.snakes.iter_mut().enumerate() {
+        snake.name = names[arena.player(i)].clone();
+    }
+    if clear {
+        print!("\x1b[2J\x1b[H");
+    }
+    println!("Turn {}", arena.turn);
+    print_board(&board, "snake-0");
+    for snake in &board.snakes {
+        let letter = snake.name.chars().next().unwrap_or('s');
+        let letter = if snake.id == "snake-0" {
+            letter.to_ascii_uppercase()
+        } else {
+            letter.to_ascii_lowercase()
+        };
+        println!(
+            "{} {:<8} {:<12} health {:>3}  length {:>3}",
+            letter, snake.id, snake.name, snake.health, snake.length
+        );
+    }
+    println!();
+}
+
+// Entry point for `starter-snake-rust simulate [--opponents a,b,...] [--depth D] [--seed S]
+// [--size WxH] [--ruleset standard|royale|wrapped|constrictor] [--step] [--delay MS]`: one
+// game of the engine (snake-0) against the baseline bots, flood-fill by default, printed
+// turn by turn. `--step` waits for Enter after every turn, otherwise the turns go by every
+// `--delay` milliseconds (200 by default).
+pub fn simulate(args: &[String]) -> Result<(), String> {
+    let mut config = ArenaConfig::default();
+    let mut opponents = vec!["flood-fill".to_string()];
+    let (mut depth, mut seed, mut step) = (4, rand::random(), false);
+    let mut delay = Duration::from_millis(200);
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        if flag == "--step" {
+            step = true;
+            continue;
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", flag))?;
+        let invalid = |_| format!("invalid value '{}' for {}", value, flag);
+        match flag.as_str() {
+            "--opponents" => opponents = value.split(',').map(str::to_string).collect(),
+            "--depth" => depth = value.parse().map_err(invalid)?,
+            "--seed" => seed = value.parse().map_err(invalid)?,
+            "--size" => (config.width, config.height) = parse_size(value)?,
+            "--ruleset" => set_ruleset(&mut config.rules, value)?,
+            "--delay" => delay = Duration::from_millis(value.parse().map_err(invalid)?),
+            _ => return Err(format!("unknown option '{}'", flag)),
+        }
+    }
+    if opponents.is_empty() || opponents.len() > 7 {
+        return Err("--opponents names 1 to 7 baseline bots".to_string());
+    }
+
+    let mut strategies: Vec<Box<dyn Strategy>> = vec![Box::new(EngineStrategy::new(depth))];
+    for (i, name) in opponents.iter().enumerate() {
+        strategies.push(baseline(name, seed + 1 + i as u64).ok_or_else(|| {
+            format!(
+                "unknown opponent '{}', expected some of {}",
+                name,
+                BASELINES.join(", ")
+            )
+        })?);
+    }
+    let names: Vec<String> = strategies.iter().map(|s| s.name()).collect();
+    // Frames replace each other on a terminal, unless someone steps through them
+    let clear = !step && io::stdout().is_terminal();
+    let mut pace = if step {
+        println!("Enter plays the next turn, r runs the game to the end, q stops it.");
+        Pace::Step
+    } else {
+        Pace::Delay(delay)
+    };
+    let mut input = io::stdin().lock();
+    let mut stopped = false;
+    let mut rng = StdRng::seed_from_u64(seed);
+    let outcome = play_game_watched(
+        &format!("simulate-{}", seed),
+        &mut strategies,
+        &config,
+        &mut rng,
+        |arena| {
+            show_turn(arena, &names, clear);
+            match pace {
+                Pace::Delay(delay) => {
+                    thread::sleep(delay);
+                    true
+                }
+                Pace::Step => {
+                    let mut line = String::new();
+                    // Without input left the game runs on to the end
+                    if input.read_line(&mut line).unwrap_or(0) == 0 {
+                        pace = Pace::Delay(Duration::ZERO);
+                        return true;
+                    }
+                    match line.trim() {
+                        "q" => {
+                            stopped = true;
+                            false
+                        }
+                        "r" => {
+                            pace = Pace::Delay(Duration::ZERO);
+                            true
+                        }
+                        _ => true,
+                    }
+                }
+            }
+        },
+    );
+    match outcome.winner {
+        _ if stopped => println!("Stopped at turn {}. Seed {}.", outcome.turns, seed),
+        Some(winner) => println!(
+            "{} (snake-{}) won after {} turns. Seed {}.",
+            names[winner], winner, outcome.turns, seed
+        ),
+        None => println!("Nobody won, {} turns. Seed {}.", outcome.turns, seed),
+    }
+    Ok(())
+}
@@ -0,0 +1,104 @@
+// Shortest paths to food for the evaluation. The straight-line distance to food says nothing
+// about a wall of bodies in between, nor about hazards on the way, so the evaluation asks an
+// A* search for the food our snake reaches for the least health instead. Every step costs a
+// point of health and a step that ends in a hazard costs the game's hazard damage on top,
+// except onto the food itself, where eating makes up for it. A body cell is in the way until
+// its snake has moved off it by the time we get there, like in `reachable_area`: the segment
+// `n` cells from the tail is gone after `n + 1` turns.
+//
+// All the food is searched for at once, with the distance to the closest piece as the
+// heuristic. A step never costs less than one, so the heuristic never overestimates and the
+// first food taken off the heap is the cheapest to reach.
+
+use std::cmp::Reverse;
+
+use crate::direction::Direction;
+use crate::logic::{distance, neighbour};
+use crate::workspace::SearchWorkspace;
+use crate::{Battlesnake, Board, Coord};
+
+// The cheapest way to food: its length in moves and the health it costs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FoodPath {
+    pub steps: i32,
+    pub cost: i32,
+}
+
+pub fn nearest_food(
+    board: &Board,
+    snake: &Battlesnake,
+    workspace: &mut SearchWorkspace,
+) -> Option<FoodPath> {
+    let head = *snake.body.first()?;
+    if board.food.is_empty() {
+        return None;
+    }
+    let width = board.width;
+    let height = board.height as i32;
+    let cells = (width * height) as usize;
+    let index = |c: &Coord| (c.y * width + c.x) as usize;
+    let cell = |i: i32| Coord {
+        x: i % width,
+        y: i / width,
+    };
+    let heuristic = |c: &Coord| {
+        board
+            .food
+            .iter()
+            .map(|food| distance(board, c, food))
+            .min()
+            .unwrap_or(0)
+    };
+
+    let hazard_damage = workspace.rules.hazard_damage_per_turn;
+    workspace.reset(cells);
+    workspace.vacated.clear();
+    workspace.vacated.resize(cells, 0);
+    let SearchWorkspace {
+        costs,
+        heap,
+        vacated,
+        ..
+    } = workspace;
+    for other in &board.snakes {
+        for (from_tail, segment) in other.body.iter().rev().enumerate() {
+            if segment.x >= 0 && segment.x < width && segment.y >= 0 && segment.y < height {
+                let turns = &mut vacated[index(segment)];
+                *turns = (*turns).max(from_tail as i32 + 1);
+            }
+        }
+    }
+
+    // Heap entries are (cost + heuristic, cell, steps), the cost is in `costs`
+    costs[index(&head)] = 0;
+    heap.push(Reverse((heuristic(&head), index(&head) as i32, 0)));
+    while let Some(Reverse((estimate, i, steps))) = heap.pop() {
+        let current = cell(i);
+        let cost = costs[i as usize];
+        // A cheaper way here was found after this entry was pushed
+        if estimate > cost + heuristic(&current) {
+            continue;
+        }
+        if current != head && board.food.contains(&current) {
+            return Some(FoodPath { steps, cost });
+        }
+        for direction in Direction::ALL {
+            let Some(next) = neighbour(board, &current, direction) else {
+                continue;
+            };
+            let n = index(&next);
+            if vacated[n] > steps + 1 {
+                continue;
+            }
+            let mut next_cost = cost + 1;
+            if board.hazards.contains(&next) && !board.food.contains(&next) {
+                next_cost += hazard_damage;
+            }
+            if next_cost < costs[n] {
+                costs[n] = next_cost;
+                heap.push(Reverse((next_cost + heuristic(&next), n as i32, steps + 1)));
+            }
+        }
+    }
+    None
+}
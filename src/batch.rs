@@ -0,0 +1,50 @@
+// Many independent searches in one call, for the offline tools that search thousands of
+// positions: the exploit checker moves the engine in all its games at once, the post-mortem
+// searches every turn of a recording. The searches share one pool of search threads (the
+// same scheduler the server uses, sized by SEARCH_THREADS) that is started on the first batch
+// and kept, so every thread keeps its search workspace from one batch to the next.
+
+use std::sync::mpsc;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use crate::logic::{analyse, Analysis, SearchConfig};
+use crate::preset;
+use crate::scheduler::{SearchScheduler, Urgency};
+use crate::GameState;
+
+static POOL: OnceLock<SearchScheduler> = OnceLock::new();
+
+fn pool() -> &'static SearchScheduler {
+    POOL.get_or_init(SearchScheduler::from_env)
+}
+
+// Searches every state from the point of view of its `you`. The result at each index is None
+// when that snake isn't on the board or its search panicked.
+pub fn search_batch(states: &[GameState], config: &SearchConfig) -> Vec<Option<Analysis>> {
+    let (sender, receiver) = mpsc::channel();
+    // Same urgency for all, the scheduler then runs them in order
+    let urgency = Urgency {
+        deadline: Instant::now(),
+        criticality: 0,
+    };
+    for (i, state) in states.iter().enumerate() {
+        let you_index = match state.board.snakes.iter().position(|s| s.id == state.you.id) {
+            Some(index) => index,
+            None => continue,
+        };
+        let board = state.board.clone();
+        let config = *config;
+        let sender = sender.clone();
+        pool().execute(preset::active().name, urgency, move || {
+            let _ = sender.send((i, analyse(&board, you_index, &config)));
+        });
+    }
+    drop(sender);
+
+    let mut results: Vec<Option<Analysis>> = states.iter().map(|_| None).collect();
+    for (i, analysis) in receiver {
+        results[i] = Some(analysis);
+    }
+    results
+}
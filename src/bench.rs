@@ -0,0 +1,192 @@
+// The search's hot path for the Criterion benchmarks in benches/, which only see the public
+// API of the crate. The positions are canned, the same on every run: one of every scenario
+// family (see scenario.rs) and the middle of seeded arena games between baseline bots, on
+// the standard board and a large one.
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::arena::{ArenaConfig, ArenaGame};
+use crate::bitboard;
+use crate::direction::Direction;
+use crate::logic::{
+    evaluate_board, flood_fill_queue, is_move_legal, minimax, order_moves_by_partition,
+    simulate_move, undo_move, EvalTier,
+};
+use crate::parallel;
+use crate::rules::RulesConfig;
+use crate::scenario::{self, FAMILIES};
+use crate::strategy::{baseline, Strategy};
+use crate::workspace::{with_workspace, CutoffStats};
+use crate::GameState;
+
+// Turn the arena positions are taken at
+const MIDGAME_TURN: i32 = 40;
+
+pub struct Position {
+    pub name: String,
+    state: GameState,
+    you_index: usize,
+}
+
+impl Position {
+    fn new(name: String, state: GameState) -> Option<Position> {
+        let you_index = state
+            .board
+            .snakes
+            .iter()
+            .position(|s| s.id == state.you.id)?;
+        Some(Position {
+            name,
+            state,
+            you_index,
+        })
+    }
+
+    // Whether the board fits in bit sets (see bitboard.rs)
+    pub fn has_bitboard(&self) -> bool {
+        let board = &self.state.board;
+        bitboard::flood_fill_area(board, &board.snakes[self.you_index].body[0], 1).is_some()
+    }
+}
+
+// Turn MIDGAME_TURN of a game of flood-fill bots, as snake-0 sees it, None when the game
+// or snake-0 doesn't get that far
+fn midgame(snakes: usize, config: &ArenaConfig, seed: u64) -> Option<GameState> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut game = ArenaGame::new(&format!("bench-{}", seed), snakes, config, &mut rng);
+    let mut bots: Vec<Box<dyn Strategy>> = (0..snakes)
+        .map(|i| baseline("flood-fill", seed + i as u64).unwrap())
+        .collect();
+    while game.turn < MIDGAME_TURN && !game.is_over(config) {
+        let moves: Vec<Direction> = (0..game.board.snakes.len())
+            .map(|i| {
+                let snake = &game.board.snakes[i];
+                bots[game.player(i)].choose_move(&game.game, game.turn, &game.board, snake)
+            })
+            .collect();
+        game.advance(&moves, config, &mut rng);
+    }
+    if game.turn < MIDGAME_TURN {
+        return None;
+    }
+    game.state(0)
+}
+
+pub fn positions() -> Vec<Position> {
+    let mut positions = Vec::new();
+    let mut rng = StdRng::seed_from_u64(1);
+    for family in FAMILIES {
+        if let Some(scenario) = scenario::generate(family, 1, &mut rng) {
+            positions.extend(Position::new(scenario.name, scenario.state));
+        }
+    }
+    let large = ArenaConfig {
+        width: 19,
+        height: 19,
+        ..ArenaConfig::default()
+    };
+    for (name, snakes, config) in [
+        ("midgame-duel", 2, ArenaConfig::default()),
+        ("midgame-4p", 4, ArenaConfig::default()),
+        ("midgame-4p-19x19", 4, large),
+    ] {
+        // The first seed from 1 on whose game gets there
+        let state = (1..100).find_map(|seed| midgame(snakes, &config, seed));
+        positions.extend(state.and_then(|state| Position::new(name.to_string(), state)));
+    }
+    positions
+}
+
+// Plays and takes back every legal move of our snake, returns how many there were
+pub fn simulate_moves(position: &mut Position) -> usize {
+    let board = &mut position.state.board;
+    let rules = RulesConfig::default();
+    let mut played = 0;
+    for direction in Direction::ALL {
+        if is_move_legal(board, &board.snakes[position.you_index], direction) {
+            let undo = simulate_move(board, position.you_index, direction, &rules);
+            undo_move(board, undo);
+            played += 1;
+        }
+    }
+    played
+}
+
+// The free cells our snake reaches from its head, filled cell by cell
+pub fn flood_fill(position: &Position) -> usize {
+    let board = &position.state.board;
+    let head = board.snakes[position.you_index].body[0];
+    let cells = (board.width * board.height as i32) as usize;
+    with_workspace(|workspace| flood_fill_queue(board, &head, cells, workspace))
+}
+
+// The same flood fill on bit sets, 0 when the board doesn't fit in them
+pub fn flood_fill_bits(position: &Position) -> usize {
+    let board = &position.state.board;
+    let head = board.snakes[position.you_index].body[0];
+    let cells = (board.width * board.height as i32) as usize;
+    bitboard::flood_fill_area(board, &head, cells).unwrap_or(0)
+}
+
+// The rich evaluation of the position for our snake
+pub fn evaluate(position: &Position) -> i32 {
+    let board = &position.state.board;
+    with_workspace(|workspace| {
+        workspace.start_search(board, 1, None, false);
+        evaluate_board(board, position.you_index, EvalTier::Rich, workspace)
+    })
+}
+
+// Our moves in the order the search tries them deep in the tree, by how they split the
+// board two turns ahead
+pub fn partition_ordering(position: &mut Position) -> [Direction; 4] {
+    let you = position.you_index;
+    let board = &mut position.state.board;
+    with_workspace(|workspace| {
+        workspace.start_search(board, 1, None, false);
+        order_moves_by_partition(board, you, workspace)
+    })
+}
+
+// A minimax search of the position `depth` plies deep, without a deadline
+pub fn search(position: &mut Position, depth: i32) -> Option<Direction> {
+    let you = position.you_index;
+    let board = &mut position.state.board;
+    with_workspace(|workspace| {
+        workspace.start_search(board, depth, None, false);
+        minimax(board, depth, i32::MIN, i32::MAX, you, you, workspace).1
+    })
+}
+
+// The same search with our root moves split between rayon's threads, see parallel.rs
+pub fn split_search(position: &mut Position, depth: i32) -> Option<Direction> {
+    let you = position.you_index;
+    let board = &mut position.state.board;
+    with_workspace(|workspace| {
+        workspace.start_search(board, depth, None, false);
+        match parallel::search_root(board, depth, you, workspace) {
+            Some(result) => result.1,
+            None => minimax(board, depth, i32::MIN, i32::MAX, you, you, workspace).1,
+        }
+    })
+}
+
+// How well the searches of `search` up to `depth` prune, deepening one ply at a time like
+// the server does
+pub fn cutoff_stats(position: &mut Position, depth: i32) -> CutoffStats {
+    let you = position.you_index;
+    let board = &mut position.state.board;
+    with_workspace(|workspace| {
+        workspace.take_cutoff_stats();
+        for iteration in 1..=depth {
+            if iteration == 1 {
+                workspace.start_search(board, iteration, None, false);
+            } else {
+                workspace.deepen_search(board, iteration, None, false);
+            }
+            minimax(board, iteration, i32::MIN, i32::MAX, you, you, workspace);
+        }
+        workspace.take_cutoff_stats()
+    })
+}
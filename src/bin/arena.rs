@@ -0,0 +1,7 @@
+// Self-play between configurations of the engine, see selfplay.rs:
+//
+// cargo run --release --bin arena -- --engine ladder-default --engine ladder-default::weights.json
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    starter_snake_rust::run_command("selfplay", &args);
+}
@@ -0,0 +1,338 @@
+// Soak test for the snake server: plays lots of synthetic games against it at the same time
+// for a long while and reports latency percentiles, failed requests (a panicking handler
+// answers with a 500) and, when given the server's pid, how its memory use grows.
+//
+// cargo run --release --bin soak -- --addr 127.0.0.1:8000 --workers 200 --minutes 120 --pid 1234
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde_json::{json, Value};
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const BOARD_SIZE: i32 = 11;
+const MAX_TURNS: i32 = 300;
+const REPORT_EVERY: Duration = Duration::from_secs(30);
+// Latencies are kept in 1ms buckets so memory stays flat however long the test runs, the
+// last bucket collects everything slower
+const LATENCY_BUCKETS: usize = 5001;
+
+struct Options {
+    addr: String,
+    workers: usize,
+    duration: Duration,
+    pid: Option<u32>,
+}
+
+struct Stats {
+    latency_histogram: Mutex<Vec<u64>>,
+    requests: AtomicU64,
+    failures: AtomicU64,
+    games: AtomicU64,
+}
+
+impl Stats {
+    fn new() -> Stats {
+        Stats {
+            latency_histogram: Mutex::new(vec![0; LATENCY_BUCKETS]),
+            requests: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            games: AtomicU64::new(0),
+        }
+    }
+
+    fn record_latency(&self, latency: Duration) {
+        let bucket = (latency.as_millis() as usize).min(LATENCY_BUCKETS - 1);
+        self.latency_histogram.lock().unwrap()[bucket] += 1;
+    }
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options {
+        addr: "127.0.0.1:8000".to_string(),
+        workers: 100,
+        duration: Duration::from_secs(60 * 60),
+        pid: None,
+    };
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut i = 0;
+    while i < args.len() {
+        let value = args
+            .get(i + 1)
+            .ok_or_else(|| format!("{} needs a value", args[i]))?;
+        match args[i].as_str() {
+            "--addr" => options.addr = value.clone(),
+            "--workers" => options.workers = value.parse().map_err(|_| "invalid --workers")?,
+            "--minutes" => {
+                let minutes: u64 = value.parse().map_err(|_| "invalid --minutes")?;
+                options.duration = Duration::from_secs(60 * minutes);
+            }
+            "--seconds" => {
+                let seconds: u64 = value.parse().map_err(|_| "invalid --seconds")?;
+                options.duration = Duration::from_secs(seconds);
+            }
+            "--pid" => options.pid = Some(value.parse().map_err(|_| "invalid --pid")?),
+            other => return Err(format!("unknown option '{}'", other)),
+        }
+        i += 2;
+    }
+    Ok(options)
+}
+
+// Minimal HTTP/1.1 POST, the server closes the connection after every response
+fn post(addr: &str, path: &str, body: &Value) -> Result<(u16, Value), String> {
+    let body = body.to_string();
+    let mut stream = TcpStream::connect(addr).map_err(|e| e.to_string())?;
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .map_err(|e| e.to_string())?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        addr,
+        body.len(),
+        body
+    )
+    .map_err(|e| e.to_string())?;
+
+    let mut response = String::new();
+    stream
+        .read_to_string(&mut response)
+        .map_err(|e| e.to_string())?;
+    let status = response
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or("malformed response")?;
+    let payload = response
+        .split("\r\n\r\n")
+        .nth(1)
+        .and_then(|text| serde_json::from_str(text).ok())
+        .unwrap_or(Value::Null);
+    Ok((status, payload))
+}
+
+fn step(head: &Value, direction: &str) -> Value {
+    let (x, y) = (head["x"].as_i64().unwrap(), head["y"].as_i64().unwrap());
+    match direction {
+        "up" => json!({ "x": x, "y": y + 1 }),
+        "down" => json!({ "x": x, "y": y - 1 }),
+        "left" => json!({ "x": x - 1, "y": y }),
+        _ => json!({ "x": x + 1, "y": y }),
+    }
+}
+
+fn random_cell(rng: &mut impl Rng) -> Value {
+    json!({ "x": rng.gen_range(0..BOARD_SIZE), "y": rng.gen_range(0..BOARD_SIZE) })
+}
+
+fn new_snake(id: &str, rng: &mut impl Rng) -> Value {
+    let head = random_cell(rng);
+    json!({
+        "id": id, "name": id, "health": 100, "body": [head, head, head], "head": head,
+        "length": 3, "latency": "0", "shout": ""
+    })
+}
+
+fn in_bounds(cell: &Value) -> bool {
+    let (x, y) = (cell["x"].as_i64().unwrap(), cell["y"].as_i64().unwrap());
+    x >= 0 && x < BOARD_SIZE as i64 && y >= 0 && y < BOARD_SIZE as i64
+}
+
+// Applies everyone's moves with simplified rules: walls, bodies and starvation kill, food
+// heals and grows the snake by doubling up its tail. It only has to produce plausible
+// positions, not an exact replay.
+fn advance(state: &mut Value, our_move: &str, rng: &mut impl Rng) {
+    let mut snakes = state["board"]["snakes"].as_array().unwrap().clone();
+    let mut food = state["board"]["food"].as_array().unwrap().clone();
+    for snake in snakes.iter_mut() {
+        let direction = if snake["id"] == "you" {
+            our_move
+        } else {
+            ["up", "down", "left", "right"].choose(rng).unwrap()
+        };
+        let head = step(&snake["body"][0], direction);
+        let body = snake["body"].as_array_mut().unwrap();
+        body.insert(0, head.clone());
+        body.pop();
+        if let Some(i) = food.iter().position(|f| *f == head) {
+            food.remove(i);
+            body.push(body.last().unwrap().clone());
+            snake["health"] = json!(100);
+        } else {
+            snake["health"] = json!(snake["health"].as_i64().unwrap() - 1);
+        }
+        snake["head"] = head;
+        snake["length"] = json!(snake["body"].as_array().unwrap().len());
+    }
+
+    let occupied: Vec<Value> = snakes
+        .iter()
+        .flat_map(|s| s["body"].as_array().unwrap()[1..].to_vec())
+        .collect();
+    snakes.retain(|s| {
+        in_bounds(&s["head"]) && s["health"].as_i64().unwrap() > 0 && !occupied.contains(&s["head"])
+    });
+    if food.is_empty() || rng.gen_bool(0.15) {
+        food.push(random_cell(rng));
+    }
+
+    let you = snakes.iter().find(|s| s["id"] == "you").cloned();
+    state["board"]["snakes"] = json!(snakes);
+    state["board"]["food"] = json!(food);
+    state["turn"] = json!(state["turn"].as_i64().unwrap() + 1);
+    if let Some(you) = you {
+        state["you"] = you;
+    }
+}
+
+fn play_game(addr: &str, game_id: u64, stats: &Stats, stop: &AtomicBool) {
+    let mut rng = rand::thread_rng();
+    let opponents = rng.gen_range(1..4);
+    let mut snakes = vec![new_snake("you", &mut rng)];
+    for i in 0..opponents {
+        snakes.push(new_snake(&format!("opponent-{}", i), &mut rng));
+    }
+    let mut state = json!({
+        "game": { "id": format!("soak-{}", game_id), "ruleset": { "name": "standard" }, "timeout": 500 },
+        "turn": 0,
+        "board": { "height": BOARD_SIZE, "width": BOARD_SIZE, "food": [random_cell(&mut rng)], "hazards": [], "snakes": snakes.clone() },
+        "you": snakes[0].clone(),
+    });
+
+    let request = |path: &str, state: &Value| -> Option<Value> {
+        let started = Instant::now();
+        let result = post(addr, path, state);
+        stats.requests.fetch_add(1, Ordering::Relaxed);
+        stats.record_latency(started.elapsed());
+        match result {
+            Ok((200, payload)) => Some(payload),
+            _ => {
+                stats.failures.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    };
+
+    request("/start", &state);
+    while state["turn"].as_i64().unwrap() < MAX_TURNS as i64 && !stop.load(Ordering::Relaxed) {
+        let our_move = match request("/move", &state) {
+            Some(payload) => payload["move"].as_str().unwrap_or("up").to_string(),
+            None => break,
+        };
+        advance(&mut state, &our_move, &mut rng);
+        let alive = state["board"]["snakes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|s| s["id"] == "you");
+        if !alive {
+            break;
+        }
+    }
+    request("/end", &state);
+    stats.games.fetch_add(1, Ordering::Relaxed);
+}
+
+fn resident_memory_kb(pid: u32) -> Option<u64> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    status
+        .lines()
+        .find(|line| line.starts_with("VmRSS:"))?
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()
+}
+
+// Smallest latency (in ms) that at least a fraction `p` of the requests stayed under
+fn percentile(histogram: &[u64], p: f64) -> usize {
+    let total: u64 = histogram.iter().sum();
+    let target = (total as f64 * p).ceil() as u64;
+    let mut seen = 0;
+    for (ms, count) in histogram.iter().enumerate() {
+        seen += count;
+        if seen >= target.max(1) {
+            return ms;
+        }
+    }
+    0
+}
+
+fn report(stats: &Stats, started: Instant, pid: Option<u32>, initial_memory: Option<u64>) {
+    let histogram = stats.latency_histogram.lock().unwrap().clone();
+    let mut line = format!(
+        "[{:>6}s] games {} requests {} failures {} latency p50 {}ms p90 {}ms p99 {}ms max {}ms",
+        started.elapsed().as_secs(),
+        stats.games.load(Ordering::Relaxed),
+        stats.requests.load(Ordering::Relaxed),
+        stats.failures.load(Ordering::Relaxed),
+        percentile(&histogram, 0.5),
+        percentile(&histogram, 0.9),
+        percentile(&histogram, 0.99),
+        percentile(&histogram, 1.0),
+    );
+    if let Some(memory) = pid.and_then(resident_memory_kb) {
+        let growth = memory as i64 - initial_memory.unwrap_or(memory) as i64;
+        line += &format!(" rss {}kB ({:+}kB)", memory, growth);
+    }
+    println!("{}", line);
+}
+
+fn main() {
+    let options = match parse_options() {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let stats = Arc::new(Stats::new());
+    let stop = Arc::new(AtomicBool::new(false));
+    let next_game = Arc::new(AtomicU64::new(0));
+    let started = Instant::now();
+    let initial_memory = options.pid.and_then(resident_memory_kb);
+
+    let workers: Vec<_> = (0..options.workers)
+        .map(|_| {
+            let (stats, stop, next_game) = (stats.clone(), stop.clone(), next_game.clone());
+            let addr = options.addr.clone();
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    play_game(
+                        &addr,
+                        next_game.fetch_add(1, Ordering::Relaxed),
+                        &stats,
+                        &stop,
+                    );
+                }
+            })
+        })
+        .collect();
+
+    let mut last_report = Instant::now();
+    while started.elapsed() < options.duration {
+        thread::sleep(Duration::from_millis(200));
+        if last_report.elapsed() >= REPORT_EVERY {
+            report(&stats, started, options.pid, initial_memory);
+            last_report = Instant::now();
+        }
+    }
+    stop.store(true, Ordering::Relaxed);
+    for worker in workers {
+        let _ = worker.join();
+    }
+    report(&stats, started, options.pid, initial_memory);
+
+    if stats.failures.load(Ordering::Relaxed) > 0 {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,281 @@
+// Bit sets of cells. A set of cells has a bit per cell at `y * width + x`, and grows by a whole
+// breadth-first layer in a few shifts (see `BitGrid::grow`). The flood fills and the Voronoi
+// split of the evaluation run on them; the property tests in tests/simulate_move.rs and the
+// board size tests in tests/board_sizes.rs check them against the breadth-first queues of
+// logic.rs (see invariants.rs). The search itself plays its moves on the Board.
+//
+// Boards of up to 128 cells, like the standard 11x11 with its 121, take a u128. Larger ones
+// take a `Wide` set of u64 words: 8 of them up to 512 cells (19x19 has 361) and 16 up to
+// 1024 (25x25 has 625). The set is picked from the size of the board as it comes in, and
+// the code on it is compiled for each kind of set. Boards larger still, which custom
+// community games can have, are filled cell by cell by the queues of logic.rs, which take
+// any size.
+
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Not, Shl, Shr};
+
+use crate::logic::staying_body;
+use crate::{Board, Coord};
+
+// A set of cells of a board
+pub trait Cells:
+    Copy
+    + PartialEq
+    + BitAnd<Output = Self>
+    + BitAndAssign
+    + BitOr<Output = Self>
+    + BitOrAssign
+    + Not<Output = Self>
+    + Shl<i32, Output = Self>
+    + Shr<i32, Output = Self>
+{
+    // Cells a set holds at the most
+    const CAPACITY: usize;
+    const EMPTY: Self;
+
+    // The set of the cells `0..count`
+    fn first(count: usize) -> Self;
+    fn cell(index: usize) -> Self;
+    fn count(&self) -> usize;
+}
+
+impl Cells for u128 {
+    const CAPACITY: usize = 128;
+    const EMPTY: u128 = 0;
+
+    fn first(count: usize) -> u128 {
+        if count >= 128 {
+            u128::MAX
+        } else {
+            (1 << count) - 1
+        }
+    }
+
+    fn cell(index: usize) -> u128 {
+        1 << index
+    }
+
+    fn count(&self) -> usize {
+        self.count_ones() as usize
+    }
+}
+
+// A set of up to 64 * WORDS cells, the first 64 in the first word
+#[derive(Clone, Copy, PartialEq)]
+pub struct Wide<const WORDS: usize>([u64; WORDS]);
+
+impl<const WORDS: usize> Cells for Wide<WORDS> {
+    const CAPACITY: usize = 64 * WORDS;
+    const EMPTY: Wide<WORDS> = Wide([0; WORDS]);
+
+    fn first(count: usize) -> Wide<WORDS> {
+        let mut words = [0; WORDS];
+        for (i, word) in words.iter_mut().enumerate() {
+            let bits = count.saturating_sub(64 * i);
+            *word = if bits >= 64 {
+                u64::MAX
+            } else {
+                (1 << bits) - 1
+            };
+        }
+        Wide(words)
+    }
+
+    fn cell(index: usize) -> Wide<WORDS> {
+        let mut words = [0; WORDS];
+        words[index / 64] = 1 << (index % 64);
+        Wide(words)
+    }
+
+    fn count(&self) -> usize {
+        self.0.iter().map(|word| word.count_ones() as usize).sum()
+    }
+}
+
+impl<const WORDS: usize> BitAnd for Wide<WORDS> {
+    type Output = Wide<WORDS>;
+
+    fn bitand(mut self, other: Wide<WORDS>) -> Wide<WORDS> {
+        self &= other;
+        self
+    }
+}
+
+impl<const WORDS: usize> BitAndAssign for Wide<WORDS> {
+    fn bitand_assign(&mut self, other: Wide<WORDS>) {
+        for (word, other) in self.0.iter_mut().zip(other.0) {
+            *word &= other;
+        }
+    }
+}
+
+impl<const WORDS: usize> BitOr for Wide<WORDS> {
+    type Output = Wide<WORDS>;
+
+    fn bitor(mut self, other: Wide<WORDS>) -> Wide<WORDS> {
+        self |= other;
+        self
+    }
+}
+
+impl<const WORDS: usize> BitOrAssign for Wide<WORDS> {
+    fn bitor_assign(&mut self, other: Wide<WORDS>) {
+        for (word, other) in self.0.iter_mut().zip(other.0) {
+            *word |= other;
+        }
+    }
+}
+
+impl<const WORDS: usize> Not for Wide<WORDS> {
+    type Output = Wide<WORDS>;
+
+    fn not(self) -> Wide<WORDS> {
+        Wide(self.0.map(|word| !word))
+    }
+}
+
+// Towards the higher cells, what goes past the last word falls off
+impl<const WORDS: usize> Shl<i32> for Wide<WORDS> {
+    type Output = Wide<WORDS>;
+
+    fn shl(self, by: i32) -> Wide<WORDS> {
+        let (words, bits) = (by as usize / 64, by as u32 % 64);
+        let mut shifted = [0; WORDS];
+        for (i, word) in shifted.iter_mut().enumerate().skip(words) {
+            let from = i - words;
+            *word = self.0[from] << bits;
+            if bits > 0 && from > 0 {
+                *word |= self.0[from - 1] >> (64 - bits);
+            }
+        }
+        Wide(shifted)
+    }
+}
+
+impl<const WORDS: usize> Shr<i32> for Wide<WORDS> {
+    type Output = Wide<WORDS>;
+
+    fn shr(self, by: i32) -> Wide<WORDS> {
+        let (words, bits) = (by as usize / 64, by as u32 % 64);
+        let mut shifted = [0; WORDS];
+        for (i, word) in shifted
+            .iter_mut()
+            .take(WORDS.saturating_sub(words))
+            .enumerate()
+        {
+            let from = i + words;
+            *word = self.0[from] >> bits;
+            if bits > 0 && from + 1 < WORDS {
+                *word |= self.0[from + 1] << (64 - bits);
+            }
+        }
+        Wide(shifted)
+    }
+}
+
+// Whether the board fits in a u128, the sets that are cheap enough to split the board with
+// at every leaf of the search
+pub fn fits(board: &Board) -> bool {
+    BitGrid::<u128>::new(board).is_some()
+}
+
+// The shape of a board, to turn cells into bits and grow sets of them
+#[derive(Clone, Copy)]
+pub struct BitGrid<S: Cells> {
+    width: i32,
+    height: i32,
+    wrapped: bool,
+    // Every cell of the board, and the cells of its left and right columns
+    full: S,
+    first_column: S,
+    last_column: S,
+}
+
+impl<S: Cells> BitGrid<S> {
+    // None for boards that don't fit in an S
+    pub fn new(board: &Board) -> Option<BitGrid<S>> {
+        let cells = board.width as i64 * board.height as i64;
+        if board.width <= 0 || cells <= 0 || cells > S::CAPACITY as i64 {
+            return None;
+        }
+        let width = board.width;
+        let height = board.height as i32;
+        let mut first_column = S::EMPTY;
+        for y in 0..height {
+            first_column |= S::cell((y * width) as usize);
+        }
+        Some(BitGrid {
+            width,
+            height,
+            wrapped: board.wrapped,
+            full: S::first(cells as usize),
+            first_column,
+            last_column: first_column << (width - 1),
+        })
+    }
+
+    pub fn index(&self, cell: &Coord) -> Option<usize> {
+        let inside = cell.x >= 0 && cell.x < self.width && cell.y >= 0 && cell.y < self.height;
+        inside.then(|| (cell.y * self.width + cell.x) as usize)
+    }
+
+    // The set of just `cell`, empty off the board
+    pub fn cell_bit(&self, cell: &Coord) -> S {
+        self.index(cell).map_or(S::EMPTY, S::cell)
+    }
+
+    // The cells next to the ones of `cells`. On wrapped boards the edge columns and rows are
+    // shifted over to the opposite side as well.
+    pub fn grow(&self, cells: S) -> S {
+        let width = self.width;
+        let cells_total = width * self.height;
+        let mut next = ((cells << 1) & !self.first_column)
+            | ((cells >> 1) & !self.last_column)
+            | (cells << width)
+            | (cells >> width);
+        if self.wrapped {
+            next |= ((cells & self.last_column) >> (width - 1))
+                | ((cells & self.first_column) << (width - 1))
+                | (cells >> (cells_total - width))
+                | (cells << (cells_total - width));
+        }
+        next & self.full
+    }
+
+    // The cells of `free` reachable from the one at `start`, not counting `start` unless the
+    // fill comes back to it, up to `limit` of them. The same count as `flood_fill_area` in
+    // logic.rs, a breadth-first layer at a time.
+    pub fn flood(&self, free: S, start: usize, limit: usize) -> usize {
+        let mut frontier = S::cell(start);
+        let mut reached = S::EMPTY;
+        let mut area = 0;
+        while frontier != S::EMPTY {
+            frontier = self.grow(frontier) & free & !reached;
+            reached |= frontier;
+            area += frontier.count();
+            if area >= limit && area > 0 {
+                return limit.max(1);
+            }
+        }
+        area
+    }
+}
+
+// `flood_fill_area` of logic.rs on bit sets, None when the board is too large for them or
+// `start` isn't on it
+pub fn flood_fill_area(board: &Board, start: &Coord, limit: usize) -> Option<usize> {
+    flood_fill_on::<u128>(board, start, limit)
+        .or_else(|| flood_fill_on::<Wide<8>>(board, start, limit))
+        .or_else(|| flood_fill_on::<Wide<16>>(board, start, limit))
+}
+
+fn flood_fill_on<S: Cells>(board: &Board, start: &Coord, limit: usize) -> Option<usize> {
+    let grid = BitGrid::<S>::new(board)?;
+    let start = grid.index(start)?;
+    let mut taken = S::EMPTY;
+    for snake in &board.snakes {
+        for segment in staying_body(snake) {
+            taken |= grid.cell_bit(segment);
+        }
+    }
+    Some(grid.flood(grid.full & !taken, start, limit))
+}
@@ -0,0 +1,156 @@
+// Snake bodies. The search plays its moves on the board of the request, so a move puts a new
+// head in front of every body it plays and takes the tail off the back, and taking it back
+// does the opposite. With a Vec the new head shifts the whole body over, and every copy of
+// a board allocates every body again. A `Body` keeps its first INLINE slots inline, so
+// copying a short snake doesn't allocate, and leaves free slots in front of its head: a new
+// head takes the slot before the current one. Only once none are left does the body move
+// back, to the end of the inline slots while it fits in them and otherwise by as many slots
+// as it has segments, so a head goes in in O(1) on average. The segments stay in one piece
+// and a body reads like a slice of Coords, head first.
+//
+// The inline slots hold the segments and the free slots together. A tail that goes on when
+// they're all taken moves the segments over into the free slots first, so a body of up to
+// 12 segments (INLINE less MIN_ROOM) stays inline and never allocates. A longer one spills
+// to the heap the first time a head goes in without a free slot, or right away when it's
+// built with more than INLINE segments. It stays there from then on, also when it gets
+// shorter, and every copy of it allocates once.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use smallvec::SmallVec;
+use std::fmt;
+use std::iter::FromIterator;
+use std::ops::{Deref, DerefMut};
+
+use crate::Coord;
+
+// Slots stored inline, segments and free slots together, before a body spills to the heap
+const INLINE: usize = 16;
+// Free slots made in front of a body at the least
+const MIN_ROOM: usize = 4;
+
+#[derive(Clone, Default)]
+pub struct Body {
+    // The segments are `slots[start..]`, the slots before them are free
+    slots: SmallVec<[Coord; INLINE]>,
+    start: usize,
+}
+
+impl Body {
+    pub fn push_front(&mut self, head: Coord) {
+        if self.start == 0 {
+            let length = self.len();
+            let room = if length + MIN_ROOM <= INLINE {
+                INLINE - length
+            } else {
+                length.max(MIN_ROOM)
+            };
+            self.slots.insert_many(0, std::iter::repeat_n(head, room));
+            self.start = room;
+        }
+        self.start -= 1;
+        self.slots[self.start] = head;
+    }
+
+    pub fn pop_front(&mut self) -> Option<Coord> {
+        let head = self.first().copied()?;
+        self.start += 1;
+        Some(head)
+    }
+
+    pub fn push(&mut self, segment: Coord) {
+        if self.slots.len() == INLINE && self.start > 0 && !self.slots.spilled() {
+            self.slots.drain(..self.start);
+            self.start = 0;
+        }
+        self.slots.push(segment);
+    }
+
+    // Whether the body moved to the heap
+    pub fn spilled(&self) -> bool {
+        self.slots.spilled()
+    }
+
+    pub fn pop(&mut self) -> Option<Coord> {
+        if self.is_empty() {
+            return None;
+        }
+        self.slots.pop()
+    }
+}
+
+impl Deref for Body {
+    type Target = [Coord];
+
+    fn deref(&self) -> &[Coord] {
+        &self.slots[self.start..]
+    }
+}
+
+impl DerefMut for Body {
+    fn deref_mut(&mut self) -> &mut [Coord] {
+        &mut self.slots[self.start..]
+    }
+}
+
+impl<'a> IntoIterator for &'a Body {
+    type Item = &'a Coord;
+    type IntoIter = std::slice::Iter<'a, Coord>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl FromIterator<Coord> for Body {
+    fn from_iter<I: IntoIterator<Item = Coord>>(segments: I) -> Body {
+        Body {
+            slots: segments.into_iter().collect(),
+            start: 0,
+        }
+    }
+}
+
+impl From<Vec<Coord>> for Body {
+    fn from(segments: Vec<Coord>) -> Body {
+        segments.into_iter().collect()
+    }
+}
+
+impl From<&[Coord]> for Body {
+    fn from(segments: &[Coord]) -> Body {
+        segments.iter().copied().collect()
+    }
+}
+
+impl PartialEq for Body {
+    fn eq(&self, other: &Body) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for Body {}
+
+impl PartialEq<Vec<Coord>> for Body {
+    fn eq(&self, other: &Vec<Coord>) -> bool {
+        **self == other[..]
+    }
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+// In requests and recordings a body is the list of its segments, head first
+impl Serialize for Body {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de> Deserialize<'de> for Body {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Body, D::Error> {
+        Vec::<Coord>::deserialize(deserializer).map(Body::from)
+    }
+}
@@ -0,0 +1,401 @@
+// Compact binary encoding of recorded games. A JSON lines recording repeats the whole board
+// every turn; here only the first frame is stored in full and every following frame as the
+// difference to the one before: the move of every snake, whether it grew, eliminations, and
+// the food and hazards that appeared or went away.
+//
+// Anything the deltas can't express (snakes joining, a changed game, unusual health or body
+// changes) is stored as a full keyframe instead, and every delta is checked against the real
+// frame while encoding, so decoding always gives back exactly the recorded GameStates.
+//
+// Layout: the magic bytes and a version, then one record per frame starting with a tag byte,
+// all integers as LEB128 varints (signed ones zigzag encoded).
+
+use std::fs;
+use std::path::Path;
+
+use crate::direction::Direction;
+use crate::fixture::load_recorded_game;
+use crate::logic::step;
+use crate::{Battlesnake, Board, Coord, GameState};
+
+pub const COMPACT_MAGIC: &[u8; 4] = b"BSRC";
+pub const COMPACT_EXTENSION: &str = "rec";
+const VERSION: u8 = 1;
+
+const TAG_KEYFRAME: u8 = 0;
+const TAG_DELTA: u8 = 1;
+
+// Per snake flags of a delta, the low two bits are the direction
+const ELIMINATED: u8 = 1 << 2;
+const GREW: u8 = 1 << 3;
+const HEALTH: u8 = 1 << 4;
+const LATENCY: u8 = 1 << 5;
+const SHOUT: u8 = 1 << 6;
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_signed(out: &mut Vec<u8>, value: i64) {
+    write_varint(out, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn write_coord(out: &mut Vec<u8>, coord: &Coord) {
+    write_signed(out, coord.x as i64);
+    write_signed(out, coord.y as i64);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, String> {
+        let byte = *self
+            .bytes
+            .get(self.pos)
+            .ok_or_else(|| format!("truncated at byte {}", self.pos))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn varint(&mut self) -> Result<u64, String> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(format!("varint too long at byte {}", self.pos))
+    }
+
+    fn signed(&mut self) -> Result<i64, String> {
+        let value = self.varint()?;
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    fn len(&mut self) -> Result<usize, String> {
+        let len = self.varint()? as usize;
+        if len > self.bytes.len() - self.pos {
+            return Err(format!("length {} past the end at byte {}", len, self.pos));
+        }
+        Ok(len)
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], String> {
+        let len = self.len()?;
+        let bytes = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        String::from_utf8(self.bytes()?.to_vec()).map_err(|e| e.to_string())
+    }
+
+    fn coord(&mut self) -> Result<Coord, String> {
+        Ok(Coord {
+            x: self.signed()? as i32,
+            y: self.signed()? as i32,
+        })
+    }
+}
+
+// Indices of `previous` entries that are gone in `next`, and the entries appended to the rest.
+// None when `next` isn't `previous` with some entries removed and new ones added at the end.
+fn list_delta(previous: &[Coord], next: &[Coord]) -> Option<(Vec<usize>, Vec<Coord>)> {
+    let mut removed = Vec::new();
+    let mut kept = 0;
+    for (i, coord) in previous.iter().enumerate() {
+        if next.get(kept) == Some(coord) {
+            kept += 1;
+        } else {
+            removed.push(i);
+        }
+    }
+    Some((removed, next.get(kept..)?.to_vec()))
+}
+
+fn write_list_delta(out: &mut Vec<u8>, previous: &[Coord], next: &[Coord]) -> Option<()> {
+    let (removed, added) = list_delta(previous, next)?;
+    write_varint(out, removed.len() as u64);
+    for index in removed {
+        write_varint(out, index as u64);
+    }
+    write_varint(out, added.len() as u64);
+    for coord in &added {
+        write_coord(out, coord);
+    }
+    Some(())
+}
+
+fn read_list_delta(reader: &mut Reader, previous: &[Coord]) -> Result<Vec<Coord>, String> {
+    let removed = (0..reader.len()?)
+        .map(|_| reader.varint().map(|i| i as usize))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut list: Vec<Coord> = previous
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !removed.contains(i))
+        .map(|(_, coord)| *coord)
+        .collect();
+    for _ in 0..reader.len()? {
+        list.push(reader.coord()?);
+    }
+    Ok(list)
+}
+
+fn predicted_health(snake: &Battlesnake, grew: bool) -> i32 {
+    if grew {
+        100
+    } else {
+        snake.health - 1
+    }
+}
+
+// The frame as a delta to `previous`, None when it can't be written as one. The result still
+// has to be checked by decoding it.
+fn encode_delta(previous: &GameState, frame: &GameState) -> Option<Vec<u8>> {
+    let mut out = vec![TAG_DELTA];
+    write_signed(&mut out, (frame.turn - previous.turn) as i64);
+
+    let mut next = frame.board.snakes.iter().peekable();
+    for snake in &previous.board.snakes {
+        let moved = match next.peek() {
+            Some(s) if s.id == snake.id => next.next().unwrap(),
+            _ => {
+                out.push(ELIMINATED);
+                continue;
+            }
+        };
+        let (from, to) = (snake.body.first()?, moved.body.first()?);
+        let direction = Direction::between(&previous.board, from, to)?;
+        let mut flags = Direction::ALL.iter().position(|&d| d == direction)? as u8;
+        let grew = moved.body.len() > snake.body.len();
+        if grew {
+            flags |= GREW;
+        }
+        if moved.health != predicted_health(snake, grew) {
+            flags |= HEALTH;
+        }
+        if moved.latency != snake.latency {
+            flags |= LATENCY;
+        }
+        if moved.shout != snake.shout {
+            flags |= SHOUT;
+        }
+        out.push(flags);
+        if flags & HEALTH != 0 {
+            write_signed(&mut out, moved.health as i64);
+        }
+        if flags & LATENCY != 0 {
+            write_bytes(&mut out, moved.latency.as_bytes());
+        }
+        if flags & SHOUT != 0 {
+            match &moved.shout {
+                Some(shout) => {
+                    out.push(1);
+                    write_bytes(&mut out, shout.as_bytes());
+                }
+                None => out.push(0),
+            }
+        }
+    }
+    if next.next().is_some() {
+        return None;
+    }
+
+    write_list_delta(&mut out, &previous.board.food, &frame.board.food)?;
+    write_list_delta(&mut out, &previous.board.hazards, &frame.board.hazards)?;
+    let you = frame
+        .board
+        .snakes
+        .iter()
+        .position(|s| s.id == frame.you.id)?;
+    write_varint(&mut out, you as u64);
+    Some(out)
+}
+
+// Moves the snake one step on `board` and grows it the way the engine does, by repeating the
+// tail
+fn advance(
+    board: &Board,
+    snake: &Battlesnake,
+    direction: Direction,
+    grew: bool,
+) -> Result<Battlesnake, String> {
+    let head = snake
+        .body
+        .first()
+        .ok_or_else(|| format!("snake {} has no body", snake.id))?;
+    let mut body = Vec::with_capacity(snake.body.len() + 1);
+    body.push(step(board, head, direction));
+    body.extend_from_slice(&snake.body[..snake.body.len() - 1]);
+    if grew {
+        body.push(*body.last().unwrap());
+    }
+    Ok(Battlesnake {
+        health: predicted_health(snake, grew),
+        head: body[0],
+        length: body.len() as i32,
+        body: body.into(),
+        ..snake.clone()
+    })
+}
+
+fn decode_delta(previous: &GameState, reader: &mut Reader) -> Result<GameState, String> {
+    let turn = previous.turn + reader.signed()? as i32;
+    let mut snakes = Vec::with_capacity(previous.board.snakes.len());
+    for snake in &previous.board.snakes {
+        let flags = reader.byte()?;
+        if flags & ELIMINATED != 0 {
+            continue;
+        }
+        let mut moved = advance(
+            &previous.board,
+            snake,
+            Direction::ALL[(flags & 3) as usize],
+            flags & GREW != 0,
+        )?;
+        if flags & HEALTH != 0 {
+            moved.health = reader.signed()? as i32;
+        }
+        if flags & LATENCY != 0 {
+            moved.latency = reader.string()?;
+        }
+        if flags & SHOUT != 0 {
+            moved.shout = match reader.byte()? {
+                0 => None,
+                _ => Some(reader.string()?),
+            };
+        }
+        snakes.push(moved);
+    }
+
+    let mut board = previous.board.clone();
+    board.food = read_list_delta(reader, &previous.board.food)?;
+    board.hazards = read_list_delta(reader, &previous.board.hazards)?;
+    let you = snakes
+        .get(reader.varint()? as usize)
+        .cloned()
+        .ok_or("`you` is not on the board")?;
+    board.snakes = snakes;
+    Ok(GameState {
+        game: previous.game.clone(),
+        turn,
+        board,
+        you,
+    })
+}
+
+fn same_frame(a: &GameState, b: &GameState) -> bool {
+    serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
+}
+
+pub fn encode_game(frames: &[GameState]) -> Result<Vec<u8>, String> {
+    let mut out = COMPACT_MAGIC.to_vec();
+    out.push(VERSION);
+    let mut previous: Option<&GameState> = None;
+    for frame in frames {
+        let delta = previous.and_then(|previous| {
+            let delta = encode_delta(previous, frame)?;
+            let mut reader = Reader {
+                bytes: &delta[1..],
+                pos: 0,
+            };
+            let decoded = decode_delta(previous, &mut reader).ok()?;
+            same_frame(&decoded, frame).then_some(delta)
+        });
+        match delta {
+            Some(delta) => out.extend_from_slice(&delta),
+            None => {
+                out.push(TAG_KEYFRAME);
+                let json = serde_json::to_vec(frame).map_err(|e| e.to_string())?;
+                write_bytes(&mut out, &json);
+            }
+        }
+        previous = Some(frame);
+    }
+    Ok(out)
+}
+
+pub fn is_compact(bytes: &[u8]) -> bool {
+    bytes.starts_with(COMPACT_MAGIC)
+}
+
+pub fn decode_game(bytes: &[u8]) -> Result<Vec<GameState>, String> {
+    if !is_compact(bytes) {
+        return Err("not a compact recording".to_string());
+    }
+    let mut reader = Reader { bytes, pos: 4 };
+    let version = reader.byte()?;
+    if version != VERSION {
+        return Err(format!("unsupported compact recording version {}", version));
+    }
+    let mut frames: Vec<GameState> = Vec::new();
+    while reader.pos < bytes.len() {
+        let start = reader.pos;
+        let frame = match (reader.byte()?, frames.last()) {
+            (TAG_KEYFRAME, _) => serde_json::from_slice(reader.bytes()?)
+                .map_err(|e| format!("invalid keyframe at byte {}: {}", start, e))?,
+            (TAG_DELTA, Some(previous)) => decode_delta(previous, &mut reader)
+                .map_err(|e| format!("invalid frame at byte {}: {}", start, e))?,
+            (tag, _) => return Err(format!("unexpected frame tag {} at byte {}", tag, start)),
+        };
+        frames.push(frame);
+    }
+    Ok(frames)
+}
+
+// Entry point for `starter-snake-rust compress <recording.jsonl>...`, writes a compact copy
+// of every recording next to it
+pub fn compress(args: &[String]) -> Result<(), String> {
+    if args.is_empty() {
+        return Err("usage: compress <recording.jsonl>...".to_string());
+    }
+    for path in args {
+        let path = Path::new(path);
+        let frames = load_recorded_game(path)?;
+        let encoded = encode_game(&frames)?;
+        let out_path = path.with_extension(COMPACT_EXTENSION);
+        fs::write(&out_path, &encoded)
+            .map_err(|e| format!("could not write {}: {}", out_path.display(), e))?;
+        let original = fs::metadata(path).map_or(0, |m| m.len());
+        println!(
+            "{} -> {}: {} frames, {} -> {} bytes",
+            path.display(),
+            out_path.display(),
+            frames.len(),
+            original,
+            encoded.len()
+        );
+    }
+    Ok(())
+}
+
+// Entry point for `starter-snake-rust decompress <recording.rec>`, prints the recording as
+// JSON lines
+pub fn decompress(args: &[String]) -> Result<(), String> {
+    let path = args
+        .first()
+        .ok_or_else(|| "usage: decompress <recording.rec>".to_string())?;
+    for frame in load_recorded_game(Path::new(path))? {
+        println!(
+            "{}",
+            serde_json::to_string(&frame).map_err(|e| e.to_string())?
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,124 @@
+// Debug check of the search against the evaluation it searches with. With CONSISTENCY_CHECK
+// set to 1 the server compares every turn where the searched line takes us with where the
+// evaluation on its own pulls us: the move that scores best one step ahead, and the food the
+// food term then measures (or the quarter of the board the move goes into when there is no
+// food). A search that plans for one target while every leaf pulls towards another tends to
+// alternate between the two from turn to turn, and neither the line nor the scores show it.
+
+use log::info;
+use std::env;
+use std::fmt;
+
+use crate::direction::Direction;
+use crate::logic::{distance, evaluate_board, is_move_safe, simulate_move, step, EvalTier};
+use crate::workspace::SearchWorkspace;
+use crate::{Board, Coord};
+
+pub const CONSISTENCY_ENV: &str = "CONSISTENCY_CHECK";
+
+pub fn enabled() -> bool {
+    env::var(CONSISTENCY_ENV).is_ok_and(|value| value == "1")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Food(Coord),
+    // Quarter of the board as (east, north)
+    Region(bool, bool),
+}
+
+impl Target {
+    fn region(board: &Board, cell: &Coord) -> Target {
+        Target::Region(2 * cell.x >= board.width, 2 * cell.y >= board.height as i32)
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Target::Food(food) => write!(f, "the food at ({}, {})", food.x, food.y),
+            Target::Region(east, north) => write!(
+                f,
+                "the {}-{} quarter",
+                if north { "north" } else { "south" },
+                if east { "east" } else { "west" }
+            ),
+        }
+    }
+}
+
+// Our first move along the searched line and where the line takes us: the first food we eat
+// on it, or the quarter of the board we end up in
+fn planned(
+    board: &Board,
+    you_index: usize,
+    pv: &[(usize, Direction)],
+) -> Option<(Direction, Target)> {
+    let ours = pv
+        .iter()
+        .filter(|&&(snake, _)| snake == you_index)
+        .map(|&(_, direction)| direction);
+    let first = ours.clone().next()?;
+    let mut head = board.snakes[you_index].body[0];
+    for direction in ours {
+        head = step(board, &head, direction);
+        if board.food.contains(&head) {
+            return Some((first, Target::Food(head)));
+        }
+    }
+    Some((first, Target::region(board, &head)))
+}
+
+// The move the evaluation scores best one step ahead and what it leads towards
+fn pulled(
+    board: &Board,
+    you_index: usize,
+    workspace: &mut SearchWorkspace,
+) -> Option<(Direction, Target)> {
+    let (_, direction) = Direction::ALL
+        .iter()
+        .copied()
+        .filter(|&d| is_move_safe(board, &board.snakes[you_index], d))
+        .map(|d| {
+            let mut after = board.clone();
+            simulate_move(&mut after, you_index, d, &workspace.rules);
+            (
+                evaluate_board(&after, you_index, EvalTier::Rich, workspace),
+                d,
+            )
+        })
+        .max_by_key(|&(score, _)| score)?;
+    let head = step(board, &board.snakes[you_index].body[0], direction);
+    let nearest = board
+        .food
+        .iter()
+        .min_by_key(|food| distance(board, food, &head));
+    let target = match nearest {
+        Some(&food) => Target::Food(food),
+        None => Target::region(board, &head),
+    };
+    Some((direction, target))
+}
+
+// Logs the turn when the searched line and the evaluation go separate ways
+pub fn check(
+    turn: i32,
+    board: &Board,
+    you_index: usize,
+    pv: &[(usize, Direction)],
+    workspace: &mut SearchWorkspace,
+) {
+    let (planned, pulled) = match (
+        planned(board, you_index, pv),
+        pulled(board, you_index, workspace),
+    ) {
+        (Some(planned), Some(pulled)) => (planned, pulled),
+        _ => return,
+    };
+    if planned.0 != pulled.0 && planned.1 != pulled.1 {
+        info!(
+            "CONSISTENCY {}: the search plays '{}' for {}, the evaluation pulls '{}' towards {}",
+            turn, planned.0, planned.1, pulled.0, pulled.1
+        );
+    }
+}
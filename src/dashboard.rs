@@ -0,0 +1,272 @@
+// Morning-after report on a session of ladder play. Reads the recordings of the last two days
+// (by file time, in UTC) from the recording directory, the results database of the
+// post-mortem and the decision log, and prints in one screen: the games of today per mode
+// with their win rate next to yesterday's, the average search depth, the turns we timed out
+// on, the most common causes of our losses and the three worst blunders the post-mortem
+// found, each with the board it happened on.
+
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::compact::COMPACT_EXTENSION;
+use crate::decision_log::DECISION_LOG_ENV;
+use crate::fixture::load_recorded_game;
+use crate::postmortem::{
+    classify_loss, load_results, GameResult, LossCause, Pivot, DEFAULT_RESULTS_DB,
+};
+use crate::recorder::RECORD_DIR_ENV;
+use crate::render::render_board;
+use crate::stall::find_stall;
+use crate::GameState;
+
+const SECONDS_PER_DAY: u64 = 24 * 3600;
+const FAILURE_MODES: usize = 3;
+const BLUNDERS: usize = 3;
+
+// What the report needs of one recorded game
+struct Played {
+    day: u64,
+    game_id: String,
+    mode: String,
+    won: bool,
+    cause: Option<LossCause>,
+    stalled: bool,
+    timeouts: usize,
+    frames: Vec<GameState>,
+}
+
+// "standard duel", "royale 4p", ...: the ruleset and how many snakes started
+fn mode(first: &GameState) -> String {
+    let ruleset = first.game.ruleset.name();
+    match first.board.snakes.len() {
+        2 => format!("{} duel", ruleset),
+        snakes => format!("{} {}p", ruleset, snakes),
+    }
+}
+
+fn day_of(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs() / SECONDS_PER_DAY)
+}
+
+fn is_recording(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e == COMPACT_EXTENSION || e == "jsonl")
+}
+
+// The games recorded in `dir` on `days`
+fn load_games(dir: &Path, days: &[u64]) -> Result<Vec<Played>, String> {
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("could not read {}: {}", dir.display(), e))?;
+    let mut games = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let day = match entry.metadata().and_then(|m| m.modified()) {
+            Ok(modified) => day_of(modified),
+            Err(_) => continue,
+        };
+        if !is_recording(&path) || !days.contains(&day) {
+            continue;
+        }
+        let frames = match load_recorded_game(&path) {
+            Ok(frames) if !frames.is_empty() => frames,
+            // Not a recording after all, or cut off
+            _ => continue,
+        };
+        let cause = classify_loss(&frames);
+        let timeouts = frames
+            .iter()
+            .filter(|f| {
+                f.you
+                    .latency
+                    .parse::<u32>()
+                    .is_ok_and(|l| l >= f.game.timeout)
+            })
+            .count();
+        games.push(Played {
+            day,
+            game_id: frames[0].game.id.clone(),
+            mode: mode(&frames[0]),
+            won: cause.is_none(),
+            cause,
+            stalled: find_stall(&frames).is_some(),
+            timeouts,
+            frames,
+        });
+    }
+    Ok(games)
+}
+
+// Average search depth of the decisions logged for `games`, and how many there were
+fn average_depth(path: &Path, games: &HashSet<&str>) -> Option<(f64, usize)> {
+    let contents = fs::read_to_string(path).ok()?;
+    let depths: Vec<i64> = contents
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter(|d| d["game_id"].as_str().is_some_and(|id| games.contains(id)))
+        .filter_map(|d| d["depth"].as_i64())
+        .collect();
+    if depths.is_empty() {
+        return None;
+    }
+    Some((
+        depths.iter().sum::<i64>() as f64 / depths.len() as f64,
+        depths.len(),
+    ))
+}
+
+fn win_rate(games: &[&Played]) -> String {
+    if games.is_empty() {
+        return "-".to_string();
+    }
+    let wins = games.iter().filter(|g| g.won).count();
+    format!("{:.0}%", 100.0 * wins as f64 / games.len() as f64)
+}
+
+fn print_modes(today: &[&Played], yesterday: &[&Played]) {
+    let mut modes: BTreeMap<&str, (Vec<&Played>, Vec<&Played>)> = BTreeMap::new();
+    for game in today {
+        modes.entry(&game.mode).or_default().0.push(game);
+    }
+    for game in yesterday {
+        modes.entry(&game.mode).or_default().1.push(game);
+    }
+    println!(
+        "{:<16} {:>6} {:>6} {:>10} {:>6}",
+        "mode", "today", "won", "yesterday", "won"
+    );
+    for (mode, (now, before)) in &modes {
+        println!(
+            "{:<16} {:>6} {:>6} {:>10} {:>6}",
+            mode,
+            now.len(),
+            win_rate(now),
+            before.len(),
+            win_rate(before)
+        );
+    }
+    println!(
+        "{:<16} {:>6} {:>6} {:>10} {:>6}",
+        "all",
+        today.len(),
+        win_rate(today),
+        yesterday.len(),
+        win_rate(yesterday)
+    );
+}
+
+fn print_failure_modes(today: &[&Played]) {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for game in today.iter().filter(|g| !g.won) {
+        // Like the post-mortem, a stalled game isn't lost by a mistake near the end
+        let cause = if game.stalled {
+            "stalled"
+        } else {
+            game.cause.map_or("unknown", |c| c.as_str())
+        };
+        *counts.entry(cause).or_default() += 1;
+    }
+    let mut counts: Vec<(&str, usize)> = counts.into_iter().collect();
+    counts.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+    let top: Vec<String> = counts
+        .iter()
+        .take(FAILURE_MODES)
+        .map(|(cause, count)| format!("{} {}", cause, count))
+        .collect();
+    if top.is_empty() {
+        println!("no losses");
+    } else {
+        println!("top failure modes: {}", top.join(", "));
+    }
+}
+
+// The pivots of today's lost games where the deeper search disagreed with our move, by how
+// far our survival chances dropped
+fn print_blunders(today: &[&Played], db: &Path) -> Result<(), String> {
+    let games: HashMap<&str, &Played> = today.iter().map(|g| (g.game_id.as_str(), *g)).collect();
+    // A game the post-mortem saw more than once counts with its latest result
+    let results = load_results(db)?;
+    let latest: HashMap<&str, &GameResult> = results
+        .iter()
+        .filter(|r| games.contains_key(r.game_id.as_str()))
+        .map(|r| (r.game_id.as_str(), r))
+        .collect();
+    let mut blunders: Vec<_> = latest
+        .into_values()
+        .filter_map(|r| Some((r, r.pivot.as_ref()?)))
+        .filter(|(_, p)| p.best != p.played)
+        .collect();
+    blunders.sort_by(|a, b| {
+        let drop = |p: &Pivot| p.survival_before - p.survival_after;
+        drop(b.1).total_cmp(&drop(a.1))
+    });
+    if blunders.is_empty() {
+        println!("no blunders found, run the post-mortem on today's recordings to look for them");
+        return Ok(());
+    }
+    println!("worst blunders:");
+    for (result, pivot) in blunders.into_iter().take(BLUNDERS) {
+        println!("  {}: {}", result.game_id, pivot.describe());
+        let frame = games[result.game_id.as_str()]
+            .frames
+            .iter()
+            .find(|f| f.turn == pivot.turn);
+        if let Some(frame) = frame {
+            for row in render_board(&frame.board, &frame.you.id) {
+                println!("    {}", row);
+            }
+        }
+    }
+    Ok(())
+}
+
+// Entry point for `starter-snake-rust dashboard [--recordings DIR] [--db results.jsonl]
+// [--decisions FILE]`. The directory and decision log default to RECORD_DIR and
+// DECISION_LOG.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut recordings = env::var(RECORD_DIR_ENV).ok().filter(|d| !d.is_empty());
+    let mut db = DEFAULT_RESULTS_DB.to_string();
+    let mut decisions = env::var(DECISION_LOG_ENV).ok().filter(|d| !d.is_empty());
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", flag))?;
+        match flag.as_str() {
+            "--recordings" => recordings = Some(value.clone()),
+            "--db" => db = value.clone(),
+            "--decisions" => decisions = Some(value.clone()),
+            _ => return Err(format!("unknown option '{}'", flag)),
+        }
+    }
+    let recordings = PathBuf::from(recordings.ok_or_else(|| {
+        format!(
+            "no recordings, pass --recordings DIR or set {}",
+            RECORD_DIR_ENV
+        )
+    })?);
+
+    let today = day_of(SystemTime::now());
+    let games = load_games(&recordings, &[today, today.saturating_sub(1)])?;
+    let (today_games, yesterday_games): (Vec<&Played>, Vec<&Played>) =
+        games.iter().partition(|g| g.day == today);
+
+    println!("session report, today and yesterday in UTC");
+    print_modes(&today_games, &yesterday_games);
+    println!();
+
+    let ids: HashSet<&str> = today_games.iter().map(|g| g.game_id.as_str()).collect();
+    match decisions.and_then(|path| average_depth(Path::new(&path), &ids)) {
+        Some((depth, count)) => println!("average depth {:.1} over {} moves", depth, count),
+        None => println!("average depth unknown, no decision log for today's games"),
+    }
+    let timeouts: usize = today_games.iter().map(|g| g.timeouts).sum();
+    println!("{} turns timed out", timeouts);
+    print_failure_modes(&today_games);
+    println!();
+    print_blunders(&today_games, Path::new(&db))
+}
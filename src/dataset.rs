@@ -0,0 +1,207 @@
+// Imitation learning dataset from recorded games. Every turn where a snake's move can be
+// read off the next frame becomes one sample: the position from that snake's point of view,
+// the move it played, and an "author" label with the snake's name.
+//
+// Our own moves are always kept. Opponents are only kept when they are strong, meaning they
+// outlived us in at least `--min-rate` of the `--min-games` or more recorded games we played
+// against them, so the dataset imitates the snakes that keep beating us and not everyone.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+use crate::direction::Direction;
+use crate::fixture::load_recorded_game;
+use crate::GameState;
+
+pub const DEFAULT_DATASET: &str = "dataset.jsonl";
+
+#[derive(Serialize, Debug)]
+pub struct Sample {
+    game_id: String,
+    turn: i32,
+    author: String,
+    snake_id: String,
+    // The /move request the author would have received, `you` is the author's snake
+    state: GameState,
+    #[serde(rename = "move")]
+    direction: Direction,
+    // Whether the author is still on the board at the end of the recording
+    survived: bool,
+}
+
+impl Sample {
+    // A sample for a position that wasn't played, labelled with its known right move
+    pub fn synthetic(name: &str, state: GameState, direction: Direction, author: &str) -> Sample {
+        Sample {
+            game_id: name.to_string(),
+            turn: state.turn,
+            author: author.to_string(),
+            snake_id: state.you.id.clone(),
+            state,
+            direction,
+            survived: true,
+        }
+    }
+}
+
+struct Options {
+    out: String,
+    min_games: usize,
+    min_rate: f64,
+    recordings: Vec<String>,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        out: DEFAULT_DATASET.to_string(),
+        min_games: 3,
+        min_rate: 0.5,
+        recordings: Vec::new(),
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            options.recordings.push(arg.clone());
+            continue;
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", arg))?;
+        let invalid = || format!("invalid value '{}' for {}", value, arg);
+        match arg.as_str() {
+            "--out" => options.out = value.clone(),
+            "--min-games" => options.min_games = value.parse().map_err(|_| invalid())?,
+            "--min-rate" => options.min_rate = value.parse().map_err(|_| invalid())?,
+            _ => return Err(format!("unknown option '{}'", arg)),
+        }
+    }
+    if options.recordings.is_empty() {
+        return Err("usage: dataset <recording.jsonl>... [--out dataset.jsonl] \
+                    [--min-games N] [--min-rate R]"
+            .to_string());
+    }
+    Ok(options)
+}
+
+// Ids of the snakes still on the board in the last frame
+fn survivors(frames: &[GameState]) -> Vec<String> {
+    let last = match frames.last() {
+        Some(last) => last,
+        None => return Vec::new(),
+    };
+    last.board.snakes.iter().map(|s| s.id.clone()).collect()
+}
+
+// How often every opponent (by name) played us and outlived us
+fn opponent_records(games: &[Vec<GameState>]) -> HashMap<String, (usize, usize)> {
+    let mut records: HashMap<String, (usize, usize)> = HashMap::new();
+    for frames in games {
+        let first = match frames.first() {
+            Some(first) => first,
+            None => continue,
+        };
+        let alive = survivors(frames);
+        let we_survived = alive.contains(&first.you.id);
+        for snake in first.board.snakes.iter().filter(|s| s.id != first.you.id) {
+            let record = records.entry(snake.name.clone()).or_default();
+            record.0 += 1;
+            if !we_survived && alive.contains(&snake.id) {
+                record.1 += 1;
+            }
+        }
+    }
+    records
+}
+
+// One sample per snake and turn where the snake is kept and its next head is known
+fn extract_samples(frames: &[GameState], keep: &dyn Fn(&str, &str) -> bool) -> Vec<Sample> {
+    let alive = survivors(frames);
+    let mut samples = Vec::new();
+    for pair in frames.windows(2) {
+        let (frame, next) = (&pair[0], &pair[1]);
+        for snake in &frame.board.snakes {
+            if !keep(&snake.id, &snake.name) {
+                continue;
+            }
+            let direction = next
+                .board
+                .snakes
+                .iter()
+                .find(|s| s.id == snake.id)
+                .and_then(|s| Direction::between(&frame.board, &snake.body[0], &s.body[0]));
+            if let Some(direction) = direction {
+                let mut state = frame.clone();
+                state.you = snake.clone();
+                samples.push(Sample {
+                    game_id: frame.game.id.clone(),
+                    turn: frame.turn,
+                    author: snake.name.clone(),
+                    snake_id: snake.id.clone(),
+                    state,
+                    direction,
+                    survived: alive.contains(&snake.id),
+                });
+            }
+        }
+    }
+    samples
+}
+
+// Entry point for `starter-snake-rust dataset <recording.jsonl>... [--out dataset.jsonl]
+// [--min-games N] [--min-rate R]`. Appends the samples to the dataset.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let options = parse_options(args)?;
+    let games = options
+        .recordings
+        .iter()
+        .map(|path| load_recorded_game(Path::new(path)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let records = opponent_records(&games);
+    let mut strong: Vec<&String> = records
+        .iter()
+        .filter(|(_, &(played, outlived))| {
+            played >= options.min_games && outlived as f64 >= options.min_rate * played as f64
+        })
+        .map(|(name, _)| name)
+        .collect();
+    strong.sort();
+    for name in &strong {
+        let (played, outlived) = records[*name];
+        println!(
+            "strong opponent: {} (outlived us in {} of {})",
+            name, outlived, played
+        );
+    }
+
+    let path = Path::new(&options.out);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("could not open {}: {}", path.display(), e))?;
+    let mut per_author: HashMap<String, usize> = HashMap::new();
+    for frames in &games {
+        let our_id = match frames.first() {
+            Some(first) => first.you.id.clone(),
+            None => continue,
+        };
+        let keep = |id: &str, name: &str| id == our_id || strong.iter().any(|s| *s == name);
+        for sample in extract_samples(frames, &keep) {
+            *per_author.entry(sample.author.clone()).or_default() += 1;
+            let line = serde_json::to_string(&sample).map_err(|e| e.to_string())?;
+            writeln!(file, "{}", line).map_err(|e| e.to_string())?;
+        }
+    }
+
+    let mut authors: Vec<_> = per_author.into_iter().collect();
+    authors.sort();
+    for (author, count) in authors {
+        println!("{:<20} {:>6} samples", author, count);
+    }
+    println!("appended to {}", path.display());
+    Ok(())
+}
@@ -0,0 +1,112 @@
+// Room to move on that a snake can actually use. A flood fill counts every free cell it
+// reaches, but a snake can't visit two dead ends hanging off the same cell: once it goes down
+// one it never comes back out. So a 20-cell dead end next to a 20-cell one looks like 40 cells
+// of room to a flood fill and is 20 to the snake.
+//
+// The free cells (bodies are in the way, less the tails that move on) are searched depth
+// first from the head for articulation points, the cells whose occupation splits the free
+// space in two, with Tarjan's low links. Past an articulation point the part of the free
+// space beyond it is a pocket. The room of a cell is then the cells of its own block, that
+// stay connected to it without it, plus the room of the largest pocket it leads into. The
+// head's own neighbours are the pockets of the head, we get the largest of them.
+
+use crate::direction::Direction;
+use crate::logic::{neighbour, staying_body};
+use crate::workspace::SearchWorkspace;
+use crate::{Board, Coord};
+
+// The room the snake with its head at `head` can commit to, not counting the head
+pub fn room(board: &Board, head: &Coord, workspace: &mut SearchWorkspace) -> i32 {
+    let width = board.width;
+    let height = board.height as i32;
+    let in_bounds = |c: &Coord| c.x >= 0 && c.x < width && c.y >= 0 && c.y < height;
+    if !in_bounds(head) {
+        return 0;
+    }
+    let index = |c: &Coord| (c.y * width + c.x) as usize;
+    let cell = |i: usize| Coord {
+        x: i as i32 % width,
+        y: i as i32 / width,
+    };
+    let cells = (width * height) as usize;
+
+    workspace.reset(cells);
+    for buffer in [
+        &mut workspace.low,
+        &mut workspace.block,
+        &mut workspace.pocket,
+    ] {
+        buffer.clear();
+        buffer.resize(cells, 0);
+    }
+    workspace.dfs.clear();
+    let SearchWorkspace {
+        visited,
+        costs: discovered,
+        low,
+        block,
+        pocket,
+        dfs,
+        ..
+    } = workspace;
+    for snake in &board.snakes {
+        for segment in staying_body(snake).iter().filter(|c| in_bounds(c)) {
+            visited[index(segment)] = true;
+        }
+    }
+
+    // The stack holds (cell, directions tried so far) for every cell on the current path.
+    // Our neck takes the head's place after the move, so nothing links back to it and every
+    // neighbour of the head starts a search of its own.
+    let root = index(head);
+    visited[root] = true;
+    let mut order = 0;
+    discovered[root] = order;
+    dfs.push((root, 0));
+    let mut best = 0;
+    while let Some(&mut (current, ref mut tried)) = dfs.last_mut() {
+        if let Some(&direction) = Direction::ALL.get(*tried) {
+            *tried += 1;
+            let Some(next) = neighbour(board, &cell(current), direction) else {
+                continue;
+            };
+            let n = index(&next);
+            if visited[n] {
+                continue;
+            }
+            if discovered[n] == i32::MAX {
+                order += 1;
+                discovered[n] = order;
+                low[n] = order;
+                block[n] = 1;
+                pocket[n] = 0;
+                dfs.push((n, 0));
+            } else {
+                let parent = dfs.len().checked_sub(2).map(|p| dfs[p].0);
+                if parent != Some(n) {
+                    low[current] = low[current].min(discovered[n]);
+                }
+            }
+            continue;
+        }
+
+        // Every neighbour is done, hand the room this cell leads to over to its parent
+        dfs.pop();
+        let Some(&(parent, _)) = dfs.last() else {
+            break;
+        };
+        let room = block[current] + pocket[current];
+        if parent == root {
+            best = best.max(room);
+            continue;
+        }
+        low[parent] = low[parent].min(low[current]);
+        if low[current] >= discovered[parent] {
+            pocket[parent] = pocket[parent].max(room);
+        } else {
+            block[parent] += block[current];
+            pocket[parent] = pocket[parent].max(pocket[current]);
+        }
+    }
+    best
+}
@@ -0,0 +1,123 @@
+// Optional per-turn log of what the search considered, written as one JSON object per line
+// to the file named by the DECISION_LOG environment variable. Each entry has the score of
+// every root move and the principal variation with the cell each step moves to, so the
+// alternatives can be drawn over a replay of the game. With DECISION_TRACE_PLIES set the
+// entries also record the top of the search node by node, see search_trace.rs.
+
+use serde::Serialize;
+use std::env;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+
+use crate::direction::Direction;
+use crate::logic::step;
+use crate::search_trace::TraceRecord;
+use crate::workspace::SearchWorkspace;
+use crate::{Battlesnake, Board, Coord, Game};
+
+pub const DECISION_LOG_ENV: &str = "DECISION_LOG";
+
+#[derive(Serialize, Debug)]
+pub struct Candidate {
+    #[serde(rename = "move")]
+    direction: Direction,
+    score: i32,
+    // "exact", or "upper" when the search only proved the move can't beat the best one
+    bound: &'static str,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PvStep {
+    snake_id: String,
+    #[serde(rename = "move")]
+    direction: Direction,
+    cell: Coord,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Decision {
+    game_id: String,
+    turn: i32,
+    snake_id: String,
+    // "none" when every move loses
+    #[serde(rename = "move")]
+    direction: &'static str,
+    score: i32,
+    depth: i32,
+    candidates: Vec<Candidate>,
+    pv: Vec<PvStep>,
+    // The top of the search node by node, see search_trace.rs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace: Option<TraceRecord>,
+}
+
+impl Decision {
+    pub fn new(
+        game: &Game,
+        turn: i32,
+        board: &Board,
+        you: &Battlesnake,
+        depth: i32,
+        result: &(i32, Option<Direction>),
+        workspace: &SearchWorkspace,
+    ) -> Decision {
+        let candidates = workspace
+            .root_candidates
+            .iter()
+            .map(|c| Candidate {
+                direction: c.direction,
+                score: c.score,
+                bound: if c.exact { "exact" } else { "upper" },
+            })
+            .collect();
+
+        // Replay the line from the current heads to find the cell of every step
+        let mut heads: Vec<Coord> = board
+            .snakes
+            .iter()
+            .map(|s| s.body.first().copied().unwrap_or(s.head))
+            .collect();
+        let pv = workspace
+            .principal_variation()
+            .iter()
+            .map(|&(snake_index, direction)| {
+                heads[snake_index] = step(board, &heads[snake_index], direction);
+                PvStep {
+                    snake_id: board.snakes[snake_index].id.clone(),
+                    direction,
+                    cell: heads[snake_index],
+                }
+            })
+            .collect();
+
+        Decision {
+            game_id: game.id.clone(),
+            turn,
+            snake_id: you.id.clone(),
+            direction: result.1.map_or("none", Direction::as_str),
+            score: result.0,
+            depth,
+            candidates,
+            pv,
+            trace: None,
+        }
+    }
+
+    pub fn with_trace(self, trace: Option<TraceRecord>) -> Decision {
+        Decision { trace, ..self }
+    }
+}
+
+pub fn log_path() -> Option<String> {
+    env::var(DECISION_LOG_ENV)
+        .ok()
+        .filter(|path| !path.is_empty())
+}
+
+// Searches of different games append to the same file at the same time, so every entry goes
+// out in a single write to keep the lines from interleaving
+pub fn append(path: &str, decision: &Decision) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let line = serde_json::to_string(decision)? + "\n";
+    file.write_all(line.as_bytes())
+}
@@ -0,0 +1,39 @@
+// Iterative deepening: the server searches depth 1, 2, 3, ... up to the preset's depth and
+// plays the result of the deepest search that finished. A search that runs past the turn's
+// budget is abandoned halfway and the depth before it is played, so four snake games go as
+// deep as the time allows instead of timing out. A new depth only starts when it can finish
+// in time, going by how long the last one took. The turn's deadline already leaves room for
+// the network (see network.rs), and the searches stop 100 - SEARCH_BUDGET percent of the
+// game's timeout before it (SEARCH_BUDGET is 90 when unset) to unwind and send the answer.
+
+use std::env;
+use std::time::{Duration, Instant};
+
+pub const BUDGET_ENV: &str = "SEARCH_BUDGET";
+const DEFAULT_BUDGET: u32 = 90;
+
+// A depth takes about this many times as long as the one before
+const GROWTH: u32 = 4;
+
+// Nodes searched between two looks at the clock
+pub const CLOCK_INTERVAL: u32 = 1024;
+
+// Percent of the timeout the searches of a turn may use
+pub fn budget_share() -> u32 {
+    env::var(BUDGET_ENV)
+        .ok()
+        .and_then(|share| share.parse().ok())
+        .filter(|share| (1..=100).contains(share))
+        .unwrap_or(DEFAULT_BUDGET)
+}
+
+// When the searches of a turn whose answer is due at `answer_by` have to stop
+pub fn search_deadline(answer_by: Instant, timeout: Duration) -> Instant {
+    let margin = timeout * (100 - budget_share()) / 100;
+    answer_by.checked_sub(margin).unwrap_or(answer_by)
+}
+
+// Whether a search one ply deeper than one that took `last` can finish by `stop`
+pub fn next_fits(last: Duration, stop: Instant) -> bool {
+    Instant::now() + last * GROWTH <= stop
+}
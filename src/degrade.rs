@@ -0,0 +1,101 @@
+// What the server does when one of its parts fails at runtime, instead of failing the move
+// and with it the game. Every subsystem that can fail has one fallback, and the server keeps
+// answering with it:
+//
+//   search threads   a thread that can't be started is left out of the pool, with none at
+//                    all the searches run on the request's own thread
+//   search           a search that panics is answered with a legal move found without one
+//   mcts             an MCTS search that panics is searched again with minimax
+//   game store       with Redis unreachable the game state is kept in memory, per replica
+//   recorder         without a writer thread or a writable directory nothing is recorded
+//   telemetry        without an exporter thread nothing is exported
+//
+// Every degradation is logged as DEGRADED and counted, the counts are under `degraded` in
+// /metrics.
+
+use log::warn;
+use serde_json::{json, Map, Value};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subsystem {
+    SearchThreads,
+    Search,
+    Mcts,
+    GameStore,
+    Recorder,
+    Telemetry,
+}
+
+const SUBSYSTEMS: [Subsystem; 6] = [
+    Subsystem::SearchThreads,
+    Subsystem::Search,
+    Subsystem::Mcts,
+    Subsystem::GameStore,
+    Subsystem::Recorder,
+    Subsystem::Telemetry,
+];
+
+impl Subsystem {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Subsystem::SearchThreads => "search_threads",
+            Subsystem::Search => "search",
+            Subsystem::Mcts => "mcts",
+            Subsystem::GameStore => "game_store",
+            Subsystem::Recorder => "recorder",
+            Subsystem::Telemetry => "telemetry",
+        }
+    }
+
+    pub fn fallback(&self) -> &'static str {
+        match self {
+            Subsystem::SearchThreads => "searching with the threads that started",
+            Subsystem::Search => "answering with a legal move found without searching",
+            Subsystem::Mcts => "searching the move with minimax",
+            Subsystem::GameStore => "keeping game state in memory",
+            Subsystem::Recorder => "not recording",
+            Subsystem::Telemetry => "not exporting telemetry",
+        }
+    }
+
+    fn index(&self) -> usize {
+        SUBSYSTEMS.iter().position(|s| s == self).unwrap()
+    }
+}
+
+// Degradations per subsystem since the server started. Counters only, so any thread can
+// report without a lock.
+static COUNTS: [AtomicU64; 6] = [
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+];
+
+// `subsystem` failed with `error` and falls back
+pub fn report(subsystem: Subsystem, error: &str) {
+    COUNTS[subsystem.index()].fetch_add(1, Ordering::Relaxed);
+    warn!(
+        "DEGRADED {}: {}, {}",
+        subsystem.name(),
+        error,
+        subsystem.fallback()
+    );
+}
+
+pub fn to_json() -> Value {
+    let mut out = Map::new();
+    for subsystem in SUBSYSTEMS {
+        out.insert(
+            subsystem.name().to_string(),
+            json!({
+                "count": COUNTS[subsystem.index()].load(Ordering::Relaxed),
+                "fallback": subsystem.fallback(),
+            }),
+        );
+    }
+    Value::Object(out)
+}
@@ -0,0 +1,83 @@
+// The four moves a snake can make. The engine passes these around instead of the strings of
+// the API, which only show up where requests are read and answers are written.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::logic::step;
+use crate::{Board, Coord};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    // In the order the search tries them when nothing says otherwise
+    pub const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ];
+
+    // Change of the head's x and y, y grows upwards
+    pub fn offset(self) -> (i32, i32) {
+        match self {
+            Direction::Up => (0, 1),
+            Direction::Down => (0, -1),
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+        }
+    }
+
+    // The move back to where this one came from
+    pub fn opposite(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Direction::Up => "up",
+            Direction::Down => "down",
+            Direction::Left => "left",
+            Direction::Right => "right",
+        }
+    }
+
+    // The move from `from` to the neighbouring cell `to`, across the edge of a wrapped board
+    pub fn between(board: &Board, from: &Coord, to: &Coord) -> Option<Direction> {
+        Direction::ALL
+            .iter()
+            .copied()
+            .find(|&direction| step(board, from, direction) == *to)
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Direction, String> {
+        Direction::ALL
+            .iter()
+            .copied()
+            .find(|direction| direction.as_str() == s)
+            .ok_or_else(|| format!("'{}' is not a direction", s))
+    }
+}
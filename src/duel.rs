@@ -0,0 +1,63 @@
+// Duels: games that are 1v1 from the first turn. The request doesn't name the game mode, the
+// source only says where the game comes from (ladder, league, arena, ...), so a duel is a game
+// that starts with exactly two snakes under any ruleset besides solo and squad. Unless
+// SNAKE_PRESET names a preset, duels are searched with the duel preset from their first turn
+// and every other game with the default one. Whether a game is a duel is decided once, at its
+// `/start` or at its first `/move` when the start went to another replica, so a four snake
+// game down to its last two keeps its preset. A game first seen after turn 1 is not a duel.
+
+use log::info;
+use std::sync::Arc;
+
+use crate::preset::{self, Preset};
+use crate::shared_store::{GameMap, SharedStore};
+use crate::{Board, Game};
+
+// Rulesets where two snakes at the start don't make a duel
+const NOT_DUELS: [&str; 2] = ["solo", "squad"];
+
+pub fn is_duel(game: &Game, board: &Board) -> bool {
+    let ruleset = game.ruleset.name();
+    board.snakes.len() == 2 && !NOT_DUELS.contains(&ruleset)
+}
+
+// Game modes of the games in progress on the server
+pub struct DuelMonitor {
+    // None until the game's mode is known
+    games: GameMap<Option<bool>>,
+}
+
+impl DuelMonitor {
+    pub fn new(store: Option<Arc<SharedStore>>) -> DuelMonitor {
+        DuelMonitor {
+            games: GameMap::new("mode", store),
+        }
+    }
+
+    // The preset to search the turn with
+    pub fn preset(&self, game: &Game, turn: i32, board: &Board) -> &'static Preset {
+        let duel = self.games.update(&game.id, |mode| {
+            *mode.get_or_insert_with(|| {
+                let duel = turn <= 1 && is_duel(game, board);
+                if duel {
+                    info!(
+                        "Game {} from '{}' is a duel, playing it with preset {}",
+                        game.id,
+                        game.source,
+                        preset::for_game(true).name
+                    );
+                }
+                duel
+            })
+        });
+        preset::for_game(duel)
+    }
+
+    pub fn start_game(&self, game: &Game, turn: i32, board: &Board) {
+        self.preset(game, turn, board);
+    }
+
+    pub fn end_game(&self, game_id: &str) {
+        self.games.remove(game_id);
+    }
+}
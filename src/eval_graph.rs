@@ -0,0 +1,210 @@
+// Optional per-game series of how the position evolved, for seeing where an advantage slipped
+// away. With EVAL_GRAPH_DIR set, every move appends one CSV row to
+// `<game id>-<snake id>.csv` in that directory: the search score, a rough survival
+// probability derived from it, our length and health, and our share of the free cells. The
+// `graph` command draws a finished series as a small SVG chart.
+
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::mate;
+use crate::{Battlesnake, Board, Game};
+
+pub const EVAL_GRAPH_ENV: &str = "EVAL_GRAPH_DIR";
+
+const HEADER: &str = "turn,eval,survival,length,health,space_share";
+
+// The score of a quiet, healthy position, and how many points it takes to move the survival
+// estimate from 50% to about 73%. Rough, the evaluation isn't calibrated as a probability.
+const SURVIVAL_MIDPOINT: f64 = 100.0;
+const SURVIVAL_SCALE: f64 = 300.0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Point {
+    pub turn: i32,
+    pub eval: i32,
+    pub survival: f64,
+    pub length: usize,
+    pub health: i32,
+    pub space_share: f64,
+}
+
+// 0 when every line loses, 1 when every line wins, a logistic of the score in between
+pub fn survival_probability(score: i32) -> f64 {
+    if mate::is_loss(score) {
+        0.0
+    } else if mate::is_win(score) {
+        1.0
+    } else {
+        1.0 / (1.0 + (-(score as f64 - SURVIVAL_MIDPOINT) / SURVIVAL_SCALE).exp())
+    }
+}
+
+pub fn graph_dir() -> Option<PathBuf> {
+    env::var(EVAL_GRAPH_ENV)
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+}
+
+pub fn point(turn: i32, score: i32, board: &Board, you: &Battlesnake, our_cells: i32) -> Point {
+    let occupied: usize = board.snakes.iter().map(|s| s.body.len()).sum();
+    let free = (board.width as usize * board.height as usize).saturating_sub(occupied);
+    Point {
+        turn,
+        eval: score,
+        survival: survival_probability(score),
+        length: you.body.len(),
+        health: you.health,
+        space_share: our_cells as f64 / free.max(1) as f64,
+    }
+}
+
+pub fn append(dir: &Path, game: &Game, you: &Battlesnake, point: &Point) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{}-{}.csv", game.id, you.id));
+    let new = !path.exists();
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    if new {
+        writeln!(file, "{}", HEADER)?;
+    }
+    writeln!(
+        file,
+        "{},{},{:.3},{},{},{:.3}",
+        point.turn, point.eval, point.survival, point.length, point.health, point.space_share
+    )
+}
+
+fn read_series(path: &Path) -> Result<Vec<Point>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    text.lines()
+        .skip(1)
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(i, line)| {
+            let invalid = || format!("{}:{}: invalid row '{}'", path.display(), i + 2, line);
+            let fields: Vec<&str> = line.split(',').collect();
+            if fields.len() != 6 {
+                return Err(invalid());
+            }
+            Ok(Point {
+                turn: fields[0].parse().map_err(|_| invalid())?,
+                eval: fields[1].parse().map_err(|_| invalid())?,
+                survival: fields[2].parse().map_err(|_| invalid())?,
+                length: fields[3].parse().map_err(|_| invalid())?,
+                health: fields[4].parse().map_err(|_| invalid())?,
+                space_share: fields[5].parse().map_err(|_| invalid())?,
+            })
+        })
+        .collect()
+}
+
+const WIDTH: f64 = 640.0;
+const HEIGHT: f64 = 240.0;
+const MARGIN: f64 = 30.0;
+const LINES: [(&str, &str); 4] = [
+    ("survival", "#1f77b4"),
+    ("space share", "#2ca02c"),
+    ("health", "#d62728"),
+    ("length", "#7f7f7f"),
+];
+
+// Survival, space share and health on a shared 0 to 1 scale, and length scaled to the
+// longest length of the game, over the turns of the game
+pub fn render_svg(series: &[Point]) -> String {
+    let first = series.first().map_or(0, |p| p.turn) as f64;
+    let last = series.last().map_or(1, |p| p.turn) as f64;
+    let max_length = series.iter().map(|p| p.length).max().unwrap_or(1).max(1) as f64;
+    let x = |turn: i32| {
+        MARGIN + (turn as f64 - first) / (last - first).max(1.0) * (WIDTH - 2.0 * MARGIN)
+    };
+    let y = |value: f64| HEIGHT - MARGIN - value.clamp(0.0, 1.0) * (HEIGHT - 2.0 * MARGIN);
+
+    // Every line on the 0 to 1 scale of the chart
+    let values = |p: &Point| {
+        [
+            p.survival,
+            p.space_share,
+            p.health as f64 / 100.0,
+            p.length as f64 / max_length,
+        ]
+    };
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" \
+         font-family=\"sans-serif\" font-size=\"11\">\n\
+         <rect width=\"{w}\" height=\"{h}\" fill=\"white\"/>\n\
+         <line x1=\"{m}\" y1=\"{b}\" x2=\"{r}\" y2=\"{b}\" stroke=\"black\"/>\n\
+         <line x1=\"{m}\" y1=\"{m}\" x2=\"{m}\" y2=\"{b}\" stroke=\"black\"/>\n\
+         <text x=\"{m}\" y=\"{t}\">turn {first}</text>\n\
+         <text x=\"{r}\" y=\"{t}\" text-anchor=\"end\">turn {last}</text>\n",
+        w = WIDTH,
+        h = HEIGHT,
+        m = MARGIN,
+        b = HEIGHT - MARGIN,
+        r = WIDTH - MARGIN,
+        t = HEIGHT - MARGIN / 3.0,
+        first = first,
+        last = last,
+    );
+    for (i, (name, color)) in LINES.iter().enumerate() {
+        let points: Vec<String> = series
+            .iter()
+            .map(|p| format!("{:.1},{:.1}", x(p.turn), y(values(p)[i])))
+            .collect();
+        svg += &format!(
+            "<polyline fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\" points=\"{}\"/>\n",
+            color,
+            points.join(" ")
+        );
+        svg += &format!(
+            "<text x=\"{:.0}\" y=\"{:.0}\" fill=\"{}\">{}</text>\n",
+            MARGIN + 10.0 + 110.0 * i as f64,
+            MARGIN - 10.0,
+            color,
+            name
+        );
+    }
+    svg + "</svg>\n"
+}
+
+// Entry point for `starter-snake-rust graph <series.csv> [chart.svg]`
+pub fn run(args: &[String]) -> Result<(), String> {
+    let path = Path::new(
+        args.first()
+            .ok_or_else(|| "usage: graph <series.csv> [chart.svg]".to_string())?,
+    );
+    let series = read_series(path)?;
+    if series.is_empty() {
+        return Err(format!("{} has no turns", path.display()));
+    }
+    let out = args
+        .get(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| path.with_extension("svg"));
+    fs::write(&out, render_svg(&series))
+        .map_err(|e| format!("could not write {}: {}", out.display(), e))?;
+
+    // Where the survival estimate dropped the most from one turn to the next
+    let slip = series
+        .windows(2)
+        .max_by(|a, b| {
+            let drop = |w: &[Point]| w[0].survival - w[1].survival;
+            drop(a).total_cmp(&drop(b))
+        })
+        .filter(|w| w[1].survival < w[0].survival);
+    if let Some(w) = slip {
+        println!(
+            "largest drop: turn {} -> {}, survival {:.0}% -> {:.0}%",
+            w[0].turn,
+            w[1].turn,
+            100.0 * w[0].survival,
+            100.0 * w[1].survival
+        );
+    }
+    println!("wrote {}", out.display());
+    Ok(())
+}
@@ -0,0 +1,191 @@
+// Best-response check: looks for a simple scripted opponent that consistently beats the
+// engine. A handful of hand-picked styles plus randomly weighted scripted bots each play a
+// series of 1v1 games against the engine, and the best of them tells how exploitable the
+// engine is. The games of a series are played side by side, so the engine's moves of every
+// game can be searched as one batch per turn.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::arena::{ArenaConfig, ArenaGame};
+use crate::batch::search_batch;
+use crate::direction::Direction;
+use crate::logic::SearchConfig;
+use crate::strategy::{EngineStrategy, ScriptedStrategy, ScriptedWeights, Strategy};
+use crate::GameState;
+
+struct Options {
+    games: u32,
+    random_policies: u32,
+    depth: i32,
+    seed: u64,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        games: 20,
+        random_policies: 12,
+        depth: 4,
+        seed: 1,
+    };
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", flag))?;
+        let invalid = |_| format!("invalid value '{}' for {}", value, flag);
+        match flag.as_str() {
+            "--games" => options.games = value.parse().map_err(invalid)?,
+            "--policies" => options.random_policies = value.parse().map_err(invalid)?,
+            "--depth" => options.depth = value.parse().map_err(invalid)?,
+            "--seed" => options.seed = value.parse().map_err(invalid)?,
+            _ => return Err(format!("unknown option '{}'", flag)),
+        }
+    }
+    Ok(options)
+}
+
+fn presets() -> Vec<(&'static str, ScriptedWeights)> {
+    let weights = |food, wall, aggression, space| ScriptedWeights {
+        food,
+        wall,
+        aggression,
+        space,
+    };
+    vec![
+        ("wall-cutter", weights(0.2, 1.0, 1.0, 0.5)),
+        ("food-rusher", weights(1.0, 0.0, 0.0, 0.3)),
+        ("head-hunter", weights(0.3, 0.0, 1.0, 0.3)),
+        ("space-hog", weights(0.2, -0.3, 0.0, 1.0)),
+    ]
+}
+
+// Lower bound of the 95% Wilson score interval for `wins` out of `games`
+fn wilson_lower_bound(wins: u32, games: u32) -> f64 {
+    if games == 0 {
+        return 0.0;
+    }
+    let n = games as f64;
+    let p = wins as f64 / n;
+    let z = 1.96;
+    let center = p + z * z / (2.0 * n);
+    let margin = z * (p * (1.0 - p) / n + z * z / (4.0 * n * n)).sqrt();
+    (center - margin) / (1.0 + z * z / n)
+}
+
+// Plays `options.games` games of the engine (player 0) against the scripted policy and
+// returns its wins, its losses and the total number of turns
+fn play_series(
+    name: &str,
+    weights: ScriptedWeights,
+    options: &Options,
+    config: &ArenaConfig,
+    rng: &mut StdRng,
+) -> (u32, u32, i32) {
+    let search = SearchConfig {
+        regret: true,
+        ..SearchConfig::new(options.depth)
+    };
+    let mut games: Vec<(ArenaGame, ScriptedStrategy)> = (0..options.games)
+        .map(|game| {
+            let game_id = format!("exploit-{}-{}", name, game);
+            let arena = ArenaGame::new(&game_id, 2, config, rng);
+            (arena, ScriptedStrategy::new(name, weights))
+        })
+        .collect();
+
+    loop {
+        let (running, states): (Vec<usize>, Vec<GameState>) = (0..games.len())
+            .filter(|&i| !games[i].0.is_over(config))
+            .filter_map(|i| Some((i, games[i].0.state(0)?)))
+            .unzip();
+        if running.is_empty() {
+            break;
+        }
+        let engine_moves = search_batch(&states, &search);
+        for (&i, analysis) in running.iter().zip(engine_moves) {
+            let (arena, scripted) = &mut games[i];
+            // No direction when every move loses
+            let engine_move = analysis.and_then(|a| a.direction).unwrap_or(Direction::Up);
+            let moves: Vec<Direction> = arena
+                .board
+                .snakes
+                .iter()
+                .enumerate()
+                .map(|(b, snake)| match arena.player(b) {
+                    0 => engine_move,
+                    _ => scripted.choose_move(&arena.game, arena.turn, &arena.board, snake),
+                })
+                .collect();
+            arena.advance(&moves, config, rng);
+        }
+    }
+
+    let (mut wins, mut losses, mut turns) = (0, 0, 0);
+    for (arena, _) in &games {
+        let outcome = arena.outcome();
+        match outcome.winner {
+            Some(1) => wins += 1,
+            Some(0) => losses += 1,
+            _ => {}
+        }
+        turns += outcome.turns;
+    }
+    (wins, losses, turns)
+}
+
+// Entry point for `starter-snake-rust exploit [--games N] [--policies K] [--depth D] [--seed S]`
+pub fn run(args: &[String]) -> Result<(), String> {
+    let options = parse_options(args)?;
+    let config = ArenaConfig::default();
+    let mut rng = StdRng::seed_from_u64(options.seed);
+
+    let mut policies: Vec<(String, ScriptedWeights)> = presets()
+        .into_iter()
+        .map(|(name, weights)| (name.to_string(), weights))
+        .collect();
+    for i in 0..options.random_policies {
+        let mut weight = || rng.gen_range(-1.0..1.0);
+        let weights = ScriptedWeights {
+            food: weight(),
+            wall: weight(),
+            aggression: weight(),
+            space: weight(),
+        };
+        policies.push((format!("random-{}", i), weights));
+    }
+
+    let engine = EngineStrategy::new(options.depth).name();
+    let mut results = Vec::new();
+    for (name, weights) in &policies {
+        let (wins, losses, turns) = play_series(name, *weights, &options, &config, &mut rng);
+        println!(
+            "{:<12} won {:>3} lost {:>3} of {} games against {}, {:.0} turns on average",
+            name,
+            wins,
+            losses,
+            options.games,
+            engine,
+            turns as f64 / options.games.max(1) as f64
+        );
+        results.push((name, weights, wins));
+    }
+
+    results.sort_by_key(|&(_, _, wins)| std::cmp::Reverse(wins));
+    let (name, weights, wins) = results[0];
+    let lower_bound = wilson_lower_bound(wins, options.games);
+    println!();
+    println!(
+        "best response: {} {:?} wins {:.0}% of games (95% lower bound {:.0}%)",
+        name,
+        weights,
+        100.0 * wins as f64 / options.games as f64,
+        100.0 * lower_bound
+    );
+    if lower_bound > 0.5 {
+        println!("the engine is exploitable: this policy beats it consistently");
+    } else {
+        println!("no scripted policy beats the engine consistently");
+    }
+    Ok(())
+}
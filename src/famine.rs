@@ -0,0 +1,44 @@
+// Famines: games where the board stays without food, on custom maps or rulesets without a
+// minimum of food. Looking for food that isn't there only adds noise to the evaluation, and
+// the search never lets a snake starve. Once the board has had no food for FAMINE_TURNS turns
+// in a row, health becomes a strict countdown: in the search a snake that runs out of health
+// dies, and the evaluation weighs how many turns we outlast the opponents by and whether we
+// keep enough room, see `evaluate_board`. The first food on the board ends the famine.
+
+use std::sync::Arc;
+
+use crate::shared_store::{GameMap, SharedStore};
+use crate::Board;
+
+// Turns in a row without food before a game counts as a famine
+pub const FAMINE_TURNS: u32 = 3;
+
+// Foodless turns of the games in progress on the server
+pub struct FamineMonitor {
+    games: GameMap<u32>,
+}
+
+impl FamineMonitor {
+    pub fn new(store: Option<Arc<SharedStore>>) -> FamineMonitor {
+        FamineMonitor {
+            games: GameMap::new("famine", store),
+        }
+    }
+
+    // Adds the turn to the game's count, returns the number of turns without food when the
+    // game is in a famine
+    pub fn observe(&self, game_id: &str, board: &Board) -> Option<u32> {
+        self.games.update(game_id, |foodless| {
+            *foodless = if board.food.is_empty() {
+                *foodless + 1
+            } else {
+                0
+            };
+            (*foodless >= FAMINE_TURNS).then_some(*foodless)
+        })
+    }
+
+    pub fn end_game(&self, game_id: &str) {
+        self.games.remove(game_id);
+    }
+}
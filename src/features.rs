@@ -0,0 +1,238 @@
+// Runtime switches for the experimental parts of the engine, so one can be turned off on the
+// ladder without a new build. Picked with SNAKE_FEATURES, a comma separated list where `name`
+// turns a feature on and `-name` turns it off; unlisted features keep their default. The
+// active set is stored with every recorded game (see recorder.rs) so the post-mortem can tell
+// which combination of changes a result belongs to.
+
+use std::env;
+use std::sync::OnceLock;
+
+pub const FEATURES_ENV: &str = "SNAKE_FEATURES";
+
+// The discriminant of a feature is its bit in the active set and its place in FEATURES
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Feature {
+    Voronoi,
+    Regret,
+    PartitionOrdering,
+    ThreatWeighting,
+    JointMoves,
+    ReachableSpace,
+    Mcts,
+    MaxN,
+    Refutations,
+    ShrinkForecast,
+    RootParallel,
+    MoveHistory,
+    Pondering,
+    AstarFood,
+    FoodRace,
+    Squeeze,
+    DeadEnds,
+    OpponentModel,
+    FoodSpawns,
+}
+
+pub struct FeatureInfo {
+    pub feature: Feature,
+    pub name: &'static str,
+    pub description: &'static str,
+    pub default: bool,
+}
+
+pub const FEATURES: [FeatureInfo; 19] = [
+    FeatureInfo {
+        feature: Feature::Voronoi,
+        name: "use_voronoi",
+        description: "Territory from a Voronoi split of the board in the evaluation",
+        default: true,
+    },
+    FeatureInfo {
+        feature: Feature::Regret,
+        name: "use_regret",
+        description: "Pick between near-equal root moves by regret across opponent models",
+        default: true,
+    },
+    FeatureInfo {
+        feature: Feature::PartitionOrdering,
+        name: "use_partition_ordering",
+        description: "Order our moves near the root by how they split the board",
+        default: true,
+    },
+    FeatureInfo {
+        feature: Feature::ThreatWeighting,
+        name: "use_threat_weighting",
+        description: "Let opponents veto our plans by how much harm they can do us",
+        default: true,
+    },
+    FeatureInfo {
+        feature: Feature::JointMoves,
+        name: "use_joint_moves",
+        description: "Resolve the moves of a turn in the search at the same time",
+        default: true,
+    },
+    FeatureInfo {
+        feature: Feature::ReachableSpace,
+        name: "use_reachable_space",
+        description: "Score the room our head can reach, with bodies moving out of the way",
+        default: true,
+    },
+    FeatureInfo {
+        feature: Feature::Mcts,
+        name: "use_mcts",
+        description: "Search games of three or more snakes with Monte Carlo tree search",
+        default: false,
+    },
+    FeatureInfo {
+        feature: Feature::MaxN,
+        name: "use_maxn",
+        description: "Search games of three or more snakes with every snake playing for itself",
+        default: false,
+    },
+    FeatureInfo {
+        feature: Feature::Refutations,
+        name: "use_refutations",
+        description: "Try the reply that refuted one of our root moves first against the others",
+        default: true,
+    },
+    FeatureInfo {
+        feature: Feature::ShrinkForecast,
+        name: "use_shrink_forecast",
+        description: "Play out the next royale shrink in the search",
+        default: true,
+    },
+    FeatureInfo {
+        feature: Feature::RootParallel,
+        name: "use_root_parallel",
+        description: "Search our root moves on several threads at the same time",
+        default: false,
+    },
+    FeatureInfo {
+        feature: Feature::MoveHistory,
+        name: "use_move_history",
+        description: "Try killer moves first, then the moves with the most cutoffs this turn",
+        default: true,
+    },
+    FeatureInfo {
+        feature: Feature::Pondering,
+        name: "use_pondering",
+        description: "Search the predicted next position while waiting for its request",
+        default: false,
+    },
+    FeatureInfo {
+        feature: Feature::AstarFood,
+        name: "use_astar_food",
+        description: "Measure the way to food with A* around bodies and through hazards",
+        default: false,
+    },
+    FeatureInfo {
+        feature: Feature::FoodRace,
+        name: "use_food_race",
+        description: "Only score food we win the race to, penalize food a longer snake ties",
+        default: false,
+    },
+    FeatureInfo {
+        feature: Feature::Squeeze,
+        name: "use_squeeze",
+        description: "Score snakes pinned by a body in less room than they are long",
+        default: false,
+    },
+    FeatureInfo {
+        feature: Feature::DeadEnds,
+        name: "use_dead_ends",
+        description: "Penalize heading into dead ends smaller than our body",
+        default: false,
+    },
+    FeatureInfo {
+        feature: Feature::OpponentModel,
+        name: "use_opponent_model",
+        description: "Average opponent replies by how likely the opponent is to play them",
+        default: false,
+    },
+    FeatureInfo {
+        feature: Feature::FoodSpawns,
+        name: "use_food_spawns",
+        description: "Score food at the leaves over the food that may spawn along the line",
+        default: false,
+    },
+];
+
+// Checked when building: FEATURES lists the features in the order of the enum, and they all
+// fit in the bits of ACTIVE
+const _: () = {
+    let mut index = 0;
+    while index < FEATURES.len() {
+        assert!(FEATURES[index].feature as usize == index);
+        index += 1;
+    }
+    assert!(FEATURES.len() <= u32::BITS as usize);
+};
+
+// One bit per feature, in the order of FEATURES
+static ACTIVE: OnceLock<u32> = OnceLock::new();
+
+fn bit(feature: Feature) -> u32 {
+    1 << feature as u32
+}
+
+fn defaults() -> u32 {
+    FEATURES
+        .iter()
+        .filter(|f| f.default)
+        .fold(0, |bits, f| bits | bit(f.feature))
+}
+
+// Applies SNAKE_FEATURES to the defaults. Has to run before the first search to take effect.
+pub fn select_from_env() -> Result<Vec<&'static str>, String> {
+    let mut bits = defaults();
+    for entry in env::var(FEATURES_ENV).unwrap_or_default().split(',') {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            continue;
+        }
+        let (on, name) = match entry.strip_prefix('-') {
+            Some(name) => (false, name),
+            None => (true, entry),
+        };
+        let info = FEATURES.iter().find(|f| f.name == name).ok_or_else(|| {
+            let names: Vec<&str> = FEATURES.iter().map(|f| f.name).collect();
+            format!(
+                "unknown feature '{}' in {}, expected one of {}",
+                name,
+                FEATURES_ENV,
+                names.join(", ")
+            )
+        })?;
+        if on {
+            bits |= bit(info.feature);
+        } else {
+            bits &= !bit(info.feature);
+        }
+    }
+    ACTIVE.get_or_init(|| bits);
+    Ok(active_names())
+}
+
+pub fn enabled(feature: Feature) -> bool {
+    ACTIVE.get_or_init(defaults) & bit(feature) != 0
+}
+
+// Names of the features that are on
+pub fn active_names() -> Vec<&'static str> {
+    FEATURES
+        .iter()
+        .filter(|f| enabled(f.feature))
+        .map(|f| f.name)
+        .collect()
+}
+
+// Entry point for `starter-snake-rust features`
+pub fn run(_args: &[String]) -> Result<(), String> {
+    select_from_env()?;
+    for info in &FEATURES {
+        let marker = if enabled(info.feature) { "*" } else { " " };
+        println!("{} {:<24} {}", marker, info.name, info.description);
+    }
+    Ok(())
+}
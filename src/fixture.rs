@@ -0,0 +1,130 @@
+// Turns a turn of a recorded game into a regression fixture.
+//
+// A recorded game is a JSON lines file with one `/move` request (GameState) per turn, or the
+// same frames in the compact encoding of compact.rs. The fixture keeps the request as-is next
+// to an ASCII render of the board and the move our snake actually played (found by comparing
+// its head with the next recorded turn), plus empty annotation fields to fill in by hand
+// before committing it.
+
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+use crate::compact::{decode_game, is_compact};
+use crate::direction::Direction;
+use crate::phase::{classify_phase, GamePhase};
+use crate::render::{print_board, render_board};
+use crate::GameState;
+
+pub const DEFAULT_FIXTURE_DIR: &str = "tests/positions";
+
+#[derive(Serialize, Debug)]
+pub struct Fixture {
+    name: String,
+    phase: GamePhase,
+    board: Vec<String>,
+    request: GameState,
+    played_move: Option<String>,
+    acceptable_moves: Vec<String>,
+    notes: String,
+}
+
+impl Fixture {
+    // A fixture for a position that wasn't played, with its answer already known
+    pub fn new(
+        name: &str,
+        request: GameState,
+        acceptable_moves: Vec<String>,
+        notes: String,
+    ) -> Fixture {
+        Fixture {
+            name: name.to_string(),
+            phase: classify_phase(&request),
+            board: render_board(&request.board, &request.you.id),
+            request,
+            played_move: None,
+            acceptable_moves,
+            notes,
+        }
+    }
+}
+
+pub fn load_recorded_game(path: &Path) -> Result<Vec<GameState>, String> {
+    let bytes = fs::read(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    if is_compact(&bytes) {
+        return decode_game(&bytes).map_err(|e| format!("{}: {}", path.display(), e));
+    }
+    let contents = String::from_utf8(bytes)
+        .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .map_err(|e| format!("{}:{}: invalid game state: {}", path.display(), i + 1, e))
+        })
+        .collect()
+}
+
+pub fn build_fixture(frames: Vec<GameState>, turn: i32) -> Result<Fixture, String> {
+    let index = frames
+        .iter()
+        .position(|frame| frame.turn == turn)
+        .ok_or_else(|| format!("turn {} is not in the recording", turn))?;
+
+    // Our snake isn't in the next frame if it died this turn, then the move stays unknown
+    let played_move = frames.get(index + 1).and_then(|next| {
+        let you = &frames[index].you;
+        next.board
+            .snakes
+            .iter()
+            .find(|s| s.id == you.id)
+            .and_then(|s| Direction::between(&frames[index].board, &you.body[0], &s.body[0]))
+            .map(|m| m.to_string())
+    });
+
+    let request = frames.into_iter().nth(index).unwrap();
+    Ok(Fixture {
+        name: format!("{}-turn-{}", request.game.id, turn),
+        phase: classify_phase(&request),
+        board: render_board(&request.board, &request.you.id),
+        request,
+        played_move,
+        acceptable_moves: Vec::new(),
+        notes: String::new(),
+    })
+}
+
+// Entry point for `starter-snake-rust fixture <recording.jsonl> <turn> [output dir]`
+pub fn run(args: &[String]) -> Result<(), String> {
+    if args.len() < 2 {
+        return Err("usage: fixture <recording.jsonl> <turn> [output dir]".to_string());
+    }
+    let turn: i32 = args[1]
+        .parse()
+        .map_err(|_| format!("invalid turn number '{}'", args[1]))?;
+    let out_dir = Path::new(
+        args.get(2)
+            .map(String::as_str)
+            .unwrap_or(DEFAULT_FIXTURE_DIR),
+    );
+
+    let frames = load_recorded_game(Path::new(&args[0]))?;
+    let fixture = build_fixture(frames, turn)?;
+
+    fs::create_dir_all(out_dir)
+        .map_err(|e| format!("could not create {}: {}", out_dir.display(), e))?;
+    let out_path = out_dir.join(format!("{}.json", fixture.name));
+    let text = serde_json::to_string_pretty(&fixture).map_err(|e| e.to_string())?;
+    fs::write(&out_path, text + "\n")
+        .map_err(|e| format!("could not write {}: {}", out_path.display(), e))?;
+
+    print_board(&fixture.request.board, &fixture.request.you.id);
+    println!(
+        "played move: {}",
+        fixture.played_move.as_deref().unwrap_or("unknown")
+    );
+    println!("wrote {}", out_path.display());
+    Ok(())
+}
@@ -0,0 +1,142 @@
+// Which food we would actually win. The nearest piece of food is worth nothing when an
+// opponent gets there first, and heading for one a longer opponent reaches on the same turn
+// ends in a head-to-head we lose. So with `use_food_race` the evaluation times the race to
+// every piece: one breadth-first search from our head, and one from every opponent head at
+// once that keeps, per cell, the turn the first of them gets there and the longest snake
+// arriving on that turn. Bodies are in the way, like in `voronoi_territory`.
+//
+// Food we reach strictly first is ours, and so is food we reach together with shorter
+// snakes only, since both of us eat and we stay the longer one. Food a snake at least as long
+// as us reaches on the same turn is contested, and food one reaches sooner is lost.
+
+use smallvec::SmallVec;
+
+use crate::direction::Direction;
+use crate::logic::neighbour;
+use crate::workspace::SearchWorkspace;
+use crate::{Battlesnake, Board, Coord};
+
+// Distances to the nearest food of each kind, i32::MAX when there's none
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FoodRace {
+    pub won: i32,
+    pub contested: i32,
+}
+
+pub fn food_race(board: &Board, you_id: usize, workspace: &mut SearchWorkspace) -> FoodRace {
+    let mut race = FoodRace {
+        won: i32::MAX,
+        contested: i32::MAX,
+    };
+    let length = board.snakes[you_id].body.len();
+    if board.food.is_empty() || length == 0 {
+        return race;
+    }
+    let width = board.width;
+    let height = board.height as i32;
+    let in_bounds = |c: &Coord| c.x >= 0 && c.x < width && c.y >= 0 && c.y < height;
+    let index = |c: &Coord| (c.y * width + c.x) as usize;
+
+    // Our arrival at every piece of food
+    let ours: SmallVec<[i32; 16]> = {
+        arrivals(board, workspace, |i, _| i == you_id);
+        let costs = &workspace.costs;
+        board
+            .food
+            .iter()
+            .map(|food| {
+                if in_bounds(food) {
+                    costs[index(food)]
+                } else {
+                    i32::MAX
+                }
+            })
+            .collect()
+    };
+    // The first opponent arrival and the longest snake arriving then
+    arrivals(board, workspace, |i, snake| {
+        i != you_id && !snake.body.is_empty()
+    });
+
+    for (food, &ours) in board.food.iter().zip(&ours) {
+        if ours == i32::MAX {
+            continue;
+        }
+        let (theirs, longest) = if in_bounds(food) {
+            let i = index(food);
+            (workspace.costs[i], workspace.owners[i])
+        } else {
+            (i32::MAX, 0)
+        };
+        if ours < theirs || (ours == theirs && longest < length) {
+            race.won = race.won.min(ours);
+        } else if ours == theirs {
+            race.contested = race.contested.min(ours);
+        }
+    }
+    race
+}
+
+// Breadth-first search from the heads of the snakes `from` picks, leaves the turn the first
+// of them gets to every cell in `costs` and the length of the longest one arriving on that
+// turn in `owners`
+fn arrivals<F>(board: &Board, workspace: &mut SearchWorkspace, from: F)
+where
+    F: Fn(usize, &Battlesnake) -> bool,
+{
+    let width = board.width;
+    let height = board.height as i32;
+    let in_bounds = |c: &Coord| c.x >= 0 && c.x < width && c.y >= 0 && c.y < height;
+    let index = |c: &Coord| (c.y * width + c.x) as usize;
+    let cells = (width * height) as usize;
+
+    workspace.reset(cells);
+    workspace.owners.clear();
+    workspace.owners.resize(cells, 0);
+    let SearchWorkspace {
+        visited,
+        costs,
+        queue,
+        owners,
+        ..
+    } = workspace;
+    for snake in &board.snakes {
+        for segment in snake.body.iter().filter(|c| in_bounds(c)) {
+            visited[index(segment)] = true;
+        }
+    }
+    for (i, snake) in board.snakes.iter().enumerate() {
+        if !from(i, snake) {
+            continue;
+        }
+        if let Some(head) = snake.body.first().filter(|c| in_bounds(c)) {
+            let h = index(head);
+            if costs[h] == i32::MAX {
+                queue.push_back(*head);
+            }
+            costs[h] = 0;
+            owners[h] = owners[h].max(snake.body.len());
+        }
+    }
+
+    // Every cell of a turn is reached before any of them is expanded, so a cell's longest
+    // arrival is settled by the time it's taken off the queue
+    while let Some(current) = queue.pop_front() {
+        let c = index(&current);
+        let (distance, longest) = (costs[c] + 1, owners[c]);
+        for direction in Direction::ALL {
+            let next = match neighbour(board, &current, direction) {
+                Some(next) if !visited[index(&next)] => next,
+                _ => continue,
+            };
+            let n = index(&next);
+            if costs[n] == i32::MAX {
+                costs[n] = distance;
+                owners[n] = longest;
+                queue.push_back(next);
+            } else if costs[n] == distance {
+                owners[n] = owners[n].max(longest);
+            }
+        }
+    }
+}
@@ -0,0 +1,82 @@
+// Food spawns. New food turns up at random: a piece on a random free cell with
+// `foodSpawnChance` percent every turn, and right away whenever the board has less than
+// `minimumFood`. The search can't know where, so every line is played on the food of the root.
+// With `use_food_spawns` the leaves of the search are chance nodes over the spawns of their
+// line. With the chance that at least one piece spawned in the turns played down to the
+// leaf, the nearest food is the closer of the nearest piece on the board and a piece on a
+// free cell drawn at random; otherwise it's the nearest piece on the board. A spawn only
+// changes the food term of the evaluation, so the expectation is taken over that term alone,
+// in one pass over the board instead of an evaluation per free cell. Deep lines of a snake
+// low on health can then count on some food turning up, short ones hardly do.
+
+use crate::logic::distance;
+use crate::rules::RulesConfig;
+use crate::workspace::SearchWorkspace;
+use crate::{Board, Coord};
+
+// Chance in percent that at least one piece of food spawned in the `turns` turns of a line,
+// from a board with `food` pieces on it
+pub fn spawn_chance(rules: &RulesConfig, food: usize, turns: i32) -> i32 {
+    if rules.constrictor || turns <= 0 {
+        return 0;
+    }
+    if food < rules.minimum_food {
+        return 100;
+    }
+    let none = (1.0 - rules.food_spawn_chance.min(100) as f64 / 100.0).powi(turns);
+    ((1.0 - none) * 100.0).round() as i32
+}
+
+// Expected value of `term`, the score for being some distance from the nearest food, at a
+// leaf with the nearest food on the board `nearest` moves from `head` (i32::MAX without any)
+// and a piece spawned somewhere with `chance` percent
+pub fn expected_term(
+    board: &Board,
+    head: &Coord,
+    nearest: i32,
+    chance: i32,
+    workspace: &mut SearchWorkspace,
+    term: impl Fn(i32) -> i32,
+) -> i32 {
+    let on_board = if nearest == i32::MAX {
+        0
+    } else {
+        term(nearest)
+    };
+    if chance <= 0 {
+        return on_board;
+    }
+    let width = board.width;
+    let height = board.height as i32;
+    let in_bounds = |c: &Coord| c.x >= 0 && c.x < width && c.y >= 0 && c.y < height;
+    let index = |c: &Coord| (c.y * width + c.x) as usize;
+    workspace.reset((width * height) as usize);
+    let visited = &mut workspace.visited;
+    let taken = board
+        .snakes
+        .iter()
+        .flat_map(|snake| snake.body.iter())
+        .chain(board.food.iter());
+    for cell in taken.filter(|c| in_bounds(c)) {
+        visited[index(cell)] = true;
+    }
+
+    // Free cells are as likely as each other to get the piece
+    let mut free = 0;
+    let mut sum: i64 = 0;
+    for y in 0..height {
+        for x in 0..width {
+            let cell = Coord { x, y };
+            if visited[index(&cell)] {
+                continue;
+            }
+            free += 1;
+            sum += term(distance(board, head, &cell).min(nearest)) as i64;
+        }
+    }
+    if free == 0 {
+        return on_board;
+    }
+    let spawned = (sum / free) as i32;
+    ((100 - chance) * on_board + chance * spawned) / 100
+}
@@ -0,0 +1,427 @@
+// Release gating for engine changes. `gauntlet` plays a candidate build against the current
+// champion build in the local arena, cycling through standard, royale and duel games, until a
+// sequential probability ratio test (SPRT) decides whether the candidate is stronger by at
+// least `--elo1` or not stronger than `--elo0`. `promote` then makes an accepted candidate the
+// new champion.
+//
+// Builds are release binaries of this crate: each one is started as a server on its own port
+// and played through the HTTP API, so any two versions of the engine can meet. The champion
+// binary is copied into `champion/` on promotion and recorded in `champion.json`, the last
+// gauntlet result is kept in `gauntlet.json`.
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::Hasher;
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::arena::{play_game, ArenaConfig};
+use crate::rules::RulesConfig;
+use crate::strategy::{HttpStrategy, Strategy};
+
+pub const GAUNTLET_RESULT: &str = "gauntlet.json";
+pub const CHAMPION_FILE: &str = "champion.json";
+pub const CHAMPION_DIR: &str = "champion";
+
+// How long a freshly started server gets to open its port
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(15);
+// No verdict before this many games, the variance estimate is meaningless before that
+const MIN_GAMES: u32 = 30;
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Tally {
+    wins: u32,
+    draws: u32,
+    losses: u32,
+}
+
+impl Tally {
+    fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    // Candidate score per game, draws count half
+    fn score(&self) -> f64 {
+        (self.wins as f64 + 0.5 * self.draws as f64) / self.games().max(1) as f64
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Verdict {
+    Accepted,
+    Rejected,
+    Inconclusive,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GauntletResult {
+    candidate: String,
+    // Hash of the candidate binary, promotion refuses a binary that changed since
+    candidate_fingerprint: String,
+    champion: String,
+    elo0: f64,
+    elo1: f64,
+    total: Tally,
+    per_config: Vec<(String, Tally)>,
+    llr: f64,
+    verdict: Verdict,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Champion {
+    binary: String,
+    fingerprint: String,
+    // Seconds since the Unix epoch
+    promoted_at: u64,
+    // The gauntlet that got it promoted, None for the initial champion
+    gauntlet: Option<GauntletResult>,
+}
+
+struct Options {
+    candidate: String,
+    champion: Option<String>,
+    max_games: u32,
+    elo0: f64,
+    elo1: f64,
+    alpha: f64,
+    beta: f64,
+    seed: u64,
+    port: u16,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        candidate: String::new(),
+        champion: None,
+        max_games: 600,
+        elo0: 0.0,
+        elo1: 20.0,
+        alpha: 0.05,
+        beta: 0.05,
+        seed: 1,
+        port: 8810,
+    };
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", flag))?;
+        let invalid = || format!("invalid value '{}' for {}", value, flag);
+        match flag.as_str() {
+            "--candidate" => options.candidate = value.clone(),
+            "--champion" => options.champion = Some(value.clone()),
+            "--max-games" => options.max_games = value.parse().map_err(|_| invalid())?,
+            "--elo0" => options.elo0 = value.parse().map_err(|_| invalid())?,
+            "--elo1" => options.elo1 = value.parse().map_err(|_| invalid())?,
+            "--alpha" => options.alpha = value.parse().map_err(|_| invalid())?,
+            "--beta" => options.beta = value.parse().map_err(|_| invalid())?,
+            "--seed" => options.seed = value.parse().map_err(|_| invalid())?,
+            "--port" => options.port = value.parse().map_err(|_| invalid())?,
+            _ => return Err(format!("unknown option '{}'", flag)),
+        }
+    }
+    if options.candidate.is_empty() {
+        return Err(
+            "usage: gauntlet --candidate <binary> [--champion <binary>] \
+                    [--max-games N] [--elo0 E] [--elo1 E] [--alpha A] [--beta B] [--seed S] \
+                    [--port P]"
+                .to_string(),
+        );
+    }
+    Ok(options)
+}
+
+fn fingerprint(path: &Path) -> Result<String, String> {
+    let bytes = fs::read(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    let mut hasher = DefaultHasher::new();
+    hasher.write(&bytes);
+    Ok(format!("{:016x}", hasher.finish()))
+}
+
+fn read_json<T: for<'de> Deserialize<'de>>(path: &str) -> Result<Option<T>, String> {
+    if !Path::new(path).exists() {
+        return Ok(None);
+    }
+    let text = fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+    serde_json::from_str(&text)
+        .map(Some)
+        .map_err(|e| format!("{}: {}", path, e))
+}
+
+fn write_json<T: Serialize>(path: &str, value: &T) -> Result<(), String> {
+    let text = serde_json::to_string_pretty(value).map_err(|e| e.to_string())?;
+    fs::write(path, text + "\n").map_err(|e| format!("could not write {}: {}", path, e))
+}
+
+// A snake server started from a binary, stopped when dropped
+struct EngineProcess {
+    child: Child,
+    addr: String,
+}
+
+impl EngineProcess {
+    fn spawn(binary: &str, port: u16) -> Result<EngineProcess, String> {
+        let child = Command::new(binary)
+            .env("PORT", port.to_string())
+            .env("ROCKET_ADDRESS", "127.0.0.1")
+            .env("RUST_LOG", "warn")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("could not start {}: {}", binary, e))?;
+        let process = EngineProcess {
+            child,
+            addr: format!("127.0.0.1:{}", port),
+        };
+        let started = Instant::now();
+        while TcpStream::connect(&process.addr).is_err() {
+            if started.elapsed() > STARTUP_TIMEOUT {
+                return Err(format!(
+                    "{} did not start listening on {}",
+                    binary, process.addr
+                ));
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        Ok(process)
+    }
+}
+
+impl Drop for EngineProcess {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+// Log likelihood ratio of "the candidate is elo1 stronger" against "elo0 stronger", with the
+// normal approximation of the score distribution (the generalized SPRT)
+fn log_likelihood_ratio(tally: &Tally, elo0: f64, elo1: f64) -> f64 {
+    let n = tally.games() as f64;
+    if n == 0.0 {
+        return 0.0;
+    }
+    let s = tally.score();
+    let variance = (tally.wins as f64 * (1.0 - s).powi(2)
+        + tally.draws as f64 * (0.5 - s).powi(2)
+        + tally.losses as f64 * s.powi(2))
+        / n;
+    // All wins or all losses so far, don't let the variance collapse to zero
+    let variance = variance.max(0.01);
+    let (s0, s1) = (expected_score(elo0), expected_score(elo1));
+    (s1 - s0) * (2.0 * s - s0 - s1) * n / (2.0 * variance)
+}
+
+fn arena_configs() -> Vec<(&'static str, usize, ArenaConfig)> {
+    let royale = ArenaConfig {
+        rules: RulesConfig {
+            shrink_every: Some(25),
+            ..RulesConfig::default()
+        },
+        ..ArenaConfig::default()
+    };
+    vec![
+        ("standard", 4, ArenaConfig::default()),
+        ("royale", 4, royale),
+        ("duel", 2, ArenaConfig::default()),
+    ]
+}
+
+fn run_gauntlet(options: &Options) -> Result<(), String> {
+    let champion = match &options.champion {
+        Some(champion) => champion.clone(),
+        None => read_json::<Champion>(CHAMPION_FILE)?
+            .map(|c| c.binary)
+            .ok_or_else(|| {
+                format!(
+                    "no {} yet, pass --champion or make one with `promote --initial <binary>`",
+                    CHAMPION_FILE
+                )
+            })?,
+    };
+    let candidate_fingerprint = fingerprint(Path::new(&options.candidate))?;
+
+    let candidate_server = EngineProcess::spawn(&options.candidate, options.port)?;
+    let champion_server = EngineProcess::spawn(&champion, options.port + 1)?;
+    let lower = (options.beta / (1.0 - options.alpha)).ln();
+    let upper = ((1.0 - options.beta) / options.alpha).ln();
+    println!(
+        "{} vs {}: H0 elo {} / H1 elo {}, LLR bounds [{:.2}, {:.2}]",
+        options.candidate, champion, options.elo0, options.elo1, lower, upper
+    );
+
+    let configs = arena_configs();
+    let mut per_config = vec![Tally::default(); configs.len()];
+    let mut total = Tally::default();
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let mut llr = 0.0;
+    let mut verdict = Verdict::Inconclusive;
+
+    for game in 0..options.max_games {
+        let index = game as usize % configs.len();
+        let (name, snakes, config) = &configs[index];
+        // Swap seats every round of configurations so neither side keeps the first snakes
+        let candidate_first = (game as usize / configs.len()).is_multiple_of(2);
+        let is_candidate = |seat: usize| seat.is_multiple_of(2) == candidate_first;
+        let mut strategies: Vec<Box<dyn Strategy>> = (0..*snakes)
+            .map(|seat| -> Box<dyn Strategy> {
+                if is_candidate(seat) {
+                    Box::new(HttpStrategy::new("candidate", &candidate_server.addr))
+                } else {
+                    Box::new(HttpStrategy::new("champion", &champion_server.addr))
+                }
+            })
+            .collect();
+
+        let game_id = format!("gauntlet-{}", game);
+        let outcome = play_game(&game_id, &mut strategies, config, &mut rng);
+        let result = match outcome.winner {
+            Some(seat) if is_candidate(seat) => "candidate won",
+            Some(_) => "champion won",
+            None => "draw",
+        };
+        for tally in [&mut per_config[index], &mut total] {
+            match outcome.winner {
+                Some(seat) if is_candidate(seat) => tally.wins += 1,
+                Some(_) => tally.losses += 1,
+                None => tally.draws += 1,
+            }
+        }
+
+        llr = log_likelihood_ratio(&total, options.elo0, options.elo1);
+        println!(
+            "game {:>4} {:<8} {:<13} W-D-L {}-{}-{} LLR {:.2}",
+            game + 1,
+            name,
+            result,
+            total.wins,
+            total.draws,
+            total.losses,
+            llr
+        );
+        if total.games() >= MIN_GAMES && llr >= upper {
+            verdict = Verdict::Accepted;
+            break;
+        }
+        if total.games() >= MIN_GAMES && llr <= lower {
+            verdict = Verdict::Rejected;
+            break;
+        }
+    }
+
+    println!();
+    for ((name, _, _), tally) in configs.iter().zip(&per_config) {
+        println!(
+            "{:<8} W-D-L {}-{}-{} score {:.1}%",
+            name,
+            tally.wins,
+            tally.draws,
+            tally.losses,
+            100.0 * tally.score()
+        );
+    }
+    println!(
+        "verdict: {}",
+        match verdict {
+            Verdict::Accepted => "accepted, run `promote` to make it the champion",
+            Verdict::Rejected => "rejected",
+            Verdict::Inconclusive => "inconclusive, no decision within --max-games",
+        }
+    );
+
+    let result = GauntletResult {
+        candidate: options.candidate.clone(),
+        candidate_fingerprint,
+        champion,
+        elo0: options.elo0,
+        elo1: options.elo1,
+        total,
+        per_config: configs
+            .iter()
+            .map(|(name, _, _)| name.to_string())
+            .zip(per_config)
+            .collect(),
+        llr,
+        verdict,
+    };
+    write_json(GAUNTLET_RESULT, &result)
+}
+
+// Entry point for `starter-snake-rust gauntlet --candidate <binary> ...`
+pub fn run(args: &[String]) -> Result<(), String> {
+    let options = parse_options(args)?;
+    run_gauntlet(&options)
+}
+
+// Copies `binary` into the champion directory and records it as the champion
+fn install_champion(binary: &str, gauntlet: Option<GauntletResult>) -> Result<(), String> {
+    let fingerprint = fingerprint(Path::new(binary))?;
+    if let Some(gauntlet) = &gauntlet {
+        if gauntlet.candidate_fingerprint != fingerprint {
+            return Err(format!(
+                "{} changed since the gauntlet, run it again",
+                binary
+            ));
+        }
+    }
+    fs::create_dir_all(CHAMPION_DIR)
+        .map_err(|e| format!("could not create {}: {}", CHAMPION_DIR, e))?;
+    let target: PathBuf =
+        Path::new(CHAMPION_DIR).join(format!("starter-snake-rust-{}", fingerprint));
+    fs::copy(binary, &target)
+        .map_err(|e| format!("could not copy {} to {}: {}", binary, target.display(), e))?;
+
+    let promoted_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let champion = Champion {
+        binary: target.display().to_string(),
+        fingerprint,
+        promoted_at,
+        gauntlet,
+    };
+    write_json(CHAMPION_FILE, &champion)?;
+    println!("{} is the new champion", champion.binary);
+    Ok(())
+}
+
+// Entry point for `starter-snake-rust promote`, or `promote --initial <binary>` to set the
+// first champion when there is none to play against yet
+pub fn promote(args: &[String]) -> Result<(), String> {
+    match args {
+        [flag, binary] if flag == "--initial" => {
+            if Path::new(CHAMPION_FILE).exists() {
+                return Err(format!(
+                    "there already is a champion in {}, candidates have to beat it",
+                    CHAMPION_FILE
+                ));
+            }
+            install_champion(binary, None)
+        }
+        [] => {
+            let result = read_json::<GauntletResult>(GAUNTLET_RESULT)?
+                .ok_or_else(|| format!("no {}, run a gauntlet first", GAUNTLET_RESULT))?;
+            if result.verdict != Verdict::Accepted {
+                return Err(format!(
+                    "the last gauntlet of {} wasn't accepted",
+                    result.candidate
+                ));
+            }
+            let binary = result.candidate.clone();
+            install_champion(&binary, Some(result))?;
+            fs::remove_file(GAUNTLET_RESULT).map_err(|e| e.to_string())
+        }
+        _ => Err("usage: promote [--initial <binary>]".to_string()),
+    }
+}
@@ -0,0 +1,86 @@
+// Where the opponents have been lately. Snakes tend to run the same corridors again and
+// again, so the cells opponent heads went through in the last turns are contested, while an
+// area they left a while ago is safer. Every game keeps a grid of decaying "heat" per cell,
+// updated on each `/move`: a cell gets HEAT_VISIT when an opponent head enters it, and loses
+// HEAT_DECAY percent every turn, so after about 20 turns a visit is forgotten. The search
+// gets a copy for the traffic term of the evaluation and for ordering the opponents' moves.
+
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::shared_store::{GameMap, SharedStore};
+use crate::{Board, Coord};
+
+// Heat of a cell an opponent head just entered
+pub const HEAT_VISIT: i32 = 100;
+// Percent of the heat lost every turn
+const HEAT_DECAY: i32 = 15;
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct HeatGrid {
+    width: i32,
+    cells: Vec<i32>,
+}
+
+impl HeatGrid {
+    // Adds the turn: old heat cools down and the opponent heads heat up their cells
+    pub fn observe(&mut self, board: &Board, you_id: &str) {
+        let size = (board.width * board.height as i32) as usize;
+        if self.width != board.width || self.cells.len() != size {
+            self.width = board.width;
+            self.cells = vec![0; size];
+        }
+        for heat in self.cells.iter_mut() {
+            *heat = *heat * (100 - HEAT_DECAY) / 100;
+        }
+        for snake in board.snakes.iter().filter(|s| s.id != you_id) {
+            if let Some(index) = snake.body.first().and_then(|head| cell_index(board, head)) {
+                self.cells[index] = HEAT_VISIT;
+            }
+        }
+    }
+
+    pub fn cells(&self) -> &[i32] {
+        &self.cells
+    }
+}
+
+// Index of `cell` in a heat grid of `board`, None off the board
+pub fn cell_index(board: &Board, cell: &Coord) -> Option<usize> {
+    let on_board =
+        cell.x >= 0 && cell.x < board.width && cell.y >= 0 && cell.y < board.height as i32;
+    on_board.then(|| (cell.y * board.width + cell.x) as usize)
+}
+
+// Heat of `cell` in `heat`, 0 off the board or without a grid
+pub fn heat_at(heat: &[i32], board: &Board, cell: &Coord) -> i32 {
+    cell_index(board, cell)
+        .and_then(|index| heat.get(index).copied())
+        .unwrap_or(0)
+}
+
+// Heat grids of the games in progress on the server, per game and snake of ours
+pub struct HeatMonitor {
+    games: GameMap<HeatGrid>,
+}
+
+impl HeatMonitor {
+    pub fn new(store: Option<Arc<SharedStore>>) -> HeatMonitor {
+        HeatMonitor {
+            games: GameMap::new("heat", store),
+        }
+    }
+
+    // Adds the turn to the grid of the game and returns a copy of it for the search
+    pub fn observe(&self, game_id: &str, board: &Board, you_id: &str) -> Vec<i32> {
+        let key = format!("{}:{}", game_id, you_id);
+        self.games.update(&key, |grid| {
+            grid.observe(board, you_id);
+            grid.cells().to_vec()
+        })
+    }
+
+    pub fn end_game(&self, game_id: &str, you_id: &str) {
+        self.games.remove(&format!("{}:{}", game_id, you_id));
+    }
+}
@@ -0,0 +1,438 @@
+// What the search takes for granted about `simulate_move` and `simulate_turn`, checked on
+// random boards by the property tests in tests/simulate_move.rs:
+//
+//   - a move grows the body by at most one segment, and `length` follows the body
+//   - after a turn from a board where everyone has 1 to 100 health, every snake still on the
+//     board has 0 to 100 (0 when it ran out outside a hazard, see `starves`) and every
+//     eliminated snake an empty body and no health
+//   - no two snakes left on the board share a cell, and no head is in its own body
+//   - taking a move or a turn back gives the board it was played on, exactly, also after the
+//     turns the move ordering plays ahead to see how the board gets split up
+//   - a turn leaves every snake with the body, length and health the official rules give it,
+//     the way rules.rs plays them for the arena: an eating snake grows by doubling up its
+//     tail, which stays put for one turn
+//   - eliminated snakes don't get plies of their own: a search scores a board with them on
+//     it like the board without them, so a line down to one opponent takes two plies a turn
+//   - the flood fills on bit sets (see bitboard.rs) reach as many cells as the ones cell by
+//     cell, from every cell of a board
+//
+// `check_oracle_corpus` holds the search's turns to the official rules on positions from
+// real games as well: every line the oracle (see oracle.rs) plays out from them.
+//
+// On boards of every size, from 7x7 to past the 1024 cells bit sets hold, the board size
+// tests in tests/board_sizes.rs play whole games and check the flood fills and the Voronoi
+// split on bit sets against the queues, and that the engine's moves are legal.
+//
+// Boards are compared through their JSON, like the requests they come from.
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde_json::Value;
+
+use crate::arena::{ArenaConfig, ArenaGame};
+use crate::bitboard;
+use crate::direction::Direction;
+use crate::logic::{
+    flood_fill_queue, is_move_legal, minimax, order_moves_by_partition, simulate_move,
+    simulate_turn, undo_move, undo_turn, voronoi_queue, voronoi_territory,
+};
+use crate::oracle;
+use crate::rules::{advance_turn, RulesConfig};
+use crate::strategy::{baseline, EngineStrategy, Strategy};
+use crate::workspace::with_workspace;
+use crate::{Battlesnake, Board, Coord};
+
+fn rules(constrictor: bool) -> RulesConfig {
+    RulesConfig {
+        constrictor,
+        ..RulesConfig::default()
+    }
+}
+
+fn snapshot(board: &Board) -> Value {
+    serde_json::to_value(board).unwrap()
+}
+
+fn check_growth(before: &Battlesnake, after: &Battlesnake) -> Result<(), String> {
+    let grown = after.body.len() as i32 - before.body.len() as i32;
+    if !(0..=1).contains(&grown) {
+        return Err(format!(
+            "{} went from {} to {} segments",
+            before.id,
+            before.body.len(),
+            after.body.len()
+        ));
+    }
+    if after.length - before.length != grown {
+        return Err(format!(
+            "{} grew {} segments but its length went from {} to {}",
+            before.id, grown, before.length, after.length
+        ));
+    }
+    Ok(())
+}
+
+// Plays `direction` for the snake at `snake` and takes it back
+pub fn check_move(
+    board: &Board,
+    snake: usize,
+    direction: Direction,
+    constrictor: bool,
+) -> Result<(), String> {
+    let mut played = board.clone();
+    let undo = simulate_move(&mut played, snake, direction, &rules(constrictor));
+    check_growth(&board.snakes[snake], &played.snakes[snake])?;
+    undo_move(&mut played, undo);
+    if snapshot(&played) != snapshot(board) {
+        return Err(format!(
+            "taking back {} of snake {} changed the board",
+            direction, snake
+        ));
+    }
+    Ok(())
+}
+
+// Plays `moves` as one turn and takes it back
+pub fn check_turn(
+    board: &Board,
+    moves: &[(usize, Direction)],
+    famine: bool,
+    constrictor: bool,
+) -> Result<(), String> {
+    let mut played = board.clone();
+    let undo = simulate_turn(&mut played, moves, famine, &rules(constrictor));
+
+    for (before, after) in board.snakes.iter().zip(&played.snakes) {
+        if before.body.is_empty() {
+            continue;
+        }
+        if after.body.is_empty() {
+            if after.health != 0 {
+                return Err(format!(
+                    "{} was eliminated with {} health",
+                    after.id, after.health
+                ));
+            }
+            continue;
+        }
+        if !(0..=100).contains(&after.health) {
+            return Err(format!(
+                "{} is on the board with {} health",
+                after.id, after.health
+            ));
+        }
+        if moves
+            .iter()
+            .any(|&(index, _)| board.snakes[index].id == before.id)
+        {
+            check_growth(before, after)?;
+        }
+        if after.body[1..].contains(&after.body[0]) {
+            return Err(format!("{} has its head in its own body", after.id));
+        }
+    }
+    let alive: Vec<&Battlesnake> = played
+        .snakes
+        .iter()
+        .filter(|s| !s.body.is_empty())
+        .collect();
+    for (i, a) in alive.iter().enumerate() {
+        for b in &alive[i + 1..] {
+            if let Some(cell) = a.body.iter().find(|cell| b.body.contains(cell)) {
+                return Err(format!("{} and {} are both on {:?}", a.id, b.id, cell));
+            }
+        }
+    }
+
+    undo_turn(&mut played, undo);
+    if snapshot(&played) != snapshot(board) {
+        return Err("taking back the turn changed the board".to_string());
+    }
+    Ok(())
+}
+
+// Orders the moves of every snake of `board` by how they split the board, which plays two
+// turns ahead on it and takes them back, and checks it's left as it was
+pub fn check_partition_forecast(board: &Board, constrictor: bool) -> Result<(), String> {
+    let before = snapshot(board);
+    let mut board = board.clone();
+    for index in 0..board.snakes.len() {
+        if board.snakes[index].body.is_empty() {
+            continue;
+        }
+        with_workspace(|workspace| {
+            workspace.start_search(&board, 2, None, false);
+            workspace.rules = rules(constrictor);
+            order_moves_by_partition(&mut board, index, workspace)
+        });
+        if snapshot(&board) != before {
+            return Err(format!(
+                "ordering the moves of snake {} left {} instead of {}",
+                index,
+                snapshot(&board),
+                before
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Searches `board` for the snake at `you` `depth` plies deep, once as it is and once with an
+// eliminated snake after every snake on it, and checks both searches score it the same and
+// pick the same move: the eliminated snakes don't get plies of their own
+pub fn check_eliminated_skipped(board: &Board, you: usize, depth: i32) -> Result<(), String> {
+    let search = |board: &mut Board, you: usize| {
+        with_workspace(|workspace| {
+            workspace.start_search(board, depth, None, false);
+            minimax(board, depth, i32::MIN, i32::MAX, you, you, workspace)
+        })
+    };
+    let mut padded = board.clone();
+    let snakes = std::mem::take(&mut padded.snakes);
+    for snake in snakes {
+        let mut eliminated = snake.clone();
+        eliminated.id = format!("{}-eliminated", snake.id);
+        eliminated.health = 0;
+        eliminated.body = Default::default();
+        padded.snakes.push(snake);
+        padded.snakes.push(eliminated);
+    }
+    let plain = search(&mut board.clone(), you);
+    let skipped = search(&mut padded, 2 * you);
+    if plain != skipped {
+        return Err(format!(
+            "{} plies deep the search found {:?}, and {:?} with eliminated snakes on the board",
+            depth, plain, skipped
+        ));
+    }
+    Ok(())
+}
+
+// The board after `moves`, played as one turn the way the search plays them. Eliminated
+// snakes stay on it with an empty body, and eaten food stays on the board.
+pub fn play_turn(board: &Board, moves: &[(usize, Direction)], constrictor: bool) -> Board {
+    let mut played = board.clone();
+    // Out of health is out of the game under the official rules
+    simulate_turn(&mut played, moves, true, &rules(constrictor));
+    played
+}
+
+// Plays a move for every snake of `board`, in its order, once like the search does and once
+// with the official rules, and compares the snakes left
+pub fn check_rules(board: &Board, moves: &[Direction], constrictor: bool) -> Result<(), String> {
+    let indexed: Vec<(usize, Direction)> = moves.iter().copied().enumerate().collect();
+    let searched = play_turn(board, &indexed, constrictor);
+
+    let mut official = board.clone();
+    let config = RulesConfig {
+        food_spawn_chance: 0,
+        minimum_food: 0,
+        ..rules(constrictor)
+    };
+    advance_turn(&mut official, moves, &config, &mut StdRng::seed_from_u64(0));
+
+    for snake in &searched.snakes {
+        let expected = official.snakes.iter().find(|s| s.id == snake.id);
+        match expected {
+            None if !snake.body.is_empty() => {
+                return Err(format!(
+                    "{} is eliminated by the rules but not by the search",
+                    snake.id
+                ));
+            }
+            None => {}
+            Some(_) if snake.body.is_empty() => {
+                return Err(format!(
+                    "{} is eliminated by the search but not by the rules",
+                    snake.id
+                ));
+            }
+            Some(expected) => {
+                if snake.body != expected.body {
+                    return Err(format!(
+                        "{} ends on {:?}, the rules put it on {:?}",
+                        snake.id, snake.body, expected.body
+                    ));
+                }
+                if snake.length != expected.length || snake.health != expected.health {
+                    return Err(format!(
+                        "{} has length {} and {} health, the rules give it {} and {}",
+                        snake.id, snake.length, snake.health, expected.length, expected.health
+                    ));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Every combination of moves of the snakes of `board` for `turns` turns, played with the
+// official rules like the oracle plays them, and every turn also checked with `check_rules`.
+// Returns the number of turns checked.
+fn check_oracle_tree(board: &Board, turns: usize, rng: &mut StdRng) -> Result<usize, String> {
+    let rules = oracle::rules();
+    let mut checked = 0;
+    for ours in Direction::ALL {
+        for moves in oracle::joint_moves(board.snakes.len(), 0, ours) {
+            check_rules(board, &moves, false).map_err(|e| {
+                let moves: Vec<&str> = moves.iter().map(|d| d.as_str()).collect();
+                format!("{} after {}", e, moves.join(" "))
+            })?;
+            checked += 1;
+            if turns > 1 {
+                let mut after = board.clone();
+                advance_turn(&mut after, &moves, &rules, rng);
+                checked += check_oracle_tree(&after, turns - 1, rng)?;
+            }
+        }
+    }
+    Ok(checked)
+}
+
+// The oracle's corpus of `positions` positions from baseline games with `seed`, each played
+// out for the oracle's two turns with every combination of moves, and every turn played by
+// `simulate_turn` of the search too. Fails at the first turn where the search and the rules
+// disagree, with the position. Returns the number of turns checked.
+pub fn check_oracle_corpus(positions: usize, seed: u64) -> Result<usize, String> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut checked = 0;
+    for state in oracle::corpus(positions, &mut rng) {
+        checked += check_oracle_tree(&state.board, oracle::TURNS, &mut rng).map_err(|e| {
+            format!(
+                "{} turn {}: {}\n{}",
+                state.game.id,
+                state.turn,
+                e,
+                serde_json::to_string(&state.board).unwrap()
+            )
+        })?;
+    }
+    Ok(checked)
+}
+
+// Checks the flood fills on bit sets (see bitboard.rs) against the breadth-first queue, from
+// every cell of `board`. Boards too large for bit sets pass.
+pub fn check_flood_fill(board: &Board) -> Result<(), String> {
+    if !bitboard::fits(board) {
+        return Ok(());
+    }
+    let cells = (board.width * board.height as i32) as usize;
+    for y in 0..board.height as i32 {
+        for x in 0..board.width {
+            let start = Coord { x, y };
+            for limit in [1, 4, cells] {
+                let queue =
+                    with_workspace(|workspace| flood_fill_queue(board, &start, limit, workspace));
+                let bits = bitboard::flood_fill_area(board, &start, limit);
+                if bits != Some(queue) {
+                    return Err(format!(
+                        "flood fill from {:?} up to {} cells: {:?} on bit sets, {} cell by cell",
+                        start, limit, bits, queue
+                    ));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// The flood fills from the heads and from every `stride`th cell, and the Voronoi split for
+// every snake, on bit sets against the queues. Boards too large for bit sets only check that
+// the dispatch falls back to the queues.
+fn check_fills_and_split(board: &Board, stride: usize) -> Result<(), String> {
+    let cells = (board.width * board.height as i32) as usize;
+    let heads = board.snakes.iter().filter_map(|snake| snake.body.first());
+    let sampled = (0..cells).step_by(stride).map(|i| Coord {
+        x: i as i32 % board.width,
+        y: i as i32 / board.width,
+    });
+    for start in heads.copied().chain(sampled) {
+        for limit in [4, cells] {
+            let queue =
+                with_workspace(|workspace| flood_fill_queue(board, &start, limit, workspace));
+            match bitboard::flood_fill_area(board, &start, limit) {
+                Some(bits) if bits != queue => {
+                    return Err(format!(
+                        "flood fill from {:?} up to {} cells: {} on bit sets, {} cell by cell",
+                        start, limit, bits, queue
+                    ));
+                }
+                None if cells <= 1024 => {
+                    return Err(format!("no bit sets for a board of {} cells", cells));
+                }
+                _ => {}
+            }
+        }
+    }
+    for you in 0..board.snakes.len() {
+        let (split, queue) = with_workspace(|workspace| {
+            (
+                voronoi_territory(board, you, workspace),
+                voronoi_queue(board, you, workspace),
+            )
+        });
+        if split != queue {
+            return Err(format!(
+                "Voronoi split for {}: {:?} on bit sets, {:?} cell by cell",
+                board.snakes[you].id, split, queue
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Plays a seeded game on a `width` x `height` board, the engine searching `depth` plies
+// against three flood-fill bots, for up to `turns` turns. Every position on the way is
+// checked with `check_fills_and_split`, and every move of the engine has to be legal when it
+// has a legal move.
+pub fn check_board_size(
+    width: i32,
+    height: i32,
+    wrapped: bool,
+    depth: i32,
+    turns: i32,
+    seed: u64,
+) -> Result<(), String> {
+    let config = ArenaConfig {
+        width,
+        height,
+        max_turns: turns,
+        rules: RulesConfig {
+            wrapped,
+            ..RulesConfig::default()
+        },
+    };
+    let mut rng = StdRng::seed_from_u64(seed);
+    let name = format!("{}x{}", width, height);
+    let mut game = ArenaGame::new(&name, 4, &config, &mut rng);
+    let mut players: Vec<Box<dyn Strategy>> = vec![Box::new(EngineStrategy::new(depth))];
+    for i in 1..4 {
+        players.push(baseline("flood-fill", seed + i).unwrap());
+    }
+    for (player, snake) in players.iter_mut().zip(&game.board.snakes) {
+        player.start(&game.game, &game.board, snake);
+    }
+    let stride = (width * height / 40).max(1) as usize;
+    while !game.is_over(&config) {
+        let board = &game.board;
+        check_fills_and_split(board, stride)
+            .map_err(|e| format!("{} turn {}: {}", name, game.turn, e))?;
+        let mut moves = Vec::with_capacity(board.snakes.len());
+        for (i, snake) in board.snakes.iter().enumerate() {
+            let player = game.player(i);
+            let direction = players[player].choose_move(&game.game, game.turn, board, snake);
+            let stuck = !Direction::ALL
+                .iter()
+                .any(|&d| is_move_legal(board, snake, d));
+            if player == 0 && !stuck && !is_move_legal(board, snake, direction) {
+                return Err(format!(
+                    "{} turn {}: the engine played {} into a wall or a body",
+                    name, game.turn, direction
+                ));
+            }
+            moves.push(direction);
+        }
+        game.advance(&moves, &config, &mut rng);
+    }
+    Ok(())
+}
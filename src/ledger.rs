@@ -0,0 +1,158 @@
+// Which requests of every game the server has seen, so retried and late requests do no harm.
+// The platform sometimes sends a `/move` twice or delivers `/end` while the last `/move` is
+// still being searched. A `/move` for a turn that was already answered gets the same answer
+// again, one for a turn that is still being searched waits for that search until its own
+// deadline (and gets the cheap move if the search isn't done by then or fails), and one for an
+// older turn or a game that has ended gets a quick safe move without touching the per-game
+// state. Only the first `/end` of a game counts. Games are remembered for a while after their
+// last request to recognize stragglers, also the ones whose `/end` never came.
+//
+// The search of a turn is pending as long as the handler searching it holds its
+// `PendingSearch`. A handler that goes away before it answers (its client hung up, it
+// panicked) drops it, which takes the turn off the pending ones and lets the retries
+// waiting for it know there's no answer coming.
+
+use rocket::tokio::sync::watch;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Answers kept per game for retries
+const DECISIONS_KEPT: usize = 4;
+// How long a game is remembered after its last request
+const GAME_TTL: Duration = Duration::from_secs(600);
+
+struct GameLedger {
+    last_request: Instant,
+    // Latest turn a `/move` came in for
+    latest_turn: Option<i32>,
+    // Answers of the last turns, oldest first
+    decisions: VecDeque<(i32, Value)>,
+    // Searches in progress per turn, their answer comes through here when they're done
+    pending: HashMap<i32, watch::Receiver<Option<Value>>>,
+    ended: bool,
+}
+
+impl GameLedger {
+    fn new() -> GameLedger {
+        GameLedger {
+            last_request: Instant::now(),
+            latest_turn: None,
+            decisions: VecDeque::new(),
+            pending: HashMap::new(),
+            ended: false,
+        }
+    }
+}
+
+// What to do with a `/move`
+pub enum Admission<'a> {
+    // A new turn: update the game's state and search, then answer through the guard
+    Search(PendingSearch<'a>),
+    // Answered before
+    Cached(Value),
+    // The same turn is being searched, its answer comes through here (None, or the channel
+    // closed, if it failed)
+    Pending(watch::Receiver<Option<Value>>),
+    // An older turn or a game that has ended, the answer doesn't matter any more
+    Late,
+}
+
+// Requests seen per game and snake of ours
+#[derive(Default)]
+pub struct RequestLedger {
+    games: Mutex<HashMap<(String, String), GameLedger>>,
+}
+
+impl RequestLedger {
+    // The ledger of a game, forgetting the games that went quiet
+    fn game<'a>(
+        games: &'a mut HashMap<(String, String), GameLedger>,
+        game_id: &str,
+        you_id: &str,
+    ) -> &'a mut GameLedger {
+        // A search still pending after GAME_TTL belongs to a handler that's gone for good
+        games.retain(|_, game| game.last_request.elapsed() < GAME_TTL);
+        let game = games
+            .entry((game_id.to_string(), you_id.to_string()))
+            .or_insert_with(GameLedger::new);
+        game.last_request = Instant::now();
+        game
+    }
+
+    pub fn admit_move(&self, game_id: &str, you_id: &str, turn: i32) -> Admission<'_> {
+        let mut games = self.games.lock().unwrap();
+        let game = RequestLedger::game(&mut games, game_id, you_id);
+        if let Some((_, decision)) = game.decisions.iter().find(|(t, _)| *t == turn) {
+            return Admission::Cached(decision.clone());
+        }
+        if let Some(search) = game.pending.get(&turn) {
+            return Admission::Pending(search.clone());
+        }
+        if game.ended || game.latest_turn.is_some_and(|latest| turn < latest) {
+            return Admission::Late;
+        }
+        game.latest_turn = Some(turn);
+        let (answer, search) = watch::channel(None);
+        game.pending.insert(turn, search);
+        Admission::Search(PendingSearch {
+            ledger: self,
+            game: (game_id.to_string(), you_id.to_string()),
+            turn,
+            decision: None,
+            answer,
+        })
+    }
+
+    // Takes `turn` off the pending searches, and keeps `decision` for retries
+    fn settle(&self, game: &(String, String), turn: i32, decision: Option<&Value>) {
+        let mut games = self.games.lock().unwrap();
+        let game = match games.get_mut(game) {
+            Some(game) => game,
+            None => return,
+        };
+        game.pending.remove(&turn);
+        if let Some(decision) = decision {
+            game.decisions.push_back((turn, decision.clone()));
+            if game.decisions.len() > DECISIONS_KEPT {
+                game.decisions.pop_front();
+            }
+        }
+    }
+
+    // Returns whether this is the first `/end` of the game
+    pub fn end_game(&self, game_id: &str, you_id: &str) -> bool {
+        let mut games = self.games.lock().unwrap();
+        let game = RequestLedger::game(&mut games, game_id, you_id);
+        !std::mem::replace(&mut game.ended, true)
+    }
+}
+
+// The search of a turn, held by the handler searching it. Dropping it settles the turn.
+pub struct PendingSearch<'a> {
+    ledger: &'a RequestLedger,
+    game: (String, String),
+    turn: i32,
+    decision: Option<Value>,
+    answer: watch::Sender<Option<Value>>,
+}
+
+impl PendingSearch<'_> {
+    // The search is done, retries get `decision` from now on
+    pub fn decided(mut self, decision: &Value) {
+        self.decision = Some(decision.clone());
+    }
+}
+
+// Without a decision the turn is no longer pending and the retries waiting for it see the
+// channel close
+impl Drop for PendingSearch<'_> {
+    fn drop(&mut self) {
+        self.ledger
+            .settle(&self.game, self.turn, self.decision.as_ref());
+        if let Some(decision) = self.decision.take() {
+            self.answer.send_replace(Some(decision));
+        }
+    }
+}
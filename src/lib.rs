@@ -0,0 +1,617 @@
+#[macro_use]
+extern crate rocket;
+
+use log::info;
+use rocket::fairing::AdHoc;
+use rocket::http::Status;
+use rocket::serde::{json::Json, Deserialize};
+use rocket::tokio::time::timeout_at;
+use rocket::{Build, Rocket, State};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::env;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use degrade::Subsystem;
+use duel::DuelMonitor;
+use famine::FamineMonitor;
+use features::Feature;
+use heat::HeatMonitor;
+use logic::TurnContext;
+use metrics::EvalMetrics;
+use network::NetworkMonitor;
+use ponder::Ponderer;
+use recorder::GameRecorder;
+use rules::RulesConfig;
+use session::SessionStore;
+use stall::StallMonitor;
+use telemetry::{MoveTrace, Telemetry};
+use throttle::Throttle;
+use watchdog::SearchProgress;
+
+pub use body::Body;
+pub use direction::Direction;
+pub use ledger::{Admission, PendingSearch, RequestLedger};
+pub use normalize::normalize_state;
+pub use postmortem::{classify_loss, load_results, record_result, GameResult, LossCause};
+pub use scheduler::{SearchScheduler, Urgency};
+pub use shared_store::{read_reply, GameMap, Reply};
+
+mod arena;
+mod astar;
+mod batch;
+pub mod bench;
+mod bitboard;
+mod body;
+mod compact;
+mod consistency;
+mod dashboard;
+mod dataset;
+mod dead_ends;
+mod decision_log;
+mod deepening;
+mod degrade;
+mod direction;
+mod duel;
+mod eval_graph;
+mod exploit;
+mod famine;
+mod features;
+mod fixture;
+mod food_race;
+mod food_spawns;
+mod gauntlet;
+mod heat;
+pub mod invariants;
+mod ledger;
+mod logic;
+mod mate;
+mod maxn;
+mod mcts;
+mod metrics;
+mod network;
+mod normalize;
+mod opponent_book;
+mod opponent_model;
+mod opponent_style;
+mod oracle;
+mod parallel;
+mod personality;
+mod phase;
+mod ponder;
+pub mod positions;
+mod postmortem;
+mod preset;
+mod recorder;
+mod regret;
+mod render;
+mod repl;
+mod royale;
+mod rules;
+mod scenario;
+mod scheduler;
+mod search_trace;
+mod selfplay;
+mod session;
+mod shared_store;
+mod squad;
+mod squeeze;
+mod stall;
+mod status_shout;
+mod strategy;
+mod telemetry;
+mod threat;
+mod throttle;
+mod tuner;
+mod watchdog;
+mod workspace;
+
+// API and Response Objects
+// See https://docs.battlesnake.com/api
+
+// Fields some engines leave out get defaults instead of failing the whole request, see
+// normalize.rs for the rest of the cleanup
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Game {
+    id: String,
+    // Where the game comes from: ladder, league, arena, custom, ...
+    #[serde(default)]
+    source: String,
+    #[serde(default)]
+    ruleset: Ruleset,
+    #[serde(default = "default_timeout")]
+    timeout: u32,
+}
+
+// The rules of the game as the request gives them, see `RulesConfig` for what the search and
+// the local rules make of them. Settings left out take the official defaults there.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Ruleset {
+    name: String,
+    version: String,
+    settings: RulesetSettings,
+}
+
+impl Ruleset {
+    // Games that don't say are standard games
+    pub fn name(&self) -> &str {
+        if self.name.is_empty() {
+            "standard"
+        } else {
+            &self.name
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RulesetSettings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    food_spawn_chance: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    minimum_food: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hazard_damage_per_turn: Option<i32>,
+    royale: RoyaleSettings,
+    squad: SquadSettings,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RoyaleSettings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shrink_every_n_turns: Option<i32>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SquadSettings {
+    allow_body_collisions: bool,
+    shared_elimination: bool,
+    shared_health: bool,
+    shared_length: bool,
+}
+
+fn default_timeout() -> u32 {
+    500
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Board {
+    height: u32,
+    width: i32,
+    #[serde(default)]
+    food: Vec<Coord>,
+    snakes: Vec<Battlesnake>,
+    #[serde(default)]
+    hazards: Vec<Coord>,
+    // Moving off one edge comes back in at the opposite one. Not part of the API's board,
+    // it's set from the ruleset when a request comes in and kept in recordings.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    wrapped: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Battlesnake {
+    id: String,
+    name: String,
+    health: i32,
+    body: Body,
+    #[serde(default)]
+    head: Coord,
+    #[serde(default)]
+    length: i32,
+    #[serde(default)]
+    latency: String,
+    shout: Option<String>,
+    squad: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Hash, PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct Coord {
+    x: i32,
+    y: i32,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct GameState {
+    game: Game,
+    turn: i32,
+    board: Board,
+    you: Battlesnake,
+}
+
+// How long the shared store calls of one request can take together
+fn store_budget(game: &Game) -> Duration {
+    Duration::from_millis(game.timeout as u64) / shared_store::TURN_SHARE
+}
+
+#[get("/")]
+fn handle_index() -> Json<Value> {
+    Json(logic::info())
+}
+
+// Takes the request out of its Json wrapper and fixes anything the search can't handle
+fn normalized(request: Json<GameState>) -> GameState {
+    let mut state = request.into_inner();
+    let fixes = normalize::normalize_state(&mut state);
+    state.board.wrapped = RulesConfig::from_ruleset(&state.game.ruleset).wrapped;
+    if !fixes.is_empty() {
+        info!(
+            "Normalized request for game {}: {}",
+            state.game.id,
+            fixes.join(", ")
+        );
+    }
+    state
+}
+
+#[post("/start", format = "json", data = "<start_req>")]
+fn handle_start(
+    start_req: Json<GameState>,
+    duels: &State<DuelMonitor>,
+    sessions: &State<SessionStore>,
+) -> Status {
+    let received = Instant::now();
+    let start_req = normalized(start_req);
+    let store_by = received + store_budget(&start_req.game);
+    shared_store::within(store_by, || {
+        sessions.start_game(&start_req.game.id, start_req.turn);
+        duels.start_game(&start_req.game, start_req.turn, &start_req.board);
+    });
+    logic::start(
+        &start_req.game,
+        &start_req.turn,
+        &start_req.board,
+        &start_req.you,
+    );
+
+    Status::Ok
+}
+
+#[post("/move", format = "json", data = "<move_req>")]
+// One guard per piece of server state the move touches
+#[allow(clippy::too_many_arguments)]
+async fn handle_move(
+    move_req: Json<GameState>,
+    metrics: &State<Arc<EvalMetrics>>,
+    scheduler: &State<SearchScheduler>,
+    stalls: &State<StallMonitor>,
+    recorder: &State<GameRecorder>,
+    throttle: &State<Throttle>,
+    heat: &State<HeatMonitor>,
+    trace: &MoveTrace,
+    ledger: &State<RequestLedger>,
+    duels: &State<DuelMonitor>,
+    famines: &State<FamineMonitor>,
+    network: &State<NetworkMonitor>,
+    sessions: &State<SessionStore>,
+    ponderer: &State<Arc<Ponderer>>,
+) -> Result<Json<Value>, Status> {
+    let received = Instant::now();
+    let move_req = normalized(move_req);
+    let budget = Duration::from_millis(move_req.game.timeout as u64);
+    trace.parsed(&move_req.game.id, move_req.turn, budget);
+    let (game_id, you_id, turn) = (
+        move_req.game.id.clone(),
+        move_req.you.id.clone(),
+        move_req.turn,
+    );
+    // Dropped without a decision when this handler doesn't get to answer, see ledger.rs
+    let pending = match ledger.admit_move(&game_id, &you_id, turn) {
+        Admission::Search(pending) => pending,
+        Admission::Cached(decision) => return Ok(Json(decision)),
+        Admission::Pending(mut search) => {
+            // The search may have been sent later than this copy, which has to be answered in
+            // its own time, with the cheap move if the search isn't done by then
+            let answer_by = received + budget - network::margin_floor().min(budget / 2);
+            let searched = timeout_at(answer_by.into(), async {
+                // A failed search closes the channel without an answer
+                while search.borrow().is_none() {
+                    if search.changed().await.is_err() {
+                        break;
+                    }
+                }
+                search.borrow().clone()
+            });
+            let failure = match searched.await {
+                Ok(Some(decision)) => return Ok(Json(decision)),
+                Ok(None) => "the search failed",
+                Err(_) => "the search wasn't done in time",
+            };
+            info!("RETRY in game {} at turn {}: {}", game_id, turn, failure);
+            let direction = logic::safe_move(&move_req.board, &move_req.you);
+            return Ok(Json(json!({ "move": direction })));
+        }
+        Admission::Late => {
+            let direction = logic::safe_move(&move_req.board, &move_req.you);
+            return Ok(Json(json!({ "move": direction })));
+        }
+    };
+    recorder.observe(&move_req);
+    // What the per-game state says about this turn, see shared_store.rs for the store's budget
+    let store_by = received + store_budget(&move_req.game);
+    let (stall, famine, wire, traffic, styles, preset) = shared_store::within(store_by, || {
+        let stall = stalls.observe(&move_req.game.id, &move_req.board);
+        if let Some(quiet_turns) = stall {
+            info!(
+                "STALL in game {} at turn {}: no length change for {} turns",
+                move_req.game.id, move_req.turn, quiet_turns
+            );
+        }
+        let famine = famines.observe(&move_req.game.id, &move_req.board);
+        if famine == Some(famine::FAMINE_TURNS) {
+            info!(
+                "FAMINE in game {} at turn {}: no food for {} turns",
+                move_req.game.id,
+                move_req.turn,
+                famine::FAMINE_TURNS
+            );
+        }
+        (
+            stall,
+            famine,
+            network.margin(&game_id, turn, &move_req.you.latency, budget),
+            heat.observe(&move_req.game.id, &move_req.board, &move_req.you.id),
+            sessions.observe_opponents(&game_id, &move_req.board, turn, &you_id),
+            duels.preset(&move_req.game, move_req.turn, &move_req.board),
+        )
+    });
+    let progress = Arc::new(SearchProgress::default());
+    let personality = personality::of(&move_req.board, preset);
+    let context = TurnContext {
+        deadline: received + budget - wire - throttle.reserve(budget),
+        stalled: stall.is_some(),
+        heat: traffic,
+        styles,
+        preset,
+        famine: famine.is_some(),
+        ponder: features::enabled(Feature::Pondering)
+            .then(|| ponderer.turn(&game_id, &you_id, turn, &move_req.board, personality)),
+        progress: Some(Arc::clone(&progress)),
+    };
+    let urgency = Urgency {
+        deadline: context.deadline,
+        criticality: logic::position_criticality(&move_req.board, &move_req.you),
+    };
+    let metrics = Arc::clone(metrics);
+    let depth = throttle.depth(context.preset.search.depth);
+    // For when the search fails
+    let (board, you) = (move_req.board.clone(), move_req.you.clone());
+    let submitted = Instant::now();
+    let response = scheduler.submit(personality, urgency, move || {
+        let started = Instant::now();
+        let response = logic::get_move_at_depth(
+            &move_req.game,
+            &move_req.turn,
+            &move_req.board,
+            &move_req.you,
+            depth,
+            context,
+            &metrics,
+        );
+        (response, started, Instant::now())
+    });
+
+    // The search only fails to answer when it panicked, the watchdog doesn't wait for it
+    // past the time the answer is due
+    let answer_by = received + budget - wire;
+    let (response, searched) = match timeout_at(answer_by.into(), response).await {
+        Ok(Ok((response, started, finished))) => {
+            trace.searched(submitted, started, finished, depth);
+            (response, finished - started)
+        }
+        Ok(Err(_)) => {
+            let error = format!("the search of game {} turn {} panicked", game_id, turn);
+            degrade::report(Subsystem::Search, &error);
+            (
+                json!({ "move": logic::safe_move(&board, &you) }),
+                Duration::ZERO,
+            )
+        }
+        Err(_) => {
+            let direction = match progress.best() {
+                Some((depth, direction)) => {
+                    info!(
+                        "WATCHDOG in game {} at turn {}: no answer from the search in time, playing '{}' from depth {}",
+                        game_id, turn, direction, depth
+                    );
+                    direction
+                }
+                None => {
+                    let direction = logic::safe_move(&board, &you);
+                    info!(
+                        "WATCHDOG in game {} at turn {}: no answer from the search in time, playing the safe move '{}'",
+                        game_id, turn, direction
+                    );
+                    direction
+                }
+            };
+            (json!({ "move": direction }), received.elapsed())
+        }
+    };
+    trace.handled(&response);
+    pending.decided(&response);
+    // Past the time the answer is due these stay in memory for the turn
+    shared_store::within(answer_by, || {
+        network.answered(&game_id, turn, received.elapsed());
+        sessions.moved(&game_id, turn, searched, received.elapsed());
+    });
+    if throttle.record(received.elapsed(), budget).is_some() {
+        scheduler.set_limit(throttle.threads(scheduler.threads()));
+    }
+    Ok(Json(response))
+}
+
+#[post("/end", format = "json", data = "<end_req>")]
+// Every piece of per-game state is cleaned up here
+#[allow(clippy::too_many_arguments)]
+fn handle_end(
+    end_req: Json<GameState>,
+    metrics: &State<Arc<EvalMetrics>>,
+    stalls: &State<StallMonitor>,
+    recorder: &State<GameRecorder>,
+    heat: &State<HeatMonitor>,
+    ledger: &State<RequestLedger>,
+    duels: &State<DuelMonitor>,
+    famines: &State<FamineMonitor>,
+    network: &State<NetworkMonitor>,
+    sessions: &State<SessionStore>,
+    ponderer: &State<Arc<Ponderer>>,
+) -> Status {
+    let received = Instant::now();
+    let end_req = normalized(end_req);
+    // A retried `/end` would write the recording again with only the final frame
+    if !ledger.end_game(&end_req.game.id, &end_req.you.id) {
+        return Status::Ok;
+    }
+    let store_by = received + store_budget(&end_req.game);
+    shared_store::within(store_by, || {
+        stalls.end_game(&end_req.game.id);
+        heat.end_game(&end_req.game.id, &end_req.you.id);
+        duels.end_game(&end_req.game.id);
+        famines.end_game(&end_req.game.id);
+        network.end_game(&end_req.game.id);
+        sessions.end_game(&end_req.game.id);
+    });
+    ponderer.end_game(&end_req.game.id, &end_req.you.id);
+    recorder.end_game(&end_req);
+    logic::end(
+        &end_req.game,
+        &end_req.turn,
+        &end_req.board,
+        &end_req.you,
+        metrics,
+    );
+
+    Status::Ok
+}
+
+#[get("/metrics")]
+fn handle_metrics(
+    metrics: &State<Arc<EvalMetrics>>,
+    scheduler: &State<SearchScheduler>,
+    throttle: &State<Throttle>,
+) -> Json<Value> {
+    let mut json = metrics.to_json();
+    json["throttle"] = throttle.to_json(preset::active().search.depth, scheduler.threads());
+    json["personalities"] = scheduler.to_json();
+    json["degraded"] = degrade::to_json();
+    Json(json)
+}
+
+// The server with its state, also for the tests in tests/ that play requests against it
+pub fn rocket() -> Rocket<Build> {
+    info!("Starting Battlesnake Server...");
+    info!("Using preset {}", preset::active().name);
+    if let Ok(spec) = env::var(preset::WEIGHTS_ENV) {
+        info!("Evaluation weights from {}", spec);
+    }
+    info!("Features: {}", features::active_names().join(", "));
+
+    let store = shared_store::SharedStore::from_env();
+    let scheduler = SearchScheduler::from_env();
+    rocket::build()
+        .attach(AdHoc::on_response("Server ID Middleware", |_, res| {
+            Box::pin(async move {
+                res.set_raw_header("Server", "battlesnake/github/starter-snake-rust");
+            })
+        }))
+        .attach(Telemetry::from_env())
+        .manage(Arc::new(EvalMetrics::default()))
+        .manage(Arc::new(Ponderer::new(scheduler.clone())))
+        .manage(scheduler)
+        .manage(StallMonitor::new(store.clone()))
+        .manage(HeatMonitor::new(store.clone()))
+        .manage(DuelMonitor::new(store.clone()))
+        .manage(FamineMonitor::new(store.clone()))
+        .manage(NetworkMonitor::new(store.clone()))
+        .manage(SessionStore::new(store.clone()))
+        .manage(GameRecorder::from_env(store))
+        .manage(Throttle::default())
+        .manage(RequestLedger::default())
+        .mount(
+            "/",
+            routes![
+                handle_index,
+                handle_start,
+                handle_move,
+                handle_end,
+                handle_metrics
+            ],
+        )
+}
+
+// Runs the offline tool `command` with `args`, after picking the preset and the features
+// from the environment. Exits the process on errors.
+pub fn run_command(command: &str, args: &[String]) {
+    if let Err(e) = preset::select_from_env().and(features::select_from_env()) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+    let result = match command {
+        "arena" => arena::run(args),
+        "compress" => compact::compress(args),
+        "dashboard" => dashboard::run(args),
+        "dataset" => dataset::run(args),
+        "decompress" => compact::decompress(args),
+        "exploit" => exploit::run(args),
+        "features" => features::run(args),
+        "fixture" => fixture::run(args),
+        "gauntlet" => gauntlet::run(args),
+        "graph" => eval_graph::run(args),
+        "opponents" => opponent_book::run(args),
+        "oracle" => oracle::run(args),
+        "phases" => phase::run(args),
+        "play" => arena::play(args),
+        "postmortem" => postmortem::run(args),
+        "presets" => preset::run(args),
+        "promote" => gauntlet::promote(args),
+        "repl" => repl::run(args),
+        "scenarios" => scenario::run(args),
+        "selfplay" => selfplay::run(args),
+        "simulate" => arena::simulate(args),
+        "tune" => tuner::run(args),
+        _ => Err(format!("unknown command '{}'", command)),
+    };
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+// Entry point of the server binary. Offline tools are run as subcommands of the same binary,
+// without arguments we start the server.
+pub fn main() {
+    let args: Vec<String> = env::args().collect();
+    if let Some(command) = args.get(1) {
+        run_command(command, &args[2..]);
+        return;
+    }
+    if let Err(e) = preset::select_from_env().and(features::select_from_env()) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+
+    // Lots of web hosting services expect you to bind to the port specified by the `PORT`
+    // environment variable. However, Rocket looks at the `ROCKET_PORT` environment variable.
+    // If we find a value for `PORT`, we set `ROCKET_PORT` to that value.
+    if let Ok(port) = env::var("PORT") {
+        env::set_var("ROCKET_PORT", &port);
+    }
+
+    // We default to 'info' level logging. But if the `RUST_LOG` environment variable is set,
+    // we keep that value instead.
+    if env::var("RUST_LOG").is_err() {
+        env::set_var("RUST_LOG", "info");
+    }
+
+    env_logger::init();
+
+    if let Err(e) = rocket::execute(rocket().launch()) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -13,25 +13,77 @@
 use log::info;
 use serde_json::{json, Value};
 
-use rand::seq::SliceRandom;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use smallvec::SmallVec;
+use std::cmp::Reverse;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use crate::astar;
+use crate::bitboard::{self, BitGrid, Cells, Wide};
+use crate::body::Body;
+use crate::consistency;
+use crate::dead_ends;
+use crate::decision_log;
+use crate::deepening;
+use crate::degrade::{self, Subsystem};
+use crate::direction::Direction;
+use crate::eval_graph;
+use crate::features::{self, Feature};
+use crate::food_race;
+use crate::food_spawns;
+use crate::heat::{heat_at, HEAT_VISIT};
+use crate::mate::{self, GameOutcome};
+use crate::maxn;
+use crate::mcts;
+use crate::metrics::{EvalBreakdown, EvalMetrics};
+use crate::normalize;
+use crate::opponent_model;
+use crate::opponent_style::OpponentStyle;
+use crate::parallel;
+use crate::personality;
+use crate::phase::phase_of;
+use crate::ponder::PonderTurn;
+use crate::preset::{EvalWeights, Preset};
+use crate::regret;
+use crate::royale;
+use crate::rules::RulesConfig;
+use crate::search_trace::{
+    self, MovePath, SearchTrace, TraceChild, TraceNode, TraceRecord, TracedDecision,
+};
+use crate::squad;
+use crate::squeeze;
+use crate::status_shout;
+use crate::threat;
+use crate::watchdog::SearchProgress;
+use crate::workspace::{with_workspace, RootCandidate, SearchHandoff, SearchWorkspace};
 use crate::{Battlesnake, Board, Coord, Game};
 const PRINT: bool = false;
-static mut GAME_STARTED: bool = false;
+// There are never more than 4 moves to consider, so move lists live on the stack
+type MoveList = SmallVec<[Direction; 4]>;
+// Set by the first /start the server sees. Searches for several games run on different
+// threads at the same time, so this is the one piece of process-wide state and it's atomic.
+static GAME_STARTED: AtomicBool = AtomicBool::new(false);
 // info is called when you create your Battlesnake on play.battlesnake.com
 // and controls your Battlesnake's appearance
 // TIP: If you open your Battlesnake URL in a browser you should see this data
 pub fn info() -> Value {
     info!("INFO");
 
-    return json!({
+    json!({
         "apiversion": "1",
         "author": "Ilias_Saad", // TODO: Your Battlesnake Username
         "color": "#006233", // TODO: Choose color
 
         "head": "do-sammy", // TODO: Choose head
         "tail": "mystic-moon", // TODO: Choose tail
-    });
+    })
 }
 
 // start is called when your Battlesnake begins a game
@@ -42,37 +94,88 @@ pub fn start(_game: &Game, _turn: &i32, _board: &Board, _you: &Battlesnake) {
             _you.id
         );
     }
-    unsafe { GAME_STARTED = true };
+    GAME_STARTED.store(true, Ordering::Relaxed);
     info!("GAME START");
 }
 
 // end is called when your Battlesnake finishes a game
-pub fn end(_game: &Game, _turn: &i32, _board: &Board, _you: &Battlesnake) {
+pub fn end(game: &Game, _turn: &i32, _board: &Board, _you: &Battlesnake, metrics: &EvalMetrics) {
+    metrics.end_game(&game.id);
     info!("GAME OVER");
 }
 
-fn is_move_safe(board: &Board, you: &Battlesnake, direction: &str) -> bool {
-    let head = you.body.first().unwrap();
-    let mut new_x = head.x;
-    let mut new_y = head.y;
+// Length of `snake` when a turn where it moves to `destination` is resolved. Food is eaten
+// before collisions are checked, so a snake eating this turn is already one longer when a
+// head-to-head is decided. Two snakes meeting head-on share the destination, so both get
+// the food there and only their current lengths decide.
+pub(crate) fn length_at_resolution(
+    board: &Board,
+    snake: &Battlesnake,
+    destination: &Coord,
+) -> usize {
+    snake.body.len() + board.food.contains(destination) as usize
+}
+
+// A safe move without searching, for requests whose answer no longer matters or can't wait
+// for the search: the one that leaves the most room, the first of them on a tie
+pub fn safe_move(board: &Board, you: &Battlesnake) -> Direction {
+    let cells = (board.width * board.height as i32) as usize;
+    with_workspace(|workspace| {
+        Direction::ALL
+            .iter()
+            .rev()
+            .copied()
+            .filter(|&direction| is_move_safe(board, you, direction))
+            .max_by_key(|&direction| {
+                let next = step(board, &you.body[0], direction);
+                flood_fill_area(board, &next, cells, workspace)
+            })
+    })
+    .unwrap_or(Direction::Up)
+}
+
+// Whether the tail of `snake` leaves its cell when the snake moves. A snake that just ate
+// has its tail doubled up, and that one stays put for a turn.
+pub(crate) fn tail_moves_away(snake: &Battlesnake) -> bool {
+    let length = snake.body.len();
+    length > 1 && snake.body[length - 1] != snake.body[length - 2]
+}
 
-    // Determine the new head position based on the given direction
-    match direction {
-        "up" => new_y += 1,
-        "down" => new_y -= 1,
-        "left" => new_x -= 1,
-        "right" => new_x += 1,
-        _ => return false, // Return false on invalid direction
+// The cells of a snake's body that are still taken once it has moved
+pub(crate) fn staying_body(snake: &Battlesnake) -> &[Coord] {
+    if tail_moves_away(snake) {
+        &snake.body[..snake.body.len() - 1]
+    } else {
+        &snake.body
     }
+}
+
+// Whether a move stays on the board, out of every body and out of head-to-heads we'd lose,
+// at the start of a turn: every tail that moves away is free to step on
+pub(crate) fn is_move_safe(board: &Board, you: &Battlesnake, direction: Direction) -> bool {
+    is_move_safe_mid_turn(board, you, direction, |_| true)
+}
+
+// `is_move_safe` in a turn played one snake after the other. Only the snakes
+// `still_to_move` takes by their index on the board free their tail before we get there,
+// the ones that moved already have theirs where it is now.
+pub(crate) fn is_move_safe_mid_turn(
+    board: &Board,
+    you: &Battlesnake,
+    direction: Direction,
+    still_to_move: impl Fn(usize) -> bool,
+) -> bool {
+    let head = you.body.first().unwrap();
+    let next = step(board, head, direction);
+    let (new_x, new_y) = (next.x, next.y);
 
     // Check for out-of-bounds movement
-    if new_x < 0 || new_x >= board.width as i32 || new_y < 0 || new_y >= board.height as i32 {
+    if new_x < 0 || new_x >= board.width || new_y < 0 || new_y >= board.height as i32 {
         return false;
     }
 
-    // Check for collisions with itself
-    if you
-        .body
+    // Check for collisions with itself, our tail moves on with us
+    if staying_body(you)
         .iter()
         .any(|segment| segment.x == new_x && segment.y == new_y)
     {
@@ -81,36 +184,39 @@ fn is_move_safe(board: &Board, you: &Battlesnake, direction: &str) -> bool {
 
     // Avoid head-to-head collisions unless we are longer
     let my_head = (you.body[0].x, you.body[0].y);
-    let binding = [
-        (new_x + 1, new_y),
-        (new_x - 1, new_y),
-        (new_x, new_y + 1),
-        (new_x, new_y - 1),
-    ];
-    let surrounding_positions: Vec<_> = IntoIterator::into_iter(binding)
-        .filter(|&pos| {
-            pos.0 >= 0 && pos.0 < board.width && pos.1 >= 0 && pos.1 < board.height as i32
-        })
+    let surrounding_positions: SmallVec<[(i32, i32); 4]> = Direction::ALL
+        .iter()
+        .filter_map(|&d| neighbour(board, &next, d))
+        .map(|cell| (cell.x, cell.y))
         .filter(|&pos| (pos != my_head) || (you.body.len() == 1))
         .collect();
 
     for snake in &board.snakes {
-        if snake.id != you.id {
-            if surrounding_positions.iter().any(|(x, y)| {snake.body[0].x == *x && snake.body[0].y == *y}) {
-                if snake.body.len() >= you.body.len() {
-                    return false;
-                }
-                else if snake.name != "L7anch"{
-                    return true;
-                }
+        if snake.id != you.id
+            && !snake.body.is_empty()
+            && surrounding_positions
+                .iter()
+                .any(|(x, y)| snake.body[0].x == *x && snake.body[0].y == *y)
+        {
+            let destination = Coord { x: new_x, y: new_y };
+            if length_at_resolution(board, snake, &destination)
+                >= length_at_resolution(board, you, &destination)
+            {
+                return false;
+            } else if snake.name != "L7anch" {
+                return true;
             }
         }
     }
 
     // Check for collisions with other snakes
-    for snake in &board.snakes {
-        if snake
-            .body
+    for (index, snake) in board.snakes.iter().enumerate() {
+        let body = if snake.id == you.id || still_to_move(index) {
+            staying_body(snake)
+        } else {
+            &snake.body
+        };
+        if body
             .iter()
             .any(|segment| segment.x == new_x && segment.y == new_y)
         {
@@ -120,149 +226,1163 @@ fn is_move_safe(board: &Board, you: &Battlesnake, direction: &str) -> bool {
     true
 }
 
-fn simulate_move(board: &mut Board, snake_id: usize, move_dir: &str) -> Option<Coord> {
-    let (dx, dy) = match move_dir {
-        "up" => (0, 1),
-        "down" => (0, -1),
-        "left" => (-1, 0),
-        "right" => (1, 0),
-        _ => (0, 0),
-    };
+// What `undo_move` needs to take a move back, so the search can play moves on its one board
+// instead of cloning the snakes at every node
+pub(crate) struct MoveUndo {
+    snake: usize,
+    tail: Coord,
+    health: i32,
+    length: i32,
+    ate: bool,
+    // The food eaten and where it was in `board.food`, None once it's back on the board
+    food: Option<(usize, Coord)>,
+}
 
-    let mut new_head = board.snakes[snake_id].body[0].clone();
-    new_head.x += dx;
-    new_head.y += dy;
+impl MoveUndo {
+    // Puts the eaten food back where it was, the search plays on as if the food stayed
+    pub(crate) fn put_food_back(&mut self, board: &mut Board) {
+        if let Some((index, food)) = self.food.take() {
+            board.food.insert(index, food);
+        }
+    }
+}
 
-    // Check if the new head position is on a food
-    if let Some(index) = board
+// Plays one snake's move under `rules`. A head that ends in a hazard cell without food there
+// loses the hazard damage on top of the usual 1 health, like in the official rules. In
+// constrictor games every move grows the snake as if it ate, at full health.
+pub(crate) fn simulate_move(
+    board: &mut Board,
+    snake_id: usize,
+    move_dir: Direction,
+    rules: &RulesConfig,
+) -> MoveUndo {
+    let new_head = step(board, &board.snakes[snake_id].body[0], move_dir);
+
+    let eaten = board
         .food
         .iter()
-        .position(|f| f.x == new_head.x && f.y == new_head.y)
+        .position(|f| f.x == new_head.x && f.y == new_head.y);
+    let in_hazard = eaten.is_none() && board.hazards.contains(&new_head);
+    let snake = &mut board.snakes[snake_id];
+    let health = snake.health;
+    let length = snake.length;
+    let tail = snake.body.pop().unwrap_or(new_head); // Remove the last segment of the body
+    snake.body.push_front(new_head); // Add new head to the body
+
+    // Check if the new head position is on a food
+    let food = match eaten {
+        None if rules.constrictor => {
+            snake.health = 100;
+            snake.length += 1;
+            let tail = *snake.body.last().unwrap_or(&new_head);
+            snake.body.push(tail);
+            None
+        }
+        Some(index) => {
+            // Like the official rules: the snake moves, then grows by doubling up its tail,
+            // which stays put next turn
+            snake.health = 100;
+            snake.length += 1;
+            let tail = *snake.body.last().unwrap_or(&new_head);
+            snake.body.push(tail);
+            Some((index, board.food.remove(index)))
+        }
+        None => {
+            snake.health -= 1;
+            if in_hazard {
+                snake.health -= rules.hazard_damage_per_turn;
+            }
+            None
+        }
+    };
+    MoveUndo {
+        snake: snake_id,
+        tail,
+        health,
+        length,
+        ate: food.is_some() || rules.constrictor,
+        food,
+    }
+}
+
+// Takes back a move made by `simulate_move`, the board is as it was before
+pub(crate) fn undo_move(board: &mut Board, undo: MoveUndo) {
+    let snake = &mut board.snakes[undo.snake];
+    if undo.ate {
+        snake.body.pop();
+    }
+    snake.body.pop_front();
+    snake.body.push(undo.tail);
+    snake.health = undo.health;
+    snake.length = undo.length;
+    if let Some((index, food)) = undo.food {
+        board.food.insert(index, food);
+    }
+}
+
+// Whether a move stays on the board and out of every body. Tails don't count, they move
+// away unless their snake eats, which `simulate_turn` finds out.
+pub(crate) fn is_move_legal(board: &Board, you: &Battlesnake, direction: Direction) -> bool {
+    let next = step(board, &you.body[0], direction);
+    next.x >= 0
+        && next.x < board.width
+        && next.y >= 0
+        && next.y < board.height as i32
+        && !board
+            .snakes
+            .iter()
+            .any(|s| s.body.len() > 1 && s.body[..s.body.len() - 1].contains(&next))
+}
+
+// The moves of `you` that don't lose on the spot whatever the others play: the ones that stay
+// on the board and out of the cells bodies still take after the turn, our own neck first of
+// all. Only tails that leave their cell are free, so a move this lets through can still lose.
+fn non_fatal_moves(board: &Board, you: &Battlesnake) -> MoveList {
+    Direction::ALL
+        .iter()
+        .copied()
+        .filter(|&direction| {
+            let next = step(board, &you.body[0], direction);
+            next.x >= 0
+                && next.x < board.width
+                && next.y >= 0
+                && next.y < board.height as i32
+                && !board
+                    .snakes
+                    .iter()
+                    .any(|snake| staying_body(snake).contains(&next))
+        })
+        .collect()
+}
+
+// Plays the moves of a turn at the same time, like the official rules do, and takes the
+// snakes they eliminate off the board: the ones that left it, ran into a body, starved in a
+// famine or in a hazard, or met a head at least as long as them. An eliminated snake keeps
+// its place in `board.snakes` with an empty body and no health. Eaten food goes back on the
+// board like everywhere in the search. `undo_turn` takes the turn back.
+pub(crate) fn simulate_turn(
+    board: &mut Board,
+    moves: &[(usize, Direction)],
+    famine: bool,
+    rules: &RulesConfig,
+) -> TurnUndo {
+    let mut undo = TurnUndo {
+        moves: SmallVec::new(),
+        eliminated: SmallVec::new(),
+    };
+    for &(index, direction) in moves {
+        let mut made = simulate_move(board, index, direction, rules);
+        made.put_food_back(board);
+        undo.moves.push(made);
+    }
+    let eliminated: SmallVec<[usize; 4]> = moves
+        .iter()
+        .map(|&(index, _)| index)
+        .filter(|&index| is_eliminated(board, index, famine))
+        .collect();
+    for index in eliminated {
+        let snake = &mut board.snakes[index];
+        let body = mem::take(&mut snake.body);
+        let health = mem::take(&mut snake.health);
+        undo.eliminated.push((index, body, health));
+    }
+    undo
+}
+
+pub(crate) struct TurnUndo {
+    moves: SmallVec<[MoveUndo; 4]>,
+    // Bodies and health of the snakes the turn took off the board
+    eliminated: SmallVec<[(usize, Body, i32); 4]>,
+}
+
+pub(crate) fn undo_turn(board: &mut Board, undo: TurnUndo) {
+    for (index, body, health) in undo.eliminated {
+        board.snakes[index].body = body;
+        board.snakes[index].health = health;
+    }
+    for made in undo.moves.into_iter().rev() {
+        undo_move(board, made);
+    }
+}
+
+// Whether a snake out of health starves in the search: always when a hazard took the last of
+// it, otherwise only in a famine since the search doesn't look far enough to run out
+pub(crate) fn starves(board: &Board, snake: &Battlesnake, famine: bool) -> bool {
+    snake.health <= 0 && (famine || board.hazards.contains(&snake.body[0]))
+}
+
+fn is_eliminated(board: &Board, index: usize, famine: bool) -> bool {
+    let snake = &board.snakes[index];
+    let head = snake.body[0];
+    if starves(board, snake, famine)
+        || head.x < 0
+        || head.x >= board.width
+        || head.y < 0
+        || head.y >= board.height as i32
     {
-        board.snakes[snake_id].health = 100;
-        board.snakes[snake_id].length += 1;
-        board.snakes[snake_id].body.insert(0, new_head); // Add new head to the body
-        let removed_food = board.food.remove(index);
-        return Some(removed_food);
-    } else {
-        board.snakes[snake_id].health -= 1;
-        board.snakes[snake_id].body.pop(); // Remove the last segment of the body if not eating
-        board.snakes[snake_id].body.insert(0, new_head); // Add new head to the body
-        return None;
+        return true;
     }
+    board.snakes.iter().enumerate().any(|(i, other)| {
+        other.body.iter().skip(1).any(|segment| *segment == head)
+            || (i != index
+                && other.body.first() == Some(&head)
+                && other.body.len() >= snake.body.len())
+    })
+}
 
-    // here add head-to-head collision detection
+// Whether the moves picked so far in the turn being searched eliminate the snake at `index`.
+// An opponent with no move left otherwise ends the line before the turn is played.
+fn loses_turn(board: &mut Board, index: usize, workspace: &SearchWorkspace) -> bool {
+    let undo = simulate_turn(
+        board,
+        &workspace.pending[workspace.turn_start..],
+        workspace.famine,
+        &workspace.rules,
+    );
+    let lost = board.snakes[index].body.is_empty();
+    undo_turn(board, undo);
+    lost
+}
+
+// Whether the snakes on the board besides the one at `index` are all out of moves, so they
+// go out on the same turn as it does. False when it's the only one.
+fn others_stuck(board: &Board, index: usize) -> bool {
+    let mut others = board
+        .snakes
+        .iter()
+        .enumerate()
+        .filter(|&(i, snake)| i != index && !snake.body.is_empty())
+        .peekable();
+    others.peek().is_some()
+        && others.all(|(_, snake)| {
+            Direction::ALL
+                .iter()
+                .all(|&direction| !is_move_legal(board, snake, direction))
+        })
+}
+
+// The snake after `index` that is still on the board, in the order the search moves them.
+// `index` itself when it's the only one left, or when none is.
+pub(crate) fn next_to_move(board: &Board, index: usize) -> usize {
+    let count = board.snakes.len();
+    (1..=count)
+        .map(|offset| (index + offset) % count)
+        .find(|&next| !board.snakes[next].body.is_empty())
+        .unwrap_or(index)
 }
 
-fn predict_snake_move_towards_food(snake: &Battlesnake, board: &Board) -> Coord {
+pub(crate) fn predict_snake_move_towards_food(snake: &Battlesnake, board: &Board) -> Coord {
     if let Some(food) = board
         .food
         .iter()
-        .min_by_key(|f| (f.x - snake.body[0].x).abs() + (f.y - snake.body[0].y).abs())
+        .min_by_key(|f| distance(board, f, &snake.body[0]))
     {
         let head = &snake.body[0];
-        if food.x > head.x {
-            return Coord {
-                x: head.x + 1,
-                y: head.y,
+        // On wrapped boards the food on the far side is closer across the edge
+        let across = |offset: i32, size: i32| board.wrapped && 2 * offset.abs() > size;
+        let (mut dx, mut dy) = (food.x - head.x, food.y - head.y);
+        if across(dx, board.width) {
+            dx = -dx;
+        }
+        if across(dy, board.height as i32) {
+            dy = -dy;
+        }
+        let direction = if dx > 0 {
+            Some(Direction::Right)
+        } else if dx < 0 {
+            Some(Direction::Left)
+        } else if dy > 0 {
+            Some(Direction::Up)
+        } else if dy < 0 {
+            Some(Direction::Down)
+        } else {
+            None
+        };
+        if let Some(direction) = direction {
+            return step(board, head, direction);
+        }
+    }
+    snake.body[0] // Return current head position if no food or can't move closer
+}
+
+// Steps between two cells, across the edges of a wrapped board when that's shorter
+pub(crate) fn distance(board: &Board, a: &Coord, b: &Coord) -> i32 {
+    let (dx, dy) = ((a.x - b.x).abs(), (a.y - b.y).abs());
+    if board.wrapped {
+        dx.min(board.width - dx) + dy.min(board.height as i32 - dy)
+    } else {
+        dx + dy
+    }
+}
+
+// The evaluation weights were tuned on 11x11 boards. Distances and areas in the evaluation
+// are measured relative to that board, so the same weights work on 7x7 or 19x19 boards.
+const REFERENCE_SIDE: i32 = 11;
+
+// Board side length in units of the reference board side
+fn board_scale(board: &Board) -> f64 {
+    (board.width + board.height as i32) as f64 / (2 * REFERENCE_SIDE) as f64
+}
+
+// `weight` divided by a distance (plus one) as it would be on the reference board
+fn inverse_distance_term(board: &Board, weight: i32, distance: i32) -> i32 {
+    (weight as f64 / (distance as f64 / board_scale(board) + 1.0)) as i32
+}
+
+// A number of cells as the same fraction of the reference board
+fn scaled_area(board: &Board, cells: i32) -> i32 {
+    let board_cells = (board.width * board.height as i32).max(1);
+    cells * REFERENCE_SIDE * REFERENCE_SIDE / board_cells
+}
+
+// Food within this distance of another piece of food counts as part of the same cluster, on
+// the reference board
+const FOOD_CLUSTER_RADIUS: i32 = 3;
+
+fn food_cluster_radius(board: &Board) -> i32 {
+    ((FOOD_CLUSTER_RADIUS as f64 * board_scale(board)).round() as i32).max(1)
+}
+// Above this health we don't need to eat right away and care more about food distribution
+const FOOD_DENSITY_MIN_HEALTH: i32 = 60;
+
+// Percent of the food distance weight a snake with `health` scores for food `distance` moves
+// away: `sated_food` when it would get there with all its health to spare, up to
+// `starving_food` when it would get there with none. The health missing on arrival counts
+// squared, so a healthy snake barely cares about food and a starving one little else.
+fn food_urgency(weights: &EvalWeights, health: i32, distance: i32) -> i32 {
+    let hunger = 100 - (health - distance).clamp(0, 100);
+    weights.sated_food + (weights.starving_food - weights.sated_food) * hunger * hunger / 10_000
+}
+
+// Estimates how much food we can expect around a cell, both from the food already on the
+// board (clustered food is worth more since eating one piece leaves the others close by)
+// and from future spawns, which land on free cells so open areas will get more of them.
+fn food_density_at(board: &Board, cell: &Coord) -> i32 {
+    let radius = food_cluster_radius(board);
+    let mut density = 0;
+    for food in &board.food {
+        let distance = distance(board, food, cell);
+        if distance > 2 * radius {
+            continue;
+        }
+        let cluster_size = board
+            .food
+            .iter()
+            .filter(|other| self::distance(board, food, other) <= radius)
+            .count() as i32;
+        // Closeness in reference board units
+        density += (2 * radius + 1 - distance) * cluster_size * FOOD_CLUSTER_RADIUS / radius;
+    }
+
+    // Count free cells around the cell versus on the whole board to get our share of spawns
+    let occupied: usize = board.snakes.iter().map(|s| s.body.len()).sum();
+    let total_free = (board.width * board.height as i32) - occupied as i32;
+    if total_free > 0 {
+        let mut free_nearby = 0;
+        for dx in -radius..=radius {
+            let span = radius - dx.abs();
+            for dy in -span..=span {
+                let c = wrap(
+                    board,
+                    Coord {
+                        x: cell.x + dx,
+                        y: cell.y + dy,
+                    },
+                );
+                if c.x < 0 || c.x >= board.width || c.y < 0 || c.y >= board.height as i32 {
+                    continue;
+                }
+                if board.snakes.iter().any(|s| s.body.contains(&c)) {
+                    continue;
+                }
+                free_nearby += 1;
+            }
+        }
+        density += 100 * free_nearby / total_free;
+    }
+
+    density
+}
+
+// Returns the least amount of health it costs to walk from `from` to `to`, going around
+// snake bodies, or None if `to` can't be reached. Every step costs 1 health and steps that
+// end in a hazard cost the game's hazard damage more, so the path found may go around a
+// hazard region instead of straight through it when that is cheaper.
+fn hazard_crossing_cost(
+    board: &Board,
+    from: &Coord,
+    to: &Coord,
+    workspace: &mut SearchWorkspace,
+) -> Option<i32> {
+    let width = board.width;
+    let height = board.height as i32;
+    let index = |c: &Coord| (c.y * width + c.x) as usize;
+
+    let hazard_damage = workspace.rules.hazard_damage_per_turn;
+    workspace.reset((width * height) as usize);
+    let best = &mut workspace.costs;
+    let queue = &mut workspace.heap;
+    best[index(from)] = 0;
+    queue.push(Reverse((0, from.x, from.y)));
+
+    while let Some(Reverse((cost, x, y))) = queue.pop() {
+        let current = Coord { x, y };
+        if current == *to {
+            return Some(cost);
+        }
+        if cost > best[index(&current)] {
+            continue;
+        }
+        for direction in Direction::ALL {
+            let Some(next) = neighbour(board, &current, direction) else {
+                continue;
             };
-        } else if food.x < head.x {
-            return Coord {
-                x: head.x - 1,
-                y: head.y,
+            if next != *to && board.snakes.iter().any(|s| s.body.contains(&next)) {
+                continue;
+            }
+            let mut next_cost = cost + 1;
+            if board.hazards.contains(&next) {
+                next_cost += hazard_damage;
+            }
+            if next_cost < best[index(&next)] {
+                best[index(&next)] = next_cost;
+                queue.push(Reverse((next_cost, next.x, next.y)));
+            }
+        }
+    }
+    None
+}
+
+// Whether the snake can reach `target` alive with its current health, taking hazard damage
+// along the cheapest route into account
+fn can_survive_crossing(
+    board: &Board,
+    snake: &Battlesnake,
+    target: &Coord,
+    workspace: &mut SearchWorkspace,
+) -> bool {
+    match hazard_crossing_cost(board, &snake.body[0], target, workspace) {
+        Some(cost) => cost < snake.health,
+        None => false,
+    }
+}
+
+// Counts the free cells reachable from `start` (not counting `start` itself), stopping
+// once `limit` cells have been found. Tails that move away next turn count as free. Boards
+// of up to 1024 cells are filled on bits (see bitboard.rs), the others cell by cell.
+pub(crate) fn flood_fill_area(
+    board: &Board,
+    start: &Coord,
+    limit: usize,
+    workspace: &mut SearchWorkspace,
+) -> usize {
+    match bitboard::flood_fill_area(board, start, limit) {
+        Some(area) => area,
+        None => flood_fill_queue(board, start, limit, workspace),
+    }
+}
+
+// `flood_fill_area` with a breadth-first queue, on any board
+pub(crate) fn flood_fill_queue(
+    board: &Board,
+    start: &Coord,
+    limit: usize,
+    workspace: &mut SearchWorkspace,
+) -> usize {
+    let width = board.width;
+    let height = board.height as i32;
+    let index = |c: &Coord| (c.y * width + c.x) as usize;
+
+    workspace.reset((width * height) as usize);
+    let visited = &mut workspace.visited;
+    let queue = &mut workspace.queue;
+    for snake in &board.snakes {
+        for segment in staying_body(snake) {
+            if segment.x >= 0 && segment.x < width && segment.y >= 0 && segment.y < height {
+                visited[index(segment)] = true;
+            }
+        }
+    }
+
+    let mut area = 0;
+    queue.push_back(*start);
+    while let Some(current) = queue.pop_front() {
+        for direction in Direction::ALL {
+            let Some(next) = neighbour(board, &current, direction) else {
+                continue;
             };
-        } else if food.y > head.y {
-            return Coord {
-                x: head.x,
-                y: head.y + 1,
+            if visited[index(&next)] {
+                continue;
+            }
+            visited[index(&next)] = true;
+            area += 1;
+            if area >= limit {
+                return area;
+            }
+            queue.push_back(next);
+        }
+    }
+    area
+}
+
+// Counts the free cells our snake can reach from `start` like `flood_fill_area`, except that
+// a body cell counts once it's empty by the time we get there: the segment `n` cells from its
+// snake's tail is gone after `n + 1` turns, unless the snake eats on the way. In constrictor
+// games bodies never move away.
+pub(crate) fn reachable_area(
+    board: &Board,
+    start: &Coord,
+    limit: usize,
+    workspace: &mut SearchWorkspace,
+) -> usize {
+    let width = board.width;
+    let height = board.height as i32;
+    let index = |c: &Coord| (c.y * width + c.x) as usize;
+    let cells = (width * height) as usize;
+
+    workspace.reset(cells);
+    workspace.vacated.clear();
+    workspace.vacated.resize(cells, 0);
+    let constrictor = workspace.rules.constrictor;
+    let SearchWorkspace {
+        visited,
+        costs,
+        queue,
+        vacated,
+        ..
+    } = workspace;
+    for snake in &board.snakes {
+        for (from_tail, segment) in snake.body.iter().rev().enumerate() {
+            if segment.x >= 0 && segment.x < width && segment.y >= 0 && segment.y < height {
+                let turns = &mut vacated[index(segment)];
+                *turns = if constrictor {
+                    i32::MAX
+                } else {
+                    (*turns).max(from_tail as i32 + 1)
+                };
+            }
+        }
+    }
+
+    let mut area = 0;
+    costs[index(start)] = 0;
+    visited[index(start)] = true;
+    queue.push_back(*start);
+    while let Some(current) = queue.pop_front() {
+        let distance = costs[index(&current)] + 1;
+        for direction in Direction::ALL {
+            let Some(next) = neighbour(board, &current, direction) else {
+                continue;
             };
-        } else if food.y < head.y {
-            return Coord {
-                x: head.x,
-                y: head.y - 1,
+            // A body still there may be gone when we come back to it the long way round
+            let i = index(&next);
+            if visited[i] || vacated[i] > distance {
+                continue;
+            }
+            visited[i] = true;
+            costs[i] = distance;
+            area += 1;
+            if area >= limit {
+                return area;
+            }
+            queue.push_back(next);
+        }
+    }
+    area
+}
+
+// Below this health food is never considered optional
+const LENGTH_MANAGEMENT_MIN_HEALTH: i32 = 50;
+
+// Marks cells that two or more snakes reach at the same time
+const CONTESTED: usize = usize::MAX;
+
+// Splits the board into the cells every snake reaches strictly before all the others.
+// Returns the number of cells we own and the most cells owned by any opponent. Boards of up
+// to 1024 cells are split on bits (see bitboard.rs), the others cell by cell.
+pub(crate) fn voronoi_territory(
+    board: &Board,
+    you_id: usize,
+    workspace: &mut SearchWorkspace,
+) -> (i32, i32) {
+    voronoi_bits::<u128>(board, you_id)
+        .or_else(|| voronoi_bits::<Wide<8>>(board, you_id))
+        .or_else(|| voronoi_bits::<Wide<16>>(board, you_id))
+        .unwrap_or_else(|| voronoi_queue(board, you_id, workspace))
+}
+
+// `voronoi_territory` by a breadth-first search from all heads at once, on any board
+pub(crate) fn voronoi_queue(
+    board: &Board,
+    you_id: usize,
+    workspace: &mut SearchWorkspace,
+) -> (i32, i32) {
+    let width = board.width;
+    let height = board.height as i32;
+    let in_bounds = |c: &Coord| c.x >= 0 && c.x < width && c.y >= 0 && c.y < height;
+    let index = |c: &Coord| (c.y * width + c.x) as usize;
+    let cells = (width * height) as usize;
+
+    workspace.reset(cells);
+    workspace.owners.clear();
+    workspace.owners.resize(cells, CONTESTED);
+    let SearchWorkspace {
+        visited,
+        costs,
+        queue,
+        owners,
+        ..
+    } = workspace;
+    for snake in &board.snakes {
+        for segment in snake.body.iter().filter(|c| in_bounds(c)) {
+            visited[index(segment)] = true;
+        }
+    }
+    for (i, snake) in board.snakes.iter().enumerate() {
+        if let Some(head) = snake.body.first().filter(|c| in_bounds(c)) {
+            costs[index(head)] = 0;
+            owners[index(head)] = i;
+            queue.push_back(*head);
+        }
+    }
+
+    let mut owned: SmallVec<[i32; 8]> = SmallVec::from_elem(0, board.snakes.len());
+    while let Some(current) = queue.pop_front() {
+        let owner = owners[index(&current)];
+        if owner == CONTESTED {
+            continue;
+        }
+        let distance = costs[index(&current)] + 1;
+        for direction in Direction::ALL {
+            let next = match neighbour(board, &current, direction) {
+                Some(next) if !visited[index(&next)] => next,
+                _ => continue,
             };
+            let i = index(&next);
+            if costs[i] == i32::MAX {
+                costs[i] = distance;
+                owners[i] = owner;
+                owned[owner] += 1;
+                queue.push_back(next);
+            } else if costs[i] == distance && owners[i] != owner && owners[i] != CONTESTED {
+                owned[owners[i]] -= 1;
+                owners[i] = CONTESTED;
+            }
+        }
+    }
+
+    let best_opponent = owned
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| i != you_id)
+        .map(|(_, &cells)| cells)
+        .max()
+        .unwrap_or(0);
+    (owned[you_id], best_opponent)
+}
+
+// On bit sets (see bitboard.rs) the split of `voronoi_territory` goes a whole breadth-first
+// layer at a time: every snake's frontier grows by one step with a few shifts, and cells two
+// frontiers reach in the same step are contested and stop there. None when the board is too
+// large for an S.
+pub(crate) fn voronoi_bits<S: Cells>(board: &Board, you_id: usize) -> Option<(i32, i32)> {
+    let grid = BitGrid::<S>::new(board)?;
+
+    let mut claimed = S::EMPTY;
+    let mut frontiers: SmallVec<[S; 8]> = SmallVec::new();
+    for snake in &board.snakes {
+        for segment in &snake.body {
+            claimed |= grid.cell_bit(segment);
+        }
+        frontiers.push(
+            snake
+                .body
+                .first()
+                .map_or(S::EMPTY, |head| grid.cell_bit(head)),
+        );
+    }
+
+    let mut owned: SmallVec<[i32; 8]> = SmallVec::from_elem(0, board.snakes.len());
+    loop {
+        let (mut once, mut twice) = (S::EMPTY, S::EMPTY);
+        for frontier in frontiers.iter_mut() {
+            let reached = grid.grow(*frontier) & !claimed;
+            twice |= once & reached;
+            once |= reached;
+            *frontier = reached;
+        }
+        if once == S::EMPTY {
+            break;
+        }
+        for (frontier, owned) in frontiers.iter_mut().zip(owned.iter_mut()) {
+            *frontier &= !twice;
+            *owned += frontier.count() as i32;
+        }
+        claimed |= once;
+    }
+
+    let best_opponent = owned
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| i != you_id)
+        .map(|(_, &cells)| cells)
+        .max()
+        .unwrap_or(0);
+    Some((owned[you_id], best_opponent))
+}
+
+// The cheap evaluation only looks at food, health, length and the opponents' heads. The rich
+// one also splits the board into territories, which costs a full board search per leaf.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EvalTier {
+    Cheap,
+    Rich,
+}
+
+pub(crate) fn eval_tier(workspace: &SearchWorkspace) -> EvalTier {
+    let settings = &workspace.preset.search;
+    if workspace.force_rich {
+        return EvalTier::Rich;
+    }
+    if workspace.root_depth > settings.rich_eval_max_root_depth {
+        return EvalTier::Cheap;
+    }
+    match workspace.deadline {
+        Some(deadline)
+            if deadline.saturating_duration_since(Instant::now())
+                < settings.rich_eval_min_time_left =>
+        {
+            EvalTier::Cheap
         }
+        _ => EvalTier::Rich,
     }
-    snake.body[0].clone() // Return current head position if no food or can't move closer
 }
 
-fn evaluate_board(board: &Board, you_id: usize) -> i32 {
+pub(crate) fn evaluate_board(
+    board: &Board,
+    you_id: usize,
+    tier: EvalTier,
+    workspace: &mut SearchWorkspace,
+) -> i32 {
+    if workspace.rules.constrictor {
+        return evaluate_constrictor(board, you_id, workspace);
+    }
     let you = &board.snakes[you_id];
     let head = &you.body[0];
-    let mut score = 0;
+    let weights = &workspace.preset.eval;
+    let mut terms = EvalBreakdown::default();
 
     // Check if the snake just ate food (health is max)
     let just_ate_food = you.health > 90;
     //let dead = you.health == 0;
 
-    // Calculate distance to the nearest food
-    let mut min_food_distance = std::i32::MAX;
-    for food in &board.food {
-        let food_distance = (food.x - head.x).abs() + (food.y - head.y).abs();
-        if food_distance < min_food_distance {
-            min_food_distance = food_distance;
+    // Distance to the nearest food. With `use_food_race` it's the moves to the nearest food we
+    // win the race to (see food_race.rs), and food a longer opponent ties us at counts against
+    // us. With `use_astar_food` it's the moves to the food we reach for the least health,
+    // around the bodies in the way (see astar.rs), otherwise the distance as the crow flies.
+    // When there are hazards on the board, food we would starve on the way to doesn't count,
+    // except in the food race.
+    let mut min_contested_food = i32::MAX;
+    let min_food_distance = if features::enabled(Feature::FoodRace) {
+        let race = food_race::food_race(board, you_id, workspace);
+        min_contested_food = race.contested;
+        race.won
+    } else if features::enabled(Feature::AstarFood) {
+        astar::nearest_food(board, you, workspace)
+            .filter(|path| board.hazards.is_empty() || path.cost < you.health)
+            .map_or(i32::MAX, |path| path.steps)
+    } else {
+        let mut min_food_distance = i32::MAX;
+        for food in &board.food {
+            if !board.hazards.is_empty() && !can_survive_crossing(board, you, food, workspace) {
+                continue;
+            }
+            min_food_distance = min_food_distance.min(distance(board, food, head));
         }
-    }
+        min_food_distance
+    };
 
     // Calculate distance to the nearest opponent
-    let mut min_enemy_distance = std::i32::MAX;
+    let mut min_enemy_distance = i32::MAX;
     for snake in &board.snakes {
-        if snake.id != you.id {
+        if snake.id != you.id && !snake.body.is_empty() {
             let predicted_position = predict_snake_move_towards_food(snake, board);
-            let distance_to_predicted =
-                (predicted_position.x - head.x).abs() + (predicted_position.y - head.y).abs();
+            let distance_to_predicted = distance(board, &predicted_position, head);
             if distance_to_predicted < min_enemy_distance {
                 min_enemy_distance = distance_to_predicted;
             }
         }
     }
 
+    // Only opponents that died along the searched line count, the ones already dead at the
+    // root would add the same bonus to every leaf
     let mut nb_of_snakes_dead = 0;
-    for s in &board.snakes {
-        if s.id != you.id && s.body.len() == 0 {
+    for (i, s) in board.snakes.iter().enumerate() {
+        if i != you_id && s.body.is_empty() && workspace.root_alive.get(i) == Some(&true) {
             nb_of_snakes_dead += 1;
         }
     }
 
+    // In tight spots extra length only takes away room to move, so when we aren't hungry and
+    // the space around us is barely bigger than our body, stop rewarding food and start
+    // penalizing length instead
+    let length = you.body.len() as i32;
+    let space_margin = flood_fill_area(board, head, 2 * you.body.len(), workspace) as i32 - length;
+    let growth_hurts = weights.length_space_penalty > 0
+        && you.health > LENGTH_MANAGEMENT_MIN_HEALTH
+        && space_margin < length;
+    if growth_hurts {
+        terms.length -= weights.length_space_penalty * length;
+    }
+
     // Factor food distance into the score
-    if just_ate_food {
-        score += 100; // High score for eating food
+    if just_ate_food && !growth_hurts {
+        terms.food += weights.eat_bonus; // High score for eating food
+    }
+    let food_term = |distance: i32| {
+        let urgency = food_urgency(weights, you.health, distance);
+        inverse_distance_term(board, weights.food_distance * urgency / 100, distance)
+    };
+    // With `use_food_spawns` the food that may have spawned along the line counts as well,
+    // see food_spawns.rs
+    if features::enabled(Feature::FoodSpawns) {
+        let turns = workspace.turns_played;
+        let chance = food_spawns::spawn_chance(&workspace.rules, board.food.len(), turns);
+        terms.food += food_spawns::expected_term(
+            board,
+            head,
+            min_food_distance,
+            chance,
+            workspace,
+            food_term,
+        );
+    } else if min_food_distance != i32::MAX {
+        terms.food += food_term(min_food_distance);
+    }
+    if min_contested_food != i32::MAX {
+        terms.food -= inverse_distance_term(board, weights.contested_food, min_contested_food);
     }
-    if min_food_distance != std::i32::MAX {
-        score += 100 / (min_food_distance + 1); // Normal weight
+    // When we're healthy, position ourselves near clustered food and open areas instead
+    // of only caring about the closest piece
+    if you.health > FOOD_DENSITY_MIN_HEALTH {
+        terms.food_density += food_density_at(board, head) * weights.food_density / 100;
     }
     // Apply a non-linear penalty for being close to an enemy
-    if min_enemy_distance != std::i32::MAX {
-        score -= 100 / (min_enemy_distance + 1);
+    if min_enemy_distance != i32::MAX {
+        terms.enemy -= inverse_distance_term(board, weights.enemy_proximity, min_enemy_distance);
     }
 
     // Include health in the scoring
-    score += you.health; // Add health as a positive factor
-    if you.health < 50 {
-        score -= (50 - you.health) * 2; // Penalize low health more heavily
+    terms.health += you.health;
+    // Every turn in a hazard costs its damage on top of the usual health
+    if board.hazards.contains(head) {
+        terms.health -= weights.hazard * workspace.rules.hazard_damage_per_turn;
+    }
+    if workspace.famine {
+        // Without food, health only counts against the opponents': whoever runs out first dies
+        let best_opponent = board
+            .snakes
+            .iter()
+            .enumerate()
+            .filter(|&(i, s)| i != you_id && !s.body.is_empty())
+            .map(|(_, s)| s.health)
+            .max()
+            .unwrap_or(0);
+        terms.health += weights.starvation * (you.health - best_opponent);
+    } else if you.health < 50 {
+        terms.health -= (50 - you.health) * weights.low_health; // Penalize low health more heavily
+    }
+
+    // Eating is what ends a stall, so in a stalled game food is worth more
+    if workspace.stalled {
+        terms.food += terms.food * weights.contempt / 100;
+    }
+
+    // Cells next to our head that opponents went through lately are contested
+    if !workspace.heat.is_empty() {
+        let traffic: i32 = Direction::ALL
+            .iter()
+            .map(|&d| heat_at(&workspace.heat, board, &step(board, head, d)))
+            .sum();
+        terms.traffic -= weights.traffic * traffic / HEAT_VISIT;
     }
 
-    
     // print nb of snakes dead
     //println!("nb of snakes dead: {}", nb_of_snakes_dead);
-    score += 1500 * nb_of_snakes_dead;
+    terms.kills += weights.kill_bonus * nb_of_snakes_dead;
+
+    // Room to move on from here, so a dead-end corridor scores as little as it leaves us
+    if features::enabled(Feature::ReachableSpace) {
+        let reachable = reachable_area(board, head, 2 * you.body.len(), workspace) as i32;
+        terms.space += weights.reachable_space * scaled_area(board, reachable);
+    }
 
+    // On boards that fit a bitboard the territory split is cheap enough for every leaf
+    let territory = tier == EvalTier::Rich || bitboard::fits(board);
+    if territory && features::enabled(Feature::Voronoi) {
+        let (ours, theirs) = voronoi_territory(board, you_id, workspace);
+        terms.space += weights.territory * scaled_area(board, ours - theirs);
+    }
+
+    // Opponents our body pins in less room than they are long, and whether one pins us
+    if features::enabled(Feature::Squeeze) {
+        let mut squeezed_us = false;
+        for (i, snake) in board.snakes.iter().enumerate() {
+            if i == you_id || snake.body.is_empty() {
+                continue;
+            }
+            if squeeze::squeezed(board, i, you_id, workspace) {
+                terms.squeeze += weights.squeeze_bonus;
+            }
+            squeezed_us = squeezed_us || squeeze::squeezed(board, you_id, i, workspace);
+        }
+        if squeezed_us {
+            terms.squeeze -= weights.squeezed_penalty;
+        }
+    }
+
+    // A dead end smaller than our body is as bad as being walled in, even with room to spare
+    // elsewhere that we can't get to once we're in it. Only looked for in tight spots: with
+    // twice our length of room, one of the ways on is long enough but for rare boards.
+    if features::enabled(Feature::DeadEnds)
+        && (0..length).contains(&space_margin)
+        && dead_ends::room(board, head, workspace) < length
+    {
+        terms.space -= weights.trapped_penalty;
+    }
+
+    if tier == EvalTier::Rich {
+        if space_margin < 0 {
+            terms.space -= weights.trapped_penalty;
+        }
+    } else if workspace.famine && space_margin < 0 {
+        // Room to outlast the opponents in is all a famine is about
+        terms.space -= weights.trapped_penalty;
+    }
+
+    workspace.eval_totals.add(&terms);
+    terms.total()
+}
+
+// In constrictor games there's no food and health never runs out, every snake grows every
+// turn until one of them runs out of room. So only room counts: the room our head can reach,
+// the territory we have over the best placed opponent, and the opponents that ran out of it.
+fn evaluate_constrictor(board: &Board, you_id: usize, workspace: &mut SearchWorkspace) -> i32 {
+    let you = &board.snakes[you_id];
+    let head = &you.body[0];
+    let weights = &workspace.preset.eval;
+    let mut terms = EvalBreakdown::default();
+
+    let dead = board
+        .snakes
+        .iter()
+        .enumerate()
+        .filter(|&(i, s)| {
+            i != you_id && s.body.is_empty() && workspace.root_alive.get(i) == Some(&true)
+        })
+        .count() as i32;
+    terms.kills += weights.kill_bonus * dead;
+
+    // Everyone grows a cell a turn, the room we need is as much as the board has
+    let limit = (board.width * board.height as i32).max(0) as usize;
+    let reachable = reachable_area(board, head, limit, workspace) as i32;
+    terms.space += weights.reachable_space * scaled_area(board, reachable);
+    if reachable < you.body.len() as i32 {
+        terms.space -= weights.trapped_penalty;
+    }
+    if features::enabled(Feature::Voronoi) {
+        let (ours, theirs) = voronoi_territory(board, you_id, workspace);
+        terms.space += weights.territory * scaled_area(board, ours - theirs);
+    }
+
+    workspace.eval_totals.add(&terms);
+    terms.total()
+}
+
+// Looks one or two turns ahead to see how the board gets split up if the snake commits to
+// `move_dir`: the returned value is the space the snake keeps minus the space of the best
+// placed opponent, with a large penalty if the snake seals itself into a region smaller
+// than its own body. Every turn the opponent closest to the snake's head replies, with the
+// reply worst for the snake, since it's the one that can cut it off soonest. The turns are
+// played on `board` and taken back, it's as it was when this returns.
+fn forecast_partition(
+    board: &mut Board,
+    snake_index: usize,
+    move_dir: Direction,
+    plies: u32,
+    workspace: &mut SearchWorkspace,
+) -> i32 {
+    let head = board.snakes[snake_index].body[0];
+    let rival = board
+        .snakes
+        .iter()
+        .enumerate()
+        .filter(|(i, s)| *i != snake_index && !s.body.is_empty())
+        .min_by_key(|(_, s)| distance(board, &s.body[0], &head))
+        .map(|(i, _)| i);
+    let mut replies: SmallVec<[Option<(usize, Direction)>; 4]> = match rival {
+        Some(rival) => Direction::ALL
+            .iter()
+            .filter(|&&m| is_move_safe(board, &board.snakes[rival], m))
+            .map(|&m| Some((rival, m)))
+            .collect(),
+        None => SmallVec::new(),
+    };
+    if replies.is_empty() {
+        replies.push(None);
+    }
+
+    let mut worst = i32::MAX;
+    for reply in replies {
+        let mut moves: SmallVec<[(usize, Direction); 2]> = SmallVec::new();
+        moves.push((snake_index, move_dir));
+        moves.extend(reply);
+        let undo = simulate_turn(board, &moves, false, &workspace.rules);
+        let mut score = None;
+        if plies > 1 && !board.snakes[snake_index].body.is_empty() {
+            for m in Direction::ALL {
+                if is_move_safe(board, &board.snakes[snake_index], m) {
+                    let followup = forecast_partition(board, snake_index, m, plies - 1, workspace);
+                    score = score.max(Some(followup));
+                }
+            }
+        }
+        let score = score.unwrap_or_else(|| partition_score(board, snake_index, workspace));
+        undo_turn(board, undo);
+        worst = worst.min(score);
+    }
+    worst
+}
+
+// The space the snake at `snake_index` has on `board` against the best placed opponent,
+// see `forecast_partition`. A snake the turn took off the board has none.
+fn partition_score(board: &Board, snake_index: usize, workspace: &mut SearchWorkspace) -> i32 {
+    let limit = (board.width * board.height as i32) as usize;
+    let snake = &board.snakes[snake_index];
+    let our_area = match snake.body.first() {
+        Some(head) => flood_fill_area(board, head, limit, workspace) as i32,
+        None => 0,
+    };
+    let best_opponent_area = board
+        .snakes
+        .iter()
+        .enumerate()
+        .filter(|(i, s)| *i != snake_index && !s.body.is_empty())
+        .map(|(_, s)| flood_fill_area(board, &s.body[0], limit, workspace) as i32)
+        .max()
+        .unwrap_or(0);
+
+    let mut score = our_area - best_opponent_area;
+    if snake.body.is_empty() || our_area < snake.body.len() as i32 {
+        score -= limit as i32;
+    }
     score
 }
 
-fn minimax(
-    mut board: &mut Board,
+// Orders the moves so that the ones leaving us with the best share of the board after a
+// partition are searched first
+pub(crate) fn order_moves_by_partition(
+    board: &mut Board,
+    snake_index: usize,
+    workspace: &mut SearchWorkspace,
+) -> [Direction; 4] {
+    let mut scored = Direction::ALL.map(|direction| (0, direction));
+    for (score, m) in scored.iter_mut() {
+        *score = if is_move_safe(board, &board.snakes[snake_index], *m) {
+            forecast_partition(board, snake_index, *m, 2, workspace)
+        } else {
+            i32::MIN
+        };
+    }
+    scored.sort_by_key(|&(score, _)| Reverse(score));
+    [scored[0].1, scored[1].1, scored[2].1, scored[3].1]
+}
+
+// Orders an opponent's moves by how recently it was seen in the cell they lead to, since
+// snakes tend to keep to the corridors they like
+fn order_moves_by_heat(board: &Board, snake_index: usize, heat: &[i32]) -> [Direction; 4] {
+    let head = &board.snakes[snake_index].body[0];
+    let mut directions = Direction::ALL;
+    directions.sort_by_key(|&d| Reverse(heat_at(heat, board, &step(board, head, d))));
+    directions
+}
+
+// The order a node tries its moves in, before the refutations
+pub(crate) fn move_order(
+    board: &mut Board,
+    depth: i32,
+    maximizing_player_index: usize,
+    current_player_index: usize,
+    workspace: &mut SearchWorkspace,
+) -> [Direction; 4] {
+    if current_player_index == maximizing_player_index
+        && depth >= workspace.preset.search.partition_ordering_min_depth
+        && features::enabled(Feature::PartitionOrdering)
+    {
+        order_moves_by_partition(board, current_player_index, workspace)
+    } else if current_player_index != maximizing_player_index && !workspace.heat.is_empty() {
+        order_moves_by_heat(board, current_player_index, &workspace.heat)
+    } else {
+        Direction::ALL
+    }
+}
+
+// `directions` with the snake's killer moves of the ply first, latest first, and the other
+// moves by their history, keeping their order between moves that rank the same
+fn order_by_history(
+    board: &Board,
+    snake_index: usize,
+    ply: i32,
+    mut directions: [Direction; 4],
+    workspace: &SearchWorkspace,
+) -> [Direction; 4] {
+    directions.sort_by_key(|&d| {
+        (
+            workspace.killer_rank(snake_index, ply, d),
+            Reverse(workspace.history(board, snake_index, d)),
+        )
+    });
+    directions
+}
+
+// `directions` with `refutation` moved to the front
+fn refutation_first(mut directions: [Direction; 4], refutation: Direction) -> [Direction; 4] {
+    if let Some(at) = directions.iter().position(|&d| d == refutation) {
+        directions[..=at].rotate_right(1);
+    }
+    directions
+}
+
+// Whether `hunter`'s body takes away at least one of the moves of the snake at `index`
+fn blocked_by(board: &Board, index: usize, hunter: &Battlesnake) -> bool {
+    let head = &board.snakes[index].body[0];
+    Direction::ALL
+        .iter()
+        .copied()
+        .any(|direction| hunter.body.contains(&step(board, head, direction)))
+}
+
+pub(crate) fn minimax(
+    board: &mut Board,
     depth: i32,
     alpha: i32,
     beta: i32,
     maximizing_player_index: usize,
-    mut current_player_index: usize,
-) -> (i32, String) {
-    while board.snakes[current_player_index].body.len() == 0 {
-        current_player_index = (current_player_index + 1) % board.snakes.len();
+    current_player_index: usize,
+    workspace: &mut SearchWorkspace,
+) -> (i32, Option<Direction>) {
+    // The score doesn't matter, the whole search gets thrown away
+    if workspace.out_of_time() {
+        return (0, None);
+    }
+    let ply = workspace.root_depth - depth;
+    if let Some(outcome) = mate::outcome(board, maximizing_player_index, &workspace.root_alive) {
+        workspace.clear_pv(depth);
+        return (mate::outcome_score(outcome, ply), None);
     }
     if depth == 0 {
-        let score = evaluate_board(board, maximizing_player_index);
+        let tier = eval_tier(workspace);
+        let score = evaluate_board(board, maximizing_player_index, tier, workspace);
         if PRINT {
             println!(
                 "depth: {}, snake id: {}, score: {}",
@@ -270,13 +1390,35 @@ fn minimax(
             );
         }
 
-        return (score, String::from("none"));
+        workspace.clear_pv(0);
+        return (score, None);
     }
 
     let mut alpha = alpha;
     let mut beta = beta;
-    let mut current_best_move = String::from("none");
-    let directions = ["up", "down", "left", "right"];
+    let mut current_best_move = None;
+    let directions = move_order(
+        board,
+        depth,
+        maximizing_player_index,
+        current_player_index,
+        workspace,
+    );
+    // An opponent node of the first turn tries the reply that refuted another of our root
+    // moves first, it's likely to refute this one as well
+    let history = features::enabled(Feature::MoveHistory);
+    let directions = if history {
+        order_by_history(board, current_player_index, ply, directions, workspace)
+    } else {
+        directions
+    };
+    let first_turn = current_player_index != maximizing_player_index
+        && (ply as usize) < workspace.root_alive.iter().filter(|&&alive| alive).count()
+        && features::enabled(Feature::Refutations);
+    let directions = match workspace.refutation(current_player_index, ply) {
+        Some(refutation) if first_turn => refutation_first(directions, refutation),
+        _ => directions,
+    };
     let mut best_score = if current_player_index == maximizing_player_index {
         i32::MIN
     } else {
@@ -284,30 +1426,209 @@ fn minimax(
     };
     let mut move_found = false; // Track if any valid move is found
 
-    for &move_dir in &directions {
-        if is_move_safe(board, &board.snakes[current_player_index], move_dir) {
+    // Legal moves searched so far
+    let mut searched = 0;
+
+    // A weaker opponent backs up a mix of its worst and its average reply (see threat.rs),
+    // which needs the exact score of every reply and so no cutoffs below it
+    let threat = if current_player_index == maximizing_player_index {
+        threat::FULL_THREAT
+    } else {
+        workspace
+            .threat
+            .get(current_player_index)
+            .copied()
+            .unwrap_or(threat::FULL_THREAT)
+    };
+    let weighted = threat < threat::FULL_THREAT;
+    let mut reply_sum: i64 = 0;
+    let mut replies: i64 = 0;
+    // With `use_opponent_model` the average reply is weighed by how likely each one is
+    let likelihoods = if weighted && features::enabled(Feature::OpponentModel) {
+        let style = workspace
+            .styles
+            .get(current_player_index)
+            .copied()
+            .unwrap_or_default();
+        opponent_model::likelihoods(board, current_player_index, style, workspace)
+    } else {
+        [1; 4]
+    };
+
+    // Node of the search trace, when this one is recorded (see search_trace.rs)
+    let traced = if workspace.trace.is_some() {
+        let rich = eval_tier(workspace) == EvalTier::Rich;
+        workspace.trace.as_mut().and_then(|trace| {
+            trace.enter(
+                current_player_index,
+                depth,
+                (alpha, beta),
+                rich,
+                &directions,
+            )
+        })
+    } else {
+        None
+    };
+
+    // With joint moves a snake's move waits in `workspace.pending` until every snake has
+    // picked one, so no snake sees where the others went before the turn is played. The
+    // turn is also played when the search runs out of depth halfway through it.
+    let joint = features::enabled(Feature::JointMoves);
+    let turn_over = joint
+        && (depth == 1 || next_to_move(board, current_player_index) == maximizing_player_index);
+    let turn_start = workspace.turn_start;
+    // Whether the move played here completes a turn of the line, see royale.rs
+    let turn_ends = if joint {
+        turn_over
+    } else {
+        next_to_move(board, current_player_index) == maximizing_player_index
+    };
+
+    // A split root only searches the move it was given, see parallel.rs
+    let root_node =
+        depth == workspace.root_depth && current_player_index == maximizing_player_index;
+    for (tried, &move_dir) in directions.iter().enumerate() {
+        if root_node && workspace.root_move.is_some_and(|only| only != move_dir) {
+            continue;
+        }
+        let legal = if joint {
+            is_move_legal(board, &board.snakes[current_player_index], move_dir)
+        } else {
+            // The snakes after us in the turn haven't moved yet
+            let count = board.snakes.len();
+            let order = |index: usize| (index + count - maximizing_player_index) % count;
+            is_move_safe_mid_turn(
+                board,
+                &board.snakes[current_player_index],
+                move_dir,
+                |index| order(index) > order(current_player_index),
+            )
+        };
+        if !legal {
+            if let (Some(node), Some(trace)) = (traced, workspace.trace.as_mut()) {
+                trace.node(node).blocked.push(move_dir);
+            }
+        } else {
             move_found = true;
             // let mut new_board = board.clone();
 
-            // Simulate move for the current player
-            let original_snake = board.snakes[current_player_index].clone();
-            let removed_food = simulate_move(&mut board, current_player_index, move_dir);
+            // The move is played on `board` itself and taken back after the child
+            let mut turn_undo = None;
+            let mut move_undo = None;
+            let mut starved_body = None;
+            // We were eliminated, nothing below matters
+            let lost = if joint {
+                workspace.pending.push((current_player_index, move_dir));
+                if turn_over {
+                    let moves = &workspace.pending[turn_start..];
+                    turn_undo = Some(simulate_turn(
+                        board,
+                        moves,
+                        workspace.famine,
+                        &workspace.rules,
+                    ));
+                    workspace.turn_start = workspace.pending.len();
+                }
+                board.snakes[maximizing_player_index].body.is_empty()
+            } else {
+                // Simulate move for the current player
+                let mut made =
+                    simulate_move(board, current_player_index, move_dir, &workspace.rules);
+                made.put_food_back(board);
+                move_undo = Some(made);
+                let snake = &board.snakes[current_player_index];
+                let starved = starves(board, snake, workspace.famine);
+                if starved {
+                    starved_body = Some(mem::take(&mut board.snakes[current_player_index].body));
+                }
+                starved && current_player_index == maximizing_player_index
+            };
+            if turn_ends {
+                royale::turn_played(board, workspace);
+            }
 
-            if let Some(food) = removed_food {
-                board.food.insert(0, food);
+            // Root moves are searched with alpha lowered by the regret noise band, so every
+            // move close to the best one gets an exact score to compare
+            let at_root = root_node;
+            let child_alpha = if at_root && features::enabled(Feature::Regret) {
+                alpha.saturating_sub(regret::NOISE_BAND)
+            } else {
+                alpha
+            };
+            let (child_alpha, child_beta) = if weighted {
+                (i32::MIN, i32::MAX)
+            } else {
+                (child_alpha, beta)
+            };
+            // Eliminated snakes don't move, the next ply goes to the next snake still on the
+            // board, so a line down to one opponent takes two plies a turn
+            let next_player_index = next_to_move(board, current_player_index);
+            if let Some(trace) = workspace.trace.as_mut() {
+                trace.descend(current_player_index, move_dir);
+            }
+            let (score, _) = if lost {
+                workspace.clear_pv(depth - 1);
+                let outcome = mate::outcome(board, maximizing_player_index, &workspace.root_alive);
+                (
+                    mate::outcome_score(outcome.unwrap_or(GameOutcome::Lost), ply + 1),
+                    None,
+                )
+            } else {
+                minimax(
+                    board,
+                    depth - 1,
+                    child_alpha,
+                    child_beta,
+                    maximizing_player_index,
+                    next_player_index,
+                    workspace,
+                )
+            };
+            if let Some(trace) = workspace.trace.as_mut() {
+                trace.ascend();
+                if let Some(node) = traced {
+                    trace.node(node).children.push(TraceChild {
+                        direction: move_dir,
+                        score,
+                        alpha: child_alpha,
+                        beta: child_beta,
+                    });
+                }
             }
 
-            let next_player_index = (current_player_index + 1) % board.snakes.len();
-            let (score, _) = minimax(
-                &mut board,
-                depth - 1,
-                alpha,
-                beta,
-                maximizing_player_index,
-                next_player_index,
-            );
+            if turn_ends {
+                royale::turn_undone(board, workspace);
+            }
+            if let Some(body) = starved_body {
+                board.snakes[current_player_index].body = body;
+            }
+            if let Some(made) = move_undo {
+                undo_move(board, made);
+            }
+            if joint {
+                if let Some(undo) = turn_undo {
+                    undo_turn(board, undo);
+                }
+                workspace.pending.pop();
+                workspace.turn_start = turn_start;
+            }
+            if workspace.aborted {
+                return (0, None);
+            }
+            let likelihood = likelihoods[move_dir as usize];
+            reply_sum += likelihood * score as i64;
+            replies += likelihood;
+            searched += 1;
 
-            board.snakes[current_player_index] = original_snake;
+            if at_root {
+                workspace.root_candidates.push(RootCandidate {
+                    direction: move_dir,
+                    score,
+                    exact: score > child_alpha || child_alpha == i32::MIN,
+                    kills: workspace.pv_kills(depth - 1),
+                });
+            }
 
             if PRINT {
                 println!(
@@ -316,86 +1637,764 @@ fn minimax(
                 );
             }
 
+            // Between root moves with the same score, prefer the line where we trap more
+            // opponents over one where they only die by accident or not at all
+            let credited_tie = at_root
+                && score == best_score
+                && current_best_move.is_some()
+                && workspace.pv_kills(depth - 1) > workspace.pv_kills(depth);
             if (current_player_index == maximizing_player_index && score > best_score)
                 || (current_player_index != maximizing_player_index && score < best_score)
+                || credited_tie
             {
                 best_score = score;
-                current_best_move = move_dir.to_string();
+                current_best_move = Some(move_dir);
+                workspace.update_pv(depth, current_player_index, move_dir);
             }
 
-            if current_player_index == maximizing_player_index {
+            let cutoff = if current_player_index == maximizing_player_index {
                 alpha = std::cmp::max(alpha, score);
-                if beta <= alpha {
-                    break;
-                }
+                // A forced win only cuts off the root once the other moves were searched,
+                // another winning line may be the one where we trap the opponent ourselves
+                beta <= alpha && !at_root
             } else {
                 beta = std::cmp::min(beta, score);
-                if beta <= alpha {
-                    break;
+                beta <= alpha && !weighted
+            };
+            if first_turn && (cutoff || mate::is_loss(score)) {
+                workspace.record_refutation(current_player_index, ply, move_dir);
+            }
+            if cutoff {
+                if history {
+                    workspace.record_cutoff(board, current_player_index, depth, move_dir);
                 }
+                workspace.cutoff_stats.cutoffs += 1;
+                workspace.cutoff_stats.first_move += (searched == 1) as u64;
+                if let (Some(node), Some(trace)) = (traced, workspace.trace.as_mut()) {
+                    let node = trace.node(node);
+                    node.cutoff = Some(move_dir);
+                    node.skipped = directions[tried + 1..].to_vec();
+                }
+                break;
             }
         }
     }
 
-    if !move_found {
+    if move_found {
+        workspace.cutoff_stats.nodes += 1;
+    }
+    let result = if !move_found {
         // Handle no safe moves found
-        return if current_player_index == maximizing_player_index {
-            (i32::MIN, String::from("none"))
+        if current_player_index == maximizing_player_index
+            || (joint && loses_turn(board, maximizing_player_index, workspace))
+        {
+            workspace.clear_pv(depth);
+            let outcome = if others_stuck(board, maximizing_player_index) {
+                GameOutcome::Drawn
+            } else {
+                GameOutcome::Lost
+            };
+            (mate::outcome_score(outcome, ply + 1), None)
         } else {
-            (i32::MAX, String::from("none"))
-        };
+            let ours = blocked_by(
+                board,
+                current_player_index,
+                &board.snakes[maximizing_player_index],
+            );
+            workspace.end_pv_with_elimination(depth, ours);
+            (mate::win_in(ply + 1), None)
+        }
+    } else {
+        if weighted {
+            best_score = threat::backup(best_score, reply_sum, replies, threat);
+        }
+        (best_score, current_best_move)
+    };
+    if let (Some(node), Some(trace)) = (traced, workspace.trace.as_mut()) {
+        trace.node(node).score = result.0;
+    }
+    result
+}
+
+// Searches `depth` plies from the root with minimax, or with MaxN when `use_maxn` is on and
+// the board has the snakes for it (see maxn.rs). MaxN's score is our share of the board.
+// With `use_root_parallel` our root moves are searched side by side, see parallel.rs.
+fn search_root(
+    board: &mut Board,
+    depth: i32,
+    you_index: usize,
+    workspace: &mut SearchWorkspace,
+) -> (i32, Option<Direction>) {
+    if features::enabled(Feature::MaxN) && maxn::applies(board) {
+        let (shares, direction) =
+            maxn::maxn(board, depth, maxn::SHARES, you_index, you_index, workspace);
+        return (shares.get(you_index).copied().unwrap_or(0), direction);
     }
+    if features::enabled(Feature::RootParallel) {
+        if let Some(result) = parallel::search_root(board, depth, you_index, workspace) {
+            return result;
+        }
+    }
+    minimax(
+        board,
+        depth,
+        i32::MIN,
+        i32::MAX,
+        you_index,
+        you_index,
+        workspace,
+    )
+}
+
+// What the searches of a turn are set up with besides the position, see `prepare_iteration`
+#[derive(Clone)]
+pub(crate) struct SearchSetup {
+    pub turn: i32,
+    pub you_index: usize,
+    // When the answer is due, and when the searches have to stop (see deepening.rs)
+    pub deadline: Instant,
+    pub stop: Instant,
+    pub stalled: bool,
+    pub preset: &'static Preset,
+    pub famine: bool,
+    pub rules: RulesConfig,
+    pub heat: Vec<i32>,
+    pub styles: Vec<OpponentStyle>,
+}
 
-    (best_score, current_best_move)
+// Prepares `workspace` for the search of `iteration` plies from `board`. The refutations and
+// move history of the shallower searches order the deeper ones.
+fn prepare_iteration(
+    workspace: &mut SearchWorkspace,
+    board: &Board,
+    iteration: i32,
+    setup: &SearchSetup,
+) {
+    if iteration == 1 {
+        workspace.start_search(board, iteration, Some(setup.deadline), setup.stalled);
+    } else {
+        workspace.deepen_search(board, iteration, Some(setup.deadline), setup.stalled);
+    }
+    workspace.stop_at = Some(setup.stop);
+    workspace.preset = setup.preset;
+    workspace.famine = setup.famine;
+    workspace.rules = setup.rules.clone();
+    if features::enabled(Feature::ShrinkForecast) {
+        workspace.shrink = royale::forecast(&setup.rules, setup.turn, board);
+    }
+    workspace.heat.extend_from_slice(&setup.heat);
+    workspace.styles.extend_from_slice(&setup.styles);
+    if features::enabled(Feature::ThreatWeighting) {
+        threat::weigh(board, setup.you_index, &mut workspace.threat);
+    }
 }
 
-pub fn get_move(_game: &Game, turn: &i32, board: &Board, you: &Battlesnake) -> Value {
-    if !unsafe { GAME_STARTED } {
+// The position after this turn when we play `ours` and every opponent its move of the
+// principal variation `pv`, with our index on that board. None when the line doesn't go a
+// whole turn or we don't survive it.
+fn predicted_position(
+    board: &Board,
+    you_index: usize,
+    ours: Direction,
+    pv: &[(usize, Direction)],
+    rules: &RulesConfig,
+    famine: bool,
+) -> Option<(Board, usize)> {
+    let alive = board.snakes.iter().filter(|s| !s.body.is_empty()).count();
+    let moves: SmallVec<[(usize, Direction); 4]> = pv
+        .iter()
+        .take(alive)
+        .map(|&(index, direction)| {
+            let direction = if index == you_index { ours } else { direction };
+            (index, direction)
+        })
+        .collect();
+    if moves.len() < alive || moves.iter().all(|&(index, _)| index != you_index) {
+        return None;
+    }
+    let mut next = board.clone();
+    simulate_turn(&mut next, &moves, famine, rules);
+    if next.snakes[you_index].body.is_empty() {
+        return None;
+    }
+    // The game takes the eaten food and the eliminated snakes off the board
+    let heads: SmallVec<[Coord; 4]> = next
+        .snakes
+        .iter()
+        .filter_map(|s| s.body.first().copied())
+        .collect();
+    next.food.retain(|food| !heads.contains(food));
+    let you_id = next.snakes[you_index].id.clone();
+    next.snakes.retain(|s| !s.body.is_empty());
+    for snake in next.snakes.iter_mut() {
+        snake.head = snake.body[0];
+    }
+    let you_index = next.snakes.iter().position(|s| s.id == you_id)?;
+    Some((next, you_index))
+}
+
+// Searches `board` deeper and deeper while we wait for its request, handing every depth that
+// finishes to `found`, until `max_depth`, the stop of `setup` or `cancel`. See ponder.rs.
+pub(crate) fn ponder(
+    board: &Board,
+    max_depth: i32,
+    setup: &SearchSetup,
+    cancel: &Arc<AtomicBool>,
+    mut found: impl FnMut((i32, Option<Direction>), SearchHandoff),
+) {
+    let mut searched = board.clone();
+    with_workspace(|workspace| {
+        for iteration in 1..=max_depth {
+            prepare_iteration(workspace, board, iteration, setup);
+            workspace.cancel = Some(Arc::clone(cancel));
+            let result = search_root(&mut searched, iteration, setup.you_index, workspace);
+            if workspace.aborted {
+                return;
+            }
+            found(result, workspace.hand_off());
+        }
+    });
+}
+
+// What we know about the turn besides the request itself
+pub struct TurnContext {
+    // When the answer has to be sent, less the reserve for slow moves (see throttle.rs). The
+    // search may have waited in the scheduler queue since the request came in.
+    pub deadline: Instant,
+    // The game has been going round in circles, see stall.rs
+    pub stalled: bool,
+    // Recent opponent traffic per cell, see heat.rs. Empty without a history of the game.
+    pub heat: Vec<i32>,
+    // How every snake has been playing this game, by index, see opponent_style.rs. Empty
+    // without a history of the game.
+    pub styles: Vec<OpponentStyle>,
+    // Search settings and evaluation weights for the game, see duel.rs
+    pub preset: &'static Preset,
+    // The board has been without food for a while, see famine.rs
+    pub famine: bool,
+    // Where the position predicted for the next turn is pondered, and what was pondered
+    // for this one, see ponder.rs. None when `use_pondering` is off.
+    pub ponder: Option<PonderTurn>,
+    // Where the deepest finished search is left for the watchdog, see watchdog.rs
+    pub progress: Option<Arc<SearchProgress>>,
+}
+
+// How much a good answer matters for this move, used to schedule searches when several
+// games are running: one point per move that is unsafe, one for being hungry and one for an
+// opponent head within two steps
+pub fn position_criticality(board: &Board, you: &Battlesnake) -> u32 {
+    if you.body.is_empty() {
+        return 0;
+    }
+    let unsafe_moves = Direction::ALL
+        .iter()
+        .copied()
+        .filter(|&m| !is_move_safe(board, you, m))
+        .count() as u32;
+    let hungry = (you.health < 30) as u32;
+    let threatened = board
+        .snakes
+        .iter()
+        .filter(|s| s.id != you.id && !s.body.is_empty())
+        .any(|s| distance(board, &s.body[0], &you.body[0]) <= 2) as u32;
+    unsafe_moves + hungry + threatened
+}
+
+// The server searches at the preset's depth, less when the machine is too slow (see
+// throttle.rs), and the offline tools use shallow searches to play many games quickly
+pub fn get_move_at_depth(
+    game: &Game,
+    turn: &i32,
+    board: &Board,
+    you: &Battlesnake,
+    depth: i32,
+    context: TurnContext,
+    metrics: &EvalMetrics,
+) -> Value {
+    // Callers that skip normalize.rs may not have our snake on the board
+    let placed;
+    let (board, you) = if board.snakes.iter().any(|s| s.id == you.id) {
+        (board, you)
+    } else {
+        let (mut board, mut you) = (board.clone(), you.clone());
+        if let Some(cause) = normalize::place_own_snake(&mut board, &mut you) {
+            info!("MOVE {}: {}, placed it for the search", turn, cause);
+        }
+        placed = (board, you);
+        (&placed.0, &placed.1)
+    };
+    if !GAME_STARTED.load(Ordering::Relaxed) {
         let head = you.body.first().unwrap();
-        
+
         // Define the middle upper part of the map
         let middle_x_start = board.width / 3;
         let middle_x_end = 2 * board.width / 3;
         let upper_y = board.height / 3;
 
         // Check if the snake's head is in the middle upper part of the map
-        if head.x >= middle_x_start as i32 && head.x <= middle_x_end as i32 && head.y <= upper_y as i32 {
-            return json!({ "move": "down" });
+        if head.x >= middle_x_start && head.x <= middle_x_end && head.y <= upper_y as i32 {
+            return json!({ "move": Direction::Down.as_str() });
         } else {
-            return json!({ "move": "up" });
+            return json!({ "move": Direction::Up.as_str() });
         }
     }
-    println!("----------------NEW TURN----------------");
-    let depth = 12;
+    if PRINT {
+        println!("----------------NEW TURN----------------");
+    }
     // let depth = if board.snakes.len() < 3 { 16 } else { 12 }; // Adjust depth based on the number of snakes
     let snakes = &board.snakes; // Add opponents here as well
 
     let my_snake_index = snakes.iter().position(|s| s.id == you.id).unwrap();
 
     let mut cloned_board = board.clone();
-    let (score, best_move) = minimax(
-        &mut cloned_board,
-        depth,
-        i32::MIN,
-        i32::MAX,
-        my_snake_index,
-        my_snake_index,
-    );
+    let stop =
+        deepening::search_deadline(context.deadline, Duration::from_millis(game.timeout as u64));
+    let max_depth = depth;
+    let rules = RulesConfig::from_game(game);
+    let mut context = context;
+    let pondered = context
+        .ponder
+        .as_mut()
+        .and_then(|ponder| ponder.found.take());
+    let setup = SearchSetup {
+        turn: *turn,
+        you_index: my_snake_index,
+        deadline: context.deadline,
+        stop,
+        stalled: context.stalled,
+        preset: context.preset,
+        famine: context.famine,
+        rules: rules.clone(),
+        heat: context.heat.clone(),
+        styles: context.styles.clone(),
+    };
+    // With a single move that isn't fatal there's nothing to search
+    let forced = match non_fatal_moves(board, you).as_slice() {
+        &[only] => Some(only),
+        _ => None,
+    };
+    let personality = personality::of(board, context.preset);
+    let searched = if forced.is_some() {
+        None
+    } else if personality == mcts::PERSONALITY {
+        let searched = panic::catch_unwind(|| {
+            mcts::search(
+                board,
+                my_snake_index,
+                &rules,
+                Some(stop),
+                u32::MAX,
+                &mut game_rng(&game.id, *turn),
+            )
+        });
+        if searched.is_err() {
+            degrade::report(
+                Subsystem::Mcts,
+                &format!("the search of turn {} panicked", turn),
+            );
+        }
+        searched.ok()
+    } else {
+        None
+    };
+    let (score, best_move, status, predicted) = if let Some(forced) = forced {
+        info!(
+            "MOVE {}: '{}' is the only move that isn't fatal, played without a search",
+            turn, forced
+        );
+        (0, Some(forced), None, None)
+    } else if let Some(searched) = searched {
+        info!(
+            "MOVE {}: {} playouts of Monte Carlo tree search",
+            turn, searched.playouts
+        );
+        metrics.record_tree(personality, searched.nodes, searched.bytes);
+        // Our average share of the win, in thousandths
+        (
+            (searched.value * 1000.0) as i32,
+            searched.direction,
+            None,
+            None,
+        )
+    } else {
+        with_workspace(|workspace| {
+            workspace.take_eval_totals();
+            // Deepest finished search so far, see deepening.rs
+            let mut finished = None;
+            let mut first = 1;
+            // The depths pondered while we waited for the request are done already
+            if let Some(pondered) = pondered {
+                info!(
+                    "MOVE {}: pondered to depth {} before the request came",
+                    turn,
+                    pondered.handoff.depth()
+                );
+                first = pondered.handoff.depth() + 1;
+                workspace.take_over(board, pondered.handoff);
+                if let (Some(progress), Some(direction)) = (&context.progress, pondered.result.1) {
+                    progress.finished(first - 1, direction);
+                }
+                finished = Some((pondered.result, workspace.snapshot()));
+            }
+            for iteration in first..=max_depth {
+                let started = Instant::now();
+                prepare_iteration(workspace, board, iteration, &setup);
+                if decision_log::log_path().is_some() {
+                    workspace.trace = search_trace::trace_plies()
+                        .map(|plies| SearchTrace::new(plies, Vec::new()));
+                }
+                let result = search_root(&mut cloned_board, iteration, my_snake_index, workspace);
+                if workspace.aborted {
+                    break;
+                }
+                if let (Some(progress), Some(direction)) = (&context.progress, result.1) {
+                    progress.finished(iteration, direction);
+                }
+                finished = Some((result, workspace.snapshot()));
+                if !deepening::next_fits(started.elapsed(), stop) {
+                    break;
+                }
+            }
+            let (mut result, snapshot) = match finished {
+                Some(finished) => finished,
+                None => return (i32::MIN, None, None, None),
+            };
+            workspace.restore(snapshot);
+            let depth = workspace.root_depth;
+            if depth < max_depth {
+                info!(
+                    "MOVE {}: searched to depth {} of {} in the time there was",
+                    turn, depth, max_depth
+                );
+            }
+            let candidates = workspace.root_candidates.clone();
+            // Regret weighs the paranoid scores against other opponent models, MaxN's
+            // opponents already play for themselves
+            let paranoid = !(features::enabled(Feature::MaxN) && maxn::applies(board));
+            let chosen = if features::enabled(Feature::Regret) && paranoid {
+                regret::choose_move(board, my_snake_index, &candidates, workspace)
+            } else {
+                None
+            };
+            if let Some(chosen) = chosen {
+                if Some(chosen.direction) != result.1 {
+                    if let Some(searched) = result.1 {
+                        info!(
+                            "MOVE {}: '{}' has less regret across opponent models than '{}'",
+                            turn, chosen.direction, searched
+                        );
+                    }
+                    result = (chosen.score, Some(chosen.direction));
+                }
+            }
+            metrics.record(&game.id, phase_of(*turn, board), &workspace.eval_totals);
+            if let Some(dir) = eval_graph::graph_dir() {
+                let (our_cells, _) = voronoi_territory(board, my_snake_index, workspace);
+                let point = eval_graph::point(*turn, result.0, board, you, our_cells);
+                if let Err(e) = eval_graph::append(&dir, game, you, &point) {
+                    info!("Could not write eval graph to {}: {}", dir.display(), e);
+                }
+            }
+            if consistency::enabled() {
+                let pv = workspace.principal_variation().to_vec();
+                consistency::check(*turn, board, my_snake_index, &pv, workspace);
+            }
+            if let Some(path) = decision_log::log_path() {
+                let trace = workspace.trace.take().map(|trace| TraceRecord {
+                    board: board.clone(),
+                    you_index: my_snake_index,
+                    stalled: context.stalled,
+                    heat: context.heat.clone(),
+                    ruleset: game.ruleset.clone(),
+                    nodes: trace.nodes,
+                });
+                let decision =
+                    decision_log::Decision::new(game, *turn, board, you, depth, &result, workspace)
+                        .with_trace(trace);
+                if let Err(e) = decision_log::append(&path, &decision) {
+                    info!("Could not write decision log to {}: {}", path, e);
+                }
+            }
+            let status = status_shout::enabled().then(|| {
+                let (our_cells, _) = voronoi_territory(board, my_snake_index, workspace);
+                let cells = (board.width * board.height as i32).max(1);
+                status_shout::status(depth, result.0, 100 * our_cells / cells)
+            });
+            let predicted = context.ponder.as_ref().and(result.1).and_then(|ours| {
+                let pv = workspace.principal_variation();
+                predicted_position(board, my_snake_index, ours, pv, &rules, context.famine)
+            });
+            (result.0, result.1, status, predicted)
+        })
+    };
 
-    if best_move == "none" {
-        println!("No best move found, choosing a random safe move...");
-        let safe_moves = ["up", "down", "left", "right"]
-            .iter()
-            .filter(|&m| is_move_safe(board, you, m))
-            .collect::<Vec<_>>();
-        let random_move = safe_moves.choose(&mut rand::thread_rng()).unwrap();
-        return json!({ "move": random_move });
+    let best_move = match best_move {
+        Some(best_move) => best_move,
+        None => {
+            // Every move loses or no depth finished: at least keep the most room
+            let fallback = safe_move(board, you);
+            info!(
+                "MOVE {}: No best move found, playing the safe move '{}'",
+                turn, fallback
+            );
+            return json!({ "move": fallback.as_str() });
+        }
+    };
+
+    if forced.is_none() {
+        info!(
+            "MOVE {}: Best move is '{}' with a score of {}",
+            turn, best_move, score
+        );
+    }
+    if let (Some(ponder), Some((next, you_index))) = (context.ponder, predicted) {
+        // The worker gives up a timeout from now when no request comes
+        let stop = Instant::now() + Duration::from_millis(game.timeout as u64);
+        let setup = SearchSetup {
+            turn: turn + 1,
+            you_index,
+            deadline: stop,
+            stop,
+            ..setup
+        };
+        ponder.start(next, max_depth, setup);
     }
 
-    info!(
-        "MOVE {}: Best move is '{}' with a score of {}",
-        turn, best_move, score
-    );
+    if squad::is_squad_game(game) {
+        if let Some(teammate) = squad::teammate(board, you) {
+            let best_move = coordinate_with_teammate(board, you, teammate, best_move);
+            let region = squad::claimed_region(you, teammate);
+            return json!({
+                "move": best_move.as_str(),
+                "shout": squad::encode_shout(best_move, region)
+            });
+        }
+    }
+
+    match status {
+        Some(status) => json!({ "move": best_move.as_str(), "shout": status }),
+        None => json!({ "move": best_move.as_str() }),
+    }
+}
+
+// How the offline tools search a position, see `analyse`
+#[derive(Debug, Clone, Copy)]
+pub struct SearchConfig {
+    pub depth: i32,
+    // Score every leaf with the rich evaluation, however deep
+    pub rich: bool,
+    // Let the regret layer pick between near-equal root moves, like the server does
+    pub regret: bool,
+}
+
+impl SearchConfig {
+    pub fn new(depth: i32) -> SearchConfig {
+        SearchConfig {
+            depth,
+            rich: false,
+            regret: false,
+        }
+    }
+}
+
+// Result of an offline search
+pub struct Analysis {
+    // None when every move loses
+    pub direction: Option<Direction>,
+    pub score: i32,
+    // The principal variation as (snake index, move)
+    pub pv: Vec<(usize, Direction)>,
+}
+
+// Searches a position for the offline tools: no deadline and none of the logging the
+// server does
+pub fn analyse(board: &Board, you_index: usize, config: &SearchConfig) -> Analysis {
+    let mut cloned_board = board.clone();
+    with_workspace(|workspace| {
+        workspace.start_search(board, config.depth, None, false);
+        workspace.force_rich = config.rich;
+        if features::enabled(Feature::ThreatWeighting) {
+            threat::weigh(board, you_index, &mut workspace.threat);
+        }
+        let (mut score, mut direction) =
+            search_root(&mut cloned_board, config.depth, you_index, workspace);
+        let paranoid = !(features::enabled(Feature::MaxN) && maxn::applies(board));
+        if config.regret && features::enabled(Feature::Regret) && paranoid {
+            let candidates = workspace.root_candidates.clone();
+            if let Some(chosen) = regret::choose_move(board, you_index, &candidates, workspace) {
+                score = chosen.score;
+                direction = Some(chosen.direction);
+            }
+        }
+        workspace.take_eval_totals();
+        Analysis {
+            direction,
+            score,
+            pv: workspace.principal_variation().to_vec(),
+        }
+    })
+}
+
+// The position a traced search was in after the moves of `path` from `root`, searched for
+// the snake at `you_index` with `to_move` to move there, under the game's `rules`. With joint
+// moves a turn is only played once it's complete, the moves of the turn still going on are
+// returned.
+pub fn position_at(
+    root: &Board,
+    path: &[(usize, Direction)],
+    you_index: usize,
+    to_move: usize,
+    rules: &RulesConfig,
+) -> Result<(Board, MovePath), String> {
+    let mut board = root.clone();
+    let joint = features::enabled(Feature::JointMoves);
+    let mut turn = Vec::new();
+    for &(snake, direction) in path {
+        if snake >= board.snakes.len() {
+            return Err(format!("no snake {} on the board", snake));
+        }
+        if !joint {
+            // Like the search, which puts eaten food back
+            simulate_move(&mut board, snake, direction, rules).put_food_back(&mut board);
+            continue;
+        }
+        // Every turn of the search starts with our move
+        if snake == you_index && !turn.is_empty() {
+            simulate_turn(&mut board, &turn, false, rules);
+            turn.clear();
+        }
+        turn.push((snake, direction));
+    }
+    if to_move == you_index && !turn.is_empty() {
+        simulate_turn(&mut board, &turn, false, rules);
+        turn.clear();
+    }
+    Ok((board, turn))
+}
+
+// Searches a node of a traced search again (see search_trace.rs): the position the node's
+// path leads to from the recorded root, with `window` for alpha and beta and the evaluation
+// tier the node had, recording `plies` below it, under the rules of the recorded game. The
+// preset and the feature toggles are the current ones, they have to be the search's to get
+// the same result.
+pub fn reenter(
+    decision: &TracedDecision,
+    node: &TraceNode,
+    window: (i32, i32),
+    plies: usize,
+) -> Result<Vec<TraceNode>, String> {
+    let record = &decision.trace;
+    let rules = record.rules();
+    let (mut board, pending) = position_at(
+        &record.board,
+        &node.path,
+        record.you_index,
+        node.snake,
+        &rules,
+    )?;
+
+    Ok(with_workspace(|workspace| {
+        workspace.start_search(&record.board, decision.depth, None, record.stalled);
+        if features::enabled(Feature::ShrinkForecast) {
+            workspace.shrink = royale::forecast(&rules, decision.turn, &record.board);
+        }
+        workspace.rules = rules;
+        if node.rich {
+            workspace.force_rich = true;
+        } else {
+            // No time left makes every leaf cheap
+            workspace.deadline = Some(Instant::now());
+        }
+        workspace.heat.extend_from_slice(&record.heat);
+        if features::enabled(Feature::ThreatWeighting) {
+            threat::weigh(&record.board, record.you_index, &mut workspace.threat);
+        }
+        workspace.pending = pending;
+        workspace.trace = Some(SearchTrace::new(plies, node.path.clone()));
+        minimax(
+            &mut board,
+            node.depth,
+            window.0,
+            window.1,
+            record.you_index,
+            node.snake,
+            workspace,
+        );
+        workspace
+            .trace
+            .take()
+            .map(|trace| trace.nodes)
+            .unwrap_or_default()
+    }))
+}
+
+// Randomness for a move, seeded from the game and the turn so games running side by side
+// never share a generator and a replayed turn makes the same choice
+fn game_rng(game_id: &str, turn: i32) -> StdRng {
+    let mut hasher = DefaultHasher::new();
+    game_id.hash(&mut hasher);
+    turn.hash(&mut hasher);
+    StdRng::seed_from_u64(hasher.finish())
+}
+
+pub(crate) fn next_head(head: &Coord, direction: Direction) -> Coord {
+    let (dx, dy) = direction.offset();
+    Coord {
+        x: head.x + dx,
+        y: head.y + dy,
+    }
+}
+
+// `cell` brought back onto a wrapped board, as it is elsewhere
+pub(crate) fn wrap(board: &Board, cell: Coord) -> Coord {
+    if !board.wrapped || board.width <= 0 || board.height == 0 {
+        return cell;
+    }
+    Coord {
+        x: cell.x.rem_euclid(board.width),
+        y: cell.y.rem_euclid(board.height as i32),
+    }
+}
+
+// The cell `direction` leads to from `cell`, which is off the board past the edges of
+// anything but a wrapped board
+pub(crate) fn step(board: &Board, cell: &Coord, direction: Direction) -> Coord {
+    wrap(board, next_head(cell, direction))
+}
 
-    json!({ "move": best_move })
+// The cell `direction` leads to from `cell`, None when that's off the board
+pub(crate) fn neighbour(board: &Board, cell: &Coord, direction: Direction) -> Option<Coord> {
+    let next = step(board, cell, direction);
+    let inside = next.x >= 0 && next.x < board.width && next.y >= 0;
+    (inside && next.y < board.height as i32).then_some(next)
+}
+
+// Makes sure we don't run into the cell our teammate is about to move to. If the searched
+// move does, switch to another safe move, preferring the ones that stay in our half.
+fn coordinate_with_teammate(
+    board: &Board,
+    you: &Battlesnake,
+    teammate: &Battlesnake,
+    best_move: Direction,
+) -> Direction {
+    let teammate_cell = squad::teammate_next_cell(board, teammate, |s| {
+        predict_snake_move_towards_food(s, board)
+    });
+    if step(board, &you.body[0], best_move) != teammate_cell {
+        return best_move;
+    }
+
+    let region = squad::claimed_region(you, teammate);
+    let alternatives: MoveList = Direction::ALL
+        .iter()
+        .copied()
+        .filter(|&m| is_move_safe(board, you, m))
+        .filter(|&m| step(board, &you.body[0], m) != teammate_cell)
+        .collect();
+    alternatives
+        .iter()
+        .copied()
+        .find(|&m| region.contains(board, &step(board, &you.body[0], m)))
+        .or_else(|| alternatives.first().copied())
+        .unwrap_or(best_move)
 }
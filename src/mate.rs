@@ -0,0 +1,85 @@
+// Scores of decided lines. A line where we're eliminated, or where an opponent runs out of
+// moves, ends the search with a score beyond anything the evaluation gives, less the plies
+// from the root it took: a win sooner scores higher than a win later, and a loss later
+// higher than a loss sooner. So a lost position is dragged out for as long as it lasts, in
+// case an opponent doesn't find the win, and a won one is finished off rather than put off.
+// i32::MIN and i32::MAX stay the bounds of the search window.
+//
+// Whether a line is decided is checked on every position of the search before it's
+// evaluated (`outcome`): we're eliminated, we're the last snake left of a game that had
+// others, or we went out together with the last of them. That last one is a draw, and a
+// draw is accepted over any loss but never over a game that goes on, however bad it looks:
+// a mutual head-to-head as the last two snakes is only taken when every other move loses.
+
+use crate::Board;
+
+// Score of a win at the root, no evaluation comes near it
+const WON: i32 = 1_000_000_000;
+// Deeper than any search goes, scores within it of WON are decided
+const MAX_PLIES: i32 = 10_000;
+// Score of a draw, between every loss and every evaluation
+pub const DRAW: i32 = -WON / 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameOutcome {
+    Won,
+    Lost,
+    Drawn,
+}
+
+// How the game ended for the snake at `you_index` on `board`, None while it goes on.
+// `root_alive` are the snakes alive at the root, a game we played alone isn't won.
+pub fn outcome(board: &Board, you_index: usize, root_alive: &[bool]) -> Option<GameOutcome> {
+    let alive = !board.snakes[you_index].body.is_empty();
+    let had_opponents = root_alive
+        .iter()
+        .enumerate()
+        .any(|(i, &alive)| i != you_index && alive);
+    let opponents_left = board
+        .snakes
+        .iter()
+        .enumerate()
+        .any(|(i, snake)| i != you_index && !snake.body.is_empty());
+    match (alive, opponents_left) {
+        (false, false) if had_opponents => Some(GameOutcome::Drawn),
+        (false, _) => Some(GameOutcome::Lost),
+        (true, false) if had_opponents => Some(GameOutcome::Won),
+        _ => None,
+    }
+}
+
+// Score of the game ending with `outcome` `ply` plies from the root
+pub fn outcome_score(outcome: GameOutcome, ply: i32) -> i32 {
+    match outcome {
+        GameOutcome::Won => win_in(ply),
+        GameOutcome::Lost => loss_in(ply),
+        GameOutcome::Drawn => DRAW,
+    }
+}
+
+// We're eliminated `ply` plies from the root
+pub fn loss_in(ply: i32) -> i32 {
+    -WON + ply
+}
+
+// An opponent runs out of moves `ply` plies from the root
+pub fn win_in(ply: i32) -> i32 {
+    WON - ply
+}
+
+pub fn is_loss(score: i32) -> bool {
+    score <= -WON + MAX_PLIES
+}
+
+pub fn is_win(score: i32) -> bool {
+    score >= WON - MAX_PLIES
+}
+
+// Plies from the root to the end of a decided line
+pub fn plies(score: i32) -> i32 {
+    if is_win(score) {
+        (WON - score).max(0)
+    } else {
+        (score + WON).max(0)
+    }
+}
@@ -0,0 +1,171 @@
+// MaxN search, played instead of minimax in games of three or more snakes when use_maxn is on.
+// Minimax backs up the reply worst for us at every opponent node, as if all opponents played
+// together against us. In MaxN every leaf is scored for every snake on the board and every
+// snake picks the move best for itself, so an opponent only cuts us off when that's what
+// suits it. Moves are picked one snake after the other and played together once the turn is
+// complete, like the joint moves of minimax (see `simulate_turn`).
+//
+// The scores of a leaf are shares of SHARES: a snake off the board gets nothing and the
+// others split it by how far their evaluation is above the worst one on the board. With
+// the shares of every leaf adding up to the same total, a snake whose best move so far
+// already leaves the snake above less than it has gets no more out of the other moves, and
+// they are skipped (shallow pruning).
+
+use smallvec::SmallVec;
+
+use crate::direction::Direction;
+use crate::logic::{
+    eval_tier, evaluate_board, is_move_legal, next_to_move, simulate_turn, undo_turn,
+};
+use crate::royale;
+use crate::workspace::{RootCandidate, SearchWorkspace};
+use crate::Board;
+
+// What the shares of every leaf add up to
+pub const SHARES: i32 = 1000;
+// Evaluation points the worst placed snake on the board still counts above being eliminated
+const SHARE_FLOOR: i64 = 100;
+
+// One share per snake index of the board
+pub type Shares = SmallVec<[i32; 8]>;
+
+// Whether the search of a board goes to MaxN when use_maxn is on
+pub fn applies(board: &Board) -> bool {
+    board.snakes.iter().filter(|s| !s.body.is_empty()).count() >= 3
+}
+
+// Every snake's share of the board at a leaf
+fn leaf_shares(board: &Board, you_index: usize, workspace: &mut SearchWorkspace) -> Shares {
+    let mut shares = Shares::from_elem(0, board.snakes.len());
+    let alive: SmallVec<[usize; 8]> = (0..board.snakes.len())
+        .filter(|&i| !board.snakes[i].body.is_empty())
+        .collect();
+    if alive.is_empty() {
+        return shares;
+    }
+    // Without us on the board the rest doesn't matter to the search
+    if board.snakes[you_index].body.is_empty() || alive.len() == 1 {
+        for &i in &alive {
+            shares[i] = SHARES / alive.len() as i32;
+        }
+        return shares;
+    }
+
+    // Only our own terms go into the evaluation metrics
+    let tier = eval_tier(workspace);
+    let totals = workspace.eval_totals;
+    let mut scores: SmallVec<[i64; 8]> = SmallVec::new();
+    for &i in &alive {
+        if i != you_index {
+            scores.push(evaluate_board(board, i, tier, workspace) as i64);
+        } else {
+            scores.push(0);
+        }
+    }
+    workspace.eval_totals = totals;
+    let ours = alive.iter().position(|&i| i == you_index).unwrap();
+    scores[ours] = evaluate_board(board, you_index, tier, workspace) as i64;
+
+    let worst = scores.iter().copied().min().unwrap_or(0);
+    let total: i64 = scores.iter().map(|&s| s - worst + SHARE_FLOOR).sum();
+    for (&i, &score) in alive.iter().zip(scores.iter()) {
+        shares[i] = ((score - worst + SHARE_FLOOR) * SHARES as i64 / total) as i32;
+    }
+    shares
+}
+
+// Searches `depth` plies below the node where the snake at `current` picks its move, for the
+// search of the snake at `you_index`. `bound` is the share the snake that moved before can
+// still leave the others, the node stops searching once `current` is sure to have that much.
+// Returns the shares of the line played and the move `current` picks, None when it has none.
+pub(crate) fn maxn(
+    board: &mut Board,
+    depth: i32,
+    bound: i32,
+    you_index: usize,
+    current: usize,
+    workspace: &mut SearchWorkspace,
+) -> (Shares, Option<Direction>) {
+    // The scores don't matter, the whole search gets thrown away
+    if workspace.out_of_time() {
+        return (Shares::new(), None);
+    }
+    let alive = board.snakes.iter().filter(|s| !s.body.is_empty()).count();
+    if depth == 0 || alive < 2 || board.snakes[you_index].body.is_empty() {
+        workspace.clear_pv(depth.max(0));
+        return (leaf_shares(board, you_index, workspace), None);
+    }
+
+    let mut moves: SmallVec<[Direction; 4]> = Direction::ALL
+        .iter()
+        .copied()
+        .filter(|&d| is_move_legal(board, &board.snakes[current], d))
+        .collect();
+    // A snake with nowhere to go still moves, and the turn takes it off the board
+    let trapped = moves.is_empty();
+    if trapped {
+        moves.push(Direction::Up);
+    }
+
+    let turn_over = depth == 1 || next_to_move(board, current) == you_index;
+    let turn_start = workspace.turn_start;
+    let at_root = depth == workspace.root_depth && current == you_index;
+    let mut best: Option<(Shares, Direction)> = None;
+    for direction in moves {
+        workspace.pending.push((current, direction));
+        let mut turn_undo = None;
+        if turn_over {
+            let pending = &workspace.pending[turn_start..];
+            turn_undo = Some(simulate_turn(
+                board,
+                pending,
+                workspace.famine,
+                &workspace.rules,
+            ));
+            workspace.turn_start = workspace.pending.len();
+            royale::turn_played(board, workspace);
+        }
+        let child_bound = SHARES - best.as_ref().map_or(0, |(shares, _)| shares[current]);
+        let next = if board.snakes[you_index].body.is_empty() {
+            you_index
+        } else {
+            next_to_move(board, current)
+        };
+        let (shares, _) = maxn(board, depth - 1, child_bound, you_index, next, workspace);
+        if let Some(undo) = turn_undo {
+            royale::turn_undone(board, workspace);
+            undo_turn(board, undo);
+        }
+        workspace.pending.pop();
+        workspace.turn_start = turn_start;
+        if workspace.aborted {
+            return (Shares::new(), None);
+        }
+
+        if at_root {
+            workspace.root_candidates.push(RootCandidate {
+                direction,
+                score: shares[you_index],
+                exact: true,
+                kills: 0,
+            });
+        }
+        if best
+            .as_ref()
+            .is_none_or(|(best, _)| shares[current] > best[current])
+        {
+            workspace.update_pv(depth, current, direction);
+            best = Some((shares, direction));
+        }
+        if !at_root
+            && best
+                .as_ref()
+                .is_some_and(|(best, _)| best[current] >= bound)
+        {
+            break;
+        }
+    }
+
+    let (shares, direction) = best.unwrap();
+    (shares, (!trapped).then_some(direction))
+}
@@ -0,0 +1,511 @@
+// Monte Carlo tree search, played instead of minimax in games of three or more snakes when
+// use_mcts is on. Minimax is paranoid: every opponent answers with the move worst for us, as
+// if they all played together against us, which in a four snake game makes every plan near
+// an opponent look lost. Here every snake picks its own move for itself at every turn of the
+// tree, by UCT over the results it got with each of its moves there (decoupled UCT), so the
+// turns are played with all moves at once like the real game. Each playout walks down the
+// tree, adds one node and plays the game on from there to its end or ROLLOUT_TURNS turns,
+// with the same simulate_turn the search uses. A snake scores 0 when it's eliminated and its
+// share of the win, split between the snakes still on the board, otherwise. We play the move
+// of ours tried most often.
+//
+// MCTS_ROLLOUT picks how the playouts move the snakes: `random` plays a random legal move,
+// `heuristic` (the default) avoids dead ends and heads that win a collision against it, and
+// goes for food when hungry.
+//
+// Every search grows its own tree, up to MCTS_MAX_NODES nodes (200000 when unset), after
+// which the playouts start at its leaves. That bounds the memory of a search, see
+// personality.rs.
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use smallvec::SmallVec;
+use std::collections::VecDeque;
+use std::env;
+use std::mem;
+use std::time::Instant;
+
+use crate::direction::Direction;
+use crate::logic::{distance, is_move_legal, simulate_turn, step, undo_turn, TurnUndo};
+use crate::rules::RulesConfig;
+use crate::{Board, Coord};
+
+pub const ROLLOUT_ENV: &str = "MCTS_ROLLOUT";
+pub const MAX_NODES_ENV: &str = "MCTS_MAX_NODES";
+// Name of the search among the personalities of the server, see personality.rs
+pub const PERSONALITY: &str = "mcts";
+
+// A playout plays at most this many turns past the tree
+const ROLLOUT_TURNS: usize = 10;
+// Exploration constant of UCT, the rewards are between 0 and 1
+const EXPLORATION: f32 = 0.7;
+// Playouts between two looks at the clock
+const CLOCK_INTERVAL: u32 = 64;
+// The tree stops growing at this many nodes unless MCTS_MAX_NODES says otherwise
+const DEFAULT_MAX_NODES: usize = 200_000;
+// The moves of a turn are packed two bits per snake
+const MAX_SNAKES: usize = 16;
+// Snakes that run out of health starve, as they do in the game. Minimax only lets them in a
+// famine (see famine.rs) since it rarely looks far enough, playouts do.
+const STARVE: bool = true;
+// Health below which the heuristic playouts go for food, as do snakes shorter than another
+const HUNGRY: i32 = 40;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rollout {
+    Random,
+    Heuristic,
+}
+
+// The playout policy MCTS_ROLLOUT names, heuristic when it's unset or unknown
+pub fn rollout() -> Rollout {
+    match env::var(ROLLOUT_ENV).as_deref() {
+        Ok("random") => Rollout::Random,
+        _ => Rollout::Heuristic,
+    }
+}
+
+// Most nodes the tree of a search grows to
+pub fn max_nodes() -> usize {
+    env::var(MAX_NODES_ENV)
+        .ok()
+        .and_then(|nodes| nodes.parse().ok())
+        .filter(|&nodes| nodes > 0)
+        .unwrap_or(DEFAULT_MAX_NODES)
+}
+
+// Whether the search of a board goes to MCTS when use_mcts is on
+pub fn applies(board: &Board) -> bool {
+    let alive = board.snakes.iter().filter(|s| !s.body.is_empty()).count();
+    alive >= 3 && board.snakes.len() <= MAX_SNAKES
+}
+
+// What a snake knows about one of its moves at a node
+#[derive(Debug, Clone, Copy)]
+struct Edge {
+    direction: Direction,
+    visits: u32,
+    reward: f32,
+}
+
+type Edges = SmallVec<[Edge; 4]>;
+type Rewards = SmallVec<[f32; 4]>;
+type Moves = SmallVec<[(usize, Direction); 4]>;
+// The edge every snake took at a node, by snake
+type Chosen = SmallVec<[(usize, usize); 4]>;
+
+struct Node {
+    visits: u32,
+    // The moves of every snake on the board, none for the ones off it
+    edges: Vec<Edges>,
+    // The turns played from here so far and the nodes they lead to
+    children: Vec<(u32, usize)>,
+    over: bool,
+}
+
+impl Node {
+    fn new(board: &Board, you_index: usize) -> Node {
+        let over = is_over(board, you_index);
+        let edges = board
+            .snakes
+            .iter()
+            .enumerate()
+            .map(|(index, snake)| {
+                if over || snake.body.is_empty() {
+                    return Edges::new();
+                }
+                let legal: SmallVec<[Direction; 4]> = Direction::ALL
+                    .iter()
+                    .copied()
+                    .filter(|&d| is_move_legal(board, snake, d))
+                    .collect();
+                // A head-to-head we'd lose or draw is only tried when there's nothing else
+                let safe: SmallVec<[Direction; 4]> = legal
+                    .iter()
+                    .copied()
+                    .filter(|&d| !meets_head(board, index, &step(board, &snake.body[0], d)))
+                    .collect();
+                let mut edges: Edges = if safe.is_empty() { legal } else { safe }
+                    .into_iter()
+                    .map(edge)
+                    .collect();
+                // A snake with nowhere to go still moves, and is eliminated
+                if edges.is_empty() {
+                    edges.push(edge(Direction::Up));
+                }
+                edges
+            })
+            .collect();
+        Node {
+            visits: 0,
+            edges,
+            children: Vec::new(),
+            over,
+        }
+    }
+
+    // The edge UCT picks for one snake, the ones never tried first
+    fn select(&self, snake: usize) -> usize {
+        let log_visits = (self.visits.max(1) as f32).ln();
+        let uct = |e: &Edge| {
+            if e.visits == 0 {
+                return f32::INFINITY;
+            }
+            let visits = e.visits as f32;
+            e.reward / visits + EXPLORATION * (log_visits / visits).sqrt()
+        };
+        let edges = &self.edges[snake];
+        (0..edges.len())
+            .fold(None, |best: Option<(usize, f32)>, i| {
+                let value = uct(&edges[i]);
+                match best {
+                    Some((_, best_value)) if best_value >= value => best,
+                    _ => Some((i, value)),
+                }
+            })
+            .map_or(0, |(i, _)| i)
+    }
+
+    fn child(&self, code: u32) -> Option<usize> {
+        self.children
+            .iter()
+            .find(|&&(c, _)| c == code)
+            .map(|&(_, node)| node)
+    }
+}
+
+fn edge(direction: Direction) -> Edge {
+    Edge {
+        direction,
+        visits: 0,
+        reward: 0.0,
+    }
+}
+
+// Whether a head at least as long as the snake at `index` could move to `cell` as well
+fn meets_head(board: &Board, index: usize, cell: &Coord) -> bool {
+    let length = board.snakes[index].body.len();
+    board.snakes.iter().enumerate().any(|(i, other)| {
+        i != index && other.body.len() >= length && distance(board, &other.body[0], cell) == 1
+    })
+}
+
+fn is_over(board: &Board, you_index: usize) -> bool {
+    board.snakes[you_index].body.is_empty()
+        || board.snakes.iter().filter(|s| !s.body.is_empty()).count() < 2
+}
+
+// Every snake's score for a board: 0 off the board, the win split between the others
+fn outcome(board: &Board) -> Rewards {
+    let alive = board.snakes.iter().filter(|s| !s.body.is_empty()).count();
+    board
+        .snakes
+        .iter()
+        .map(|s| {
+            if s.body.is_empty() {
+                0.0
+            } else {
+                1.0 / alive as f32
+            }
+        })
+        .collect()
+}
+
+fn code(moves: &Moves) -> u32 {
+    moves.iter().fold(0, |code, &(snake, direction)| {
+        let index = Direction::ALL.iter().position(|&d| d == direction).unwrap();
+        code | (index as u32) << (2 * snake)
+    })
+}
+
+// Cells of the board a playout can't move into, and room to split it up between the snakes
+struct Scratch {
+    blocked: Vec<bool>,
+    owners: Vec<u8>,
+    distances: Vec<u16>,
+    queue: VecDeque<usize>,
+}
+
+const UNOWNED: u8 = u8::MAX;
+const CONTESTED: u8 = u8::MAX - 1;
+
+impl Scratch {
+    fn new(board: &Board) -> Scratch {
+        let cells = (board.width * board.height as i32).max(0) as usize;
+        Scratch {
+            blocked: vec![false; cells],
+            owners: vec![UNOWNED; cells],
+            distances: vec![0; cells],
+            queue: VecDeque::with_capacity(cells),
+        }
+    }
+
+    fn index(board: &Board, cell: &Coord) -> Option<usize> {
+        let inside = cell.x >= 0 && cell.x < board.width && cell.y >= 0;
+        (inside && cell.y < board.height as i32).then(|| (cell.y * board.width + cell.x) as usize)
+    }
+
+    // Every body but the tails, which move away
+    fn block_bodies(&mut self, board: &Board) {
+        self.blocked.iter_mut().for_each(|b| *b = false);
+        for snake in board.snakes.iter().filter(|s| s.body.len() > 1) {
+            for segment in &snake.body[..snake.body.len() - 1] {
+                if let Some(i) = Scratch::index(board, segment) {
+                    self.blocked[i] = true;
+                }
+            }
+        }
+    }
+
+    fn is_open(&self, board: &Board, cell: &Coord) -> bool {
+        Scratch::index(board, cell).is_some_and(|i| !self.blocked[i])
+    }
+
+    // The cells every snake gets to first, per snake on the board
+    fn territory(&mut self, board: &Board) -> Rewards {
+        self.block_bodies(board);
+        self.owners.iter_mut().for_each(|o| *o = UNOWNED);
+        self.queue.clear();
+        let mut owned = Rewards::from_elem(0.0, board.snakes.len());
+        for (snake, s) in board.snakes.iter().enumerate() {
+            if let Some(i) = s.body.first().and_then(|head| Scratch::index(board, head)) {
+                self.owners[i] = if self.owners[i] == UNOWNED {
+                    snake as u8
+                } else {
+                    CONTESTED
+                };
+                self.distances[i] = 0;
+                self.queue.push_back(i);
+            }
+        }
+        while let Some(i) = self.queue.pop_front() {
+            let owner = self.owners[i];
+            if owner == CONTESTED {
+                continue;
+            }
+            owned[owner as usize] += 1.0;
+            let cell = Coord {
+                x: i as i32 % board.width,
+                y: i as i32 / board.width,
+            };
+            for direction in Direction::ALL {
+                let next = match Scratch::index(board, &step(board, &cell, direction)) {
+                    Some(next) if !self.blocked[next] => next,
+                    _ => continue,
+                };
+                if self.owners[next] == UNOWNED {
+                    self.owners[next] = owner;
+                    self.distances[next] = self.distances[i] + 1;
+                    self.queue.push_back(next);
+                } else if self.owners[next] != owner
+                    && self.distances[next] == self.distances[i] + 1
+                {
+                    self.owners[next] = CONTESTED;
+                }
+            }
+        }
+        owned
+    }
+}
+
+// Every snake's score for a playout that ran out of turns: a third for still being there,
+// split between the snakes alive, a third by the territory they have and a third by their
+// length, since the longer snake wins the head-to-heads still to come
+fn cutoff_outcome(board: &Board, scratch: &mut Scratch) -> Rewards {
+    let mut rewards = outcome(board);
+    let territory = scratch.territory(board);
+    let total_territory: f32 = territory.iter().sum();
+    let total_length: usize = board.snakes.iter().map(|s| s.body.len()).sum();
+    for (i, reward) in rewards.iter_mut().enumerate() {
+        if *reward > 0.0 {
+            let owned = territory[i] / total_territory.max(1.0);
+            let length = board.snakes[i].body.len() as f32 / total_length.max(1) as f32;
+            *reward = (*reward + owned + length) / 3.0;
+        }
+    }
+    rewards
+}
+
+// The playout move of the snake at `index`, with the bodies of the turn blocked in `scratch`
+fn playout_move(
+    board: &Board,
+    index: usize,
+    rollout: Rollout,
+    scratch: &Scratch,
+    rng: &mut StdRng,
+) -> Direction {
+    let snake = &board.snakes[index];
+    let head = snake.body[0];
+    let legal: SmallVec<[Direction; 4]> = Direction::ALL
+        .iter()
+        .copied()
+        .filter(|&d| scratch.is_open(board, &step(board, &head, d)))
+        .collect();
+    if rollout == Rollout::Random || legal.len() < 2 {
+        return legal.choose(rng).copied().unwrap_or(Direction::Up);
+    }
+
+    let food = board.food.iter().min_by_key(|f| distance(board, f, &head));
+    let hungry = snake.health < HUNGRY
+        || board
+            .snakes
+            .iter()
+            .any(|other| other.body.len() >= snake.body.len() && other.id != snake.id);
+    let weights: SmallVec<[u32; 4]> = legal
+        .iter()
+        .map(|&direction| {
+            let next = step(board, &head, direction);
+            let exits = Direction::ALL
+                .iter()
+                .filter(|&&d| scratch.is_open(board, &step(board, &next, d)))
+                .count() as u32;
+            // Dead ends are almost never worth it
+            let mut weight = if exits == 0 { 1 } else { 8 * exits };
+            if meets_head(board, index, &next) {
+                weight = (weight / 8).max(1);
+            }
+            if let Some(food) = food {
+                let closer = distance(board, food, &next) < distance(board, food, &head);
+                if closer && hungry {
+                    weight *= 4;
+                }
+            }
+            weight
+        })
+        .collect();
+    let mut pick = rng.gen_range(0..weights.iter().sum::<u32>());
+    for (i, &weight) in weights.iter().enumerate() {
+        if pick < weight {
+            return legal[i];
+        }
+        pick -= weight;
+    }
+    legal[legal.len() - 1]
+}
+
+// Plays the game on from `board` and takes the turns back, returning every snake's score
+fn playout(
+    board: &mut Board,
+    you_index: usize,
+    rules: &RulesConfig,
+    rollout: Rollout,
+    scratch: &mut Scratch,
+    rng: &mut StdRng,
+) -> Rewards {
+    let mut undos: Vec<TurnUndo> = Vec::with_capacity(ROLLOUT_TURNS);
+    while undos.len() < ROLLOUT_TURNS && !is_over(board, you_index) {
+        scratch.block_bodies(board);
+        let moves: Moves = (0..board.snakes.len())
+            .filter(|&i| !board.snakes[i].body.is_empty())
+            .map(|i| (i, playout_move(board, i, rollout, scratch, rng)))
+            .collect();
+        undos.push(simulate_turn(board, &moves, STARVE, rules));
+    }
+    let rewards = if is_over(board, you_index) {
+        outcome(board)
+    } else {
+        cutoff_outcome(board, scratch)
+    };
+    for undo in undos.into_iter().rev() {
+        undo_turn(board, undo);
+    }
+    rewards
+}
+
+// Result of a search
+pub struct MctsResult {
+    // None when we aren't on the board
+    pub direction: Option<Direction>,
+    // Our average score with that move, between 0 and 1
+    pub value: f32,
+    pub playouts: u32,
+    // Size of the tree, and about how much memory it took
+    pub nodes: usize,
+    pub bytes: usize,
+}
+
+// Rough memory of a tree for `snakes` snakes: every node with the moves of every snake and
+// the turns played from it
+fn tree_bytes(nodes: &[Node], snakes: usize) -> usize {
+    let children: usize = nodes.iter().map(|n| n.children.capacity()).sum();
+    nodes.len() * (mem::size_of::<Node>() + snakes * mem::size_of::<Edges>())
+        + children * mem::size_of::<(u32, usize)>()
+}
+
+// Searches the board for the snake at `you_index` until `stop`, or `max_playouts` playouts,
+// playing the turns under `rules`
+pub fn search(
+    board: &Board,
+    you_index: usize,
+    rules: &RulesConfig,
+    stop: Option<Instant>,
+    max_playouts: u32,
+    rng: &mut StdRng,
+) -> MctsResult {
+    let rollout = rollout();
+    let max_nodes = max_nodes();
+    let mut board = board.clone();
+    let mut scratch = Scratch::new(&board);
+    let mut nodes = vec![Node::new(&board, you_index)];
+    let mut playouts = 0;
+    // Per turn of the tree walked this playout, the node and every snake's edge there
+    let mut path: Vec<(usize, Chosen)> = Vec::new();
+    let mut undos: Vec<TurnUndo> = Vec::new();
+    while playouts < max_playouts {
+        if playouts % CLOCK_INTERVAL == 0 && stop.is_some_and(|stop| Instant::now() >= stop) {
+            break;
+        }
+        playouts += 1;
+
+        let mut node = 0;
+        while !nodes[node].over {
+            let chosen: Chosen = (0..board.snakes.len())
+                .filter(|&snake| !nodes[node].edges[snake].is_empty())
+                .map(|snake| (snake, nodes[node].select(snake)))
+                .collect();
+            let moves: Moves = chosen
+                .iter()
+                .map(|&(snake, e)| (snake, nodes[node].edges[snake][e].direction))
+                .collect();
+            undos.push(simulate_turn(&mut board, &moves, STARVE, rules));
+            path.push((node, chosen));
+            match nodes[node].child(code(&moves)) {
+                Some(child) => node = child,
+                None => {
+                    if nodes.len() < max_nodes {
+                        nodes.push(Node::new(&board, you_index));
+                        let child = nodes.len() - 1;
+                        nodes[node].children.push((code(&moves), child));
+                    }
+                    break;
+                }
+            }
+        }
+
+        let rewards = playout(&mut board, you_index, rules, rollout, &mut scratch, rng);
+        for undo in undos.drain(..).rev() {
+            undo_turn(&mut board, undo);
+        }
+        for (node, chosen) in path.drain(..) {
+            let node = &mut nodes[node];
+            node.visits += 1;
+            for (snake, e) in chosen {
+                node.edges[snake][e].visits += 1;
+                node.edges[snake][e].reward += rewards[snake];
+            }
+        }
+    }
+
+    let best = nodes[0].edges[you_index]
+        .iter()
+        .max_by_key(|e| e.visits)
+        .copied();
+    MctsResult {
+        direction: best.map(|e| e.direction),
+        value: best.map_or(0.0, |e| e.reward / e.visits.max(1) as f32),
+        playouts,
+        nodes: nodes.len(),
+        bytes: tree_bytes(&nodes, board.snakes.len()),
+    }
+}
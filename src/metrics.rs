@@ -0,0 +1,173 @@
+// Running averages of how much each evaluation term contributes to the leaf scores, per
+// game and per game phase, served as JSON on `/metrics`. Useful to see which terms actually
+// drive the decisions before touching the weights. Also keeps the largest search tree every
+// personality (see personality.rs) grew, for its share of the memory.
+
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::phase::GamePhase;
+
+pub const TERM_NAMES: [&str; 9] = [
+    "food",
+    "food_density",
+    "length",
+    "enemy",
+    "health",
+    "kills",
+    "space",
+    "traffic",
+    "squeeze",
+];
+
+// The score of one position split up by evaluation term, the score is the sum of the terms
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EvalBreakdown {
+    pub food: i32,
+    pub food_density: i32,
+    pub length: i32,
+    pub enemy: i32,
+    pub health: i32,
+    pub kills: i32,
+    // Only filled in by the rich evaluation
+    pub space: i32,
+    // Only filled in when the server tracks the game's heat, see heat.rs
+    pub traffic: i32,
+    // Only filled in with `use_squeeze`, see squeeze.rs
+    pub squeeze: i32,
+}
+
+impl EvalBreakdown {
+    pub fn terms(&self) -> [i32; 9] {
+        [
+            self.food,
+            self.food_density,
+            self.length,
+            self.enemy,
+            self.health,
+            self.kills,
+            self.space,
+            self.traffic,
+            self.squeeze,
+        ]
+    }
+
+    pub fn total(&self) -> i32 {
+        self.terms().iter().sum()
+    }
+}
+
+// Sum of many breakdowns, kept in i64 since a search adds up millions of leaves
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EvalTotals {
+    pub samples: u64,
+    pub sums: [i64; 9],
+}
+
+impl EvalTotals {
+    pub fn add(&mut self, breakdown: &EvalBreakdown) {
+        self.samples += 1;
+        for (sum, term) in self.sums.iter_mut().zip(breakdown.terms().iter()) {
+            *sum += *term as i64;
+        }
+    }
+
+    pub fn merge(&mut self, other: &EvalTotals) {
+        self.samples += other.samples;
+        for (sum, other) in self.sums.iter_mut().zip(other.sums.iter()) {
+            *sum += other;
+        }
+    }
+
+    fn averages(&self) -> Value {
+        let mut terms = Map::new();
+        terms.insert("samples".to_string(), json!(self.samples));
+        for (name, sum) in TERM_NAMES.iter().zip(self.sums.iter()) {
+            let average = if self.samples == 0 {
+                0.0
+            } else {
+                *sum as f64 / self.samples as f64
+            };
+            terms.insert(name.to_string(), json!(average));
+        }
+        Value::Object(terms)
+    }
+}
+
+// Largest tree of a personality's searches so far
+#[derive(Debug, Default, Clone, Copy)]
+struct TreePeak {
+    nodes: usize,
+    bytes: usize,
+}
+
+#[derive(Default)]
+struct Store {
+    // Games in progress, by game id and phase
+    games: HashMap<String, HashMap<GamePhase, EvalTotals>>,
+    // Everything recorded so far, including finished games
+    phases: HashMap<GamePhase, EvalTotals>,
+    trees: HashMap<&'static str, TreePeak>,
+}
+
+#[derive(Default)]
+pub struct EvalMetrics {
+    store: Mutex<Store>,
+}
+
+impl EvalMetrics {
+    pub fn record(&self, game_id: &str, phase: GamePhase, totals: &EvalTotals) {
+        let mut store = self.store.lock().unwrap();
+        store
+            .games
+            .entry(game_id.to_string())
+            .or_default()
+            .entry(phase)
+            .or_default()
+            .merge(totals);
+        store.phases.entry(phase).or_default().merge(totals);
+    }
+
+    // A search of `personality` grew a tree of `nodes` nodes taking about `bytes`
+    pub fn record_tree(&self, personality: &'static str, nodes: usize, bytes: usize) {
+        let mut store = self.store.lock().unwrap();
+        let peak = store.trees.entry(personality).or_default();
+        peak.nodes = peak.nodes.max(nodes);
+        peak.bytes = peak.bytes.max(bytes);
+    }
+
+    // Stops reporting a finished game on its own, its numbers stay in the phase totals
+    pub fn end_game(&self, game_id: &str) {
+        self.store.lock().unwrap().games.remove(game_id);
+    }
+
+    pub fn to_json(&self) -> Value {
+        let store = self.store.lock().unwrap();
+        let by_phase = |phases: &HashMap<GamePhase, EvalTotals>| {
+            let mut out = Map::new();
+            for (phase, totals) in phases {
+                out.insert(phase.as_str().to_string(), totals.averages());
+            }
+            Value::Object(out)
+        };
+        let mut games = Map::new();
+        for (id, phases) in &store.games {
+            games.insert(id.clone(), by_phase(phases));
+        }
+        let mut trees = Map::new();
+        for (personality, peak) in &store.trees {
+            trees.insert(
+                personality.to_string(),
+                json!({ "peak_nodes": peak.nodes, "peak_bytes": peak.bytes }),
+            );
+        }
+        json!({
+            "eval_terms": {
+                "phases": by_phase(&store.phases),
+                "games": Value::Object(games),
+            },
+            "search_trees": Value::Object(trees),
+        })
+    }
+}
@@ -0,0 +1,97 @@
+// Time on the wire. In `you.latency` the platform tells us how long our answer to the last
+// turn took to arrive, in milliseconds, and whatever part of that the server didn't spend on
+// the move went to the network. The deadline of a move leaves room for the slowest network
+// time of the game's last NETWORK_WINDOW turns, and never less than NETWORK_MARGIN_MS
+// milliseconds (40 when unset), so a player far from the game engine searches less rather
+// than forfeiting moves. A game whose network time goes over the margin is logged once as
+// SLOW NETWORK.
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::shared_store::{GameMap, SharedStore};
+
+pub const MARGIN_ENV: &str = "NETWORK_MARGIN_MS";
+const DEFAULT_MARGIN_MS: u64 = 40;
+
+// Turns the slowest network time is taken over
+const NETWORK_WINDOW: usize = 5;
+// Most of the timeout the network may take from the search
+const MAX_SHARE: u32 = 50;
+
+#[derive(Serialize, Deserialize, Default)]
+struct GameNetwork {
+    // Turn we last answered and the milliseconds it took us, from request to answer
+    answered: Option<(i32, u64)>,
+    // Network time of the last turns in milliseconds, oldest first
+    recent: VecDeque<u64>,
+    slow: bool,
+}
+
+// Least room left for the network
+pub fn margin_floor() -> Duration {
+    let ms = env::var(MARGIN_ENV)
+        .ok()
+        .and_then(|ms| ms.parse().ok())
+        .unwrap_or(DEFAULT_MARGIN_MS);
+    Duration::from_millis(ms)
+}
+
+// Network times of the games in progress on the server
+pub struct NetworkMonitor {
+    games: GameMap<GameNetwork>,
+    floor: Duration,
+}
+
+impl NetworkMonitor {
+    pub fn new(store: Option<Arc<SharedStore>>) -> NetworkMonitor {
+        NetworkMonitor {
+            games: GameMap::new("network", store),
+            floor: margin_floor(),
+        }
+    }
+
+    // Takes the latency of our last answer into account and returns how much of the
+    // `timeout` to leave for the network this turn
+    pub fn margin(&self, game_id: &str, turn: i32, latency: &str, timeout: Duration) -> Duration {
+        let network = self.games.update(game_id, |game| {
+            let latency: Option<u64> = latency.parse().ok().filter(|&ms| ms > 0);
+            // Only the answer to the turn before is the one the latency is about
+            if let (Some(latency), Some((answered, took))) = (latency, game.answered) {
+                if answered == turn - 1 {
+                    game.recent.push_back(latency.saturating_sub(took));
+                    if game.recent.len() > NETWORK_WINDOW {
+                        game.recent.pop_front();
+                    }
+                }
+            }
+            let network = Duration::from_millis(game.recent.iter().copied().max().unwrap_or(0));
+            if network > self.floor && !game.slow {
+                game.slow = true;
+                info!(
+                    "SLOW NETWORK in game {} at turn {}: {} ms on the wire",
+                    game_id,
+                    turn,
+                    network.as_millis()
+                );
+            }
+            network
+        });
+        network.max(self.floor).min(timeout * MAX_SHARE / 100)
+    }
+
+    // Our answer to `turn` took `took` from request to response
+    pub fn answered(&self, game_id: &str, turn: i32, took: Duration) {
+        self.games.update(game_id, |game| {
+            game.answered = Some((turn, took.as_millis() as u64));
+        });
+    }
+
+    pub fn end_game(&self, game_id: &str) {
+        self.games.remove(game_id);
+    }
+}
@@ -0,0 +1,127 @@
+// Cleans up move requests before they reach the search. Custom and community engines send
+// payloads the official one never would: the same food listed twice, snakes listed twice,
+// bodies that are empty or don't start at the head, cells off the board. The search assumes
+// none of that happens, so it all gets fixed here once per request instead.
+
+use crate::{Battlesnake, Board, Coord, GameState};
+
+// Keeps the first of every repeated item
+fn dedup_by_key<T, K: PartialEq>(items: &mut Vec<T>, key: impl Fn(&T) -> K) -> bool {
+    let before = items.len();
+    let mut seen: Vec<K> = Vec::with_capacity(before);
+    items.retain(|item| {
+        let k = key(item);
+        if seen.contains(&k) {
+            false
+        } else {
+            seen.push(k);
+            true
+        }
+    });
+    items.len() != before
+}
+
+// Makes sure our snake is on the board, the search looks it up there. Returns what was wrong
+// when it wasn't. Test harnesses sometimes send `you` under another id than its board entry,
+// that entry is then us, found by the head; otherwise (a spectator view, a harness that only
+// lists the opponents) `you` is added to the board as it is.
+pub fn place_own_snake(board: &mut Board, you: &mut Battlesnake) -> Option<&'static str> {
+    if board.snakes.iter().any(|s| s.id == you.id) {
+        return None;
+    }
+    if you.body.is_empty() {
+        you.body.push(you.head);
+    }
+    match board
+        .snakes
+        .iter()
+        .find(|s| s.body.first() == you.body.first())
+    {
+        Some(entry) => {
+            you.id = entry.id.clone();
+            Some("own snake listed under another id")
+        }
+        None => {
+            board.snakes.push(you.clone());
+            Some("own snake missing from the board")
+        }
+    }
+}
+
+// Fixes `state` in place and returns what had to be fixed, empty for well formed requests
+pub fn normalize_state(state: &mut GameState) -> Vec<&'static str> {
+    let mut fixes = Vec::new();
+    let GameState { board, you, .. } = state;
+
+    // Nothing on a board without cells can be moved onto it, such a request is answered
+    // with whatever move it gets
+    if board.width <= 0 || board.height == 0 {
+        return vec!["board without cells"];
+    }
+
+    let food_before = board.food.len();
+    let (width, height) = (board.width, board.height as i32);
+    board
+        .food
+        .retain(|f| f.x >= 0 && f.x < width && f.y >= 0 && f.y < height);
+    if board.food.len() != food_before {
+        fixes.push("food off the board");
+    }
+    if dedup_by_key(&mut board.food, |f| *f) {
+        fixes.push("duplicate food");
+    }
+
+    // Hazards may be stacked and may cover food, both are part of the royale rules, only
+    // cells off the board are dropped
+    let hazards_before = board.hazards.len();
+    board
+        .hazards
+        .retain(|h| h.x >= 0 && h.x < width && h.y >= 0 && h.y < height);
+    if board.hazards.len() != hazards_before {
+        fixes.push("hazards off the board");
+    }
+
+    if dedup_by_key(&mut board.snakes, |s| s.id.clone()) {
+        fixes.push("snake listed twice");
+    }
+    fixes.extend(place_own_snake(board, you));
+
+    let on_board = |c: &Coord| c.x >= 0 && c.x < width && c.y >= 0 && c.y < height;
+    for snake in board.snakes.iter_mut() {
+        // Segments off the board take up no cell of it, the search would look them up in
+        // grids of the board's size
+        if !snake.body.iter().all(on_board) {
+            snake.body = snake.body.iter().copied().filter(on_board).collect();
+            fixes.push("body off the board");
+        }
+        if snake.body.is_empty() {
+            snake.head = Coord {
+                x: snake.head.x.clamp(0, width - 1),
+                y: snake.head.y.clamp(0, height - 1),
+            };
+            snake.body.push(snake.head);
+            fixes.push("empty body");
+        }
+        if snake.head != snake.body[0] {
+            snake.head = snake.body[0];
+            fixes.push("head not at the start of the body");
+        }
+        if snake.length != snake.body.len() as i32 {
+            snake.length = snake.body.len() as i32;
+            fixes.push("length doesn't match the body");
+        }
+        if !(0..=100).contains(&snake.health) {
+            snake.health = snake.health.clamp(0, 100);
+            fixes.push("health out of range");
+        }
+    }
+
+    // `you` is the same snake as its board entry after the fixes above
+    if let Some(entry) = board.snakes.iter().find(|s| s.id == you.id) {
+        *you = entry.clone();
+    }
+
+    fixes.sort_unstable();
+    fixes.dedup();
+    fixes
+}
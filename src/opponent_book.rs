@@ -0,0 +1,214 @@
+// Known opponents: how the snakes we keep meeting pick their moves, read off recorded games.
+// Every move an opponent could have made is sorted into a kind of spot by where it leads:
+// next to another snake's head (a possible head-to-head), one step towards the nearest food,
+// or anywhere else. Counting per kind how often such a move was there to pick and how often
+// it was picked tells how much the opponent goes for it. With OPPONENT_BOOK naming the file
+// `starter-snake-rust opponents` writes, the regret layer (see regret.rs) weighs the replies
+// of opponents with enough recorded moves by those preferences instead of picking uniformly.
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::direction::Direction;
+use crate::fixture::load_recorded_game;
+use crate::logic::{distance, is_move_safe, predict_snake_move_towards_food, step};
+use crate::{Board, GameState};
+
+pub const BOOK_ENV: &str = "OPPONENT_BOOK";
+pub const DEFAULT_BOOK: &str = "opponents.json";
+
+// Opponents with fewer recorded moves are treated like unknown ones
+const MIN_MOVES: u32 = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Spot {
+    Contest,
+    Food,
+    Other,
+}
+
+const SPOTS: [Spot; 3] = [Spot::Contest, Spot::Food, Spot::Other];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct OpponentRecord {
+    pub games: u32,
+    pub moves: u32,
+    // Per kind of spot, in the order of `SPOTS`: the moves of that kind there were to pick,
+    // and the ones picked
+    pub available: [u32; 3],
+    pub chosen: [u32; 3],
+}
+
+impl OpponentRecord {
+    // Relative weight of a move into `spot`, about the chance that a move of that kind gets
+    // picked. Kinds with few recorded moves stay close to 1.
+    pub fn preference(&self, spot: Spot) -> f64 {
+        let i = spot as usize;
+        (self.chosen[i] + 1) as f64 / (self.available[i] + 1) as f64
+    }
+}
+
+// Records by snake name, the ids change every game
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct OpponentBook {
+    pub opponents: HashMap<String, OpponentRecord>,
+}
+
+impl OpponentBook {
+    // The record of an opponent we've seen play enough moves to go by
+    pub fn known(&self, name: &str) -> Option<&OpponentRecord> {
+        self.opponents
+            .get(name)
+            .filter(|record| record.moves >= MIN_MOVES)
+    }
+}
+
+// Kind of spot the move of the snake at `index` in `direction` leads to
+pub fn spot(board: &Board, index: usize, direction: Direction) -> Spot {
+    let snake = &board.snakes[index];
+    let target = step(board, &snake.body[0], direction);
+    let contested = board.snakes.iter().enumerate().any(|(i, other)| {
+        i != index && !other.body.is_empty() && distance(board, &other.body[0], &target) == 1
+    });
+    if contested {
+        Spot::Contest
+    } else if predict_snake_move_towards_food(snake, board) == target {
+        Spot::Food
+    } else {
+        Spot::Other
+    }
+}
+
+// Adds the moves of every opponent in a recorded game to the book
+fn record_game(frames: &[GameState], book: &mut OpponentBook) {
+    let first = match frames.first() {
+        Some(first) => first,
+        None => return,
+    };
+    for snake in first.board.snakes.iter().filter(|s| s.id != first.you.id) {
+        book.opponents.entry(snake.name.clone()).or_default().games += 1;
+    }
+    for pair in frames.windows(2) {
+        let (frame, next) = (&pair[0], &pair[1]);
+        if next.turn != frame.turn + 1 {
+            continue;
+        }
+        for (index, snake) in frame.board.snakes.iter().enumerate() {
+            if snake.id == frame.you.id || snake.body.is_empty() {
+                continue;
+            }
+            let played = next
+                .board
+                .snakes
+                .iter()
+                .find(|s| s.id == snake.id)
+                .and_then(|s| Direction::between(&frame.board, &snake.body[0], &s.body[0]));
+            let played = match played {
+                Some(played) => played,
+                None => continue,
+            };
+            let record = book.opponents.entry(snake.name.clone()).or_default();
+            record.moves += 1;
+            // The move played counts even when we'd have called it unsafe
+            for direction in Direction::ALL {
+                if direction != played && !is_move_safe(&frame.board, snake, direction) {
+                    continue;
+                }
+                let i = spot(&frame.board, index, direction) as usize;
+                record.available[i] += 1;
+                if direction == played {
+                    record.chosen[i] += 1;
+                }
+            }
+        }
+    }
+}
+
+pub fn load(path: &Path) -> Result<OpponentBook, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+static BOOK: OnceLock<Option<OpponentBook>> = OnceLock::new();
+
+// The book OPPONENT_BOOK names, read on first use. None when it's unset or can't be read.
+pub fn book() -> Option<&'static OpponentBook> {
+    BOOK.get_or_init(|| {
+        let path = env::var(BOOK_ENV).ok().filter(|path| !path.is_empty())?;
+        match load(Path::new(&path)) {
+            Ok(book) => {
+                let known = book
+                    .opponents
+                    .keys()
+                    .filter(|name| book.known(name).is_some())
+                    .count();
+                info!("Known opponents from {}: {}", path, known);
+                Some(book)
+            }
+            Err(e) => {
+                info!("Not using known opponents: {}", e);
+                None
+            }
+        }
+    })
+    .as_ref()
+}
+
+// Entry point for `starter-snake-rust opponents <recording.jsonl>... [--out opponents.json]`.
+// Builds the book from scratch out of the recordings.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut out = DEFAULT_BOOK.to_string();
+    let mut recordings = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            recordings.push(arg.clone());
+            continue;
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", arg))?;
+        match arg.as_str() {
+            "--out" => out = value.clone(),
+            _ => return Err(format!("unknown option '{}'", arg)),
+        }
+    }
+    if recordings.is_empty() {
+        return Err("usage: opponents <recording.jsonl>... [--out opponents.json]".to_string());
+    }
+
+    let mut book = OpponentBook::default();
+    for path in &recordings {
+        record_game(&load_recorded_game(Path::new(path))?, &mut book);
+    }
+
+    let mut names: Vec<&String> = book.opponents.keys().collect();
+    names.sort_by_key(|name| std::cmp::Reverse(book.opponents[*name].moves));
+    println!(
+        "{:<24} {:>6} {:>6} {:>8} {:>6} {:>6}",
+        "opponent", "games", "moves", "contest", "food", "other"
+    );
+    for name in names {
+        let record = &book.opponents[name];
+        let [contest, food, other] = SPOTS.map(|spot| record.preference(spot));
+        let marker = if book.known(name).is_some() {
+            ""
+        } else {
+            " (too few moves)"
+        };
+        println!(
+            "{:<24} {:>6} {:>6} {:>8.2} {:>6.2} {:>6.2}{}",
+            name, record.games, record.moves, contest, food, other, marker
+        );
+    }
+
+    let json = serde_json::to_string_pretty(&book).map_err(|e| e.to_string())?;
+    fs::write(&out, json).map_err(|e| format!("could not write {}: {}", out, e))?;
+    println!("wrote {}", out);
+    Ok(())
+}
@@ -0,0 +1,64 @@
+// How likely an opponent is to play each of its moves, for the share of its replies that
+// count by their average instead of the worst one (see threat.rs). Snakes mostly go where
+// they keep room to move and, the hungrier they get, towards food. So with
+// `use_opponent_model` a move weighs one, plus the free cells the opponent reaches from
+// where it leads (up to its length), plus its length scaled by its hunger when it's a step
+// towards the nearest food. The average of the replies is weighed by that, a move into a
+// wall the opponent would hardly make counts for little against the one into the open. An
+// opponent that showed a style earlier in the game (see opponent_style.rs) plays the moves
+// it goes for STYLE_BOOST times as likely.
+
+use crate::direction::Direction;
+use crate::logic::{distance, flood_fill_area, is_move_safe, step};
+use crate::opponent_style::{move_kinds, OpponentStyle};
+use crate::workspace::SearchWorkspace;
+use crate::{Board, Coord};
+
+// How much likelier a move of the kind an opponent's style goes for is
+const STYLE_BOOST: i64 = 3;
+
+// Relative chances of the moves of the snake at `index`, of `style`, in the order of
+// Direction::ALL
+pub fn likelihoods(
+    board: &Board,
+    index: usize,
+    style: OpponentStyle,
+    workspace: &mut SearchWorkspace,
+) -> [i64; 4] {
+    let mut weights = [1; 4];
+    let snake = &board.snakes[index];
+    let Some(head) = snake.body.first() else {
+        return weights;
+    };
+    let length = snake.body.len();
+    let hunger = (100 - snake.health).clamp(0, 100) as i64;
+    let nearest_food = |from: &Coord| {
+        board
+            .food
+            .iter()
+            .map(|food| distance(board, food, from))
+            .min()
+    };
+    let food_now = nearest_food(head);
+
+    for (weight, direction) in weights.iter_mut().zip(Direction::ALL) {
+        if !is_move_safe(board, snake, direction) {
+            continue;
+        }
+        let next = step(board, head, direction);
+        *weight += flood_fill_area(board, &next, length, workspace) as i64;
+        if let (Some(now), Some(then)) = (food_now, nearest_food(&next)) {
+            if then < now {
+                *weight += length as i64 * hunger / 100;
+            }
+        }
+    }
+    if style != OpponentStyle::Unknown {
+        for (weight, kind) in weights.iter_mut().zip(move_kinds(board, index)) {
+            if kind & style.lure() != 0 {
+                *weight *= STYLE_BOOST;
+            }
+        }
+    }
+    weights
+}
@@ -0,0 +1,227 @@
+// How each opponent of a game has been playing so far. Every `/move` compares where an
+// opponent's head went with the moves it had the turn before, sorted by what they lead to:
+// closer to food, onto a cell along the wall, or next to another snake's head. A move can be
+// all of these or none. Counting per kind how often such a move was there to pick and how
+// often it was picked, like the opponent book does over recorded games (see
+// opponent_book.rs), tells what the opponent goes for in this game. Once it has made enough
+// moves, an opponent that picks one kind clearly more often than chance is food-greedy, a
+// wall-hugger or a head-hunter, and the opponent model favours its moves of that kind (see
+// opponent_model.rs). The move the model expected is kept as well, so the session log shows
+// how often it was right.
+//
+// The statistics live in the game's session (see session.rs), under the opponent's id.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::direction::Direction;
+use crate::logic::{distance, is_move_safe, step};
+use crate::opponent_model;
+use crate::workspace::with_workspace;
+use crate::{Board, Coord};
+
+// Opponents with fewer observed moves keep the unknown style
+const MIN_MOVES: u32 = 15;
+// ... and so do kinds of moves it had fewer chances to pick
+const MIN_AVAILABLE: u32 = 5;
+// How much more often than chance, in percent, an opponent has to pick a kind of move
+const STYLE_LIFT: u64 = 150;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum OpponentStyle {
+    #[default]
+    Unknown,
+    FoodGreedy,
+    WallHugger,
+    HeadHunter,
+}
+
+impl OpponentStyle {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OpponentStyle::Unknown => "unknown",
+            OpponentStyle::FoodGreedy => "food-greedy",
+            OpponentStyle::WallHugger => "wall-hugger",
+            OpponentStyle::HeadHunter => "head-hunter",
+        }
+    }
+
+    // The kind of move an opponent of this style goes for, see `move_kinds`
+    pub fn lure(self) -> u8 {
+        match self {
+            OpponentStyle::Unknown => 0,
+            OpponentStyle::FoodGreedy => FOOD,
+            OpponentStyle::WallHugger => WALL,
+            OpponentStyle::HeadHunter => HEAD,
+        }
+    }
+}
+
+// Kinds of moves, as bits
+pub const SAFE: u8 = 1;
+pub const FOOD: u8 = 2;
+pub const WALL: u8 = 4;
+pub const HEAD: u8 = 8;
+const KINDS: [(u8, OpponentStyle); 3] = [
+    (FOOD, OpponentStyle::FoodGreedy),
+    (WALL, OpponentStyle::WallHugger),
+    (HEAD, OpponentStyle::HeadHunter),
+];
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct OpponentStats {
+    pub name: String,
+    // The last turn seen, with where the head was and the kinds of its moves from there, in
+    // the order of Direction::ALL, and the move the opponent model expected
+    turn: i32,
+    head: Option<Coord>,
+    kinds: [u8; 4],
+    predicted: Option<Direction>,
+    pub moves: u32,
+    pub predicted_right: u32,
+    // Safe moves there were to pick, over all observed moves
+    options: u32,
+    // Per kind of move, in the order of KINDS: the moves of that kind there were to pick,
+    // and the ones picked
+    available: [u32; 3],
+    chosen: [u32; 3],
+}
+
+impl OpponentStats {
+    pub fn style(&self) -> OpponentStyle {
+        if self.moves < MIN_MOVES || self.options == 0 {
+            return OpponentStyle::Unknown;
+        }
+        // A kind picked as often as chance has chosen / available == moves / options
+        let lift = |i: usize| {
+            (self.chosen[i] as u64 * self.options as u64 * 100)
+                / (self.available[i] as u64 * self.moves as u64).max(1)
+        };
+        (0..KINDS.len())
+            .filter(|&i| self.available[i] >= MIN_AVAILABLE)
+            .map(|i| (lift(i), KINDS[i].1))
+            .filter(|&(lift, _)| lift >= STYLE_LIFT)
+            .max_by_key(|&(lift, _)| lift)
+            .map_or(OpponentStyle::Unknown, |(_, style)| style)
+    }
+}
+
+// The kinds of the moves of the snake at `index`, in the order of Direction::ALL
+pub fn move_kinds(board: &Board, index: usize) -> [u8; 4] {
+    let mut kinds = [0; 4];
+    let snake = &board.snakes[index];
+    let Some(head) = snake.body.first() else {
+        return kinds;
+    };
+    let nearest_food = |from: &Coord| {
+        board
+            .food
+            .iter()
+            .map(|food| distance(board, food, from))
+            .min()
+    };
+    let food_now = nearest_food(head);
+    let height = board.height as i32;
+    for (kind, direction) in kinds.iter_mut().zip(Direction::ALL) {
+        if !is_move_safe(board, snake, direction) {
+            continue;
+        }
+        let next = step(board, head, direction);
+        *kind = SAFE;
+        if let (Some(now), Some(then)) = (food_now, nearest_food(&next)) {
+            if then < now {
+                *kind |= FOOD;
+            }
+        }
+        let edge = next.x == 0 || next.y == 0 || next.x == board.width - 1 || next.y == height - 1;
+        if edge && !board.wrapped {
+            *kind |= WALL;
+        }
+        let near_head = board.snakes.iter().enumerate().any(|(i, other)| {
+            i != index
+                && other
+                    .body
+                    .first()
+                    .is_some_and(|other_head| distance(board, other_head, &next) == 1)
+        });
+        if near_head {
+            *kind |= HEAD;
+        }
+    }
+    kinds
+}
+
+// Adds `turn` of `board` to the statistics of every opponent of the snake `you_id`. Turns
+// already seen, from another of our snakes in the game, are left alone.
+pub fn observe(stats: &mut HashMap<String, OpponentStats>, board: &Board, turn: i32, you_id: &str) {
+    let styles = styles(stats, board, you_id);
+    for (index, snake) in board.snakes.iter().enumerate() {
+        if snake.id == you_id {
+            continue;
+        }
+        let Some(head) = snake.body.first() else {
+            continue;
+        };
+        let entry = stats.entry(snake.id.clone()).or_default();
+        if entry.head.is_some() && turn <= entry.turn {
+            continue;
+        }
+        let taken = entry
+            .head
+            .filter(|_| turn == entry.turn + 1)
+            .and_then(|last| {
+                Direction::ALL
+                    .iter()
+                    .copied()
+                    .find(|&d| step(board, &last, d) == *head)
+            });
+        if let Some(taken) = taken {
+            entry.moves += 1;
+            entry.predicted_right += (entry.predicted == Some(taken)) as u32;
+            let chosen = entry.kinds[Direction::ALL.iter().position(|&d| d == taken).unwrap()];
+            for kinds in entry.kinds {
+                entry.options += (kinds & SAFE != 0) as u32;
+                for (i, &(kind, _)) in KINDS.iter().enumerate() {
+                    entry.available[i] += (kinds & kind != 0) as u32;
+                }
+            }
+            for (i, &(kind, _)) in KINDS.iter().enumerate() {
+                entry.chosen[i] += (chosen & kind != 0) as u32;
+            }
+        }
+
+        let likelihoods = with_workspace(|workspace| {
+            opponent_model::likelihoods(board, index, styles[index], workspace)
+        });
+        let kinds = move_kinds(board, index);
+        entry.name.clone_from(&snake.name);
+        entry.turn = turn;
+        entry.head = Some(*head);
+        entry.kinds = kinds;
+        entry.predicted = Direction::ALL
+            .iter()
+            .copied()
+            .zip(likelihoods)
+            .zip(kinds)
+            .filter(|&(_, kind)| kind & SAFE != 0)
+            .max_by_key(|&((_, likelihood), _)| likelihood)
+            .map(|((direction, _), _)| direction);
+    }
+}
+
+// The style of every snake of `board` by index, unknown for the snake `you_id` and for
+// opponents without enough moves yet
+pub fn styles(
+    stats: &HashMap<String, OpponentStats>,
+    board: &Board,
+    you_id: &str,
+) -> Vec<OpponentStyle> {
+    board
+        .snakes
+        .iter()
+        .map(|snake| match stats.get(&snake.id) {
+            Some(stats) if snake.id != you_id => stats.style(),
+            _ => OpponentStyle::Unknown,
+        })
+        .collect()
+}
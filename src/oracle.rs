@@ -0,0 +1,305 @@
+// Brute force check of the search. The oracle plays out every combination of moves of every
+// snake for two turns under the official rules (rules.rs), with no pruning, no evaluation and
+// no shortcuts, and calls a move lost when some replies of the opponents eliminate us in the
+// first turn, or leave us where every move of ours is eliminated in the second. Over a
+// corpus of random positions from baseline games the engine must never play a move the
+// oracle proves lost while another move survives. The oracle's own moves make it one of the
+// baseline opponents of the arena as well.
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::fs;
+use std::path::Path;
+
+use crate::arena::{ArenaConfig, ArenaGame};
+use crate::batch::search_batch;
+use crate::direction::Direction;
+use crate::fixture::Fixture;
+use crate::logic::SearchConfig;
+use crate::rules::{advance_turn, RulesConfig};
+use crate::strategy::{baseline, Strategy, BASELINES};
+use crate::{Board, GameState};
+
+pub const TURNS: usize = 2;
+// Name of the oracle among the baseline opponents
+pub const ORACLE: &str = "oracle-2ply";
+
+// Nothing spawns, so the outcome only depends on the moves
+pub(crate) fn rules() -> RulesConfig {
+    RulesConfig {
+        food_spawn_chance: 0,
+        minimum_food: 0,
+        ..RulesConfig::default()
+    }
+}
+
+// Every combination of moves of the snakes at `others`, as the moves of the whole board with
+// ours at `you_index`
+pub(crate) fn joint_moves(snakes: usize, you_index: usize, ours: Direction) -> Vec<Vec<Direction>> {
+    let others: Vec<usize> = (0..snakes).filter(|&i| i != you_index).collect();
+    (0..Direction::ALL.len().pow(others.len() as u32))
+        .map(|mut code| {
+            let mut moves = vec![ours; snakes];
+            for &i in &others {
+                moves[i] = Direction::ALL[code % Direction::ALL.len()];
+                code /= Direction::ALL.len();
+            }
+            moves
+        })
+        .collect()
+}
+
+// Whether some replies to `ours` eliminate the snake `you_id` within `turns` turns, whatever
+// it plays after the first
+fn loses(board: &Board, you_id: &str, ours: Direction, turns: usize, rng: &mut StdRng) -> bool {
+    let (survived, total) = survived_lines(board, you_id, ours, turns, rng, true);
+    survived < total
+}
+
+// The replies to `ours` that don't eliminate us within `turns` turns and all the replies. A
+// `quick` count stops at the first reply that does.
+fn survived_lines(
+    board: &Board,
+    you_id: &str,
+    ours: Direction,
+    turns: usize,
+    rng: &mut StdRng,
+    quick: bool,
+) -> (u32, u32) {
+    let you_index = match board.snakes.iter().position(|s| s.id == you_id) {
+        Some(index) => index,
+        None => return (0, 1),
+    };
+    let rules = rules();
+    let lines = joint_moves(board.snakes.len(), you_index, ours);
+    let total = lines.len() as u32;
+    let mut survived = 0;
+    for moves in lines {
+        let mut after = board.clone();
+        advance_turn(&mut after, &moves, &rules, rng);
+        let alive = after.snakes.iter().any(|s| s.id == you_id);
+        let lost = !alive
+            || (turns > 1
+                && Direction::ALL
+                    .iter()
+                    .all(|&next| loses(&after, you_id, next, turns - 1, rng)));
+        if !lost {
+            survived += 1;
+        } else if quick {
+            return (0, total);
+        }
+    }
+    (survived, total)
+}
+
+// Per move of the snake `you_id`, whether the oracle proves it lost
+pub fn solve(board: &Board, you_id: &str) -> Vec<(Direction, bool)> {
+    let mut rng = StdRng::seed_from_u64(0);
+    Direction::ALL
+        .iter()
+        .map(|&direction| (direction, loses(board, you_id, direction, TURNS, &mut rng)))
+        .collect()
+}
+
+// The move that survives the most replies, lost moves only when there's nothing else
+pub fn best_move(board: &Board, you_id: &str) -> Direction {
+    let mut rng = StdRng::seed_from_u64(0);
+    Direction::ALL
+        .iter()
+        .map(|&direction| {
+            let (survived, total) =
+                survived_lines(board, you_id, direction, TURNS, &mut rng, false);
+            ((survived == total, survived), direction)
+        })
+        .fold(
+            None,
+            |best: Option<((bool, u32), Direction)>, candidate| match best {
+                Some(best) if best.0 >= candidate.0 => Some(best),
+                _ => Some(candidate),
+            },
+        )
+        .map_or(Direction::Up, |(_, direction)| direction)
+}
+
+struct Options {
+    positions: usize,
+    seed: u64,
+    fixtures: Option<String>,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        positions: 300,
+        seed: 1,
+        fixtures: None,
+    };
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", flag))?;
+        let invalid = |_| format!("invalid value '{}' for {}", value, flag);
+        match flag.as_str() {
+            "--positions" => options.positions = value.parse().map_err(invalid)?,
+            "--seed" => options.seed = value.parse().map_err(invalid)?,
+            "--fixtures" => options.fixtures = Some(value.clone()),
+            _ => return Err(format!("unknown option '{}'", flag)),
+        }
+    }
+    Ok(options)
+}
+
+// Positions from games of two to four baseline bots, a few turns apart, each seen by one of
+// the snakes on the board
+pub(crate) fn corpus(count: usize, rng: &mut StdRng) -> Vec<GameState> {
+    let config = ArenaConfig::default();
+    let mut positions = Vec::new();
+    let mut game = 0;
+    while positions.len() < count {
+        let players = rng.gen_range(2..=4);
+        let mut strategies: Vec<Box<dyn Strategy>> = (0..players)
+            .map(|i| {
+                // The oracle itself is too slow with four snakes to play whole games
+                let name = BASELINES
+                    .iter()
+                    .filter(|&&name| name != ORACLE)
+                    .collect::<Vec<_>>()
+                    .choose(rng)
+                    .copied()
+                    .unwrap();
+                baseline(name, game * 16 + i as u64).unwrap()
+            })
+            .collect();
+        let mut arena = ArenaGame::new(&format!("oracle-{}", game), players, &config, rng);
+        game += 1;
+        while !arena.is_over(&config) && positions.len() < count {
+            if rng.gen_bool(0.2) {
+                let player = arena.player(rng.gen_range(0..arena.board.snakes.len()));
+                positions.extend(arena.state(player));
+            }
+            let moves: Vec<Direction> = arena
+                .board
+                .snakes
+                .iter()
+                .enumerate()
+                .map(|(i, snake)| {
+                    strategies[arena.player(i)].choose_move(
+                        &arena.game,
+                        arena.turn,
+                        &arena.board,
+                        snake,
+                    )
+                })
+                .collect();
+            arena.advance(&moves, &config, rng);
+        }
+    }
+    positions
+}
+
+// A position where the engine played a move the oracle proves lost
+struct Violation {
+    state: GameState,
+    played: Option<Direction>,
+    survivors: Vec<Direction>,
+}
+
+fn write_fixtures(dir: &Path, violations: &[Violation]) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("could not create {}: {}", dir.display(), e))?;
+    for violation in violations {
+        let state = &violation.state;
+        let name = format!("{}-turn-{}-{}", state.game.id, state.turn, state.you.id);
+        let fixture = Fixture::new(
+            &name,
+            state.clone(),
+            violation.survivors.iter().map(|d| d.to_string()).collect(),
+            format!(
+                "the oracle proves every other move lost within {} turns",
+                TURNS
+            ),
+        );
+        let path = dir.join(format!("{}.json", name));
+        let text = serde_json::to_string_pretty(&fixture).map_err(|e| e.to_string())?;
+        fs::write(&path, text + "\n")
+            .map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+// Entry point for `starter-snake-rust oracle [--positions N] [--seed S] [--fixtures DIR]`:
+// searches N random positions with the engine, as deep as the oracle looks, and fails when
+// it plays a move the oracle proves lost in any of them
+pub fn run(args: &[String]) -> Result<(), String> {
+    let options = parse_options(args)?;
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let positions = corpus(options.positions, &mut rng);
+
+    let mut violations = Vec::new();
+    let mut hopeless = 0;
+    // One search depth per number of snakes, two turns of all of them
+    for snakes in 2..=4 {
+        let states: Vec<GameState> = positions
+            .iter()
+            .filter(|state| state.board.snakes.len() == snakes)
+            .cloned()
+            .collect();
+        let answers = search_batch(&states, &SearchConfig::new((TURNS * snakes) as i32));
+        for (state, answer) in states.into_iter().zip(answers) {
+            let verdicts = solve(&state.board, &state.you.id);
+            let survivors: Vec<Direction> = verdicts
+                .iter()
+                .filter(|&&(_, lost)| !lost)
+                .map(|&(direction, _)| direction)
+                .collect();
+            if survivors.is_empty() {
+                hopeless += 1;
+                continue;
+            }
+            let played = answer.and_then(|a| a.direction);
+            if !played.is_some_and(|played| survivors.contains(&played)) {
+                violations.push(Violation {
+                    state,
+                    played,
+                    survivors,
+                });
+            }
+        }
+    }
+
+    for violation in &violations {
+        let state = &violation.state;
+        println!(
+            "  {} turn {} ({}): engine plays {}, only {} survives {} turns",
+            state.game.id,
+            state.turn,
+            state.you.id,
+            violation.played.map_or("none", Direction::as_str),
+            violation
+                .survivors
+                .iter()
+                .map(|d| d.as_str())
+                .collect::<Vec<_>>()
+                .join(" or "),
+            TURNS
+        );
+    }
+    println!(
+        "{} positions, {} lost whatever we play, {} where the engine plays a lost move",
+        positions.len(),
+        hopeless,
+        violations.len()
+    );
+    if let Some(dir) = &options.fixtures {
+        write_fixtures(Path::new(dir), &violations)?;
+        println!("wrote {} fixtures to {}", violations.len(), dir);
+    }
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} positions disagree with the oracle",
+            violations.len()
+        ))
+    }
+}
@@ -0,0 +1,128 @@
+// Root splitting. With `use_root_parallel` every move of ours at the root gets its own
+// search, side by side on the threads of rayon's pool (one per core, RAYON_NUM_THREADS to
+// change that), each on a copy of the board and with the workspace of the thread it runs on.
+// A branch can't narrow the window with the scores of the others like the sequential search
+// does, so it searches more nodes in all, but the branches finish in about the time of the
+// slowest one. The results are put together as the sequential search would have: the best
+// score, the first of equal ones unless another traps more opponents.
+//
+// The searches of traced turns and of searches already running on the pool stay sequential.
+
+use rayon::prelude::*;
+
+use crate::direction::Direction;
+use crate::features::{self, Feature};
+use crate::logic::{is_move_legal, is_move_safe, minimax, move_order};
+use crate::metrics::EvalTotals;
+use crate::workspace::{with_workspace, CutoffStats, RootCandidate, SearchWorkspace};
+use crate::Board;
+
+// What the search of one root move leaves behind
+struct Branch {
+    direction: Direction,
+    score: i32,
+    aborted: bool,
+    candidate: Option<RootCandidate>,
+    pv: Vec<(usize, Direction)>,
+    pv_kills: u32,
+    eval_totals: EvalTotals,
+    cutoff_stats: CutoffStats,
+    refutations: Vec<(usize, i32, Direction)>,
+}
+
+// Searches `depth` plies from the root with our moves split between threads, None when it
+// has to be done sequentially. `workspace` is set up for the search and gets its results.
+pub fn search_root(
+    board: &mut Board,
+    depth: i32,
+    you_index: usize,
+    workspace: &mut SearchWorkspace,
+) -> Option<(i32, Option<Direction>)> {
+    if workspace.trace.is_some() || rayon::current_thread_index().is_some() {
+        return None;
+    }
+    let joint = features::enabled(Feature::JointMoves);
+    let order = move_order(board, depth, you_index, you_index, workspace);
+    let you = &board.snakes[you_index];
+    let moves: Vec<Direction> = order
+        .iter()
+        .copied()
+        .filter(|&direction| {
+            if joint {
+                is_move_legal(board, you, direction)
+            } else {
+                is_move_safe(board, you, direction)
+            }
+        })
+        .collect();
+    if moves.len() < 2 {
+        return None;
+    }
+
+    let board = &*board;
+    let parent = &*workspace;
+    let branches: Vec<Branch> = moves
+        .par_iter()
+        .map(|&direction| {
+            with_workspace(|branch| {
+                branch.fork(board, parent, direction);
+                let mut board = board.clone();
+                let (score, _) = minimax(
+                    &mut board,
+                    depth,
+                    i32::MIN,
+                    i32::MAX,
+                    you_index,
+                    you_index,
+                    branch,
+                );
+                Branch {
+                    direction,
+                    score,
+                    aborted: branch.aborted,
+                    candidate: branch.root_candidates.first().copied(),
+                    pv: branch.principal_variation().to_vec(),
+                    pv_kills: branch.pv_kills(depth),
+                    eval_totals: branch.take_eval_totals(),
+                    cutoff_stats: branch.take_cutoff_stats(),
+                    refutations: branch.refutations.clone(),
+                }
+            })
+        })
+        .collect();
+
+    for branch in &branches {
+        workspace.eval_totals.merge(&branch.eval_totals);
+        workspace.cutoff_stats.merge(&branch.cutoff_stats);
+        for &(snake, ply, direction) in &branch.refutations {
+            if workspace.refutation(snake, ply).is_none() {
+                workspace.record_refutation(snake, ply, direction);
+            }
+        }
+    }
+    if branches.iter().any(|branch| branch.aborted) {
+        workspace.aborted = true;
+        return Some((0, None));
+    }
+
+    let mut best: Option<&Branch> = None;
+    for branch in &branches {
+        let better = match best {
+            None => branch.score > i32::MIN,
+            Some(best) => {
+                branch.score > best.score
+                    || (branch.score == best.score && branch.pv_kills > best.pv_kills)
+            }
+        };
+        if better {
+            best = Some(branch);
+        }
+    }
+    let candidates = branches.iter().filter_map(|b| b.candidate).collect();
+    let (pv, pv_kills) = best.map_or((Vec::new(), 0), |b| (b.pv.clone(), b.pv_kills));
+    workspace.join(candidates, pv, pv_kills);
+    Some(match best {
+        Some(best) => (best.score, Some(best.direction)),
+        None => (i32::MIN, None),
+    })
+}
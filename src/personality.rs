@@ -0,0 +1,70 @@
+// Personalities are the ways one server process plays a move: minimax under the preset of the
+// game (the ladder one, the duel one) or the experimental Monte Carlo tree search. They share
+// the search threads, so every personality has a quota of them, and a personality at its
+// quota waits while the others keep going: a flood of four snake games searched by MCTS can't
+// take the threads the ladder games need. MCTS gets half the threads unless
+// PERSONALITY_THREADS says otherwise, e.g. `mcts:1,duel-aggressive:2`; personalities not
+// listed may use all of them. The memory of a search is its own as well: minimax works in its
+// thread's workspace (see workspace.rs) and every MCTS search grows its own tree, capped at
+// MCTS_MAX_NODES nodes, so a personality holds at most its quota times that.
+
+use log::info;
+use std::env;
+use std::sync::OnceLock;
+
+use crate::features::{self, Feature};
+use crate::mcts;
+use crate::preset::Preset;
+use crate::Board;
+
+pub const THREADS_ENV: &str = "PERSONALITY_THREADS";
+
+// Share of the threads, in percent, MCTS gets when PERSONALITY_THREADS doesn't name it
+const MCTS_SHARE: usize = 50;
+
+// The personality that searches `board` in a game played with `preset`
+pub fn of(board: &Board, preset: &'static Preset) -> &'static str {
+    if features::enabled(Feature::Mcts) && mcts::applies(board) {
+        mcts::PERSONALITY
+    } else {
+        preset.name
+    }
+}
+
+// PERSONALITY_THREADS as (personality, threads), entries that don't parse are left out
+fn configured() -> &'static [(String, usize)] {
+    static CONFIGURED: OnceLock<Vec<(String, usize)>> = OnceLock::new();
+    CONFIGURED.get_or_init(|| {
+        let mut quotas = Vec::new();
+        for entry in env::var(THREADS_ENV).unwrap_or_default().split(',') {
+            let entry = entry.trim();
+            if entry.is_empty() {
+                continue;
+            }
+            match entry.split_once(':').map(|(name, n)| (name, n.parse())) {
+                Some((name, Ok(threads))) => quotas.push((name.to_string(), threads)),
+                _ => info!(
+                    "Ignoring '{}' in {}, expected name:threads",
+                    entry, THREADS_ENV
+                ),
+            }
+        }
+        quotas
+    })
+}
+
+// How many of `threads` search threads `personality` may use at the same time
+pub fn thread_quota(personality: &str, threads: usize) -> usize {
+    let quota = configured()
+        .iter()
+        .find(|(name, _)| name == personality)
+        .map(|&(_, quota)| quota)
+        .unwrap_or_else(|| {
+            if personality == mcts::PERSONALITY {
+                threads * MCTS_SHARE / 100
+            } else {
+                threads
+            }
+        });
+    quota.clamp(1, threads.max(1))
+}
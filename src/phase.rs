@@ -0,0 +1,99 @@
+// Derives which phase of the game a position is in, so recorded positions can be grouped
+// and tools can look at e.g. only endgame positions.
+
+use serde::Serialize;
+use std::fmt;
+use std::path::Path;
+
+use crate::fixture::load_recorded_game;
+use crate::{Board, GameState};
+
+// Turns before this are the opening
+const OPENING_TURNS: i32 = 15;
+// A living snake below this health is out looking for food
+const FOOD_SCRAMBLE_HEALTH: i32 = 30;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum GamePhase {
+    Opening,
+    FoodScramble,
+    Midgame,
+    DuelEndgame,
+    RoyaleShrink,
+}
+
+impl GamePhase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GamePhase::Opening => "opening",
+            GamePhase::FoodScramble => "food-scramble",
+            GamePhase::Midgame => "midgame",
+            GamePhase::DuelEndgame => "duel-endgame",
+            GamePhase::RoyaleShrink => "royale-shrink",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<GamePhase> {
+        [
+            GamePhase::Opening,
+            GamePhase::FoodScramble,
+            GamePhase::Midgame,
+            GamePhase::DuelEndgame,
+            GamePhase::RoyaleShrink,
+        ]
+        .iter()
+        .copied()
+        .find(|phase| phase.as_str() == s)
+    }
+}
+
+impl fmt::Display for GamePhase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// The checks go from most to least specific: once hazards are on the board everything is
+// about the shrinking ring, and a 1v1 is an endgame regardless of health.
+pub fn classify_phase(state: &GameState) -> GamePhase {
+    phase_of(state.turn, &state.board)
+}
+
+pub fn phase_of(turn: i32, board: &Board) -> GamePhase {
+    let living: Vec<_> = board.snakes.iter().filter(|s| !s.body.is_empty()).collect();
+
+    if turn < OPENING_TURNS {
+        GamePhase::Opening
+    } else if !board.hazards.is_empty() {
+        GamePhase::RoyaleShrink
+    } else if living.len() == 2 {
+        GamePhase::DuelEndgame
+    } else if living.iter().any(|s| s.health < FOOD_SCRAMBLE_HEALTH) {
+        GamePhase::FoodScramble
+    } else {
+        GamePhase::Midgame
+    }
+}
+
+// Entry point for `starter-snake-rust phases <recording.jsonl> [phase]`, lists the phase of
+// every recorded turn, or only the turns in `phase`
+pub fn run(args: &[String]) -> Result<(), String> {
+    let path = args
+        .first()
+        .ok_or_else(|| "usage: phases <recording.jsonl> [phase]".to_string())?;
+    let filter = match args.get(1) {
+        Some(name) => {
+            Some(GamePhase::parse(name).ok_or_else(|| format!("unknown phase '{}'", name))?)
+        }
+        None => None,
+    };
+
+    for state in load_recorded_game(Path::new(path))? {
+        let phase = classify_phase(&state);
+        if filter.is_none() || filter == Some(phase) {
+            println!("{}\t{}", state.turn, phase);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,146 @@
+// Pondering. With `use_pondering` the server doesn't sit idle between our answer and the next
+// `/move` of the game: a search thread searches the position it expects next, the one after
+// our move and the opponents' replies of the principal variation, deeper and deeper like the
+// server would. The next request of the game stops the worker. When its board is exactly the
+// predicted one, the iterative deepening takes over the deepest pondered search with its
+// refutations and move history and goes on from the depth after it. Otherwise the pondered
+// work is thrown away and the turn is searched from scratch.
+//
+// A worker also stops at the game's `/end`, when another position is pondered for the same
+// snake, and a game timeout after it started when no request comes. Pondering goes through
+// the search scheduler (see scheduler.rs) as background work of the game's personality: it
+// only gets a search thread no search is waiting for, and a search of any game that finds
+// no free thread stops it.
+
+use log::info;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::direction::Direction;
+use crate::logic::{self, SearchSetup};
+use crate::scheduler::SearchScheduler;
+use crate::workspace::SearchHandoff;
+use crate::{Board, Coord};
+
+// The deepest search a worker finished
+pub struct Pondered {
+    pub result: (i32, Option<Direction>),
+    pub handoff: SearchHandoff,
+}
+
+struct Ponder {
+    board: Board,
+    turn: i32,
+    cancel: Arc<AtomicBool>,
+    found: Arc<Mutex<Option<Pondered>>>,
+}
+
+impl Ponder {
+    fn stop(&self) -> Option<Pondered> {
+        self.cancel.store(true, Ordering::Relaxed);
+        self.found.lock().unwrap().take()
+    }
+}
+
+// The workers of the games in progress, by game and our snake's id
+pub struct Ponderer {
+    games: Mutex<HashMap<(String, String), Ponder>>,
+    scheduler: SearchScheduler,
+}
+
+impl Ponderer {
+    pub fn new(scheduler: SearchScheduler) -> Ponderer {
+        Ponderer {
+            games: Mutex::default(),
+            scheduler,
+        }
+    }
+
+    // Stops pondering for our snake `you_id` in game `game_id`, whose request for `turn` on
+    // `board` came in, and hands the search of that turn what it can go on from. The next
+    // position is pondered for `personality`.
+    pub fn turn(
+        self: &Arc<Ponderer>,
+        game_id: &str,
+        you_id: &str,
+        turn: i32,
+        board: &Board,
+        personality: &'static str,
+    ) -> PonderTurn {
+        let key = (game_id.to_string(), you_id.to_string());
+        let ponder = self.games.lock().unwrap().remove(&key);
+        let found = ponder.and_then(|ponder| {
+            let found = ponder.stop();
+            let predicted = ponder.turn == turn && same_position(&ponder.board, board);
+            if !predicted {
+                info!(
+                    "PONDER in game {} at turn {}: the position wasn't the predicted one",
+                    game_id, turn
+                );
+            }
+            found.filter(|_| predicted)
+        });
+        PonderTurn {
+            ponderer: Arc::clone(self),
+            key,
+            personality,
+            found,
+        }
+    }
+
+    pub fn end_game(&self, game_id: &str, you_id: &str) {
+        let key = (game_id.to_string(), you_id.to_string());
+        if let Some(ponder) = self.games.lock().unwrap().remove(&key) {
+            ponder.stop();
+        }
+    }
+}
+
+// The pondering of one of our snakes in one game, for the turn being searched
+pub struct PonderTurn {
+    ponderer: Arc<Ponderer>,
+    key: (String, String),
+    personality: &'static str,
+    // What was pondered for the position of this turn, when it's the one that was predicted
+    pub found: Option<Pondered>,
+}
+
+impl PonderTurn {
+    // Starts pondering `board`, the position predicted for the turn of `setup`, up to
+    // `max_depth`
+    pub(crate) fn start(self, board: Board, max_depth: i32, setup: SearchSetup) {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let found = Arc::new(Mutex::new(None));
+        let ponder = Ponder {
+            board: board.clone(),
+            turn: setup.turn,
+            cancel: Arc::clone(&cancel),
+            found: Arc::clone(&found),
+        };
+        if let Some(before) = self.ponderer.games.lock().unwrap().insert(self.key, ponder) {
+            before.stop();
+        }
+        let scheduler = &self.ponderer.scheduler;
+        scheduler.execute_background(self.personality, Arc::clone(&cancel), move || {
+            logic::ponder(&board, max_depth, &setup, &cancel, |result, handoff| {
+                *found.lock().unwrap() = Some(Pondered { result, handoff });
+            });
+        });
+    }
+}
+
+// Whether the request's board is the one predicted: the same snakes in the same order, with
+// the same bodies and health, and the same food and hazards
+fn same_position(predicted: &Board, board: &Board) -> bool {
+    let same_cells =
+        |a: &[Coord], b: &[Coord]| a.len() == b.len() && a.iter().all(|cell| b.contains(cell));
+    predicted.snakes.len() == board.snakes.len()
+        && predicted
+            .snakes
+            .iter()
+            .zip(&board.snakes)
+            .all(|(a, b)| a.id == b.id && a.body == b.body && a.health == b.health)
+        && same_cells(&predicted.food, &board.food)
+        && same_cells(&predicted.hazards, &board.hazards)
+}
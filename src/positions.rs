@@ -0,0 +1,106 @@
+// The regression positions in tests/positions, replayed by tests/positions.rs: every fixture
+// (see fixture.rs) is a `/move` request with the moves that are fine there, from a recorded
+// game or the scenario and oracle generators. Each one goes through `get_move` the way the server answers
+// it, with the game's preset and own timeout, so the test catches an evaluation change that
+// brings a known blunder back. SNAKE_PRESET, SNAKE_EVAL_WEIGHTS and SNAKE_FEATURES apply like
+// they do to the server.
+
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::direction::Direction;
+use crate::duel;
+use crate::features;
+use crate::fixture::DEFAULT_FIXTURE_DIR;
+use crate::logic::{self, TurnContext};
+use crate::metrics::EvalMetrics;
+use crate::preset;
+use crate::GameState;
+
+// The parts of a fixture the replay needs
+#[derive(Deserialize)]
+struct Saved {
+    name: String,
+    request: GameState,
+    acceptable_moves: Vec<String>,
+    notes: String,
+}
+
+pub struct Position {
+    pub name: String,
+    pub notes: String,
+    pub acceptable: Vec<Direction>,
+    request: GameState,
+}
+
+// The fixtures in `dir` (tests/positions when None), by file name
+pub fn load(dir: Option<&Path>) -> Result<Vec<Position>, String> {
+    preset::select_from_env()?;
+    features::select_from_env()?;
+    let dir = dir.unwrap_or(Path::new(DEFAULT_FIXTURE_DIR));
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("could not read {}: {}", dir.display(), e))?;
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+    paths.iter().map(|path| load_one(path)).collect()
+}
+
+fn load_one(path: &Path) -> Result<Position, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    let saved: Saved = serde_json::from_str(&text)
+        .map_err(|e| format!("{}: invalid fixture: {}", path.display(), e))?;
+    // A fresh fixture from `fixture` has none, it isn't ready to be committed
+    if saved.acceptable_moves.is_empty() {
+        return Err(format!("{}: no acceptable_moves", path.display()));
+    }
+    let acceptable = saved
+        .acceptable_moves
+        .iter()
+        .map(|m| m.parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("{}: invalid move in acceptable_moves", path.display()))?;
+    Ok(Position {
+        name: saved.name,
+        notes: saved.notes,
+        acceptable,
+        request: saved.request,
+    })
+}
+
+impl Position {
+    // The move the engine answers the request with, None when the answer isn't a move
+    pub fn play(&self) -> Option<Direction> {
+        let state = &self.request;
+        let (game, board, you) = (&state.game, &state.board, &state.you);
+        logic::start(game, &state.turn, board, you);
+        // Positions are taken as the start of their game, so two snakes make a duel
+        let preset = preset::for_game(duel::is_duel(game, board));
+        let context = TurnContext {
+            deadline: Instant::now() + Duration::from_millis(game.timeout as u64),
+            stalled: false,
+            heat: Vec::new(),
+            styles: Vec::new(),
+            preset,
+            famine: false,
+            ponder: None,
+            progress: None,
+        };
+        let metrics = EvalMetrics::default();
+        let response = logic::get_move_at_depth(
+            game,
+            &state.turn,
+            board,
+            you,
+            preset.search.depth,
+            context,
+            &metrics,
+        );
+        response["move"].as_str().and_then(|m| m.parse().ok())
+    }
+}
@@ -0,0 +1,458 @@
+// Post-mortem of recorded games: works out why we lost from the last frames of a recording
+// and keeps per bot version statistics in a results database. For lost games it also finds
+// the pivotal turn, where a search of every position shows our survival chances dropping the
+// most, and searches that position again a few plies deeper with the rich evaluation to tell
+// what we should have played instead.
+//
+// Recordings only contain the turns where we were still alive, optionally followed by the
+// `/end` request. The frame where our snake disappears from the board (if any) is the death
+// frame, the one before it is the last position we could still act on.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use crate::batch::search_batch;
+use crate::direction::Direction;
+use crate::eval_graph::survival_probability;
+use crate::fixture::load_recorded_game;
+use crate::logic::{analyse, distance, length_at_resolution, neighbour, SearchConfig};
+use crate::recorder::load_meta;
+use crate::rules::RulesConfig;
+use crate::stall::find_stall;
+use crate::{Board, Coord, GameState};
+
+pub const DEFAULT_RESULTS_DB: &str = "results.jsonl";
+const BOT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// Depth of the search of every turn, and how much deeper the pivotal turn is searched
+const DEFAULT_ANALYSIS_DEPTH: i32 = 6;
+const PIVOT_EXTRA_PLIES: i32 = 4;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum LossCause {
+    Starved,
+    HeadToHead,
+    SelfTrap,
+    WalledIn,
+    HazardDeath,
+    Timeout,
+    Unknown,
+}
+
+impl LossCause {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LossCause::Starved => "starved",
+            LossCause::HeadToHead => "head-to-head",
+            LossCause::SelfTrap => "self-trap",
+            LossCause::WalledIn => "walled-in",
+            LossCause::HazardDeath => "hazard-death",
+            LossCause::Timeout => "timeout",
+            LossCause::Unknown => "unknown",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GameResult {
+    pub game_id: String,
+    pub version: String,
+    pub turns: i32,
+    pub won: bool,
+    pub cause: Option<LossCause>,
+    // Turn the game stalled at, see stall.rs. Stalled games are counted apart since they
+    // aren't lost by a mistake near the end.
+    #[serde(default)]
+    pub stalled_at: Option<i32>,
+    // Feature toggles the game was played with, None when the recording didn't say
+    #[serde(default)]
+    pub features: Option<Vec<String>>,
+    #[serde(default)]
+    pub pivot: Option<Pivot>,
+}
+
+// The turn our survival estimate dropped the most, with the move the deeper search prefers
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Pivot {
+    pub turn: i32,
+    pub survival_before: f64,
+    pub survival_after: f64,
+    pub played: String,
+    pub depth: i32,
+    pub best: String,
+    pub score: i32,
+    // Principal variation of the deep search, as snake name:move
+    pub pv: Vec<String>,
+}
+
+impl Pivot {
+    pub fn describe(&self) -> String {
+        let verdict = if self.best == "none" {
+            format!(
+                "played '{}', every move loses at depth {}",
+                self.played, self.depth
+            )
+        } else if self.best == self.played {
+            format!("depth {} agrees with '{}'", self.depth, self.played)
+        } else {
+            format!(
+                "played '{}', depth {} prefers '{}' ({:.0}%)",
+                self.played,
+                self.depth,
+                self.best,
+                100.0 * survival_probability(self.score)
+            )
+        };
+        format!(
+            "pivotal turn {} (survival {:.0}% -> {:.0}%): {}{}",
+            self.turn,
+            100.0 * self.survival_before,
+            100.0 * self.survival_after,
+            verdict,
+            if self.pv.is_empty() {
+                String::new()
+            } else {
+                format!(", pv {}", self.pv.join(" "))
+            }
+        )
+    }
+}
+
+impl GameResult {
+    // What the statistics are grouped by: the version and, when known, the features
+    fn build(&self) -> String {
+        match &self.features {
+            Some(features) => format!("{} [{}]", self.version, features.join(", ")),
+            None => self.version.clone(),
+        }
+    }
+}
+
+// Cells reachable from `start` plus which kind of obstacle bounds that region
+struct Enclosure {
+    area: usize,
+    own_walls: usize,
+    opponent_walls: usize,
+}
+
+fn enclosure(board: &Board, you_id: &str, start: &Coord) -> Enclosure {
+    let mut seen = HashSet::new();
+    let mut walls = HashSet::new();
+    let mut queue = VecDeque::new();
+    seen.insert(*start);
+    queue.push_back(*start);
+    let mut area = 0;
+    let mut own_walls = 0;
+    let mut opponent_walls = 0;
+
+    while let Some(current) = queue.pop_front() {
+        for direction in Direction::ALL {
+            let Some(next) = neighbour(board, &current, direction) else {
+                continue;
+            };
+            if let Some(owner) = board.snakes.iter().find(|s| s.body.contains(&next)) {
+                if walls.insert(next) {
+                    if owner.id == you_id {
+                        own_walls += 1;
+                    } else {
+                        opponent_walls += 1;
+                    }
+                }
+                continue;
+            }
+            if seen.insert(next) {
+                area += 1;
+                queue.push_back(next);
+            }
+        }
+    }
+
+    Enclosure {
+        area,
+        own_walls,
+        opponent_walls,
+    }
+}
+
+// Looks at the last position we were alive in (and the frame after it, when recorded) to
+// guess what killed us. Returns None if we didn't lose.
+pub fn classify_loss(frames: &[GameState]) -> Option<LossCause> {
+    let you_id = &frames.first()?.you.id;
+    let death_index = frames
+        .iter()
+        .position(|f| !f.board.snakes.iter().any(|s| &s.id == you_id));
+
+    let (last_alive, death_frame) = match death_index {
+        Some(0) => return Some(LossCause::Unknown),
+        Some(i) => (&frames[i - 1], Some(&frames[i])),
+        None => {
+            // Still on the board at the end: we won if nobody else is left
+            let last = frames.last()?;
+            if last.board.snakes.iter().all(|s| &s.id == you_id) {
+                return None;
+            }
+            (last, None)
+        }
+    };
+
+    let you = &last_alive.you;
+    let head = you.body[0];
+
+    let latency: u32 = you.latency.parse().unwrap_or(0);
+    if latency >= last_alive.game.timeout {
+        return Some(LossCause::Timeout);
+    }
+    if you.health <= 1 {
+        return Some(LossCause::Starved);
+    }
+
+    // An opponent at least as long as us moved onto a cell next to our head: we met it there,
+    // with the lengths after eating whatever food was on that cell
+    if let Some(death_frame) = death_frame {
+        let board = &last_alive.board;
+        let head_on = death_frame.board.snakes.iter().any(|s| {
+            let cell = s.body[0];
+            distance(board, &cell, &head) == 1
+                && board.snakes.iter().any(|b| {
+                    b.id == s.id
+                        && length_at_resolution(board, b, &cell)
+                            >= length_at_resolution(board, you, &cell)
+                })
+        });
+        if head_on {
+            return Some(LossCause::HeadToHead);
+        }
+    }
+
+    // A hazard step kills us at the game's damage per turn plus the normal 1
+    let lethal_health = RulesConfig::from_game(&last_alive.game).hazard_damage_per_turn + 1;
+    if you.health <= lethal_health
+        && last_alive
+            .board
+            .hazards
+            .iter()
+            .any(|h| distance(&last_alive.board, h, &head) == 1)
+    {
+        return Some(LossCause::HazardDeath);
+    }
+
+    let enclosed = enclosure(&last_alive.board, you_id, &head);
+    if enclosed.area < you.body.len() {
+        return Some(if enclosed.opponent_walls > enclosed.own_walls {
+            LossCause::WalledIn
+        } else {
+            LossCause::SelfTrap
+        });
+    }
+
+    Some(LossCause::Unknown)
+}
+
+// Searches every turn we were alive in at `depth` and re-searches the one before the largest
+// drop in survival probability at `depth + PIVOT_EXTRA_PLIES`
+pub fn find_pivot(frames: &[GameState], depth: i32) -> Option<Pivot> {
+    let you_id = &frames.first()?.you.id;
+    let alive = frames
+        .iter()
+        .take_while(|f| f.board.snakes.iter().any(|s| &s.id == you_id))
+        .count();
+    let frames = &frames[..alive];
+    let survival: Vec<f64> = search_batch(frames, &SearchConfig::new(depth))
+        .iter()
+        .map(|analysis| survival_probability(analysis.as_ref().map_or(i32::MIN, |a| a.score)))
+        .collect();
+    let i = (0..survival.len().saturating_sub(1))
+        .filter(|&i| survival[i + 1] < survival[i])
+        .max_by(|&a, &b| {
+            let drop = |i: usize| survival[i] - survival[i + 1];
+            drop(a).total_cmp(&drop(b))
+        })?;
+
+    let (frame, next) = (&frames[i], &frames[i + 1]);
+    let index = frame.board.snakes.iter().position(|s| &s.id == you_id)?;
+    let head = &frame.board.snakes[index].body[0];
+    let played = Direction::between(&frame.board, head, &next.you.body[0])
+        .map_or("unknown", Direction::as_str);
+    let deep = SearchConfig {
+        rich: true,
+        ..SearchConfig::new(depth + PIVOT_EXTRA_PLIES)
+    };
+    let analysis = analyse(&frame.board, index, &deep);
+    Some(Pivot {
+        turn: frame.turn,
+        survival_before: survival[i],
+        survival_after: survival[i + 1],
+        played: played.to_string(),
+        depth: deep.depth,
+        best: analysis
+            .direction
+            .map_or("none", Direction::as_str)
+            .to_string(),
+        score: analysis.score,
+        pv: analysis
+            .pv
+            .iter()
+            .map(|&(s, direction)| format!("{}:{}", frame.board.snakes[s].name, direction))
+            .collect(),
+    })
+}
+
+pub fn load_results(path: &Path) -> Result<Vec<GameResult>, String> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(|e| e.to_string()))
+        .collect()
+}
+
+// Adds `result` to the database, in place of an earlier result of the same game so a
+// recording that is looked at again isn't counted twice
+pub fn record_result(path: &Path, result: &GameResult) -> Result<(), String> {
+    let earlier = load_results(path)?;
+    let kept: Vec<&GameResult> = earlier
+        .iter()
+        .filter(|r| r.game_id != result.game_id)
+        .collect();
+    if kept.len() == earlier.len() {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("could not open {}: {}", path.display(), e))?;
+        let line = serde_json::to_string(result).map_err(|e| e.to_string())?;
+        return writeln!(file, "{}", line).map_err(|e| e.to_string());
+    }
+    let mut contents = String::new();
+    for result in kept.into_iter().chain([result]) {
+        contents += &serde_json::to_string(result).map_err(|e| e.to_string())?;
+        contents.push('\n');
+    }
+    fs::write(path, contents).map_err(|e| format!("could not write {}: {}", path.display(), e))
+}
+
+// Stalled games that weren't won are counted as stalls rather than under their loss cause
+#[derive(Default)]
+struct VersionStats {
+    games: usize,
+    wins: usize,
+    stalls: usize,
+    causes: BTreeMap<LossCause, usize>,
+}
+
+fn print_statistics(results: &[GameResult]) {
+    let mut per_version: BTreeMap<String, VersionStats> = BTreeMap::new();
+    for result in results {
+        let entry = per_version.entry(result.build()).or_default();
+        entry.games += 1;
+        if result.won {
+            entry.wins += 1;
+        } else if result.stalled_at.is_some() {
+            entry.stalls += 1;
+        } else if let Some(cause) = result.cause {
+            *entry.causes.entry(cause).or_default() += 1;
+        }
+    }
+
+    for (version, stats) in per_version {
+        println!(
+            "version {}: {} games, {} wins, {} stalled",
+            version, stats.games, stats.wins, stats.stalls
+        );
+        for (cause, count) in stats.causes {
+            println!(
+                "  {:<14} {:>4} ({:.0}%)",
+                cause.as_str(),
+                count,
+                100.0 * count as f64 / stats.games as f64
+            );
+        }
+    }
+}
+
+// Entry point for
+// `starter-snake-rust postmortem <recording.jsonl>... [--db results.jsonl] [--depth 6]`.
+// Classifies every recording, adds it to the results database and prints the statistics.
+// `--depth 0` skips the search for the pivotal turn.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut db = DEFAULT_RESULTS_DB.to_string();
+    let mut depth = DEFAULT_ANALYSIS_DEPTH;
+    let mut recordings = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--db" {
+            db = args
+                .next()
+                .ok_or_else(|| "--db needs a path".to_string())?
+                .clone();
+        } else if arg == "--depth" {
+            depth = args
+                .next()
+                .and_then(|d| d.parse().ok())
+                .filter(|&d| d >= 0)
+                .ok_or_else(|| "--depth needs a number".to_string())?;
+        } else {
+            recordings.push(arg.clone());
+        }
+    }
+    if recordings.is_empty() {
+        return Err(
+            "usage: postmortem <recording.jsonl>... [--db results.jsonl] [--depth 6]".to_string(),
+        );
+    }
+
+    let db = Path::new(&db);
+    for recording in &recordings {
+        let frames = load_recorded_game(Path::new(recording))?;
+        let first = frames
+            .first()
+            .ok_or_else(|| format!("{} is empty", recording))?;
+        let cause = classify_loss(&frames);
+        let stalled_at = find_stall(&frames);
+        let meta = load_meta(Path::new(recording));
+        let pivot = if cause.is_some() && depth > 0 {
+            find_pivot(&frames, depth)
+        } else {
+            None
+        };
+        let result = GameResult {
+            game_id: first.game.id.clone(),
+            version: meta
+                .as_ref()
+                .map_or(BOT_VERSION.to_string(), |m| m.version.clone()),
+            turns: frames.last().map_or(0, |f| f.turn),
+            won: cause.is_none(),
+            cause,
+            stalled_at,
+            features: meta.map(|m| m.features),
+            pivot,
+        };
+        println!(
+            "{}: {}{}",
+            recording,
+            match cause {
+                Some(cause) => format!("lost ({})", cause.as_str()),
+                None => "won".to_string(),
+            },
+            match stalled_at {
+                Some(turn) => format!(", stalled from turn {}", turn),
+                None => String::new(),
+            }
+        );
+        if let Some(pivot) = &result.pivot {
+            println!("  {}", pivot.describe());
+        }
+        record_result(db, &result)?;
+    }
+
+    print_statistics(&load_results(db)?);
+    Ok(())
+}
@@ -0,0 +1,337 @@
+// Named bundles of search settings and evaluation weights, picked with the SNAKE_PRESET
+// environment variable. Deploying for a given setting only means picking the right name
+// instead of tuning every knob one by one.
+//
+// The weights of every preset can be overridden at startup without recompiling:
+// SNAKE_EVAL_WEIGHTS holds a JSON object of weights, or the path of a file with one, e.g.
+// `{"kill_bonus": 2000}`. The weights it names replace the presets' own, the others stay.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::env;
+use std::fs;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+pub const PRESET_ENV: &str = "SNAKE_PRESET";
+pub const WEIGHTS_ENV: &str = "SNAKE_EVAL_WEIGHTS";
+
+#[derive(Debug, Clone, Copy)]
+pub struct SearchSettings {
+    // How many plies the server searches every turn
+    pub depth: i32,
+    // Only reorder moves by partition forecasts this close to the root, deeper nodes keep the
+    // plain order since the flood fills get expensive
+    pub partition_ordering_min_depth: i32,
+    // Leaves of searches at most this deep get the rich evaluation, deeper searches have too
+    // many leaves for it
+    pub rich_eval_max_root_depth: i32,
+    // ... as long as there's at least this much time left for the turn
+    pub rich_eval_min_time_left: Duration,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct EvalWeights {
+    // Bonus for a position right after eating
+    pub eat_bonus: i32,
+    // Divided by the distance to the nearest food, plus one. Distances, like the other
+    // spatial terms, are measured as if on an 11x11 board.
+    pub food_distance: i32,
+    // With `use_food_race`, divided by the distance to the nearest food an opponent at least
+    // as long as us reaches on the same turn, plus one, and subtracted
+    pub contested_food: i32,
+    // Percent of `food_distance` scored when we'd reach the food with all our health to
+    // spare, and when we'd reach it with none; in between it follows the health missing on
+    // arrival, squared
+    pub sated_food: i32,
+    pub starving_food: i32,
+    // Divided by the distance to the nearest opponent's predicted head, plus one, and
+    // subtracted
+    pub enemy_proximity: i32,
+    // Per opponent that died along the searched line
+    pub kill_bonus: i32,
+    // Penalty per body segment when growing would eat into the space we have left. Set to
+    // 0 to always take food.
+    pub length_space_penalty: i32,
+    // Score per cell of territory we have over the best placed opponent, counted as the same
+    // share of an 11x11 board
+    pub territory: i32,
+    // Score per free cell our head can reach, up to twice our length, counting the cells
+    // bodies leave before we get there, as the same share of an 11x11 board
+    pub reachable_space: i32,
+    // Walled into a region smaller than our own body
+    pub trapped_penalty: i32,
+    // With `use_squeeze`, per opponent our body pins in less room than it is long, and
+    // subtracted when an opponent's body does that to us (see squeeze.rs)
+    pub squeeze_bonus: i32,
+    pub squeezed_penalty: i32,
+    // How much we dislike draws: percent more weight on food in a stalled game
+    pub contempt: i32,
+    // Penalty per fresh opponent visit (see heat.rs) to the cells next to our head
+    pub traffic: i32,
+    // In a famine (see famine.rs), score per turn of health we have over the best supplied
+    // opponent
+    pub starvation: i32,
+    // Penalty per point of hazard damage our head takes where it stands
+    pub hazard: i32,
+    // Penalty per point of health under 50, outside famines
+    pub low_health: i32,
+    // Percent of the food density around our head (see `food_density_at`) scored when we
+    // aren't hungry
+    pub food_density: i32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Preset {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub search: SearchSettings,
+    pub eval: EvalWeights,
+}
+
+pub const DEFAULT_PRESET: &str = "ladder-default";
+// Played in duels unless SNAKE_PRESET says otherwise, see duel.rs
+pub const DUEL_PRESET: &str = "duel-aggressive";
+
+pub const PRESETS: [Preset; 4] = [
+    Preset {
+        name: "ladder-default",
+        description: "Balanced settings for standard games on the public ladder",
+        search: SearchSettings {
+            depth: 12,
+            partition_ordering_min_depth: 8,
+            rich_eval_max_root_depth: 6,
+            rich_eval_min_time_left: Duration::from_millis(150),
+        },
+        eval: EvalWeights {
+            eat_bonus: 100,
+            food_distance: 100,
+            contested_food: 100,
+            sated_food: 10,
+            starving_food: 400,
+            enemy_proximity: 100,
+            kill_bonus: 1500,
+            length_space_penalty: 3,
+            territory: 2,
+            reachable_space: 10,
+            trapped_penalty: 500,
+            squeeze_bonus: 500,
+            squeezed_penalty: 500,
+            contempt: 50,
+            traffic: 20,
+            starvation: 10,
+            hazard: 5,
+            low_health: 2,
+            food_density: 25,
+        },
+    },
+    Preset {
+        name: "royale-specialist",
+        description: "Royale maps: keeps health up and fights for territory as the safe area \
+                      shrinks",
+        search: SearchSettings {
+            depth: 10,
+            partition_ordering_min_depth: 6,
+            rich_eval_max_root_depth: 6,
+            rich_eval_min_time_left: Duration::from_millis(150),
+        },
+        eval: EvalWeights {
+            eat_bonus: 150,
+            food_distance: 150,
+            contested_food: 150,
+            sated_food: 10,
+            starving_food: 400,
+            enemy_proximity: 100,
+            kill_bonus: 1500,
+            length_space_penalty: 2,
+            territory: 4,
+            reachable_space: 10,
+            trapped_penalty: 800,
+            squeeze_bonus: 500,
+            squeezed_penalty: 800,
+            contempt: 50,
+            traffic: 20,
+            starvation: 10,
+            hazard: 10,
+            low_health: 2,
+            food_density: 25,
+        },
+    },
+    Preset {
+        name: "duel-aggressive",
+        description: "1v1 games: goes after the opponent instead of keeping its distance",
+        search: SearchSettings {
+            depth: 12,
+            partition_ordering_min_depth: 8,
+            rich_eval_max_root_depth: 6,
+            rich_eval_min_time_left: Duration::from_millis(150),
+        },
+        eval: EvalWeights {
+            eat_bonus: 80,
+            food_distance: 80,
+            contested_food: 80,
+            sated_food: 10,
+            starving_food: 400,
+            enemy_proximity: 30,
+            kill_bonus: 2500,
+            length_space_penalty: 3,
+            territory: 3,
+            reachable_space: 8,
+            trapped_penalty: 500,
+            squeeze_bonus: 1000,
+            squeezed_penalty: 500,
+            contempt: 100,
+            traffic: 10,
+            starvation: 15,
+            hazard: 5,
+            low_health: 2,
+            food_density: 25,
+        },
+    },
+    Preset {
+        name: "low-latency-vps",
+        description: "Small hosts: shallower searches that stay well inside the timeout",
+        search: SearchSettings {
+            depth: 8,
+            partition_ordering_min_depth: 8,
+            rich_eval_max_root_depth: 4,
+            rich_eval_min_time_left: Duration::from_millis(250),
+        },
+        eval: EvalWeights {
+            eat_bonus: 100,
+            food_distance: 100,
+            contested_food: 100,
+            sated_food: 10,
+            starving_food: 400,
+            enemy_proximity: 100,
+            kill_bonus: 1500,
+            length_space_penalty: 3,
+            territory: 2,
+            reachable_space: 10,
+            trapped_penalty: 500,
+            squeeze_bonus: 500,
+            squeezed_penalty: 500,
+            contempt: 50,
+            traffic: 20,
+            starvation: 10,
+            hazard: 5,
+            low_health: 2,
+            food_density: 25,
+        },
+    },
+];
+
+static ACTIVE: OnceLock<&'static Preset> = OnceLock::new();
+// PRESETS with the weights of SNAKE_EVAL_WEIGHTS, set up with the active preset
+static TABLE: OnceLock<Vec<Preset>> = OnceLock::new();
+
+fn table() -> &'static [Preset] {
+    TABLE.get_or_init(|| PRESETS.to_vec())
+}
+
+pub fn find(name: &str) -> Option<&'static Preset> {
+    table().iter().find(|preset| preset.name == name)
+}
+
+// `weights` with the ones named in `overrides` replaced. Every key has to name a weight.
+pub fn with_overrides(
+    weights: &EvalWeights,
+    overrides: &Map<String, Value>,
+) -> Result<EvalWeights, String> {
+    let mut merged = match serde_json::to_value(weights) {
+        Ok(Value::Object(merged)) => merged,
+        _ => return Err("evaluation weights don't serialize to an object".to_string()),
+    };
+    for (name, value) in overrides {
+        if !merged.contains_key(name) {
+            let names: Vec<&str> = merged.keys().map(|k| k.as_str()).collect();
+            return Err(format!(
+                "unknown weight '{}', expected one of {}",
+                name,
+                names.join(", ")
+            ));
+        }
+        merged.insert(name.clone(), value.clone());
+    }
+    serde_json::from_value(Value::Object(merged)).map_err(|e| format!("bad weight: {}", e))
+}
+
+// The weight overrides in `spec`: a JSON object, or the path of a file holding one
+pub fn parse_overrides(spec: &str) -> Result<Map<String, Value>, String> {
+    let text = if spec.trim_start().starts_with('{') {
+        spec.to_string()
+    } else {
+        fs::read_to_string(spec).map_err(|e| format!("can't read {}: {}", spec, e))?
+    };
+    match serde_json::from_str(&text) {
+        Ok(Value::Object(overrides)) => Ok(overrides),
+        Ok(_) => Err(format!("{} is not a JSON object of weights", spec)),
+        Err(e) => Err(format!("can't parse {}: {}", spec, e)),
+    }
+}
+
+// The presets with the weights of SNAKE_EVAL_WEIGHTS, or as they are when it isn't set
+fn load_table() -> Result<Vec<Preset>, String> {
+    let mut presets = PRESETS.to_vec();
+    let spec = match env::var(WEIGHTS_ENV) {
+        Ok(spec) if !spec.trim().is_empty() => spec,
+        _ => return Ok(presets),
+    };
+    let overrides = parse_overrides(&spec).map_err(|e| format!("{} in {}", e, WEIGHTS_ENV))?;
+    for preset in presets.iter_mut() {
+        preset.eval = with_overrides(&preset.eval, &overrides)
+            .map_err(|e| format!("{} in {}", e, WEIGHTS_ENV))?;
+    }
+    Ok(presets)
+}
+
+fn default_preset() -> &'static Preset {
+    find(DEFAULT_PRESET).unwrap()
+}
+
+// Picks the preset named by SNAKE_PRESET, or the default one when it isn't set, and applies
+// SNAKE_EVAL_WEIGHTS. Has to run before the first search to take effect.
+pub fn select_from_env() -> Result<&'static Preset, String> {
+    let presets = load_table()?;
+    let _ = TABLE.set(presets);
+    let preset = match env::var(PRESET_ENV) {
+        Ok(name) if !name.is_empty() => find(&name).ok_or_else(|| {
+            let names: Vec<&str> = table().iter().map(|p| p.name).collect();
+            format!(
+                "unknown preset '{}' in {}, expected one of {}",
+                name,
+                PRESET_ENV,
+                names.join(", ")
+            )
+        })?,
+        _ => default_preset(),
+    };
+    Ok(*ACTIVE.get_or_init(|| preset))
+}
+
+// The preset searches use
+pub fn active() -> &'static Preset {
+    ACTIVE.get_or_init(default_preset)
+}
+
+// The preset a game is searched with: the one SNAKE_PRESET names, otherwise the duel preset
+// in duels and the default one in other games
+pub fn for_game(duel: bool) -> &'static Preset {
+    match env::var(PRESET_ENV) {
+        Ok(name) if !name.is_empty() => active(),
+        _ if duel => find(DUEL_PRESET).unwrap(),
+        _ => active(),
+    }
+}
+
+// Entry point for `starter-snake-rust presets`
+pub fn run(_args: &[String]) -> Result<(), String> {
+    let active = select_from_env()?;
+    for preset in table() {
+        let marker = if preset.name == active.name { "*" } else { " " };
+        println!("{} {:<18} {}", marker, preset.name, preset.description);
+    }
+    let weights = serde_json::to_string(&active.eval).map_err(|e| e.to_string())?;
+    println!("\nEvaluation weights of {}: {}", active.name, weights);
+    Ok(())
+}
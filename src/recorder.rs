@@ -0,0 +1,317 @@
+// Records the games the server plays when the RECORD_DIR environment variable names a
+// directory, as one compact recording (see compact.rs) per game and snake, ready for the
+// post-mortem, fixture and dataset tools. Next to each recording goes a small `.meta.json`
+// file with the build and the feature toggles that played the game.
+//
+// Recording never holds up a request: `/move` and `/end` only queue the frame for a thread
+// of its own, and when that thread falls behind frames are dropped rather than waited for.
+// It streams every frame to a `<game id>-<snake id>.jsonl.part` file as it comes in, so only
+// the file buffers stay in memory however long the games run, and compresses the file into
+// the recording once the game ends. Games whose `/end` never comes are finished the same way
+// an hour after their last turn. With a shared store (see shared_store.rs) the frames are
+// kept there instead, since the turns of a game can hit different replicas.
+//
+// After every game the oldest recordings are deleted until the directory is back under
+// RECORD_MAX_MB megabytes (1024 when unset), and with RECORD_KEEP_DAYS set so are the ones
+// older than that, so a long tournament can't fill the disk.
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::compact::{encode_game, COMPACT_EXTENSION};
+use crate::degrade::{self, Subsystem};
+use crate::features;
+use crate::fixture::load_recorded_game;
+use crate::shared_store::{GameLog, SharedStore};
+use crate::GameState;
+
+pub const RECORD_DIR_ENV: &str = "RECORD_DIR";
+pub const MAX_MB_ENV: &str = "RECORD_MAX_MB";
+pub const KEEP_DAYS_ENV: &str = "RECORD_KEEP_DAYS";
+const DEFAULT_MAX_MB: u64 = 1024;
+
+const PART_EXTENSION: &str = "jsonl.part";
+
+// Frames waiting for the writer before new ones are dropped
+const QUEUE: usize = 1024;
+// A game without a frame for this long has ended without an `/end`
+const ABANDONED_AFTER: Duration = Duration::from_secs(3600);
+// How often the writer looks for abandoned games while no frames come in
+const ABANDONED_CHECK: Duration = Duration::from_secs(60);
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RecordingMeta {
+    pub version: String,
+    pub features: Vec<String>,
+}
+
+fn meta_path(recording: &Path) -> PathBuf {
+    recording.with_extension("meta.json")
+}
+
+// The metadata written next to a recording, None for recordings made elsewhere
+pub fn load_meta(recording: &Path) -> Option<RecordingMeta> {
+    let text = fs::read_to_string(meta_path(recording)).ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+enum Event {
+    Frame(GameState),
+    End(GameState),
+}
+
+pub struct GameRecorder {
+    queue: Option<SyncSender<Event>>,
+}
+
+impl GameRecorder {
+    pub fn from_env(store: Option<Arc<SharedStore>>) -> GameRecorder {
+        let dir = env::var(RECORD_DIR_ENV)
+            .ok()
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from);
+        GameRecorder {
+            queue: dir.and_then(|dir| {
+                let (sender, receiver) = mpsc::sync_channel(QUEUE);
+                let writer = Writer {
+                    dir,
+                    shared: store.map(|store| GameLog::new("frames", Some(store))),
+                    parts: HashMap::new(),
+                    retention: Retention::from_env(),
+                };
+                let spawned = thread::Builder::new()
+                    .name("recorder".to_string())
+                    .spawn(move || writer.run(receiver));
+                match spawned {
+                    Ok(_) => Some(sender),
+                    Err(e) => {
+                        let error = format!("could not start the writer thread: {}", e);
+                        degrade::report(Subsystem::Recorder, &error);
+                        None
+                    }
+                }
+            }),
+        }
+    }
+
+    fn send(&self, event: Event) {
+        if let Some(queue) = &self.queue {
+            // A full queue means the disk is behind, drop rather than wait
+            if queue.try_send(event).is_err() {
+                warn!("Recorder is behind, dropped a frame");
+            }
+        }
+    }
+
+    pub fn observe(&self, state: &GameState) {
+        if self.queue.is_some() {
+            self.send(Event::Frame(state.clone()));
+        }
+    }
+
+    // Adds the final frame, the recording is written once the writer gets to it
+    pub fn end_game(&self, state: &GameState) {
+        if self.queue.is_some() {
+            self.send(Event::End(state.clone()));
+        }
+    }
+}
+
+fn key(state: &GameState) -> String {
+    format!("{}-{}", state.game.id, state.you.id)
+}
+
+// The streamed frames of a game in progress
+struct Part {
+    file: BufWriter<File>,
+    last_frame: Instant,
+}
+
+struct Writer {
+    dir: PathBuf,
+    // Frames per game and snake when replicas share them
+    shared: Option<GameLog<GameState>>,
+    parts: HashMap<String, Part>,
+    retention: Retention,
+}
+
+impl Writer {
+    fn run(mut self, receiver: Receiver<Event>) {
+        loop {
+            match receiver.recv_timeout(ABANDONED_CHECK) {
+                Ok(Event::Frame(state)) => self.frame(&state),
+                Ok(Event::End(state)) => {
+                    self.frame(&state);
+                    self.finish(&key(&state));
+                    self.retention.apply(&self.dir);
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+            let abandoned: Vec<String> = self
+                .parts
+                .iter()
+                .filter(|(_, part)| part.last_frame.elapsed() >= ABANDONED_AFTER)
+                .map(|(key, _)| key.clone())
+                .collect();
+            for key in abandoned {
+                self.finish(&key);
+            }
+        }
+    }
+
+    fn part_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", key, PART_EXTENSION))
+    }
+
+    fn frame(&mut self, state: &GameState) {
+        let key = key(state);
+        if let Some(shared) = &self.shared {
+            shared.push(&key, state.clone());
+            return;
+        }
+        let path = self.part_path(&key);
+        let part = match self.parts.get_mut(&key) {
+            Some(part) => part,
+            None => {
+                let opened = fs::create_dir_all(&self.dir)
+                    .and_then(|_| OpenOptions::new().create(true).append(true).open(&path));
+                let file = match opened {
+                    Ok(file) => file,
+                    Err(e) => {
+                        let error = format!("could not record to {}: {}", path.display(), e);
+                        degrade::report(Subsystem::Recorder, &error);
+                        return;
+                    }
+                };
+                self.parts.entry(key).or_insert(Part {
+                    file: BufWriter::new(file),
+                    last_frame: Instant::now(),
+                })
+            }
+        };
+        part.last_frame = Instant::now();
+        let written = serde_json::to_writer(&mut part.file, state)
+            .map_err(|e| e.to_string())
+            .and_then(|_| writeln!(part.file).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            warn!("Could not record game to {}: {}", path.display(), e);
+        }
+    }
+
+    // Writes the recording of a game that is over
+    fn finish(&mut self, key: &str) {
+        let mut frames = match &self.shared {
+            Some(shared) => shared.take(key),
+            None => {
+                let path = self.part_path(key);
+                let flushed = match self.parts.remove(key) {
+                    Some(mut part) => part.file.flush(),
+                    None => Ok(()),
+                };
+                let frames = flushed
+                    .map_err(|e| format!("{}: {}", path.display(), e))
+                    .and_then(|_| load_recorded_game(&path));
+                let _ = fs::remove_file(&path);
+                match frames {
+                    Ok(frames) => frames,
+                    Err(e) => {
+                        warn!("Could not record game {}: {}", key, e);
+                        return;
+                    }
+                }
+            }
+        };
+        if frames.is_empty() {
+            return;
+        }
+        // Replicas may have kept some turns apart while the shared store was down
+        frames.sort_by_key(|frame| frame.turn);
+
+        let path = self.dir.join(format!("{}.{}", key, COMPACT_EXTENSION));
+        let meta = RecordingMeta {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            features: features::active_names()
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+        };
+        let written = encode_game(&frames).and_then(|encoded| {
+            let meta = serde_json::to_string(&meta).map_err(|e| e.to_string())?;
+            fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+            fs::write(&path, encoded).map_err(|e| e.to_string())?;
+            fs::write(meta_path(&path), meta + "\n").map_err(|e| e.to_string())
+        });
+        if let Err(e) = written {
+            warn!("Could not record game to {}: {}", path.display(), e);
+        }
+    }
+}
+
+// How much of the recordings is kept
+struct Retention {
+    max_bytes: u64,
+    keep: Option<Duration>,
+}
+
+impl Retention {
+    fn from_env() -> Retention {
+        let number = |name: &str| {
+            env::var(name)
+                .ok()
+                .and_then(|value| value.parse::<u64>().ok())
+        };
+        Retention {
+            max_bytes: number(MAX_MB_ENV).unwrap_or(DEFAULT_MAX_MB) * 1024 * 1024,
+            keep: number(KEEP_DAYS_ENV).map(|days| Duration::from_secs(days * 24 * 3600)),
+        }
+    }
+
+    // Deletes the recordings past their age, then the oldest ones while the directory is
+    // over its size. Games still being streamed count towards the size but stay.
+    fn apply(&self, dir: &Path) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        let mut total = 0;
+        let mut recordings = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let metadata = match entry.metadata() {
+                Ok(metadata) if metadata.is_file() => metadata,
+                _ => continue,
+            };
+            total += metadata.len();
+            if path.extension().is_some_and(|e| e == COMPACT_EXTENSION) {
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                let size = metadata.len() + fs::metadata(meta_path(&path)).map_or(0, |m| m.len());
+                recordings.push((modified, path, size));
+            }
+        }
+        recordings.sort();
+
+        let now = SystemTime::now();
+        for (modified, path, size) in recordings {
+            let expired = self
+                .keep
+                .is_some_and(|keep| now.duration_since(modified).is_ok_and(|age| age > keep));
+            if !expired && total <= self.max_bytes {
+                break;
+            }
+            if fs::remove_file(&path).is_ok() {
+                let _ = fs::remove_file(meta_path(&path));
+                total = total.saturating_sub(size);
+            }
+        }
+    }
+}
@@ -0,0 +1,171 @@
+// Decision layer on top of the search. The search assumes paranoid opponents that always play
+// the reply worst for us; when several root moves score about the same under that assumption,
+// the pick between them is mostly noise. Those moves are then also scored against opponents
+// that head for food (the same prediction the evaluation uses) and opponents playing a random
+// safe move, and the move with the smallest regret over the three models is played: the one
+// that is never far from the best choice, whichever model turns out to be right. Opponents
+// the known opponent book has enough moves of (see opponent_book.rs) don't pick their random
+// move uniformly but as often as they went for that kind of move in the recorded games.
+
+use smallvec::SmallVec;
+
+use crate::direction::Direction;
+use crate::logic::{
+    evaluate_board, is_move_safe, predict_snake_move_towards_food, simulate_move, EvalTier,
+};
+use crate::mate;
+use crate::opponent_book;
+use crate::workspace::{RootCandidate, SearchWorkspace};
+use crate::Board;
+
+// Root moves within this much of the best search score count as equally good
+pub const NOISE_BAND: i32 = 20;
+// The random model averages over every combination of opponent replies, past this many
+// opponents the first ones keep their predicted move instead
+const MAX_RANDOM_OPPONENTS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OpponentModel {
+    Predicted,
+    Random,
+}
+
+fn safe_moves(board: &Board, index: usize) -> SmallVec<[Direction; 4]> {
+    Direction::ALL
+        .iter()
+        .copied()
+        .filter(|&m| is_move_safe(board, &board.snakes[index], m))
+        .collect()
+}
+
+// Score once the opponents in `opponents` have answered, one after the other, following `model`
+fn respond(
+    board: &Board,
+    you_index: usize,
+    opponents: &[usize],
+    model: OpponentModel,
+    workspace: &mut SearchWorkspace,
+) -> f64 {
+    let (&index, rest) = match opponents.split_first() {
+        Some(split) => split,
+        None => return evaluate_board(board, you_index, EvalTier::Cheap, workspace) as f64,
+    };
+    let moves = safe_moves(board, index);
+    if moves.is_empty() {
+        return respond(board, you_index, rest, model, workspace);
+    }
+
+    let reply = |direction: Direction, workspace: &mut SearchWorkspace| {
+        let mut next = board.clone();
+        simulate_move(&mut next, index, direction, &workspace.rules);
+        respond(&next, you_index, rest, model, workspace)
+    };
+    if model == OpponentModel::Random && opponents.len() <= MAX_RANDOM_OPPONENTS {
+        let record = opponent_book::book().and_then(|book| book.known(&board.snakes[index].name));
+        let (mut total, mut weights) = (0.0, 0.0);
+        for &direction in &moves {
+            let weight = record.map_or(1.0, |r| {
+                r.preference(opponent_book::spot(board, index, direction))
+            });
+            total += weight * reply(direction, workspace);
+            weights += weight;
+        }
+        return total / weights;
+    }
+    let snake = &board.snakes[index];
+    let target = predict_snake_move_towards_food(snake, board);
+    let direction = Direction::between(board, &snake.body[0], &target)
+        .filter(|d| moves.contains(d))
+        .unwrap_or(moves[0]);
+    reply(direction, workspace)
+}
+
+fn model_score(
+    board: &Board,
+    you_index: usize,
+    direction: Direction,
+    model: OpponentModel,
+    workspace: &mut SearchWorkspace,
+) -> f64 {
+    let mut after = board.clone();
+    simulate_move(&mut after, you_index, direction, &workspace.rules);
+    let opponents: SmallVec<[usize; 8]> = (0..after.snakes.len())
+        .filter(|&i| i != you_index && !after.snakes[i].body.is_empty())
+        .collect();
+    respond(&after, you_index, &opponents, model, workspace)
+}
+
+// The move to play instead of the search's pick, or None when no other root move is close
+// enough to it. Only moves with an exact search score are considered.
+pub fn choose_move(
+    board: &Board,
+    you_index: usize,
+    candidates: &[RootCandidate],
+    workspace: &mut SearchWorkspace,
+) -> Option<RootCandidate> {
+    let best = candidates
+        .iter()
+        .filter(|c| c.exact)
+        .map(|c| c.score)
+        .max()?;
+    // A decided line is played the way it was searched, to last longest or win soonest
+    if mate::is_loss(best) || mate::is_win(best) {
+        return None;
+    }
+    let close: SmallVec<[RootCandidate; 4]> = candidates
+        .iter()
+        .copied()
+        .filter(|c| c.exact && c.score >= best.saturating_sub(NOISE_BAND))
+        .collect();
+    if close.len() < 2 {
+        return None;
+    }
+
+    // Scores under the paranoid (the search itself), predicted and random models
+    let scores: SmallVec<[[f64; 3]; 4]> = close
+        .iter()
+        .map(|c| {
+            [
+                c.score as f64,
+                model_score(
+                    board,
+                    you_index,
+                    c.direction,
+                    OpponentModel::Predicted,
+                    workspace,
+                ),
+                model_score(
+                    board,
+                    you_index,
+                    c.direction,
+                    OpponentModel::Random,
+                    workspace,
+                ),
+            ]
+        })
+        .collect();
+    let mut model_best = [f64::MIN; 3];
+    for score in &scores {
+        for (best, s) in model_best.iter_mut().zip(score) {
+            *best = best.max(*s);
+        }
+    }
+    let regret = |score: &[f64; 3]| {
+        model_best
+            .iter()
+            .zip(score)
+            .map(|(best, s)| best - s)
+            .fold(0.0, f64::max)
+    };
+
+    // Ties go to the better search score, then to the move trapping more opponents
+    let mut chosen = 0;
+    for i in 1..close.len() {
+        let (r, chosen_r) = (regret(&scores[i]), regret(&scores[chosen]));
+        let tie_break = |c: &RootCandidate| (c.score, c.kills);
+        if r < chosen_r || (r == chosen_r && tie_break(&close[i]) > tie_break(&close[chosen])) {
+            chosen = i;
+        }
+    }
+    Some(close[chosen])
+}
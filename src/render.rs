@@ -0,0 +1,48 @@
+// ASCII rendering of a board, used for debugging output and regression fixtures.
+//
+// Heads are drawn with the first letter of the snake's name (uppercase for the snake with
+// `highlight_id`), bodies with '#', food with '*', hazards with '~' and empty cells with '.'.
+// Row 0 of the output is the top of the board (highest y), like in the game viewer.
+
+use crate::{Board, Coord};
+
+pub fn render_board(board: &Board, highlight_id: &str) -> Vec<String> {
+    let mut rows = Vec::new();
+    for y in (0..board.height as i32).rev() {
+        let mut row = String::new();
+        for x in 0..board.width {
+            row.push(cell_char(board, highlight_id, &Coord { x, y }));
+        }
+        rows.push(row);
+    }
+    rows
+}
+
+pub fn print_board(board: &Board, highlight_id: &str) {
+    for row in render_board(board, highlight_id) {
+        println!("{}", row);
+    }
+}
+
+fn cell_char(board: &Board, highlight_id: &str, cell: &Coord) -> char {
+    for snake in &board.snakes {
+        if let Some(i) = snake.body.iter().position(|segment| segment == cell) {
+            if i > 0 {
+                return '#';
+            }
+            let letter = snake.name.chars().next().unwrap_or('s');
+            return if snake.id == highlight_id {
+                letter.to_ascii_uppercase()
+            } else {
+                letter.to_ascii_lowercase()
+            };
+        }
+    }
+    if board.food.contains(cell) {
+        '*'
+    } else if board.hazards.contains(cell) {
+        '~'
+    } else {
+        '.'
+    }
+}
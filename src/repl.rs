@@ -0,0 +1,595 @@
+// Interactive mode for debugging single positions, like a chess engine's console: load a
+// position, move snakes around, run searches and look at the evaluation, then undo and try
+// something else. Type `help` at the prompt for the commands.
+
+use serde_json::Value;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::direction::Direction;
+use crate::fixture::load_recorded_game;
+use crate::logic::{self, evaluate_board, is_move_safe, simulate_move, EvalTier, TurnContext};
+use crate::mate;
+use crate::metrics::{EvalMetrics, TERM_NAMES};
+use crate::preset;
+use crate::render::render_board;
+use crate::rules::{advance_turn, RulesConfig};
+use crate::search_trace::{self, MovePath, TraceNode, TracedDecision};
+use crate::workspace::with_workspace;
+use crate::{default_timeout, Board, Game, GameState, Ruleset};
+
+const HELP: &str = "\
+load <file> [turn]     load a position: a /move request, a fixture or a turn of a recording
+goto <turn>            jump to another turn of the loaded recording
+show                   print the board and the snakes
+you <snake>            look at the position as another snake
+move <snake> <dir>     move one snake, the way the search does
+turn <dir>...          play a whole turn under the standard rules, one move per snake
+search [depth]         search the position as the server would
+eval                   evaluation of the position, term by term
+threats                cells opponent heads can reach next turn: ! deadly, + we'd win
+trace <log> [turn]     load a traced search from a decision log (see DECISION_TRACE_PLIES)
+node [<move>|..|/]     show the current node of the trace, or go to a child, the parent or
+                       the root
+reenter [full] [plies] search the current node again with its window (or a full one), and
+                       trace `plies` below it (1 by default)
+undo                   take back the last move, turn or goto
+quit";
+
+#[derive(Default)]
+struct Session {
+    state: Option<GameState>,
+    // Frames of the loaded recording, for `goto`
+    frames: Vec<GameState>,
+    history: Vec<GameState>,
+    metrics: EvalMetrics,
+    // Search loaded with `trace`, and the path to the node we're at
+    traced: Option<TracedDecision>,
+    node_path: MovePath,
+}
+
+fn format_score(score: i32) -> String {
+    if mate::is_win(score) {
+        format!("win in {}", mate::plies(score))
+    } else if mate::is_loss(score) {
+        format!("loss in {}", mate::plies(score))
+    } else if score == mate::DRAW {
+        "draw".to_string()
+    } else {
+        score.to_string()
+    }
+}
+
+// A node of a traced search, with the scores of an earlier search of it next to its own
+fn print_node(node: &TraceNode, board: &Board, you_index: usize, earlier: Option<&TraceNode>) {
+    let snake = &board.snakes[node.snake];
+    println!(
+        "  {} to move at depth {}, window [{}, {}], {} evaluation",
+        snake.name,
+        node.depth,
+        format_score(node.alpha),
+        format_score(node.beta),
+        if node.rich { "rich" } else { "cheap" }
+    );
+    for direction in &node.order {
+        let before = earlier
+            .and_then(|e| e.children.iter().find(|c| &c.direction == direction))
+            .map(|c| format!("   (was {})", format_score(c.score)))
+            .unwrap_or_default();
+        if let Some(child) = node.children.iter().find(|c| &c.direction == direction) {
+            println!(
+                "    {:<6} {:>11}   window [{}, {}]{}",
+                direction,
+                format_score(child.score),
+                format_score(child.alpha),
+                format_score(child.beta),
+                before
+            );
+        } else if node.blocked.contains(direction) {
+            println!("    {:<6} {:>11}", direction, "illegal");
+        } else if node.skipped.contains(direction) {
+            println!("    {:<6} {:>11}{}", direction, "pruned", before);
+        }
+    }
+    if let Some(cutoff) = &node.cutoff {
+        let bound = if node.snake == you_index {
+            "at least beta, the opponents already have something better"
+        } else {
+            "at most alpha, we already have something better"
+        };
+        println!(
+            "  cut off after '{}': its score is {} elsewhere in the tree",
+            cutoff, bound
+        );
+    }
+    println!("  score {}", format_score(node.score));
+}
+
+// A single /move request, a regression fixture, or a recording
+fn load_frames(path: &str) -> Result<Vec<GameState>, String> {
+    if let Ok(text) = fs::read_to_string(path) {
+        if let Ok(state) = serde_json::from_str::<GameState>(&text) {
+            return Ok(vec![state]);
+        }
+        let request = serde_json::from_str::<Value>(&text)
+            .ok()
+            .and_then(|fixture| serde_json::from_value(fixture["request"].clone()).ok());
+        if let Some(state) = request {
+            return Ok(vec![state]);
+        }
+    }
+    load_recorded_game(Path::new(path))
+}
+
+impl Session {
+    fn state(&self) -> Result<&GameState, String> {
+        self.state
+            .as_ref()
+            .ok_or_else(|| "no position loaded, use `load <file>`".to_string())
+    }
+
+    // Saves the position for `undo` and returns it for changing
+    fn change(&mut self) -> Result<&mut GameState, String> {
+        let saved = self.state()?.clone();
+        self.history.push(saved);
+        Ok(self.state.as_mut().unwrap())
+    }
+
+    fn snake_index(&self, word: &str) -> Result<usize, String> {
+        let snakes = &self.state()?.board.snakes;
+        word.parse::<usize>()
+            .ok()
+            .filter(|&i| i < snakes.len())
+            .or_else(|| snakes.iter().position(|s| s.id == word || s.name == word))
+            .ok_or_else(|| format!("no snake '{}' on the board", word))
+    }
+
+    fn you_index(&self) -> Result<usize, String> {
+        let state = self.state()?;
+        state
+            .board
+            .snakes
+            .iter()
+            .position(|s| s.id == state.you.id)
+            .ok_or_else(|| format!("{} is not on the board anymore", state.you.name))
+    }
+
+    // Picks up the new body of `you` after the board changed
+    fn sync_you(&mut self) {
+        if let Some(state) = self.state.as_mut() {
+            if let Some(you) = state.board.snakes.iter().find(|s| s.id == state.you.id) {
+                state.you = you.clone();
+            }
+        }
+    }
+
+    fn show(&self) -> Result<(), String> {
+        let state = self.state()?;
+        println!("game {} turn {}", state.game.id, state.turn);
+        for row in render_board(&state.board, &state.you.id) {
+            println!("  {}", row);
+        }
+        for (i, snake) in state.board.snakes.iter().enumerate() {
+            let marker = if snake.id == state.you.id { "*" } else { " " };
+            println!(
+                "{} {} {:<20} health {:>3} length {:>2}",
+                marker,
+                i,
+                snake.name,
+                snake.health,
+                snake.body.len()
+            );
+        }
+        Ok(())
+    }
+
+    fn load(&mut self, path: &str, turn: Option<&str>) -> Result<(), String> {
+        let frames = load_frames(path)?;
+        let index = match turn {
+            Some(turn) => {
+                let turn: i32 = turn
+                    .parse()
+                    .map_err(|_| format!("invalid turn number '{}'", turn))?;
+                frames
+                    .iter()
+                    .position(|f| f.turn == turn)
+                    .ok_or_else(|| format!("turn {} is not in {}", turn, path))?
+            }
+            None => 0,
+        };
+        let state = frames
+            .get(index)
+            .cloned()
+            .ok_or_else(|| format!("{} has no positions", path))?;
+        if let Some(current) = self.state.take() {
+            self.history.push(current);
+        }
+        self.state = Some(state);
+        self.frames = frames;
+        println!("loaded {} ({} positions)", path, self.frames.len());
+        self.show()
+    }
+
+    fn goto(&mut self, turn: &str) -> Result<(), String> {
+        let turn: i32 = turn
+            .parse()
+            .map_err(|_| format!("invalid turn number '{}'", turn))?;
+        let frame = self
+            .frames
+            .iter()
+            .find(|f| f.turn == turn)
+            .cloned()
+            .ok_or_else(|| format!("turn {} is not in the loaded recording", turn))?;
+        *self.change()? = frame;
+        self.show()
+    }
+
+    fn trace(&mut self, path: &str, turn: Option<&str>) -> Result<(), String> {
+        let turn = turn
+            .map(|turn| {
+                turn.parse::<i32>()
+                    .map_err(|_| format!("invalid turn number '{}'", turn))
+            })
+            .transpose()?;
+        let decision = search_trace::load(path, turn)?;
+        let record = &decision.trace;
+        let you = record
+            .board
+            .snakes
+            .get(record.you_index)
+            .cloned()
+            .ok_or_else(|| format!("{} has no snake {}", path, record.you_index))?;
+        let state = GameState {
+            game: Game {
+                id: decision.game_id.clone(),
+                source: String::new(),
+                ruleset: Ruleset::default(),
+                timeout: default_timeout(),
+            },
+            turn: decision.turn,
+            board: record.board.clone(),
+            you,
+        };
+        if let Some(current) = self.state.take() {
+            self.history.push(current);
+        }
+        self.state = Some(state);
+        self.frames = Vec::new();
+        println!(
+            "loaded the search of turn {} at depth {}, {} nodes traced",
+            decision.turn,
+            decision.depth,
+            record.nodes.len()
+        );
+        self.traced = Some(decision);
+        self.node_path = Vec::new();
+        self.show()?;
+        self.node(&[])
+    }
+
+    fn traced(&self) -> Result<&TracedDecision, String> {
+        self.traced
+            .as_ref()
+            .ok_or_else(|| "no search loaded, use `trace <decision log> [turn]`".to_string())
+    }
+
+    fn traced_node(&self, path: &[(usize, Direction)]) -> Result<&TraceNode, String> {
+        self.traced()?
+            .trace
+            .nodes
+            .iter()
+            .find(|node| node.path == path)
+            .ok_or_else(|| {
+                "this node wasn't traced, `reenter` its parent with more plies".to_string()
+            })
+    }
+
+    fn node(&mut self, step: &[&str]) -> Result<(), String> {
+        let mut path = self.node_path.clone();
+        match step {
+            [] => {}
+            [".."] => {
+                path.pop();
+            }
+            ["/"] => path.clear(),
+            [direction] => {
+                let direction: Direction = direction.parse()?;
+                let node = self.traced_node(&path)?;
+                if !node.children.iter().any(|c| c.direction == direction) {
+                    return Err(format!("'{}' wasn't searched at this node", direction));
+                }
+                path.push((node.snake, direction));
+            }
+            _ => return Err("expected a move, `..` or `/`".to_string()),
+        }
+        let decision = self.traced()?;
+        let node = self.traced_node(&path)?.clone();
+        let you_index = decision.trace.you_index;
+        let rules = decision.trace.rules();
+        let (board, _) =
+            logic::position_at(&decision.trace.board, &path, you_index, node.snake, &rules)?;
+        if path != self.node_path {
+            self.change()?.board = board.clone();
+            self.sync_you();
+            self.node_path = path;
+            self.show()?;
+        }
+        let steps: Vec<String> = self
+            .node_path
+            .iter()
+            .map(|(i, direction)| format!("{}:{}", board.snakes[*i].name, direction))
+            .collect();
+        println!(
+            "node {}",
+            if steps.is_empty() {
+                "root".to_string()
+            } else {
+                steps.join(" ")
+            }
+        );
+        print_node(&node, &board, you_index, None);
+        Ok(())
+    }
+
+    fn reenter(&mut self, args: &[&str]) -> Result<(), String> {
+        let (full, plies) = match args {
+            ["full", rest @ ..] => (true, rest),
+            rest => (false, rest),
+        };
+        let plies = match plies {
+            [] => 1,
+            [plies] => plies
+                .parse()
+                .ok()
+                .filter(|&p| p > 0)
+                .ok_or_else(|| format!("invalid number of plies '{}'", plies))?,
+            _ => return Err("expected `reenter [full] [plies]`".to_string()),
+        };
+        let node = self.traced_node(&self.node_path)?.clone();
+        let window = if full {
+            (i32::MIN, i32::MAX)
+        } else {
+            (node.alpha, node.beta)
+        };
+        let decision = self.traced()?;
+        let started = Instant::now();
+        let nodes = logic::reenter(decision, &node, window, plies)?;
+        let fresh = nodes
+            .first()
+            .ok_or_else(|| "the search didn't get to the node".to_string())?;
+        let (board, _) = logic::position_at(
+            &decision.trace.board,
+            &node.path,
+            decision.trace.you_index,
+            node.snake,
+            &decision.trace.rules(),
+        )?;
+        println!("searched again in {:.1?}", started.elapsed());
+        print_node(fresh, &board, decision.trace.you_index, Some(&node));
+
+        // The new search of the subtree replaces the recorded one, so `node` walks into it
+        let path = self.node_path.clone();
+        let traced = self.traced.as_mut().unwrap();
+        traced.trace.nodes.retain(|n| !n.path.starts_with(&path));
+        traced.trace.nodes.extend(nodes);
+        Ok(())
+    }
+
+    fn you(&mut self, snake: &str) -> Result<(), String> {
+        let index = self.snake_index(snake)?;
+        let state = self.state.as_mut().unwrap();
+        state.you = state.board.snakes[index].clone();
+        println!("looking at the position as {}", state.you.name);
+        Ok(())
+    }
+
+    fn move_snake(&mut self, snake: &str, direction: &str) -> Result<(), String> {
+        let index = self.snake_index(snake)?;
+        let direction: Direction = direction.parse()?;
+        let state = self.change()?;
+        if !is_move_safe(&state.board, &state.board.snakes[index], direction) {
+            println!("note: {} is not a safe move", direction);
+        }
+        let rules = RulesConfig::from_game(&state.game);
+        simulate_move(&mut state.board, index, direction, &rules);
+        self.sync_you();
+        self.show()
+    }
+
+    // A full turn without food spawns, so the position stays reproducible
+    fn turn(&mut self, directions: &[&str]) -> Result<(), String> {
+        let moves = directions
+            .iter()
+            .map(|d| d.parse::<Direction>())
+            .collect::<Result<Vec<_>, _>>()?;
+        let snakes = self.state()?.board.snakes.len();
+        if moves.len() != snakes {
+            return Err(format!("expected {} moves, one per snake", snakes));
+        }
+        let rules = RulesConfig {
+            food_spawn_chance: 0,
+            minimum_food: 0,
+            ..RulesConfig::default()
+        };
+        let state = self.change()?;
+        let eliminated = advance_turn(&mut state.board, &moves, &rules, &mut rand::thread_rng());
+        state.turn += 1;
+        self.sync_you();
+        self.show()?;
+        for id in eliminated {
+            println!("{} was eliminated", id);
+        }
+        Ok(())
+    }
+
+    fn search(&self, depth: Option<&str>) -> Result<(), String> {
+        let state = self.state()?;
+        self.you_index()?;
+        let depth = match depth {
+            Some(depth) => depth
+                .parse()
+                .map_err(|_| format!("invalid depth '{}'", depth))?,
+            None => preset::active().search.depth,
+        };
+        let context = TurnContext {
+            deadline: Instant::now() + Duration::from_millis(state.game.timeout as u64),
+            stalled: false,
+            heat: Vec::new(),
+            styles: Vec::new(),
+            preset: preset::active(),
+            famine: false,
+            ponder: None,
+            progress: None,
+        };
+        // Without a /start first the engine only plays its opening move
+        logic::start(&state.game, &state.turn, &state.board, &state.you);
+        let started = Instant::now();
+        let response = logic::get_move_at_depth(
+            &state.game,
+            &state.turn,
+            &state.board,
+            &state.you,
+            depth,
+            context,
+            &self.metrics,
+        );
+        let elapsed = started.elapsed();
+
+        println!("{} at depth {} in {:.1?}", response, depth, elapsed);
+        with_workspace(|workspace| {
+            for candidate in &workspace.root_candidates {
+                let bound = if candidate.exact {
+                    ""
+                } else {
+                    " (upper bound)"
+                };
+                println!(
+                    "  {:<6} {:>8}{}",
+                    candidate.direction, candidate.score, bound
+                );
+            }
+            let pv: Vec<String> = workspace
+                .principal_variation()
+                .iter()
+                .map(|&(i, direction)| format!("{}:{}", state.board.snakes[i].name, direction))
+                .collect();
+            println!("  pv: {}", pv.join(" "));
+        });
+        Ok(())
+    }
+
+    fn eval(&self) -> Result<(), String> {
+        let state = self.state()?;
+        let you_index = self.you_index()?;
+        let (score, totals) = with_workspace(|workspace| {
+            workspace.take_eval_totals();
+            workspace.start_search(&state.board, 0, None, false);
+            let score = evaluate_board(&state.board, you_index, EvalTier::Rich, workspace);
+            (score, workspace.take_eval_totals())
+        });
+        for (name, value) in TERM_NAMES.iter().zip(totals.sums.iter()) {
+            println!("  {:<14} {:>8}", name, value);
+        }
+        println!("  {:<14} {:>8}", "total", score);
+        Ok(())
+    }
+
+    fn threats(&self) -> Result<(), String> {
+        let state = self.state()?;
+        let board = &state.board;
+        let mut rows: Vec<Vec<char>> = render_board(board, &state.you.id)
+            .iter()
+            .map(|row| row.chars().collect())
+            .collect();
+        for snake in board.snakes.iter().filter(|s| s.id != state.you.id) {
+            let deadly = snake.body.len() >= state.you.body.len();
+            for &direction in Direction::ALL
+                .iter()
+                .filter(|&&d| is_move_safe(board, snake, d))
+            {
+                let cell = logic::step(board, &snake.body[0], direction);
+                let row = &mut rows[(board.height as i32 - 1 - cell.y) as usize];
+                let shown = &mut row[cell.x as usize];
+                if matches!(*shown, '.' | '*' | '~' | '+') {
+                    *shown = if deadly { '!' } else { '+' };
+                }
+            }
+        }
+        for row in rows {
+            println!("  {}", row.into_iter().collect::<String>());
+        }
+        Ok(())
+    }
+
+    fn undo(&mut self) -> Result<(), String> {
+        let previous = self
+            .history
+            .pop()
+            .ok_or_else(|| "nothing to undo".to_string())?;
+        self.state = Some(previous);
+        self.show()
+    }
+
+    fn execute(&mut self, words: &[&str]) -> Result<(), String> {
+        match words {
+            ["help"] => {
+                println!("{}", HELP);
+                Ok(())
+            }
+            ["load", path] => self.load(path, None),
+            ["load", path, turn] => self.load(path, Some(turn)),
+            ["goto", turn] => self.goto(turn),
+            ["show"] => self.show(),
+            ["you", snake] => self.you(snake),
+            ["move", snake, direction] => self.move_snake(snake, direction),
+            ["turn", directions @ ..] if !directions.is_empty() => self.turn(directions),
+            ["search"] => self.search(None),
+            ["search", depth] => self.search(Some(depth)),
+            ["eval"] => self.eval(),
+            ["threats"] => self.threats(),
+            ["trace", path] => self.trace(path, None),
+            ["trace", path, turn] => self.trace(path, Some(turn)),
+            ["node", step @ ..] => self.node(step),
+            ["reenter", args @ ..] => self.reenter(args),
+            ["undo"] => self.undo(),
+            _ => Err(format!("unknown command '{}', try `help`", words.join(" "))),
+        }
+    }
+}
+
+// Entry point for `starter-snake-rust repl [file] [turn]`
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut session = Session::default();
+    if let Some(path) = args.first() {
+        session.load(path, args.get(1).map(String::as_str))?;
+    }
+
+    let stdin = io::stdin();
+    let mut line = String::new();
+    loop {
+        print!("> ");
+        io::stdout().flush().map_err(|e| e.to_string())?;
+        line.clear();
+        if stdin
+            .lock()
+            .read_line(&mut line)
+            .map_err(|e| e.to_string())?
+            == 0
+        {
+            println!();
+            return Ok(());
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => {}
+            ["quit"] | ["exit"] => return Ok(()),
+            _ => {
+                if let Err(e) = session.execute(&words) {
+                    println!("error: {}", e);
+                }
+            }
+        }
+    }
+}
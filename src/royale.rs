@@ -0,0 +1,65 @@
+// Royale shrink prediction. In royale games the hazard closes in by one row or column of the
+// safe area every `shrinkEveryNTurns` turns, from a side picked at random. The search knows
+// when the next shrink comes but not from which side, so it plays it out as the worst case:
+// once the line reaches the turn of the shrink, the whole border of the safe area is hazard.
+// A snake that stays on the border then takes hazard damage at the right depth, and the
+// search leaves the doomed border while there's still time instead of after the shrink.
+
+use crate::rules::RulesConfig;
+use crate::workspace::SearchWorkspace;
+use crate::{Board, Coord};
+
+// The next shrink of the safe area, as seen from the root of a search
+#[derive(Clone)]
+pub struct ShrinkForecast {
+    // Turns after the root until the board of the shrink
+    pub turns: i32,
+    // The cells that may turn into hazard then
+    pub cells: Vec<Coord>,
+}
+
+// The next shrink after `turn`, None outside royale games or with no safe area left to shrink
+pub fn forecast(rules: &RulesConfig, turn: i32, board: &Board) -> Option<ShrinkForecast> {
+    let every = rules.shrink_every?;
+    let safe: Vec<Coord> = (0..board.width)
+        .flat_map(|x| (0..board.height as i32).map(move |y| Coord { x, y }))
+        .filter(|cell| !board.hazards.contains(cell))
+        .collect();
+    if safe.len() <= 1 {
+        return None;
+    }
+    let left = safe.iter().map(|c| c.x).min()?;
+    let right = safe.iter().map(|c| c.x).max()?;
+    let bottom = safe.iter().map(|c| c.y).min()?;
+    let top = safe.iter().map(|c| c.y).max()?;
+    let cells = safe
+        .into_iter()
+        .filter(|c| c.x == left || c.x == right || c.y == bottom || c.y == top)
+        .collect();
+    Some(ShrinkForecast {
+        turns: every - turn.rem_euclid(every),
+        cells,
+    })
+}
+
+// A turn of the searched line was played on `board`, which gets the forecast shrink when the
+// line reaches it
+pub(crate) fn turn_played(board: &mut Board, workspace: &mut SearchWorkspace) {
+    workspace.turns_played += 1;
+    if let Some(shrink) = &workspace.shrink {
+        if workspace.turns_played == shrink.turns {
+            workspace.hazards_before_shrink = board.hazards.len();
+            board.hazards.extend_from_slice(&shrink.cells);
+        }
+    }
+}
+
+// Takes back `turn_played`
+pub(crate) fn turn_undone(board: &mut Board, workspace: &mut SearchWorkspace) {
+    if let Some(shrink) = &workspace.shrink {
+        if workspace.turns_played == shrink.turns {
+            board.hazards.truncate(workspace.hazards_before_shrink);
+        }
+    }
+    workspace.turns_played -= 1;
+}
@@ -0,0 +1,215 @@
+// Standard Battlesnake rules, for playing whole games locally, with the wrapped and
+// constrictor variants. All snakes move at the same time and the turn is then resolved in the
+// same order as the official engine: health, hazard damage, feeding, food spawns and finally
+// eliminations.
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::direction::Direction;
+use crate::logic::step;
+use crate::{Board, Coord, Game, Ruleset};
+
+// The rules a game is played with, by the local rules engine and in the search. Every move
+// request's ruleset is read into one of these (see `from_ruleset`).
+#[derive(Debug, Clone)]
+pub struct RulesConfig {
+    // Percent chance of a new piece of food every turn
+    pub food_spawn_chance: u32,
+    // Food is topped up to this amount every turn
+    pub minimum_food: usize,
+    pub hazard_damage_per_turn: i32,
+    // Royale: every this many turns the hazard closes in by one row or column from a random
+    // side
+    pub shrink_every: Option<i32>,
+    // Wrapped: moving off one edge comes back in at the opposite one
+    pub wrapped: bool,
+    // Constrictor: every snake grows every turn at full health, and no food spawns
+    pub constrictor: bool,
+}
+
+// Official defaults of the settings a ruleset leaves out
+const FOOD_SPAWN_CHANCE: u32 = 15;
+const MINIMUM_FOOD: usize = 1;
+pub const HAZARD_DAMAGE_PER_TURN: i32 = 14;
+
+impl Default for RulesConfig {
+    fn default() -> RulesConfig {
+        RulesConfig {
+            food_spawn_chance: FOOD_SPAWN_CHANCE,
+            minimum_food: MINIMUM_FOOD,
+            hazard_damage_per_turn: HAZARD_DAMAGE_PER_TURN,
+            shrink_every: None,
+            wrapped: false,
+            constrictor: false,
+        }
+    }
+}
+
+impl RulesConfig {
+    // The rules of a game from its ruleset. The name picks the variant: `royale` shrinks the
+    // safe area when its settings say how often, `wrapped` and `constrictor` (and
+    // `wrapped_constrictor`) are what they say; anything else plays like `standard`.
+    pub fn from_ruleset(ruleset: &Ruleset) -> RulesConfig {
+        let name = ruleset.name();
+        let settings = &ruleset.settings;
+        let shrink_every = settings
+            .royale
+            .shrink_every_n_turns
+            .filter(|&every| name == "royale" && every > 0);
+        RulesConfig {
+            food_spawn_chance: settings.food_spawn_chance.unwrap_or(FOOD_SPAWN_CHANCE),
+            minimum_food: settings.minimum_food.unwrap_or(MINIMUM_FOOD),
+            hazard_damage_per_turn: settings
+                .hazard_damage_per_turn
+                .unwrap_or(HAZARD_DAMAGE_PER_TURN),
+            shrink_every,
+            wrapped: name.starts_with("wrapped"),
+            constrictor: name.ends_with("constrictor"),
+        }
+    }
+
+    pub fn from_game(game: &Game) -> RulesConfig {
+        RulesConfig::from_ruleset(&game.ruleset)
+    }
+}
+
+fn in_bounds(board: &Board, cell: &Coord) -> bool {
+    cell.x >= 0 && cell.x < board.width && cell.y >= 0 && cell.y < board.height as i32
+}
+
+// Advances the board by one turn. `moves` has the move of every snake of `board.snakes`, in
+// the same order. Eliminated snakes are taken off the board and their ids returned.
+pub fn advance_turn(
+    board: &mut Board,
+    moves: &[Direction],
+    config: &RulesConfig,
+    rng: &mut impl Rng,
+) -> Vec<String> {
+    for (index, direction) in moves.iter().enumerate().take(board.snakes.len()) {
+        let head = step(board, &board.snakes[index].body[0], *direction);
+        let snake = &mut board.snakes[index];
+        snake.body.push_front(head);
+        snake.body.pop();
+        snake.head = head;
+        snake.health -= 1;
+        if board.hazards.contains(&head) && !board.food.contains(&head) {
+            snake.health -= config.hazard_damage_per_turn;
+        }
+    }
+
+    // Eating restores health and grows the snake by doubling up its tail, in constrictor
+    // games every turn
+    let mut eaten = Vec::new();
+    for snake in board.snakes.iter_mut() {
+        if config.constrictor {
+            snake.health = 100;
+            let tail = *snake.body.last().unwrap();
+            snake.body.push(tail);
+        } else if board.food.contains(&snake.body[0]) {
+            snake.health = 100;
+            let tail = *snake.body.last().unwrap();
+            snake.body.push(tail);
+            eaten.push(snake.body[0]);
+        }
+        snake.length = snake.body.len() as i32;
+    }
+    board.food.retain(|food| !eaten.contains(food));
+
+    let eliminated: Vec<usize> = (0..board.snakes.len())
+        .filter(|&i| is_eliminated(board, i))
+        .collect();
+    let eliminated_ids = eliminated
+        .iter()
+        .map(|&i| board.snakes[i].id.clone())
+        .collect();
+    let mut index = 0;
+    board.snakes.retain(|_| {
+        index += 1;
+        !eliminated.contains(&(index - 1))
+    });
+
+    spawn_food(board, config, rng);
+    eliminated_ids
+}
+
+fn is_eliminated(board: &Board, index: usize) -> bool {
+    let snake = &board.snakes[index];
+    let head = snake.body[0];
+    if snake.health <= 0 || !in_bounds(board, &head) {
+        return true;
+    }
+    if board
+        .snakes
+        .iter()
+        .any(|other| other.body[1..].contains(&head))
+    {
+        return true;
+    }
+    // Head-to-head: the shorter snake dies, both die when they are the same length. Feeding
+    // already happened, so the lengths are the ones at resolution.
+    board.snakes.iter().enumerate().any(|(j, other)| {
+        j != index && other.body[0] == head && other.body.len() >= snake.body.len()
+    })
+}
+
+pub fn spawn_food(board: &mut Board, config: &RulesConfig, rng: &mut impl Rng) {
+    if config.constrictor {
+        return;
+    }
+    let wanted = if board.food.len() < config.minimum_food {
+        config.minimum_food - board.food.len()
+    } else if rng.gen_range(0..100) < config.food_spawn_chance {
+        1
+    } else {
+        0
+    };
+    if wanted == 0 {
+        return;
+    }
+
+    let mut free = Vec::new();
+    for x in 0..board.width {
+        for y in 0..board.height as i32 {
+            let cell = Coord { x, y };
+            if !board.food.contains(&cell) && !board.snakes.iter().any(|s| s.body.contains(&cell)) {
+                free.push(cell);
+            }
+        }
+    }
+    free.shuffle(rng);
+    board.food.extend(free.into_iter().take(wanted));
+}
+
+// Royale shrink: covers the outermost row or column of the safe area on a random side with
+// hazard, until a single safe cell is left
+pub fn shrink_safe_area(board: &mut Board, rng: &mut impl Rng) {
+    let safe: Vec<Coord> = (0..board.width)
+        .flat_map(|x| (0..board.height as i32).map(move |y| Coord { x, y }))
+        .filter(|cell| !board.hazards.contains(cell))
+        .collect();
+    if safe.len() <= 1 {
+        return;
+    }
+    let min_x = safe.iter().map(|c| c.x).min().unwrap();
+    let max_x = safe.iter().map(|c| c.x).max().unwrap();
+    let min_y = safe.iter().map(|c| c.y).min().unwrap();
+    let max_y = safe.iter().map(|c| c.y).max().unwrap();
+
+    // (vertical, coordinate) of every side that can still close in
+    let mut sides = Vec::new();
+    if min_x < max_x {
+        sides.push((true, min_x));
+        sides.push((true, max_x));
+    }
+    if min_y < max_y {
+        sides.push((false, min_y));
+        sides.push((false, max_y));
+    }
+    if let Some(&(vertical, line)) = sides.choose(rng) {
+        let covered = safe
+            .iter()
+            .filter(|c| if vertical { c.x == line } else { c.y == line });
+        board.hazards.extend(covered.copied());
+    }
+}
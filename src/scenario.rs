@@ -0,0 +1,583 @@
+// Synthetic positions for situations that decide games but are rare in self-play: getting
+// out of a tunnel before it closes, a head-to-head we can't afford, a royale corner and a
+// food race at low health. Every family places the snakes at random and only keeps positions
+// where the right answer follows from the board alone, so each comes with its acceptable
+// moves. They can be written as regression fixtures and as samples for the imitation
+// dataset, and the engine searches them to see how many it gets right.
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use crate::arena::{new_game, ArenaConfig};
+use crate::batch::search_batch;
+use crate::dataset::Sample;
+use crate::direction::Direction;
+use crate::fixture::Fixture;
+use crate::logic::{distance, next_head, SearchConfig};
+use crate::rules::RulesConfig;
+use crate::strategy::is_legal;
+use crate::{Battlesnake, Board, Coord, GameState};
+
+pub const FAMILIES: [&str; 4] = [
+    "tunnel-escape",
+    "head-to-head",
+    "royale-corner",
+    "food-race",
+];
+
+const SIZE: i32 = 11;
+// Random positions tried for one scenario before giving up on the family
+const ATTEMPTS: usize = 20000;
+
+pub struct Scenario {
+    pub family: &'static str,
+    pub name: String,
+    pub state: GameState,
+    // Best first
+    pub acceptable: Vec<Direction>,
+    pub notes: String,
+}
+
+fn in_bounds(cell: &Coord) -> bool {
+    cell.x >= 0 && cell.x < SIZE && cell.y >= 0 && cell.y < SIZE
+}
+
+fn occupied(board: &Board, cell: &Coord) -> bool {
+    board.snakes.iter().any(|s| s.body.contains(cell))
+}
+
+fn random_cell(rng: &mut StdRng) -> Coord {
+    Coord {
+        x: rng.gen_range(0..SIZE),
+        y: rng.gen_range(0..SIZE),
+    }
+}
+
+// A snake's body as a random walk from `head` over free cells
+fn random_body(board: &Board, head: Coord, length: usize, rng: &mut StdRng) -> Option<Vec<Coord>> {
+    if occupied(board, &head) {
+        return None;
+    }
+    let mut body = vec![head];
+    while body.len() < length {
+        let last = *body.last().unwrap();
+        let next = Direction::ALL
+            .iter()
+            .map(|&d| next_head(&last, d))
+            .filter(|c| in_bounds(c) && !occupied(board, c) && !body.contains(c))
+            .collect::<Vec<_>>()
+            .choose(rng)
+            .copied()?;
+        body.push(next);
+    }
+    Some(body)
+}
+
+fn add_snake(board: &mut Board, id: &str, body: Vec<Coord>, health: i32) {
+    board.snakes.push(Battlesnake {
+        id: id.to_string(),
+        name: id.to_string(),
+        health,
+        head: body[0],
+        length: body.len() as i32,
+        body: body.into(),
+        latency: "0".to_string(),
+        shout: None,
+        squad: None,
+    });
+}
+
+fn add_random_snake(
+    board: &mut Board,
+    id: &str,
+    head: Coord,
+    length: usize,
+    rng: &mut StdRng,
+) -> Option<()> {
+    let body = random_body(board, head, length, rng)?;
+    add_snake(board, id, body, rng.gen_range(40..=100));
+    Some(())
+}
+
+fn empty_board() -> Board {
+    Board {
+        height: SIZE as u32,
+        width: SIZE,
+        food: Vec::new(),
+        snakes: Vec::new(),
+        hazards: Vec::new(),
+        wrapped: false,
+    }
+}
+
+fn join(directions: &[Direction], separator: &str) -> String {
+    let names: Vec<&str> = directions.iter().map(|d| d.as_str()).collect();
+    names.join(separator)
+}
+
+fn legal_moves(board: &Board) -> Vec<Direction> {
+    Direction::ALL
+        .iter()
+        .copied()
+        .filter(|&d| is_legal(board, &board.snakes[0], d))
+        .collect()
+}
+
+// Free cells reachable from `start` (counted too) without crossing a body, and the fewest
+// turns until one of the body cells around them moves away and opens the region up
+struct Region {
+    area: usize,
+    opens_in: usize,
+}
+
+fn region(board: &Board, start: Coord) -> Region {
+    let mut seen = vec![false; (SIZE * SIZE) as usize];
+    let index = |c: &Coord| (c.y * SIZE + c.x) as usize;
+    let mut queue = VecDeque::from([start]);
+    seen[index(&start)] = true;
+    let (mut area, mut opens_in) = (0, usize::MAX);
+    while let Some(cell) = queue.pop_front() {
+        area += 1;
+        for next in Direction::ALL.iter().map(|&d| next_head(&cell, d)) {
+            if !in_bounds(&next) || seen[index(&next)] {
+                continue;
+            }
+            seen[index(&next)] = true;
+            let wall = board.snakes.iter().find_map(|s| {
+                s.body
+                    .iter()
+                    .position(|c| *c == next)
+                    .map(|i| s.body.len() - i)
+            });
+            match wall {
+                Some(turns) => opens_in = opens_in.min(turns),
+                None => queue.push_back(next),
+            }
+        }
+    }
+    Region { area, opens_in }
+}
+
+// Moves needed from `start` to a free cell for which `goal` holds, None when there is none
+fn path_length(board: &Board, start: Coord, goal: impl Fn(&Coord) -> bool) -> Option<i32> {
+    let mut seen = vec![false; (SIZE * SIZE) as usize];
+    let index = |c: &Coord| (c.y * SIZE + c.x) as usize;
+    let mut queue = VecDeque::from([(start, 0)]);
+    seen[index(&start)] = true;
+    while let Some((cell, steps)) = queue.pop_front() {
+        if goal(&cell) {
+            return Some(steps);
+        }
+        for next in Direction::ALL.iter().map(|&d| next_head(&cell, d)) {
+            if in_bounds(&next) && !seen[index(&next)] && !occupied(board, &next) {
+                seen[index(&next)] = true;
+                queue.push_back((next, steps + 1));
+            }
+        }
+    }
+    None
+}
+
+// One of our legal moves leads into a pocket smaller than us that can't open up before we
+// have filled it, another one has plenty of room
+fn tunnel_escape(rng: &mut StdRng) -> Option<(Board, Vec<Direction>, String)> {
+    let mut board = empty_board();
+    let length = rng.gen_range(6..=12);
+    add_random_snake(&mut board, "you", random_cell(rng), length, rng)?;
+    let head = board.snakes[0].body[0];
+    for id in ["snake-1", "snake-2"] {
+        let their_head = random_cell(rng);
+        if distance(&board, &their_head, &head) < 3 {
+            return None;
+        }
+        add_random_snake(&mut board, id, their_head, rng.gen_range(10..=24), rng)?;
+    }
+
+    let (mut traps, mut escapes) = (Vec::new(), Vec::new());
+    for direction in legal_moves(&board) {
+        let pocket = region(&board, next_head(&head, direction));
+        if pocket.area < length && pocket.opens_in > pocket.area {
+            traps.push(direction);
+        } else if pocket.area >= 2 * length {
+            escapes.push((pocket.area, direction));
+        } else {
+            return None;
+        }
+    }
+    if traps.is_empty() || escapes.is_empty() {
+        return None;
+    }
+    escapes.sort_by_key(|&(area, _)| std::cmp::Reverse(area));
+    let notes = format!(
+        "{} leads into a pocket smaller than our length that can't open in time",
+        join(&traps, " and ")
+    );
+    Some((board, escapes.into_iter().map(|(_, d)| d).collect(), notes))
+}
+
+// An opponent head two steps away, at least as long as us: the cells it can reach next turn
+// lose or trade, and another move has plenty of room
+fn head_to_head(rng: &mut StdRng) -> Option<(Board, Vec<Direction>, String)> {
+    const OFFSETS: [(i32, i32); 8] = [
+        (2, 0),
+        (-2, 0),
+        (0, 2),
+        (0, -2),
+        (1, 1),
+        (1, -1),
+        (-1, 1),
+        (-1, -1),
+    ];
+    let mut board = empty_board();
+    let length = rng.gen_range(4..=10);
+    let their_length = length + rng.gen_range(0..=3);
+    let head = random_cell(rng);
+    let (dx, dy) = *OFFSETS.choose(rng).unwrap();
+    let their_head = Coord {
+        x: head.x + dx,
+        y: head.y + dy,
+    };
+    if !in_bounds(&their_head) {
+        return None;
+    }
+    let ours = random_body(&board, head, length, rng)?;
+    add_snake(&mut board, "you", ours, rng.gen_range(40..=100));
+    add_random_snake(&mut board, "snake-1", their_head, their_length, rng)?;
+    // Food away from both heads, so nobody grows on the contested cells
+    for _ in 0..2 {
+        let food = random_cell(rng);
+        if !occupied(&board, &food)
+            && distance(&board, &food, &head) > 2
+            && distance(&board, &food, &their_head) > 2
+        {
+            board.food.push(food);
+        }
+    }
+
+    let (mut contested, mut fine) = (Vec::new(), Vec::new());
+    for direction in legal_moves(&board) {
+        let cell = next_head(&head, direction);
+        if distance(&board, &cell, &their_head) == 1 {
+            contested.push(direction);
+        } else if region(&board, cell).area >= 2 * length {
+            fine.push(direction);
+        } else {
+            return None;
+        }
+    }
+    if contested.is_empty() || fine.is_empty() {
+        return None;
+    }
+    let notes = format!(
+        "{} can meet a snake of length {} head-on, we are {}",
+        join(&contested, " and "),
+        their_length,
+        length
+    );
+    Some((board, fine, notes))
+}
+
+// Deep in the hazard of a royale game with only just enough health to get out by the
+// shortest way: every move that doesn't bring us closer to the safe area starves us
+fn royale_corner(rng: &mut StdRng) -> Option<(Board, Vec<Direction>, String)> {
+    let cost = RulesConfig::default().hazard_damage_per_turn + 1;
+    let ring = rng.gen_range(2..=3);
+    let mut board = empty_board();
+    board.hazards = (0..SIZE)
+        .flat_map(|x| (0..SIZE).map(move |y| Coord { x, y }))
+        .filter(|c| c.x < ring || c.x >= SIZE - ring || c.y < ring || c.y >= SIZE - ring)
+        .collect();
+    // Fewest moves to a safe cell if nothing was in the way
+    let hazards = board.hazards.clone();
+    let to_safety = |c: &Coord| -> i32 {
+        let inside = |v: i32| (ring - v).max(0).max(v - (SIZE - 1 - ring));
+        inside(c.x) + inside(c.y)
+    };
+
+    let head = *board.hazards.choose(rng).unwrap();
+    let needed = to_safety(&head);
+    if needed < 2 {
+        return None;
+    }
+    add_random_snake(&mut board, "you", head, rng.gen_range(4..=10), rng)?;
+    let their_head = random_cell(rng);
+    if hazards.contains(&their_head) || distance(&board, &their_head, &head) < 4 {
+        return None;
+    }
+    add_random_snake(
+        &mut board,
+        "snake-1",
+        their_head,
+        rng.gen_range(4..=12),
+        rng,
+    )?;
+    let food = random_cell(rng);
+    if !hazards.contains(&food) && !occupied(&board, &food) {
+        board.food.push(food);
+    }
+
+    // Enough to survive `needed` moves, `needed - 1` of them in the hazard, but not one more
+    // hazard move
+    let health = rng.gen_range(cost * (needed - 1) + 2..=cost * needed + 1);
+    board.snakes[0].health = health.min(100);
+    let (mut out, mut stay) = (Vec::new(), Vec::new());
+    for direction in legal_moves(&board) {
+        let cell = next_head(&head, direction);
+        let shortest = path_length(&board, cell, |c| !hazards.contains(c));
+        if shortest == Some(needed - 1) {
+            out.push(direction);
+        } else if to_safety(&cell) >= needed {
+            stay.push(direction);
+        } else {
+            return None;
+        }
+    }
+    if out.is_empty() || stay.is_empty() {
+        return None;
+    }
+    let notes = format!(
+        "{} health and {} moves to the safe area, {} stays in the hazard",
+        health,
+        needed,
+        join(&stay, " and ")
+    );
+    Some((board, out, notes))
+}
+
+// The only food is exactly as many moves away as we have health left, opponents are
+// further from it: any move off a shortest path starves
+fn food_race(rng: &mut StdRng) -> Option<(Board, Vec<Direction>, String)> {
+    let mut board = empty_board();
+    let food = random_cell(rng);
+    let head = random_cell(rng);
+    let needed = distance(&board, &head, &food);
+    if !(3..=8).contains(&needed) {
+        return None;
+    }
+    add_random_snake(&mut board, "you", head, rng.gen_range(4..=8), rng)?;
+    board.snakes[0].health = needed;
+    for id in ["snake-1", "snake-2"] {
+        let their_head = random_cell(rng);
+        if distance(&board, &their_head, &food) < needed + 2 {
+            return None;
+        }
+        add_random_snake(&mut board, id, their_head, rng.gen_range(4..=12), rng)?;
+    }
+    if occupied(&board, &food) {
+        return None;
+    }
+    board.food.push(food);
+
+    let (mut towards, mut away) = (Vec::new(), Vec::new());
+    for direction in legal_moves(&board) {
+        let cell = next_head(&head, direction);
+        if path_length(&board, cell, |c| *c == food) == Some(needed - 1) {
+            towards.push(direction);
+        } else if distance(&board, &cell, &food) >= needed {
+            away.push(direction);
+        } else {
+            return None;
+        }
+    }
+    if towards.is_empty() || away.is_empty() {
+        return None;
+    }
+    let notes = format!(
+        "{} health and the food {} moves away, {} starves",
+        needed,
+        needed,
+        join(&away, " and ")
+    );
+    Some((board, towards, notes))
+}
+
+// A scenario of `family`, None when the family's positions are too hard to find
+pub fn generate(family: &'static str, number: usize, rng: &mut StdRng) -> Option<Scenario> {
+    let build = match family {
+        "tunnel-escape" => tunnel_escape,
+        "head-to-head" => head_to_head,
+        "royale-corner" => royale_corner,
+        _ => food_race,
+    };
+    let (board, acceptable, notes) = (0..ATTEMPTS).find_map(|_| build(rng))?;
+
+    let name = format!("scenario-{}-{}", family, number);
+    let mut config = ArenaConfig::default();
+    if !board.hazards.is_empty() {
+        config.rules.shrink_every = Some(25);
+    }
+    let state = GameState {
+        game: new_game(&name, &config),
+        turn: rng.gen_range(40..=150),
+        you: board.snakes[0].clone(),
+        board,
+    };
+    Some(Scenario {
+        family,
+        name,
+        state,
+        acceptable,
+        notes,
+    })
+}
+
+struct Options {
+    families: Vec<&'static str>,
+    count: usize,
+    seed: u64,
+    depth: i32,
+    fixtures: Option<String>,
+    dataset: Option<String>,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        families: FAMILIES.to_vec(),
+        count: 20,
+        seed: 1,
+        depth: 6,
+        fixtures: None,
+        dataset: None,
+    };
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", flag))?;
+        let invalid = |_| format!("invalid value '{}' for {}", value, flag);
+        match flag.as_str() {
+            "--families" => {
+                options.families = value
+                    .split(',')
+                    .map(|name| {
+                        FAMILIES
+                            .iter()
+                            .copied()
+                            .find(|&f| f == name)
+                            .ok_or_else(|| {
+                                format!(
+                                    "unknown family '{}', expected some of {}",
+                                    name,
+                                    FAMILIES.join(", ")
+                                )
+                            })
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+            "--count" => options.count = value.parse().map_err(invalid)?,
+            "--seed" => options.seed = value.parse().map_err(invalid)?,
+            "--depth" => options.depth = value.parse().map_err(invalid)?,
+            "--fixtures" => options.fixtures = Some(value.clone()),
+            "--dataset" => options.dataset = Some(value.clone()),
+            _ => return Err(format!("unknown option '{}'", flag)),
+        }
+    }
+    Ok(options)
+}
+
+fn write_fixtures(dir: &Path, scenarios: &[Scenario]) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("could not create {}: {}", dir.display(), e))?;
+    for scenario in scenarios {
+        let fixture = Fixture::new(
+            &scenario.name,
+            scenario.state.clone(),
+            scenario.acceptable.iter().map(|d| d.to_string()).collect(),
+            scenario.notes.clone(),
+        );
+        let path = dir.join(format!("{}.json", scenario.name));
+        let text = serde_json::to_string_pretty(&fixture).map_err(|e| e.to_string())?;
+        fs::write(&path, text + "\n")
+            .map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+fn append_samples(path: &Path, scenarios: &[Scenario]) -> Result<(), String> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("could not open {}: {}", path.display(), e))?;
+    for scenario in scenarios {
+        let sample = Sample::synthetic(
+            &scenario.name,
+            scenario.state.clone(),
+            scenario.acceptable[0],
+            &format!("scenario-{}", scenario.family),
+        );
+        let line = serde_json::to_string(&sample).map_err(|e| e.to_string())?;
+        writeln!(file, "{}", line).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+// Entry point for `starter-snake-rust scenarios [--families a,b,...] [--count N] [--seed S]
+// [--depth D] [--fixtures DIR] [--dataset FILE]`: generates `count` positions of every
+// family, checks the engine on them at depth D (0 to skip) and optionally writes them out
+pub fn run(args: &[String]) -> Result<(), String> {
+    let options = parse_options(args)?;
+    let mut rng = StdRng::seed_from_u64(options.seed);
+
+    let mut scenarios = Vec::new();
+    for &family in &options.families {
+        let before = scenarios.len();
+        scenarios.extend((0..options.count).map_while(|i| generate(family, i, &mut rng)));
+        if scenarios.len() - before < options.count {
+            println!(
+                "{}: only found {} of {} positions",
+                family,
+                scenarios.len() - before,
+                options.count
+            );
+        }
+    }
+
+    if options.depth > 0 {
+        let states: Vec<GameState> = scenarios.iter().map(|s| s.state.clone()).collect();
+        let search = SearchConfig {
+            regret: true,
+            ..SearchConfig::new(options.depth)
+        };
+        let answers = search_batch(&states, &search);
+        for &family in &options.families {
+            let (mut total, mut right) = (0, 0);
+            for (scenario, answer) in scenarios.iter().zip(&answers) {
+                if scenario.family != family {
+                    continue;
+                }
+                total += 1;
+                let played = answer.as_ref().and_then(|a| a.direction);
+                if played.is_some_and(|played| scenario.acceptable.contains(&played)) {
+                    right += 1;
+                } else {
+                    println!(
+                        "  {}: played {}, expected {} ({})",
+                        scenario.name,
+                        played.map_or("none", Direction::as_str),
+                        join(&scenario.acceptable, " or "),
+                        scenario.notes
+                    );
+                }
+            }
+            println!(
+                "{:<14} engine right in {} of {} at depth {}",
+                family, right, total, options.depth
+            );
+        }
+    }
+
+    if let Some(dir) = &options.fixtures {
+        write_fixtures(Path::new(dir), &scenarios)?;
+        println!("wrote {} fixtures to {}", scenarios.len(), dir);
+    }
+    if let Some(path) = &options.dataset {
+        append_samples(Path::new(path), &scenarios)?;
+        println!("appended {} samples to {}", scenarios.len(), path);
+    }
+    Ok(())
+}
@@ -0,0 +1,353 @@
+// Runs move searches on a fixed pool of search threads, most urgent first. With several
+// games going at once there can be more searches than cores; instead of letting them all
+// fight over the CPU, searches queue up here and a free thread always picks the game whose
+// deadline is closest, with critical positions pulled forward, so an easy game can't starve
+// a hard one. Every personality (see personality.rs) may only use its quota of the threads,
+// its most urgent search waits when it's at it and the next one of another personality goes.
+//
+// Background work (pondering, see ponder.rs) runs on the same threads but only when no search
+// is waiting, and a search that finds no free thread or its personality's quota taken cancels
+// the background work in its way through the work's cancel flag.
+
+use rocket::tokio::sync::oneshot;
+use serde_json::{json, Map, Value};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::env;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::degrade::{self, Subsystem};
+use crate::personality;
+
+pub const SEARCH_THREADS_ENV: &str = "SEARCH_THREADS";
+
+// Every point of criticality counts as this much less time left
+const CRITICALITY_STEP: Duration = Duration::from_millis(40);
+
+#[derive(Debug, Clone, Copy)]
+pub struct Urgency {
+    // When the answer has to be sent
+    pub deadline: Instant,
+    // How much the move matters, see logic::position_criticality
+    pub criticality: u32,
+}
+
+impl Urgency {
+    fn effective_deadline(&self) -> Instant {
+        let bonus = CRITICALITY_STEP * self.criticality;
+        self.deadline.checked_sub(bonus).unwrap_or(self.deadline)
+    }
+}
+
+struct Job {
+    urgency: Urgency,
+    personality: &'static str,
+    // Submission order, breaks ties in favor of the older job
+    sequence: u64,
+    // Set for background work, which a search can cancel through it
+    cancel: Option<Arc<AtomicBool>>,
+    work: Box<dyn FnOnce() + Send>,
+}
+
+// BinaryHeap is a max-heap, the "largest" job is a search before any background work, then
+// the one with the earliest effective deadline
+impl Ord for Job {
+    fn cmp(&self, other: &Job) -> Ordering {
+        other
+            .cancel
+            .is_some()
+            .cmp(&self.cancel.is_some())
+            .then_with(|| {
+                other
+                    .urgency
+                    .effective_deadline()
+                    .cmp(&self.urgency.effective_deadline())
+            })
+            .then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+impl PartialOrd for Job {
+    fn partial_cmp(&self, other: &Job) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Job {
+    fn eq(&self, other: &Job) -> bool {
+        self.sequence == other.sequence
+    }
+}
+
+impl Eq for Job {}
+
+// What a personality did with the search threads
+#[derive(Default)]
+struct Usage {
+    running: usize,
+    searches: u64,
+    // Thread time of its finished searches
+    busy: Duration,
+}
+
+#[derive(Default)]
+struct Queue {
+    jobs: BinaryHeap<Job>,
+    submitted: u64,
+    // Jobs being worked on, and how many may be at once (see throttle.rs)
+    running: usize,
+    limit: usize,
+    threads: usize,
+    usage: HashMap<&'static str, Usage>,
+    // The cancel flags of the background work running, by job
+    background: HashMap<u64, (&'static str, Arc<AtomicBool>)>,
+}
+
+impl Queue {
+    fn may_run(&self, personality: &str) -> bool {
+        let running = self.usage.get(personality).map_or(0, |usage| usage.running);
+        running < personality::thread_quota(personality, self.threads)
+    }
+
+    // The most urgent job whose personality is under its quota
+    fn next_job(&mut self) -> Option<Job> {
+        let mut held = Vec::new();
+        let mut next = None;
+        while let Some(job) = self.jobs.pop() {
+            if self.may_run(job.personality) {
+                next = Some(job);
+                break;
+            }
+            held.push(job);
+        }
+        self.jobs.extend(held);
+        next
+    }
+
+    // Cancels the background work a search of `personality` that can't start waits for
+    fn preempt(&self, personality: &str) {
+        let no_thread = self.running >= self.limit;
+        if !no_thread && self.may_run(personality) {
+            return;
+        }
+        for (owner, cancel) in self.background.values() {
+            if no_thread || *owner == personality {
+                cancel.store(true, AtomicOrdering::Relaxed);
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    queue: Mutex<Queue>,
+    available: Condvar,
+}
+
+#[derive(Clone)]
+pub struct SearchScheduler {
+    shared: Arc<Shared>,
+    threads: usize,
+}
+
+impl SearchScheduler {
+    // Starts `threads` search threads, or as many as the system lets us
+    pub fn new(threads: usize) -> SearchScheduler {
+        let shared = Arc::new(Shared::default());
+        let mut started = 0;
+        for i in 0..threads.max(1) {
+            let worker_shared = shared.clone();
+            let spawned = thread::Builder::new()
+                .name(format!("search-{}", i))
+                .spawn(move || worker(&worker_shared));
+            match spawned {
+                Ok(_) => started += 1,
+                Err(e) => {
+                    let error = format!("started {} of {} threads: {}", started, threads, e);
+                    degrade::report(Subsystem::SearchThreads, &error);
+                    break;
+                }
+            }
+        }
+        {
+            let mut queue = shared.queue.lock().unwrap();
+            queue.limit = started.max(1);
+            queue.threads = started.max(1);
+        }
+        SearchScheduler {
+            shared,
+            threads: started,
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads.max(1)
+    }
+
+    // Lets only `limit` of the threads search at the same time
+    pub fn set_limit(&self, limit: usize) {
+        self.shared.queue.lock().unwrap().limit = limit.clamp(1, self.threads());
+        self.shared.available.notify_all();
+    }
+
+    // One search thread per core unless SEARCH_THREADS says otherwise
+    pub fn from_env() -> SearchScheduler {
+        let threads = env::var(SEARCH_THREADS_ENV)
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+        SearchScheduler::new(threads)
+    }
+
+    // Queues `f` for `personality` and returns a receiver for its result. The receiver
+    // errors if `f` panics.
+    pub fn submit<R, F>(
+        &self,
+        personality: &'static str,
+        urgency: Urgency,
+        f: F,
+    ) -> oneshot::Receiver<R>
+    where
+        R: Send + 'static,
+        F: FnOnce() -> R + Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+        self.execute(personality, urgency, move || {
+            // Nobody waiting for the result any more is fine
+            let _ = sender.send(f());
+        });
+        receiver
+    }
+
+    // Queues `f` for `personality`, `f` has to hand over its result itself. Without any
+    // search thread it runs right away on the caller's.
+    pub fn execute<F>(&self, personality: &'static str, urgency: Urgency, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if self.threads == 0 {
+            let _ = panic::catch_unwind(AssertUnwindSafe(f));
+            return;
+        }
+        let mut queue = self.shared.queue.lock().unwrap();
+        queue.preempt(personality);
+        self.push(&mut queue, personality, urgency, None, Box::new(f));
+    }
+
+    // Queues `f` for `personality` behind every search. `f` should give up once `cancel` is
+    // set, which a search waiting for its thread does. Without any search thread it's
+    // dropped, it mustn't hold up the caller.
+    pub fn execute_background<F>(&self, personality: &'static str, cancel: Arc<AtomicBool>, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if self.threads == 0 {
+            return;
+        }
+        let urgency = Urgency {
+            deadline: Instant::now(),
+            criticality: 0,
+        };
+        let mut queue = self.shared.queue.lock().unwrap();
+        self.push(&mut queue, personality, urgency, Some(cancel), Box::new(f));
+    }
+
+    fn push(
+        &self,
+        queue: &mut Queue,
+        personality: &'static str,
+        urgency: Urgency,
+        cancel: Option<Arc<AtomicBool>>,
+        work: Box<dyn FnOnce() + Send>,
+    ) {
+        queue.submitted += 1;
+        let sequence = queue.submitted;
+        queue.jobs.push(Job {
+            urgency,
+            personality,
+            sequence,
+            cancel,
+            work,
+        });
+        self.shared.available.notify_one();
+    }
+
+    // Per personality: its thread quota, the searches running and waiting, and the searches
+    // done with the thread time they took
+    pub fn to_json(&self) -> Value {
+        let queue = self.shared.queue.lock().unwrap();
+        let mut waiting: HashMap<&str, usize> = HashMap::new();
+        for job in queue.jobs.iter() {
+            *waiting.entry(job.personality).or_default() += 1;
+        }
+        let mut names: Vec<&str> = queue.usage.keys().copied().collect();
+        names.extend(
+            waiting
+                .keys()
+                .filter(|name| !queue.usage.contains_key(*name)),
+        );
+        let mut out = Map::new();
+        for name in names {
+            let usage = queue.usage.get(name);
+            out.insert(
+                name.to_string(),
+                json!({
+                    "thread_quota": personality::thread_quota(name, queue.threads),
+                    "running": usage.map_or(0, |u| u.running),
+                    "waiting": waiting.get(name).copied().unwrap_or(0),
+                    "searches": usage.map_or(0, |u| u.searches),
+                    "busy_ms": usage.map_or(0, |u| u.busy.as_millis() as u64),
+                }),
+            );
+        }
+        Value::Object(out)
+    }
+}
+
+fn worker(shared: &Shared) {
+    loop {
+        let job = {
+            let mut queue = shared.queue.lock().unwrap();
+            loop {
+                if queue.running < queue.limit {
+                    if let Some(job) = queue.next_job() {
+                        queue.running += 1;
+                        queue.usage.entry(job.personality).or_default().running += 1;
+                        if let Some(cancel) = &job.cancel {
+                            let running = (job.personality, Arc::clone(cancel));
+                            queue.background.insert(job.sequence, running);
+                        }
+                        break job;
+                    }
+                }
+                queue = shared.available.wait(queue).unwrap();
+            }
+        };
+        let (personality, sequence) = (job.personality, job.sequence);
+        let cancelled = job
+            .cancel
+            .as_ref()
+            .is_some_and(|cancel| cancel.load(AtomicOrdering::Relaxed));
+        let started = Instant::now();
+        // A panicking search drops its sender, which fails only that request, the thread
+        // stays in the pool. Background work cancelled while it waited isn't started.
+        if !cancelled {
+            let _ = panic::catch_unwind(AssertUnwindSafe(job.work));
+        }
+        {
+            let mut queue = shared.queue.lock().unwrap();
+            queue.background.remove(&sequence);
+            queue.running -= 1;
+            let usage = queue.usage.entry(personality).or_default();
+            usage.running -= 1;
+            usage.searches += 1;
+            usage.busy += started.elapsed();
+        }
+        // A thread held back by the limit or a quota can go now
+        shared.available.notify_all();
+    }
+}
@@ -0,0 +1,162 @@
+// Node by node record of the top of a search, to find out after the game why a branch was
+// pruned. With DECISION_LOG set and DECISION_TRACE_PLIES set to N, every decision log entry
+// also gets the root position and every node within N plies of the root: the moves that lead
+// to it, the alpha-beta window it was searched with, the evaluation tier, the order its moves
+// were tried in, the score and window of every child, and the move after which the window
+// closed. The REPL loads such an entry with `trace`, walks to any recorded node with `node`
+// and searches it again with `reenter`.
+
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+
+use crate::direction::Direction;
+use crate::rules::RulesConfig;
+use crate::{Board, Ruleset};
+
+pub const TRACE_PLIES_ENV: &str = "DECISION_TRACE_PLIES";
+
+// Moves from the root of a search as (snake index, move)
+pub type MovePath = Vec<(usize, Direction)>;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TraceChild {
+    #[serde(rename = "move")]
+    pub direction: Direction,
+    pub score: i32,
+    // Window the child was searched with
+    pub alpha: i32,
+    pub beta: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TraceNode {
+    // Moves from the root to the node
+    pub path: MovePath,
+    // Snake to move
+    pub snake: usize,
+    pub depth: i32,
+    pub alpha: i32,
+    pub beta: i32,
+    // Leaves below were scored with the rich evaluation, as far as the node could tell
+    pub rich: bool,
+    pub order: Vec<Direction>,
+    // Moves that weren't legal
+    pub blocked: Vec<Direction>,
+    pub children: Vec<TraceChild>,
+    // The move whose score closed the window, and the moves never searched because of it
+    pub cutoff: Option<Direction>,
+    pub skipped: Vec<Direction>,
+    pub score: i32,
+}
+
+// What a search records while it runs, see `minimax`
+pub struct SearchTrace {
+    plies: usize,
+    path: MovePath,
+    pub nodes: Vec<TraceNode>,
+}
+
+impl SearchTrace {
+    // Traces the nodes within `plies` of the root, for a search that starts at `path`
+    pub fn new(plies: usize, path: MovePath) -> SearchTrace {
+        SearchTrace {
+            plies: plies + path.len(),
+            path,
+            nodes: Vec::new(),
+        }
+    }
+
+    // Records a node at the current path, returns its index. None below the traced plies.
+    pub fn enter(
+        &mut self,
+        snake: usize,
+        depth: i32,
+        window: (i32, i32),
+        rich: bool,
+        order: &[Direction],
+    ) -> Option<usize> {
+        if self.path.len() >= self.plies {
+            return None;
+        }
+        self.nodes.push(TraceNode {
+            path: self.path.clone(),
+            snake,
+            depth,
+            alpha: window.0,
+            beta: window.1,
+            rich,
+            order: order.to_vec(),
+            blocked: Vec::new(),
+            children: Vec::new(),
+            cutoff: None,
+            skipped: Vec::new(),
+            score: 0,
+        });
+        Some(self.nodes.len() - 1)
+    }
+
+    pub fn descend(&mut self, snake: usize, direction: Direction) {
+        self.path.push((snake, direction));
+    }
+
+    pub fn ascend(&mut self) {
+        self.path.pop();
+    }
+
+    pub fn node(&mut self, index: usize) -> &mut TraceNode {
+        &mut self.nodes[index]
+    }
+}
+
+// The root of a traced search as it goes into the decision log, with everything the search
+// depended on besides the preset and the feature toggles
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TraceRecord {
+    pub board: Board,
+    pub you_index: usize,
+    pub stalled: bool,
+    #[serde(default)]
+    pub heat: Vec<i32>,
+    // The game's rules, standard ones for traces logged without them
+    #[serde(default)]
+    pub ruleset: Ruleset,
+    pub nodes: Vec<TraceNode>,
+}
+
+impl TraceRecord {
+    pub fn rules(&self) -> RulesConfig {
+        RulesConfig::from_ruleset(&self.ruleset)
+    }
+}
+
+// A decision log entry with a trace, as read back
+#[derive(Deserialize, Debug, Clone)]
+pub struct TracedDecision {
+    pub game_id: String,
+    pub turn: i32,
+    pub depth: i32,
+    pub trace: TraceRecord,
+}
+
+pub fn trace_plies() -> Option<usize> {
+    env::var(TRACE_PLIES_ENV)
+        .ok()
+        .and_then(|plies| plies.parse().ok())
+        .filter(|&plies| plies > 0)
+}
+
+// The traced entry of `turn` in a decision log, the first traced one without a turn
+pub fn load(path: &str, turn: Option<i32>) -> Result<TracedDecision, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    text.lines()
+        .filter_map(|line| serde_json::from_str::<TracedDecision>(line).ok())
+        .find(|decision| turn.is_none_or(|turn| decision.turn == turn))
+        .ok_or_else(|| match turn {
+            Some(turn) => format!("{} has no traced search for turn {}", path, turn),
+            None => format!(
+                "{} has no traced searches, log them with {} set",
+                path, TRACE_PLIES_ENV
+            ),
+        })
+}
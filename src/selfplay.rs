@@ -0,0 +1,279 @@
+// Self-play between configurations of the engine, run by the `arena` binary (or the
+// `selfplay` subcommand). A configuration is a preset, a search depth and evaluation weights
+// on top of the preset's: `--engine PRESET[:DEPTH[:WEIGHTS]]`, with WEIGHTS a JSON object or
+// the path of a file with one, like SNAKE_EVAL_WEIGHTS (see preset.rs). Two configurations
+// play a series of duels; with more, every pair plays its own series, or with `--mode ffa`
+// all of them play on one board. Seats go round from game to game so no configuration keeps
+// the better spawn.
+//
+// Games run on `--threads` threads at once, each with random numbers seeded from `--seed`
+// and its number, so a run gives the same games whatever the number of threads as long as
+// the searches finish their depth within the 500ms timeout.
+//
+// Win rates come with their 95% Wilson score interval: a change that moves the win rate by
+// less than the interval is wide needs more games to tell.
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::arena::{parse_size, play_game, set_ruleset, ArenaConfig, GameOutcome};
+use crate::preset::{self, Preset};
+use crate::strategy::{EngineStrategy, Strategy};
+
+// 1.96 standard deviations hold 95% of a normal distribution
+const Z_95: f64 = 1.96;
+
+// One configuration of the engine
+pub(crate) struct Engine {
+    pub label: String,
+    depth: i32,
+    preset: &'static Preset,
+}
+
+impl Engine {
+    pub fn new(label: &str, depth: i32, preset: Preset) -> Engine {
+        Engine {
+            label: label.to_string(),
+            depth,
+            // A few per run, and searches want their preset for good
+            preset: Box::leak(Box::new(preset)),
+        }
+    }
+}
+
+// Every game of a series, in order: the player that won (by its index in the series) and
+// how the game went
+pub(crate) type SeriesResults = Vec<(Option<usize>, GameOutcome)>;
+
+struct Options {
+    engines: Vec<String>,
+    together: bool,
+    games: u32,
+    depth: i32,
+    seed: u64,
+    threads: usize,
+    config: ArenaConfig,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        engines: Vec::new(),
+        together: false,
+        games: 100,
+        depth: 4,
+        seed: 1,
+        threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        config: ArenaConfig::default(),
+    };
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", flag))?;
+        let invalid = |_| format!("invalid value '{}' for {}", value, flag);
+        match flag.as_str() {
+            "--engine" => options.engines.push(value.clone()),
+            "--mode" => {
+                options.together = match value.as_str() {
+                    "duel" => false,
+                    "ffa" => true,
+                    _ => return Err(format!("--mode is 'duel' or 'ffa', not '{}'", value)),
+                }
+            }
+            "--ruleset" => set_ruleset(&mut options.config.rules, value)?,
+            "--games" => options.games = value.parse().map_err(invalid)?,
+            "--depth" => options.depth = value.parse().map_err(invalid)?,
+            "--seed" => options.seed = value.parse().map_err(invalid)?,
+            "--threads" => options.threads = value.parse().map_err(invalid)?,
+            "--size" => (options.config.width, options.config.height) = parse_size(value)?,
+            _ => return Err(format!("unknown option '{}'", flag)),
+        }
+    }
+    if options.engines.len() < 2 || options.engines.len() > 8 {
+        return Err(
+            "usage: arena --engine PRESET[:DEPTH[:WEIGHTS]] --engine ... (2 to 8 of them) \
+             [--mode duel|ffa] [--games N] [--depth D] [--seed S] [--threads T] \
+             [--ruleset standard|royale|wrapped|constrictor] [--size WxH]"
+                .to_string(),
+        );
+    }
+    options.threads = options.threads.max(1);
+    Ok(options)
+}
+
+// The configuration `spec` stands for, searching `depth` plies unless it says otherwise
+fn parse_engine(spec: &str, depth: i32) -> Result<Engine, String> {
+    let mut parts = spec.splitn(3, ':');
+    let name = parts.next().unwrap_or_default();
+    let mut preset = *preset::find(name)
+        .ok_or_else(|| format!("unknown preset '{}' in --engine {}", name, spec))?;
+    let depth = match parts.next() {
+        Some(depth) if !depth.is_empty() => depth
+            .parse()
+            .map_err(|_| format!("invalid depth '{}' in --engine {}", depth, spec))?,
+        _ => depth,
+    };
+    if let Some(weights) = parts.next() {
+        let overrides = preset::parse_overrides(weights)?;
+        preset.eval = preset::with_overrides(&preset.eval, &overrides)
+            .map_err(|e| format!("{} in --engine {}", e, spec))?;
+    }
+    Ok(Engine::new(spec, depth, preset))
+}
+
+// 95% Wilson score interval of `wins` out of `games`
+pub fn wilson_interval(wins: u32, games: u32) -> (f64, f64) {
+    if games == 0 {
+        return (0.0, 1.0);
+    }
+    let n = games as f64;
+    let p = wins as f64 / n;
+    let z2 = Z_95 * Z_95;
+    let centre = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+    let half = Z_95 * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt() / (1.0 + z2 / n);
+    ((centre - half).max(0.0), (centre + half).min(1.0))
+}
+
+// The engines of every series: each pair, or all of them together
+fn series(engines: usize, together: bool) -> Vec<Vec<usize>> {
+    if together {
+        return vec![(0..engines).collect()];
+    }
+    let mut pairs = Vec::new();
+    for a in 0..engines {
+        for b in a + 1..engines {
+            pairs.push(vec![a, b]);
+        }
+    }
+    pairs
+}
+
+// Plays game `game` of a series and returns the engine that won, by its index in `players`
+fn play_one(
+    engines: &[Engine],
+    players: &[usize],
+    series: usize,
+    game: u32,
+    seed: u64,
+    config: &ArenaConfig,
+) -> (Option<usize>, GameOutcome) {
+    // Seat i goes to the player `game` places after player i
+    let seats: Vec<usize> = (0..players.len())
+        .map(|i| (i + game as usize) % players.len())
+        .collect();
+    let mut strategies: Vec<Box<dyn Strategy>> = seats
+        .iter()
+        .map(|&p| -> Box<dyn Strategy> {
+            let engine = &engines[players[p]];
+            Box::new(EngineStrategy::with_preset(engine.depth, engine.preset))
+        })
+        .collect();
+    let seed = seed + (series as u64) * 1_000_003 + game as u64;
+    let mut rng = StdRng::seed_from_u64(seed);
+    let game_id = format!("selfplay-{}-{}", series, game);
+    let outcome = play_game(&game_id, &mut strategies, config, &mut rng);
+    (outcome.winner.map(|seat| seats[seat]), outcome)
+}
+
+// Plays `games` games of every series of `engines` on `threads` threads
+pub(crate) fn play_series(
+    engines: &[Engine],
+    series: &[Vec<usize>],
+    games: u32,
+    seed: u64,
+    threads: usize,
+    config: &ArenaConfig,
+) -> Vec<SeriesResults> {
+    let jobs: Vec<(usize, u32)> = (0..series.len())
+        .flat_map(|s| (0..games).map(move |game| (s, game)))
+        .collect();
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(vec![Vec::new(); series.len()]);
+    thread::scope(|scope| {
+        for _ in 0..threads.clamp(1, jobs.len().max(1)) {
+            scope.spawn(|| loop {
+                let job = next.fetch_add(1, Ordering::Relaxed);
+                let Some(&(s, game)) = jobs.get(job) else {
+                    break;
+                };
+                let result = play_one(engines, &series[s], s, game, seed, config);
+                let mut results = results.lock().unwrap();
+                results[s].push((game, result));
+                let done: usize = results.iter().map(Vec::len).sum();
+                eprint!("\r{} of {} games played", done, jobs.len());
+                let _ = io::stderr().flush();
+            });
+        }
+    });
+    eprintln!();
+
+    let mut results = results.into_inner().unwrap();
+    results
+        .iter_mut()
+        .map(|played| {
+            played.sort_by_key(|(game, _)| *game);
+            played.drain(..).map(|(_, result)| result).collect()
+        })
+        .collect()
+}
+
+fn report(engines: &[Engine], players: &[usize], results: &[(Option<usize>, GameOutcome)]) {
+    let games = results.len() as u32;
+    let labels: Vec<&str> = players.iter().map(|&p| engines[p].label.as_str()).collect();
+    let draws = results
+        .iter()
+        .filter(|(winner, _)| winner.is_none())
+        .count();
+    let turns: i32 = results.iter().map(|(_, outcome)| outcome.turns).sum();
+    println!(
+        "{}: {} games, {} draws, {:.0} turns on average",
+        labels.join(" vs "),
+        games,
+        draws,
+        turns as f64 / games.max(1) as f64
+    );
+    let width = labels.iter().map(|l| l.len()).max().unwrap_or(0);
+    for (i, label) in labels.iter().enumerate() {
+        let wins = results
+            .iter()
+            .filter(|(winner, _)| *winner == Some(i))
+            .count() as u32;
+        let (low, high) = wilson_interval(wins, games);
+        println!(
+            "  {:<width$}  won {:>4}  {:>5.1}%  95% CI {:>5.1}% - {:>5.1}%",
+            label,
+            wins,
+            100.0 * wins as f64 / games.max(1) as f64,
+            100.0 * low,
+            100.0 * high,
+            width = width
+        );
+    }
+}
+
+// Entry point for the `arena` binary and `starter-snake-rust selfplay`
+pub fn run(args: &[String]) -> Result<(), String> {
+    let options = parse_options(args)?;
+    let engines = options
+        .engines
+        .iter()
+        .map(|spec| parse_engine(spec, options.depth))
+        .collect::<Result<Vec<Engine>, String>>()?;
+    let series = series(engines.len(), options.together);
+    let results = play_series(
+        &engines,
+        &series,
+        options.games,
+        options.seed,
+        options.threads,
+        &options.config,
+    );
+    for (players, played) in series.iter().zip(&results) {
+        report(&engines, players, played);
+    }
+    Ok(())
+}
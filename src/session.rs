@@ -0,0 +1,130 @@
+// One session per game in progress, under the game's id: opened by `/start`, brought up to
+// date after every move we answer and dropped by `/end`, which logs how the game went for
+// us. It's where state that lives for the whole game and isn't about one concern goes: the
+// timing of our answers, and how every opponent has been playing (see opponent_style.rs).
+// Like the monitors it's kept in the shared store when
+// GAME_STORE is set (see shared_store.rs), so it survives the turns of a game landing on
+// different replicas. A game whose `/start` we missed gets its session at its first move.
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::opponent_style::{self, OpponentStats, OpponentStyle};
+use crate::shared_store::{GameMap, SharedStore};
+use crate::Board;
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct GameSession {
+    // Turn of the `/start` request, None when the game was joined at a later turn
+    pub started: Option<i32>,
+    // Last turn we answered
+    pub turn: i32,
+    pub moves: u32,
+    // Milliseconds the searches took, and from request to answer, over all moves
+    pub search_ms: u64,
+    pub answer_ms: u64,
+    // Slowest answer in milliseconds and its turn
+    pub slowest: Option<(u64, i32)>,
+    // Moves of the opponents so far, by snake id
+    #[serde(default)]
+    pub opponents: HashMap<String, OpponentStats>,
+}
+
+impl GameSession {
+    fn mean_answer_ms(&self) -> u64 {
+        self.answer_ms / self.moves.max(1) as u64
+    }
+}
+
+// Sessions of the games in progress on the server
+pub struct SessionStore {
+    games: GameMap<GameSession>,
+}
+
+impl SessionStore {
+    pub fn new(store: Option<Arc<SharedStore>>) -> SessionStore {
+        SessionStore {
+            games: GameMap::new("session", store),
+        }
+    }
+
+    pub fn start_game(&self, game_id: &str, turn: i32) {
+        self.games.update(game_id, |session| {
+            *session = GameSession {
+                started: Some(turn),
+                ..GameSession::default()
+            }
+        });
+    }
+
+    // Adds the opponents' moves up to `turn` of `board` and returns the style of every snake
+    // on it by index, for the search of the snake `you_id`
+    pub fn observe_opponents(
+        &self,
+        game_id: &str,
+        board: &Board,
+        turn: i32,
+        you_id: &str,
+    ) -> Vec<OpponentStyle> {
+        self.games.update(game_id, |session| {
+            opponent_style::observe(&mut session.opponents, board, turn, you_id);
+            opponent_style::styles(&session.opponents, board, you_id)
+        })
+    }
+
+    // We answered `turn` after `answer`, `search` of which went to the search
+    pub fn moved(&self, game_id: &str, turn: i32, search: Duration, answer: Duration) {
+        self.games.update(game_id, |session| {
+            let answer_ms = answer.as_millis() as u64;
+            session.turn = turn;
+            session.moves += 1;
+            session.search_ms += search.as_millis() as u64;
+            session.answer_ms += answer_ms;
+            if session
+                .slowest
+                .is_none_or(|(slowest, _)| answer_ms > slowest)
+            {
+                session.slowest = Some((answer_ms, turn));
+            }
+        });
+    }
+
+    pub fn end_game(&self, game_id: &str) {
+        let session = self.games.update(game_id, |session| session.clone());
+        self.games.remove(game_id);
+        if session.moves == 0 {
+            return;
+        }
+        let (slowest, slowest_turn) = session.slowest.unwrap_or_default();
+        let joined = match session.started {
+            Some(turn) => format!("from turn {}", turn),
+            None => "joined late".to_string(),
+        };
+        info!(
+            "SESSION of game {} ({}): {} moves up to turn {}, {} ms per answer, {} ms searching in all, slowest {} ms at turn {}",
+            game_id,
+            joined,
+            session.moves,
+            session.turn,
+            session.mean_answer_ms(),
+            session.search_ms,
+            slowest,
+            slowest_turn
+        );
+        let mut opponents: Vec<&OpponentStats> = session.opponents.values().collect();
+        opponents.sort_by(|a, b| a.name.cmp(&b.name));
+        for stats in opponents.iter().filter(|stats| stats.moves > 0) {
+            info!(
+                "SESSION of game {}: {} looked {} over {} moves, {}% of them the move we expected",
+                game_id,
+                stats.name,
+                stats.style().as_str(),
+                stats.moves,
+                stats.predicted_right * 100 / stats.moves
+            );
+        }
+    }
+}
@@ -0,0 +1,449 @@
+// Per-game state shared between replicas. Behind a load balancer the turns of one game can
+// hit different instances, and every instance would only see part of the game: the stall
+// tracker and the heat grid start over, and the recording has holes. With GAME_STORE set to
+// a Redis address (`redis://host:port`, or `redis://:password@host:port`) that state lives
+// in Redis instead, as JSON under `<kind>:<game id>` keys that expire an hour after the last
+// turn. Without it, or while Redis can't be reached, every instance keeps its own in memory.
+//
+// A turn can't wait on Redis. The store calls of one request run through `within`, which
+// moves the executor's other tasks off the request's thread and gives all of them together a
+// deadline: a call that would start past it, or can't get the connection by then, fails
+// right away and the state of that call stays in memory. After a failed call the store is
+// left alone for a second, twice as long after every failure in a row up to half a minute,
+// so a Redis that stopped answering costs one timeout and not one per call.
+//
+// `GameMap::update` reads the state, changes it and writes it back with no lock around the
+// three, so of two updates of the same game at the same time, on one replica or two, the
+// last one to write wins. The turns of one snake come one at a time, so that only happens
+// with two of our snakes in one game, where both update the same stall and famine counts
+// and the same session.
+
+use log::info;
+use rocket::tokio::task::block_in_place;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::degrade::{self, Subsystem};
+
+pub const STORE_ENV: &str = "GAME_STORE";
+
+// Seconds a game's state is kept after its last turn, for games whose `/end` never comes
+const GAME_TTL: u64 = 3600;
+// A call can't wait long for the store, it falls back to the local state instead
+const STORE_TIMEOUT: Duration = Duration::from_millis(100);
+// Share of a game's timeout the store calls of one request get together
+pub const TURN_SHARE: u32 = 10;
+// How long the store is left alone after a failure, doubling with every failure in a row
+const FIRST_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+thread_local! {
+    // When the store calls of the request on this thread have to be done, see `within`
+    static TURN_DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+// Runs `f`, the store calls of one request, with the executor's other tasks moved off this
+// thread while it waits on Redis, and with every call of it done by `deadline`
+pub fn within<R>(deadline: Instant, f: impl FnOnce() -> R) -> R {
+    block_in_place(|| {
+        let outer = TURN_DEADLINE.with(|turn| turn.replace(Some(deadline)));
+        let result = f();
+        TURN_DEADLINE.with(|turn| turn.set(outer));
+        result
+    })
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Reply {
+    Nil,
+    Status(String),
+    Integer(i64),
+    Bulk(Vec<u8>),
+    Array(Vec<Reply>),
+}
+
+// Whether the store is being left alone after failures
+#[derive(Default)]
+struct Breaker {
+    // When to try it again, None while it works
+    retry_at: Option<Instant>,
+    backoff: Duration,
+}
+
+// Minimal Redis client, one connection shared by all requests
+pub struct SharedStore {
+    addr: String,
+    password: Option<String>,
+    connection: Mutex<Option<BufReader<Timed>>>,
+    breaker: Mutex<Breaker>,
+}
+
+impl SharedStore {
+    // The store GAME_STORE names, None when unset
+    pub fn from_env() -> Option<Arc<SharedStore>> {
+        let url = env::var(STORE_ENV).ok().filter(|url| !url.is_empty())?;
+        let rest = match url.strip_prefix("redis://") {
+            Some(rest) => rest.trim_end_matches('/'),
+            None => {
+                info!(
+                    "Keeping game state in memory: {} has to be a redis:// address, not '{}'",
+                    STORE_ENV, url
+                );
+                return None;
+            }
+        };
+        let (password, host) = match rest.rsplit_once('@') {
+            Some((user, host)) => (Some(user.trim_start_matches(':').to_string()), host),
+            None => (None, rest),
+        };
+        let addr = if host.contains(':') {
+            host.to_string()
+        } else {
+            format!("{}:6379", host)
+        };
+        info!("Keeping game state in Redis at {}", addr);
+        Some(Arc::new(SharedStore {
+            addr,
+            password,
+            connection: Mutex::new(None),
+            breaker: Mutex::new(Breaker::default()),
+        }))
+    }
+
+    fn connect(&self, deadline: Instant) -> Result<BufReader<Timed>, String> {
+        let addr = self
+            .addr
+            .to_socket_addrs()
+            .map_err(|e| e.to_string())?
+            .next()
+            .ok_or_else(|| format!("no address for {}", self.addr))?;
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let stream = TcpStream::connect_timeout(&addr, timeout).map_err(|e| e.to_string())?;
+        let mut connection = BufReader::new(Timed { stream, deadline });
+        if let Some(password) = &self.password {
+            send(&mut connection, &[b"AUTH", password.as_bytes()])?;
+        }
+        Ok(connection)
+    }
+
+    // The connection, once it's free or until `deadline`
+    fn lock_connection(
+        &self,
+        deadline: Instant,
+    ) -> Option<MutexGuard<'_, Option<BufReader<Timed>>>> {
+        loop {
+            match self.connection.try_lock() {
+                Ok(connection) => return Some(connection),
+                Err(TryLockError::Poisoned(poisoned)) => return Some(poisoned.into_inner()),
+                Err(TryLockError::WouldBlock) if Instant::now() < deadline => {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                Err(TryLockError::WouldBlock) => return None,
+            }
+        }
+    }
+
+    // Runs one command, reconnecting first when the last one failed. Fails right away while
+    // the store is left alone or when the request is out of time for it, see `within`.
+    fn command(&self, args: &[&[u8]]) -> Result<Reply, String> {
+        let now = Instant::now();
+        if self
+            .breaker
+            .lock()
+            .unwrap()
+            .retry_at
+            .is_some_and(|retry_at| now < retry_at)
+        {
+            return Err(format!("Redis at {} is left alone for now", self.addr));
+        }
+        let deadline = match TURN_DEADLINE.with(Cell::get) {
+            Some(turn) => turn.min(now + STORE_TIMEOUT),
+            None => now + STORE_TIMEOUT,
+        };
+        let out_of_time = || "out of time for the store".to_string();
+        let mut connection = self.lock_connection(deadline).ok_or_else(out_of_time)?;
+        if Instant::now() >= deadline {
+            return Err(out_of_time());
+        }
+        // A connection a call failed on is dropped, whatever is left of its reply with it
+        let result = match connection.take() {
+            Some(mut open) => {
+                open.get_mut().deadline = deadline;
+                Ok(open)
+            }
+            None => self.connect(deadline),
+        }
+        .and_then(|mut open| {
+            let reply = send(&mut open, args)?;
+            *connection = Some(open);
+            Ok(reply)
+        });
+        drop(connection);
+
+        let mut breaker = self.breaker.lock().unwrap();
+        match &result {
+            Ok(_) => {
+                if breaker.retry_at.take().is_some() {
+                    info!("Redis at {} is back", self.addr);
+                }
+                breaker.backoff = Duration::ZERO;
+            }
+            Err(e) => {
+                // Only the first failure of a streak is reported
+                if breaker.retry_at.is_none() {
+                    degrade::report(
+                        Subsystem::GameStore,
+                        &format!("Redis at {} failed: {}", self.addr, e),
+                    );
+                }
+                breaker.backoff = (breaker.backoff * 2).clamp(FIRST_BACKOFF, MAX_BACKOFF);
+                breaker.retry_at = Some(Instant::now() + breaker.backoff);
+            }
+        }
+        result
+    }
+
+    fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, String> {
+        match self.command(&[b"GET", key.as_bytes()])? {
+            Reply::Bulk(bytes) => serde_json::from_slice(&bytes)
+                .map(Some)
+                .map_err(|e| e.to_string()),
+            _ => Ok(None),
+        }
+    }
+
+    fn set<T: Serialize>(&self, key: &str, value: &T) -> Result<(), String> {
+        let json = serde_json::to_vec(value).map_err(|e| e.to_string())?;
+        let ttl = GAME_TTL.to_string();
+        self.command(&[b"SET", key.as_bytes(), &json, b"EX", ttl.as_bytes()])
+            .map(|_| ())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), String> {
+        self.command(&[b"DEL", key.as_bytes()]).map(|_| ())
+    }
+
+    // Appends `value` to the list under `key`. Once it's in, a failure to renew the list's
+    // expiry doesn't fail the push, the value would be kept a second time in memory.
+    fn push<T: Serialize>(&self, key: &str, value: &T) -> Result<(), String> {
+        let json = serde_json::to_vec(value).map_err(|e| e.to_string())?;
+        let ttl = GAME_TTL.to_string();
+        self.command(&[b"RPUSH", key.as_bytes(), &json])?;
+        let _ = self.command(&[b"EXPIRE", key.as_bytes(), ttl.as_bytes()]);
+        Ok(())
+    }
+
+    // Removes the list under `key` and returns what was in it. The list is returned even when
+    // it can't be removed, it expires with the game.
+    fn take_list<T: DeserializeOwned>(&self, key: &str) -> Result<Vec<T>, String> {
+        let items = match self.command(&[b"LRANGE", key.as_bytes(), b"0", b"-1"])? {
+            Reply::Array(items) => items,
+            _ => Vec::new(),
+        };
+        let _ = self.delete(key);
+        items
+            .into_iter()
+            .filter_map(|item| match item {
+                Reply::Bulk(bytes) => {
+                    Some(serde_json::from_slice(&bytes).map_err(|e| e.to_string()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+// The socket to Redis, every read and write of which is done by `deadline`. A timeout per
+// read wouldn't do: a Redis that sends a byte now and then would keep each one from running
+// out and the call from ever ending.
+struct Timed {
+    stream: TcpStream,
+    deadline: Instant,
+}
+
+impl Timed {
+    fn left(&self) -> io::Result<Duration> {
+        match self.deadline.saturating_duration_since(Instant::now()) {
+            left if left.is_zero() => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "out of time for the store",
+            )),
+            left => Ok(left),
+        }
+    }
+}
+
+impl Read for Timed {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.set_read_timeout(Some(self.left()?))?;
+        self.stream.read(buf)
+    }
+}
+
+impl Write for Timed {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.set_write_timeout(Some(self.left()?))?;
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+fn send(connection: &mut BufReader<Timed>, args: &[&[u8]]) -> Result<Reply, String> {
+    let mut request = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        request.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        request.extend_from_slice(arg);
+        request.extend_from_slice(b"\r\n");
+    }
+    connection
+        .get_mut()
+        .write_all(&request)
+        .map_err(|e| e.to_string())?;
+    read_reply(connection)
+}
+
+// Reads one reply in the Redis protocol (RESP 2). Error replies are Errs with their message.
+pub fn read_reply(connection: &mut impl BufRead) -> Result<Reply, String> {
+    let mut line = String::new();
+    connection.read_line(&mut line).map_err(|e| e.to_string())?;
+    let Some(line) = line.strip_suffix("\r\n") else {
+        return Err(format!("reply cut short at '{}'", line));
+    };
+    let (kind, rest) = line.split_at(line.len().min(1));
+    let number = || rest.parse::<i64>().map_err(|e| e.to_string());
+    match kind {
+        "+" => Ok(Reply::Status(rest.to_string())),
+        ":" => number().map(Reply::Integer),
+        "-" => Err(rest.to_string()),
+        "$" => match number()? {
+            -1 => Ok(Reply::Nil),
+            len if len < 0 => Err(format!("unexpected reply '{}'", line)),
+            len => {
+                let mut bytes = vec![0; len as usize + 2];
+                connection
+                    .read_exact(&mut bytes)
+                    .map_err(|e| e.to_string())?;
+                if !bytes.ends_with(b"\r\n") {
+                    return Err(format!("bulk reply of {} bytes without its CRLF", len));
+                }
+                bytes.truncate(len as usize);
+                Ok(Reply::Bulk(bytes))
+            }
+        },
+        "*" => match number()? {
+            -1 => Ok(Reply::Nil),
+            len if len < 0 => Err(format!("unexpected reply '{}'", line)),
+            len => (0..len)
+                .map(|_| read_reply(connection))
+                .collect::<Result<_, _>>()
+                .map(Reply::Array),
+        },
+        _ => Err(format!("unexpected reply '{}'", line)),
+    }
+}
+
+// Per-game state of one kind, in the shared store when there is one and in memory otherwise
+pub struct GameMap<T> {
+    kind: &'static str,
+    store: Option<Arc<SharedStore>>,
+    local: Mutex<HashMap<String, T>>,
+}
+
+impl<T: Serialize + DeserializeOwned + Default> GameMap<T> {
+    pub fn new(kind: &'static str, store: Option<Arc<SharedStore>>) -> GameMap<T> {
+        GameMap {
+            kind,
+            store,
+            local: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn store_key(&self, key: &str) -> String {
+        format!("{}:{}", self.kind, key)
+    }
+
+    // Runs `f` on the state of game `key`, a default one for a new game. State that can't be
+    // written back to the store is kept in memory, where the next turns find it while the
+    // store is left alone.
+    pub fn update<R>(&self, key: &str, f: impl FnOnce(&mut T) -> R) -> R {
+        if let Some(store) = &self.store {
+            let store_key = self.store_key(key);
+            if let Ok(value) = store.get(&store_key) {
+                let mut value = value.unwrap_or_default();
+                let result = f(&mut value);
+                if store.set(&store_key, &value).is_err() {
+                    self.local.lock().unwrap().insert(key.to_string(), value);
+                }
+                return result;
+            }
+        }
+        let mut local = self.local.lock().unwrap();
+        f(local.entry(key.to_string()).or_default())
+    }
+
+    pub fn remove(&self, key: &str) {
+        if let Some(store) = &self.store {
+            let _ = store.delete(&self.store_key(key));
+        }
+        self.local.lock().unwrap().remove(key);
+    }
+}
+
+// Per-game lists that only ever grow until the game ends, like the frames of a recording
+pub struct GameLog<T> {
+    kind: &'static str,
+    store: Option<Arc<SharedStore>>,
+    local: Mutex<HashMap<String, Vec<T>>>,
+}
+
+impl<T: Serialize + DeserializeOwned> GameLog<T> {
+    pub fn new(kind: &'static str, store: Option<Arc<SharedStore>>) -> GameLog<T> {
+        GameLog {
+            kind,
+            store,
+            local: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn store_key(&self, key: &str) -> String {
+        format!("{}:{}", self.kind, key)
+    }
+
+    pub fn push(&self, key: &str, value: T) {
+        if let Some(store) = &self.store {
+            if store.push(&self.store_key(key), &value).is_ok() {
+                return;
+            }
+        }
+        self.local
+            .lock()
+            .unwrap()
+            .entry(key.to_string())
+            .or_default()
+            .push(value);
+    }
+
+    // Everything logged for game `key`, which is then forgotten
+    pub fn take(&self, key: &str) -> Vec<T> {
+        let mut items = match &self.store {
+            Some(store) => store.take_list(&self.store_key(key)).unwrap_or_default(),
+            None => Vec::new(),
+        };
+        // Turns kept here while the store was down
+        if let Some(local) = self.local.lock().unwrap().remove(key) {
+            items.extend(local);
+        }
+        items
+    }
+}
@@ -0,0 +1,116 @@
+// Coordination between our two snakes in squad games.
+//
+// Every snake can read the shouts of the other snakes in the board payload, so each of our
+// snakes publishes the move it is about to make and the half of the board it claims, e.g.
+// "sq:up:W". The teammate parses that to avoid running into us and to stay on its own side.
+// When the teammate's shout isn't in this format (it isn't running our bot) we fall back to
+// predicting its move like any other snake.
+
+use crate::direction::Direction;
+use crate::logic::step;
+use crate::{Battlesnake, Board, Coord, Game};
+
+const SHOUT_PREFIX: &str = "sq";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    West,
+    East,
+}
+
+impl Region {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Region::West => "W",
+            Region::East => "E",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Region> {
+        match s {
+            "W" => Some(Region::West),
+            "E" => Some(Region::East),
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, board: &Board, cell: &Coord) -> bool {
+        match self {
+            Region::West => cell.x < board.width / 2,
+            Region::East => cell.x >= board.width - board.width / 2,
+        }
+    }
+
+    fn other(&self) -> Region {
+        match self {
+            Region::West => Region::East,
+            Region::East => Region::West,
+        }
+    }
+}
+
+// What a teammate told us it is going to do this turn
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TeammateIntent {
+    pub direction: Direction,
+    pub region: Region,
+}
+
+pub fn is_squad_game(game: &Game) -> bool {
+    game.ruleset.name() == "squad"
+}
+
+pub fn encode_shout(direction: Direction, region: Region) -> String {
+    format!("{}:{}:{}", SHOUT_PREFIX, direction, region.as_str())
+}
+
+pub fn parse_shout(shout: &str) -> Option<TeammateIntent> {
+    let mut parts = shout.trim().split(':');
+    if parts.next()? != SHOUT_PREFIX {
+        return None;
+    }
+    let direction = parts.next()?.parse().ok()?;
+    let region = Region::parse(parts.next()?)?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(TeammateIntent { direction, region })
+}
+
+// The other living snake in our squad, if there is one
+pub fn teammate<'a>(board: &'a Board, you: &Battlesnake) -> Option<&'a Battlesnake> {
+    let squad = you.squad.as_deref().filter(|s| !s.is_empty())?;
+    board
+        .snakes
+        .iter()
+        .find(|s| s.id != you.id && !s.body.is_empty() && s.squad.as_deref() == Some(squad))
+}
+
+// Splits the board between us and the teammate. A teammate that already claimed a half
+// keeps it, otherwise the snake furthest west takes the west half (ties broken by id so
+// both of our snakes agree).
+pub fn claimed_region(you: &Battlesnake, teammate: &Battlesnake) -> Region {
+    if let Some(intent) = teammate.shout.as_deref().and_then(parse_shout) {
+        return intent.region.other();
+    }
+    let ours = (you.body[0].x, &you.id);
+    let theirs = (teammate.body[0].x, &teammate.id);
+    if ours <= theirs {
+        Region::West
+    } else {
+        Region::East
+    }
+}
+
+// Where the teammate's head will be next turn, using its shout when it is one of ours and
+// otherwise guessing with `predict`
+pub fn teammate_next_cell(
+    board: &Board,
+    teammate: &Battlesnake,
+    predict: impl Fn(&Battlesnake) -> Coord,
+) -> Coord {
+    match teammate.shout.as_deref().and_then(parse_shout) {
+        Some(intent) => step(board, &teammate.body[0], intent.direction),
+        None => predict(teammate),
+    }
+}
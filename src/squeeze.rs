@@ -0,0 +1,90 @@
+// Squeezes: a snake pinned by another snake's body against the walls, or against other
+// bodies, in less room than it is long. It can't get out before it runs into its own tail,
+// so unless that body moves away in time it's as good as dead, and the kill only shows up in
+// the search a good many plies later. The room is counted like in `reachable_area`: a body
+// cell is free once its snake has moved off it by the time the pinned snake gets there, so
+// a squeeze by a tail that's about to move on doesn't count.
+
+use crate::direction::Direction;
+use crate::logic::neighbour;
+use crate::workspace::SearchWorkspace;
+use crate::{Board, Coord};
+
+// Whether the snake at `index` is walled into fewer cells than its length, with the body of
+// the snake at `by` part of the wall
+pub fn squeezed(board: &Board, index: usize, by: usize, workspace: &mut SearchWorkspace) -> bool {
+    let snake = &board.snakes[index];
+    let Some(head) = snake.body.first() else {
+        return false;
+    };
+    if board.snakes[by].body.is_empty() {
+        return false;
+    }
+    let width = board.width;
+    let height = board.height as i32;
+    let in_bounds = |c: &Coord| c.x >= 0 && c.x < width && c.y >= 0 && c.y < height;
+    if !in_bounds(head) {
+        return false;
+    }
+    let index_of = |c: &Coord| (c.y * width + c.x) as usize;
+    let cells = (width * height) as usize;
+    let length = snake.body.len();
+
+    workspace.reset(cells);
+    workspace.vacated.clear();
+    workspace.vacated.resize(cells, 0);
+    workspace.owners.clear();
+    workspace.owners.resize(cells, usize::MAX);
+    let constrictor = workspace.rules.constrictor;
+    let SearchWorkspace {
+        visited,
+        costs,
+        queue,
+        vacated,
+        owners,
+        ..
+    } = workspace;
+    for (i, other) in board.snakes.iter().enumerate() {
+        for (from_tail, segment) in other.body.iter().rev().enumerate() {
+            if in_bounds(segment) {
+                let c = index_of(segment);
+                vacated[c] = if constrictor {
+                    i32::MAX
+                } else {
+                    vacated[c].max(from_tail as i32 + 1)
+                };
+                owners[c] = i;
+            }
+        }
+    }
+
+    let mut area = 0;
+    let mut walled_by = false;
+    costs[index_of(head)] = 0;
+    visited[index_of(head)] = true;
+    queue.push_back(*head);
+    while let Some(current) = queue.pop_front() {
+        let distance = costs[index_of(&current)] + 1;
+        for direction in Direction::ALL {
+            let Some(next) = neighbour(board, &current, direction) else {
+                continue;
+            };
+            let n = index_of(&next);
+            if visited[n] {
+                continue;
+            }
+            if vacated[n] > distance {
+                walled_by |= owners[n] == by;
+                continue;
+            }
+            visited[n] = true;
+            costs[n] = distance;
+            area += 1;
+            if area >= length {
+                return false;
+            }
+            queue.push_back(next);
+        }
+    }
+    walled_by
+}
@@ -0,0 +1,107 @@
+// Stalled games: nobody eats, no snake changes length and the same positions keep coming
+// back, typically two snakes circling each other until the turn limit. During play the
+// evaluation gets the preset's contempt to break out of them, and the post-mortem reports
+// them on their own instead of as long losses.
+
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use crate::shared_store::{GameMap, SharedStore};
+use crate::{Board, GameState};
+
+// Turns without any length change before a game can count as stalled
+pub const STALL_TURNS: u32 = 30;
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct StallTracker {
+    lengths: Vec<(String, usize)>,
+    // Turns since a snake last changed length (ate or died)
+    quiet_turns: u32,
+    // How often every position was seen since then
+    seen: HashMap<u64, u32>,
+    repeated: bool,
+}
+
+fn position_key(board: &Board) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for snake in &board.snakes {
+        snake.id.hash(&mut hasher);
+        snake.body.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+impl StallTracker {
+    // Adds the next turn of the game, returns whether the game is stalled
+    pub fn observe(&mut self, board: &Board) -> bool {
+        let lengths: Vec<(String, usize)> = board
+            .snakes
+            .iter()
+            .map(|s| (s.id.clone(), s.body.len()))
+            .collect();
+        if lengths != self.lengths {
+            self.lengths = lengths;
+            self.quiet_turns = 0;
+            self.seen.clear();
+            self.repeated = false;
+        } else {
+            self.quiet_turns += 1;
+        }
+
+        let count = self.seen.entry(position_key(board)).or_insert(0);
+        *count += 1;
+        if *count > 1 {
+            self.repeated = true;
+        }
+        self.is_stalled()
+    }
+
+    pub fn is_stalled(&self) -> bool {
+        self.quiet_turns >= STALL_TURNS && self.repeated
+    }
+
+    pub fn quiet_turns(&self) -> u32 {
+        self.quiet_turns
+    }
+}
+
+// The first turn of a recording where the game was stalled, if it ever was
+pub fn find_stall(frames: &[GameState]) -> Option<i32> {
+    let mut tracker = StallTracker::default();
+    frames
+        .iter()
+        .find(|frame| tracker.observe(&frame.board))
+        .map(|frame| frame.turn)
+}
+
+// Stall trackers of the games in progress on the server
+pub struct StallMonitor {
+    games: GameMap<StallTracker>,
+}
+
+impl StallMonitor {
+    pub fn new(store: Option<Arc<SharedStore>>) -> StallMonitor {
+        StallMonitor {
+            games: GameMap::new("stall", store),
+        }
+    }
+
+    // Adds the turn to the game's tracker, returns the number of quiet turns when the game
+    // is stalled
+    pub fn observe(&self, game_id: &str, board: &Board) -> Option<u32> {
+        self.games.update(game_id, |tracker| {
+            if tracker.observe(board) {
+                Some(tracker.quiet_turns())
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn end_game(&self, game_id: &str) {
+        self.games.remove(game_id);
+    }
+}
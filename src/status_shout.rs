@@ -0,0 +1,28 @@
+// A status line in the shout of every move, for spectators of a tournament game who can't see
+// the server logs: the depth the search got to, its score and the share of the board we get
+// to first, e.g. "d:9 e:+240 space:61%". Off unless SHOUT_STATUS is set to 1, since some
+// events don't allow shouts that give away what the engine thinks. Squad games keep the shout
+// for the teammate (see squad.rs).
+
+use std::env;
+
+use crate::mate;
+
+pub const SHOUT_STATUS_ENV: &str = "SHOUT_STATUS";
+
+pub fn enabled() -> bool {
+    env::var(SHOUT_STATUS_ENV).is_ok_and(|value| value == "1")
+}
+
+pub fn status(depth: i32, score: i32, space_percent: i32) -> String {
+    let score = if mate::is_win(score) {
+        format!("win-in-{}", mate::plies(score))
+    } else if mate::is_loss(score) {
+        format!("lost-in-{}", mate::plies(score))
+    } else if score == mate::DRAW {
+        "draw".to_string()
+    } else {
+        format!("{:+}", score)
+    };
+    format!("d:{} e:{} space:{}%", depth, score, space_percent)
+}
@@ -0,0 +1,457 @@
+// Anything that can play a game of Battlesnake: our engine, simple scripted bots to play it
+// against in the arena, or a person at the terminal.
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{self, BufRead, Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use crate::direction::Direction;
+use crate::logic::{self, distance, TurnContext};
+use crate::metrics::EvalMetrics;
+use crate::oracle;
+use crate::preset::{self, Preset};
+use crate::render;
+use crate::workspace::with_workspace;
+use crate::{Battlesnake, Board, Game, GameState};
+
+pub trait Strategy {
+    fn name(&self) -> String;
+
+    // Called once before the first move of every game
+    fn start(&mut self, _game: &Game, _board: &Board, _you: &Battlesnake) {}
+
+    fn choose_move(
+        &mut self,
+        game: &Game,
+        turn: i32,
+        board: &Board,
+        you: &Battlesnake,
+    ) -> Direction;
+
+    // Called once after the last turn, `you_id` may not be on the board any more
+    fn end(&mut self, _game: &Game, _turn: i32, _board: &Board, _you_id: &str) {}
+}
+
+// Our engine, searching `depth` plies every turn with `preset`
+pub struct EngineStrategy {
+    depth: i32,
+    preset: &'static Preset,
+    metrics: EvalMetrics,
+}
+
+impl EngineStrategy {
+    pub fn new(depth: i32) -> EngineStrategy {
+        EngineStrategy::with_preset(depth, preset::active())
+    }
+
+    pub fn with_preset(depth: i32, preset: &'static Preset) -> EngineStrategy {
+        EngineStrategy {
+            depth,
+            preset,
+            metrics: EvalMetrics::default(),
+        }
+    }
+}
+
+impl Strategy for EngineStrategy {
+    fn name(&self) -> String {
+        format!("engine-d{}", self.depth)
+    }
+
+    fn start(&mut self, game: &Game, board: &Board, you: &Battlesnake) {
+        logic::start(game, &0, board, you);
+    }
+
+    fn choose_move(
+        &mut self,
+        game: &Game,
+        turn: i32,
+        board: &Board,
+        you: &Battlesnake,
+    ) -> Direction {
+        let context = TurnContext {
+            deadline: Instant::now() + Duration::from_millis(game.timeout as u64),
+            stalled: false,
+            heat: Vec::new(),
+            styles: Vec::new(),
+            preset: self.preset,
+            famine: false,
+            ponder: None,
+            progress: None,
+        };
+        let response =
+            logic::get_move_at_depth(game, &turn, board, you, self.depth, context, &self.metrics);
+        response["move"]
+            .as_str()
+            .and_then(|direction| direction.parse().ok())
+            .unwrap_or(Direction::Up)
+    }
+}
+
+// A one-ply bot that scores each legal move with a few weighted features. Different weights
+// give quite different styles: food rushers, wall huggers, head hunters cutting us off.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct ScriptedWeights {
+    // Getting closer to the nearest food
+    pub food: f64,
+    // Getting closer to the nearest wall
+    pub wall: f64,
+    // Getting closer to the nearest opponent head
+    pub aggression: f64,
+    // Reachable free space after the move
+    pub space: f64,
+}
+
+pub struct ScriptedStrategy {
+    name: String,
+    weights: ScriptedWeights,
+}
+
+impl ScriptedStrategy {
+    pub fn new(name: &str, weights: ScriptedWeights) -> ScriptedStrategy {
+        ScriptedStrategy {
+            name: name.to_string(),
+            weights,
+        }
+    }
+}
+
+// Doesn't leave the board or run into a body, tails are fine since they move away
+pub fn is_legal(board: &Board, you: &Battlesnake, direction: Direction) -> bool {
+    let next = logic::step(board, &you.body[0], direction);
+    if next.x < 0 || next.x >= board.width || next.y < 0 || next.y >= board.height as i32 {
+        return false;
+    }
+    !board
+        .snakes
+        .iter()
+        .any(|s| s.body[..s.body.len() - 1].contains(&next))
+}
+
+impl Strategy for ScriptedStrategy {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn choose_move(
+        &mut self,
+        _game: &Game,
+        _turn: i32,
+        board: &Board,
+        you: &Battlesnake,
+    ) -> Direction {
+        let cells = (board.width * board.height as i32) as usize;
+        let mut best: Option<(f64, Direction)> = None;
+        for direction in Direction::ALL {
+            if !is_legal(board, you, direction) {
+                continue;
+            }
+            let next = logic::step(board, &you.body[0], direction);
+            let food = board.food.iter().map(|f| distance(board, f, &next)).min();
+            let wall = next
+                .x
+                .min(next.y)
+                .min(board.width - 1 - next.x)
+                .min(board.height as i32 - 1 - next.y);
+            let opponent = board
+                .snakes
+                .iter()
+                .filter(|s| s.id != you.id)
+                .map(|s| distance(board, &s.body[0], &next))
+                .min();
+            let space =
+                with_workspace(|workspace| logic::flood_fill_area(board, &next, cells, workspace));
+
+            let score = -self.weights.food * food.unwrap_or(0) as f64
+                - self.weights.wall * wall as f64
+                - self.weights.aggression * opponent.unwrap_or(0) as f64
+                + self.weights.space * space as f64 / 10.0;
+            if best.is_none_or(|(best_score, _)| score > best_score) {
+                best = Some((score, direction));
+            }
+        }
+        best.map_or(Direction::Up, |(_, direction)| direction)
+    }
+}
+
+// Plays a uniformly random legal move, the weakest reference opponent
+pub struct RandomSafeStrategy {
+    rng: StdRng,
+}
+
+impl RandomSafeStrategy {
+    pub fn new(seed: u64) -> RandomSafeStrategy {
+        RandomSafeStrategy {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Strategy for RandomSafeStrategy {
+    fn name(&self) -> String {
+        "random-safe".to_string()
+    }
+
+    fn choose_move(
+        &mut self,
+        _game: &Game,
+        _turn: i32,
+        board: &Board,
+        you: &Battlesnake,
+    ) -> Direction {
+        let legal: Vec<Direction> = Direction::ALL
+            .iter()
+            .copied()
+            .filter(|&direction| is_legal(board, you, direction))
+            .collect();
+        legal
+            .choose(&mut self.rng)
+            .copied()
+            .unwrap_or(Direction::Up)
+    }
+}
+
+// Plays the move the exhaustive two turn solver (see oracle.rs) finds safest, it doesn't
+// look for food or space beyond that
+pub struct OracleStrategy;
+
+impl Strategy for OracleStrategy {
+    fn name(&self) -> String {
+        oracle::ORACLE.to_string()
+    }
+
+    fn choose_move(
+        &mut self,
+        _game: &Game,
+        _turn: i32,
+        board: &Board,
+        you: &Battlesnake,
+    ) -> Direction {
+        oracle::best_move(board, &you.id)
+    }
+}
+
+// Fixed reference opponents, so arena runs and tuning compare against the same opposition
+// every time instead of only against the engine itself
+pub const BASELINES: [&str; 5] = [
+    "random-safe",
+    "greedy-food",
+    "wall-hugger",
+    "flood-fill",
+    oracle::ORACLE,
+];
+
+pub fn baseline(name: &str, seed: u64) -> Option<Box<dyn Strategy>> {
+    let weights = |food, wall, space| ScriptedWeights {
+        food,
+        wall,
+        aggression: 0.0,
+        space,
+    };
+    let scripted = |weights| -> Option<Box<dyn Strategy>> {
+        Some(Box::new(ScriptedStrategy::new(name, weights)))
+    };
+    match name {
+        "random-safe" => Some(Box::new(RandomSafeStrategy::new(seed))),
+        // Straight for the nearest food, space only breaks ties
+        "greedy-food" => scripted(weights(1.0, 0.0, 0.1)),
+        // Stays along the walls unless that means a much smaller area
+        "wall-hugger" => scripted(weights(0.0, 1.0, 0.5)),
+        // Always keeps the most room to move, food only breaks ties
+        "flood-fill" => scripted(weights(0.01, 0.0, 1.0)),
+        oracle::ORACLE => Some(Box::new(OracleStrategy)),
+        _ => None,
+    }
+}
+
+// Extra time on top of the game timeout before a snake server counts as timed out
+const HTTP_TIMEOUT_SLACK: Duration = Duration::from_millis(200);
+
+// A snake server spoken to over the Battlesnake API, e.g. another build of this engine
+pub struct HttpStrategy {
+    name: String,
+    addr: String,
+    // Our snake as last seen, for the /end request after it died
+    last_you: Option<Battlesnake>,
+}
+
+impl HttpStrategy {
+    pub fn new(name: &str, addr: &str) -> HttpStrategy {
+        HttpStrategy {
+            name: name.to_string(),
+            addr: addr.to_string(),
+            last_you: None,
+        }
+    }
+
+    fn post(&self, path: &str, state: GameState) -> Result<Value, String> {
+        let body = serde_json::to_string(&state).map_err(|e| e.to_string())?;
+        let timeout = Duration::from_millis(state.game.timeout as u64) + HTTP_TIMEOUT_SLACK;
+        let mut stream = TcpStream::connect(&self.addr).map_err(|e| e.to_string())?;
+        stream
+            .set_read_timeout(Some(timeout))
+            .map_err(|e| e.to_string())?;
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            path,
+            self.addr,
+            body.len(),
+            body
+        )
+        .map_err(|e| e.to_string())?;
+
+        let mut response = String::new();
+        stream
+            .read_to_string(&mut response)
+            .map_err(|e| e.to_string())?;
+        Ok(response
+            .split("\r\n\r\n")
+            .nth(1)
+            .and_then(|text| serde_json::from_str(text).ok())
+            .unwrap_or(Value::Null))
+    }
+}
+
+fn game_state(game: &Game, turn: i32, board: &Board, you: &Battlesnake) -> GameState {
+    GameState {
+        game: game.clone(),
+        turn,
+        board: board.clone(),
+        you: you.clone(),
+    }
+}
+
+impl Strategy for HttpStrategy {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn start(&mut self, game: &Game, board: &Board, you: &Battlesnake) {
+        self.last_you = Some(you.clone());
+        if let Err(e) = self.post("/start", game_state(game, 0, board, you)) {
+            eprintln!("{}: /start failed: {}", self.name, e);
+        }
+    }
+
+    // A server that doesn't answer in time, or with a move that isn't one, keeps going
+    // straight, like on the real engine
+    fn choose_move(
+        &mut self,
+        game: &Game,
+        turn: i32,
+        board: &Board,
+        you: &Battlesnake,
+    ) -> Direction {
+        self.last_you = Some(you.clone());
+        let response = self.post("/move", game_state(game, turn, board, you));
+        response
+            .ok()
+            .and_then(|response| response["move"].as_str()?.parse().ok())
+            .or_else(|| {
+                you.body
+                    .get(1)
+                    .and_then(|neck| Direction::between(board, neck, &you.body[0]))
+            })
+            .unwrap_or(Direction::Up)
+    }
+
+    fn end(&mut self, game: &Game, turn: i32, board: &Board, you_id: &str) {
+        let you = board
+            .snakes
+            .iter()
+            .find(|s| s.id == you_id)
+            .cloned()
+            .or_else(|| self.last_you.take());
+        if let Some(you) = you {
+            let _ = self.post("/end", game_state(game, turn, board, &you));
+        }
+    }
+}
+
+// Someone at the terminal: every turn the board is drawn and the move read from `input`,
+// as w/a/s/d or the direction's name. Without input left the snake plays a safe move.
+pub struct HumanStrategy {
+    input: Box<dyn BufRead>,
+}
+
+impl HumanStrategy {
+    pub fn new(input: Box<dyn BufRead>) -> HumanStrategy {
+        HumanStrategy { input }
+    }
+
+    // Reads moves until one parses, None at the end of the input
+    fn read_move(&mut self) -> Option<Direction> {
+        loop {
+            print!("Move (w/a/s/d or up/down/left/right): ");
+            let _ = io::stdout().flush();
+            let mut line = String::new();
+            if self.input.read_line(&mut line).ok()? == 0 {
+                println!();
+                return None;
+            }
+            let direction = match line.trim().to_lowercase().as_str() {
+                "w" => Ok(Direction::Up),
+                "a" => Ok(Direction::Left),
+                "s" => Ok(Direction::Down),
+                "d" => Ok(Direction::Right),
+                other => other.parse(),
+            };
+            match direction {
+                Ok(direction) => return Some(direction),
+                Err(e) => println!("{}", e),
+            }
+        }
+    }
+}
+
+impl Strategy for HumanStrategy {
+    fn name(&self) -> String {
+        "human".to_string()
+    }
+
+    fn choose_move(
+        &mut self,
+        _game: &Game,
+        turn: i32,
+        board: &Board,
+        you: &Battlesnake,
+    ) -> Direction {
+        println!();
+        println!(
+            "Turn {}: health {}, length {} (you are {})",
+            turn,
+            you.health,
+            you.body.len(),
+            you.name.chars().next().unwrap_or('?').to_ascii_uppercase()
+        );
+        render::print_board(board, &you.id);
+        match self.read_move() {
+            Some(direction) => {
+                if !is_legal(board, you, direction) {
+                    println!("{} ends your game", direction);
+                }
+                direction
+            }
+            None => logic::safe_move(board, you),
+        }
+    }
+
+    fn end(&mut self, _game: &Game, turn: i32, board: &Board, you_id: &str) {
+        println!();
+        render::print_board(board, you_id);
+        let alive = board.snakes.iter().any(|s| s.id == you_id);
+        if !alive {
+            println!("Eliminated, the game ended on turn {}", turn);
+        } else if board.snakes.len() == 1 {
+            println!("You won on turn {}", turn);
+        } else {
+            println!("Still alive when the game ended on turn {}", turn);
+        }
+    }
+}
@@ -0,0 +1,467 @@
+// Traces and metrics in the OpenTelemetry format (OTLP over HTTP with JSON bodies), so the
+// server can report to an existing Grafana/Tempo stack during tournaments. Every `/move` is
+// one trace with a span for the request and one each for parsing it (from the request coming
+// in to the handler starting), the search (with the time it waited in the scheduler queue)
+// and sending the answer. The metrics are the number of moves, how many took longer than the
+// game's timeout and a histogram of their duration.
+//
+// The export itself is behind the `otlp` cargo feature and configured with the standard
+// OpenTelemetry variables: OTEL_EXPORTER_OTLP_ENDPOINT (a plain `http://` address, usually a
+// collector next to the server) and optionally OTEL_SERVICE_NAME. Spans go out as soon as
+// the move is answered, from a thread of their own; when the collector can't keep up they
+// are dropped rather than slowing down the server. An export that takes the collector longer
+// than EXPORT_TIMEOUT, to take the connection, the body or to answer, counts as failed.
+// Only the status line of the answer is read, so it can come in any framing; redirects count
+// as failures and there's no TLS, the collector is meant to run next to the server.
+
+use log::info;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::request::{self, FromRequest};
+use rocket::{Data, Request, Response};
+use serde_json::{json, Value};
+use std::env;
+use std::sync::mpsc::SyncSender;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "otlp")]
+use crate::degrade::{self, Subsystem};
+
+pub const ENDPOINT_ENV: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+pub const SERVICE_ENV: &str = "OTEL_SERVICE_NAME";
+
+// How often the metrics are sent, while moves are coming in
+const METRICS_INTERVAL: Duration = Duration::from_secs(10);
+// Exports waiting for the collector before new ones are dropped
+#[cfg(feature = "otlp")]
+const EXPORT_QUEUE: usize = 256;
+// How long one export may take, from connecting to the status line of the answer
+#[cfg(feature = "otlp")]
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(5);
+// Upper bounds of the move duration histogram buckets, in milliseconds
+const DURATION_BOUNDS: [f64; 10] = [
+    10.0, 25.0, 50.0, 100.0, 200.0, 300.0, 400.0, 500.0, 750.0, 1000.0,
+];
+
+// What happened to one request, filled in by the handler
+#[derive(Default)]
+struct Marks {
+    parsed: Option<Instant>,
+    // Submitted to the scheduler, started and finished searching
+    search: Option<(Instant, Instant, Instant)>,
+    handled: Option<Instant>,
+    game_id: String,
+    turn: i32,
+    budget: Duration,
+    depth: i32,
+    direction: String,
+}
+
+// Request guard for the timing of the request being handled
+pub struct MoveTrace {
+    received: Instant,
+    received_at: SystemTime,
+    marks: Mutex<Marks>,
+}
+
+impl Default for MoveTrace {
+    fn default() -> MoveTrace {
+        MoveTrace {
+            received: Instant::now(),
+            received_at: SystemTime::now(),
+            marks: Mutex::new(Marks::default()),
+        }
+    }
+}
+
+impl MoveTrace {
+    // The handler got the parsed request
+    pub fn parsed(&self, game_id: &str, turn: i32, budget: Duration) {
+        let mut marks = self.marks.lock().unwrap();
+        marks.parsed = Some(Instant::now());
+        marks.game_id = game_id.to_string();
+        marks.turn = turn;
+        marks.budget = budget;
+    }
+
+    pub fn searched(&self, submitted: Instant, started: Instant, finished: Instant, depth: i32) {
+        let mut marks = self.marks.lock().unwrap();
+        marks.search = Some((submitted, started, finished));
+        marks.depth = depth;
+    }
+
+    // The handler is done and `response` is what it answers
+    pub fn handled(&self, response: &Value) {
+        let mut marks = self.marks.lock().unwrap();
+        marks.handled = Some(Instant::now());
+        marks.direction = response["move"].as_str().unwrap_or_default().to_string();
+    }
+
+    fn unix_nanos(&self, at: Instant) -> String {
+        let time = self.received_at + at.saturating_duration_since(self.received);
+        let nanos = time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        nanos.to_string()
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r MoveTrace {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        request::Outcome::Success(request.local_cache(MoveTrace::default))
+    }
+}
+
+// Moves answered since the server started
+struct MoveStats {
+    started_at: String,
+    moves: u64,
+    late: u64,
+    duration_sum: f64,
+    buckets: [u64; DURATION_BOUNDS.len() + 1],
+    last_sent: Instant,
+}
+
+impl MoveStats {
+    fn record(&mut self, duration: Duration, budget: Duration) {
+        let ms = duration.as_secs_f64() * 1000.0;
+        self.moves += 1;
+        self.late += (duration > budget) as u64;
+        self.duration_sum += ms;
+        let bucket = DURATION_BOUNDS.iter().take_while(|&&b| ms > b).count();
+        self.buckets[bucket] += 1;
+    }
+
+    fn to_otlp(&self, resource: &Value, now: &str) -> Value {
+        let counter = |name: &str, description: &str, value: u64| {
+            json!({
+                "name": name,
+                "description": description,
+                "unit": "1",
+                "sum": {
+                    "aggregationTemporality": 2,
+                    "isMonotonic": true,
+                    "dataPoints": [{
+                        "startTimeUnixNano": self.started_at,
+                        "timeUnixNano": now,
+                        "asInt": value.to_string(),
+                    }],
+                },
+            })
+        };
+        let buckets: Vec<String> = self.buckets.iter().map(|b| b.to_string()).collect();
+        json!({
+            "resourceMetrics": [{
+                "resource": resource,
+                "scopeMetrics": [{
+                    "scope": { "name": env!("CARGO_PKG_NAME") },
+                    "metrics": [
+                        counter("snake.moves", "Moves answered", self.moves),
+                        counter("snake.moves.late", "Moves answered after the timeout", self.late),
+                        {
+                            "name": "snake.move.duration",
+                            "description": "Time from the request coming in to the answer",
+                            "unit": "ms",
+                            "histogram": {
+                                "aggregationTemporality": 2,
+                                "dataPoints": [{
+                                    "startTimeUnixNano": self.started_at,
+                                    "timeUnixNano": now,
+                                    "count": self.moves.to_string(),
+                                    "sum": self.duration_sum,
+                                    "bucketCounts": buckets,
+                                    "explicitBounds": DURATION_BOUNDS,
+                                }],
+                            },
+                        },
+                    ],
+                }],
+            }],
+        })
+    }
+}
+
+fn attribute(key: &str, value: Value) -> Value {
+    let value = match value {
+        Value::String(s) => json!({ "stringValue": s }),
+        Value::Number(n) => json!({ "intValue": n.to_string() }),
+        other => json!({ "stringValue": other.to_string() }),
+    };
+    json!({ "key": key, "value": value })
+}
+
+fn span_id() -> String {
+    format!("{:016x}", rand::random::<u64>())
+}
+
+// Spans of one answered move
+fn move_spans(trace: &MoveTrace, marks: &Marks, done: Instant, status: u16) -> Vec<Value> {
+    let trace_id = format!("{:032x}", rand::random::<u128>());
+    let root_id = span_id();
+    let span = |id: &str, parent: &str, name: &str, start: Instant, end: Instant, kind: u8| {
+        json!({
+            "traceId": trace_id,
+            "spanId": id,
+            "parentSpanId": parent,
+            "name": name,
+            "kind": kind,
+            "startTimeUnixNano": trace.unix_nanos(start),
+            "endTimeUnixNano": trace.unix_nanos(end),
+        })
+    };
+
+    let mut root = span(&root_id, "", "POST /move", trace.received, done, 2);
+    root["attributes"] = json!([
+        attribute("http.response.status_code", json!(status)),
+        attribute("game.id", json!(marks.game_id)),
+        attribute("game.turn", json!(marks.turn)),
+        attribute("snake.move", json!(marks.direction)),
+    ]);
+    let mut spans = vec![root];
+    if let Some(parsed) = marks.parsed {
+        spans.push(span(
+            &span_id(),
+            &root_id,
+            "parse",
+            trace.received,
+            parsed,
+            1,
+        ));
+    }
+    if let Some((submitted, started, finished)) = marks.search {
+        let mut search = span(&span_id(), &root_id, "search", submitted, finished, 1);
+        let queued = started.saturating_duration_since(submitted).as_millis() as u64;
+        search["attributes"] = json!([
+            attribute("search.depth", json!(marks.depth)),
+            attribute("search.queue_ms", json!(queued)),
+        ]);
+        spans.push(search);
+    }
+    if let Some(handled) = marks.handled {
+        spans.push(span(&span_id(), &root_id, "respond", handled, done, 1));
+    }
+    spans
+}
+
+// Sends the traces and metrics of the `/move` requests, when exporting is on
+pub struct Telemetry {
+    exporter: Option<SyncSender<(&'static str, Value)>>,
+    resource: Value,
+    stats: Mutex<MoveStats>,
+}
+
+impl Telemetry {
+    pub fn from_env() -> Telemetry {
+        let service = env::var(SERVICE_ENV).unwrap_or_else(|_| env!("CARGO_PKG_NAME").into());
+        let exporter = env::var(ENDPOINT_ENV)
+            .ok()
+            .filter(|endpoint| !endpoint.is_empty())
+            .and_then(|endpoint| start_exporter(&endpoint));
+        Telemetry {
+            exporter,
+            resource: json!({
+                "attributes": [
+                    attribute("service.name", json!(service)),
+                    attribute("service.version", json!(env!("CARGO_PKG_VERSION"))),
+                ],
+            }),
+            stats: Mutex::new(MoveStats {
+                started_at: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_nanos()
+                    .to_string(),
+                moves: 0,
+                late: 0,
+                duration_sum: 0.0,
+                buckets: [0; DURATION_BOUNDS.len() + 1],
+                last_sent: Instant::now(),
+            }),
+        }
+    }
+
+    fn export(&self, path: &'static str, body: Value) {
+        if let Some(exporter) = &self.exporter {
+            // A full queue means the collector is behind, drop rather than wait
+            let _ = exporter.try_send((path, body));
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Telemetry {
+    fn info(&self) -> Info {
+        Info {
+            name: "OTLP telemetry",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        if self.exporter.is_some() {
+            request.local_cache(MoveTrace::default);
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if self.exporter.is_none() || request.uri().path() != "/move" {
+            return;
+        }
+        let done = Instant::now();
+        let trace = request.local_cache(MoveTrace::default);
+        let marks = trace.marks.lock().unwrap();
+        let spans = move_spans(trace, &marks, done, response.status().code);
+        self.export(
+            "/v1/traces",
+            json!({
+                "resourceSpans": [{
+                    "resource": self.resource,
+                    "scopeSpans": [{
+                        "scope": { "name": env!("CARGO_PKG_NAME") },
+                        "spans": spans,
+                    }],
+                }],
+            }),
+        );
+
+        let mut stats = self.stats.lock().unwrap();
+        stats.record(done.duration_since(trace.received), marks.budget);
+        if stats.last_sent.elapsed() >= METRICS_INTERVAL {
+            stats.last_sent = Instant::now();
+            let metrics = stats.to_otlp(&self.resource, &trace.unix_nanos(done));
+            self.export("/v1/metrics", metrics);
+        }
+    }
+}
+
+#[cfg(not(feature = "otlp"))]
+fn start_exporter(_endpoint: &str) -> Option<SyncSender<(&'static str, Value)>> {
+    info!(
+        "{} is set, but this build has no OTLP export, build with `--features otlp`",
+        ENDPOINT_ENV
+    );
+    None
+}
+
+// Starts the thread that posts the exports to the collector at `endpoint`
+#[cfg(feature = "otlp")]
+fn start_exporter(endpoint: &str) -> Option<SyncSender<(&'static str, Value)>> {
+    let (addr, base) = match parse_endpoint(endpoint) {
+        Some(parts) => parts,
+        None => {
+            info!(
+                "Not exporting telemetry: {} has to be an http:// address, not '{}'",
+                ENDPOINT_ENV, endpoint
+            );
+            return None;
+        }
+    };
+    info!("Exporting traces and metrics to {}", endpoint);
+    let (sender, receiver) = std::sync::mpsc::sync_channel::<(&'static str, Value)>(EXPORT_QUEUE);
+    let spawned = std::thread::Builder::new()
+        .name("telemetry".to_string())
+        .spawn(move || {
+            // Only log the first failure of a streak, the collector may be down for a while
+            let mut failing = false;
+            for (path, body) in receiver {
+                match post(&addr, &format!("{}{}", base, path), &body) {
+                    Ok(()) if failing => {
+                        info!("Telemetry export to {} works again", addr);
+                        failing = false;
+                    }
+                    Ok(()) => {}
+                    Err(e) if !failing => {
+                        info!("Telemetry export to {} failed: {}", addr, e);
+                        failing = true;
+                    }
+                    Err(_) => {}
+                }
+            }
+        });
+    if let Err(e) = spawned {
+        let error = format!("could not start the exporter thread: {}", e);
+        degrade::report(Subsystem::Telemetry, &error);
+        return None;
+    }
+    Some(sender)
+}
+
+// `http://host:port/base` as the address to connect to and the base path
+#[cfg(feature = "otlp")]
+fn parse_endpoint(endpoint: &str) -> Option<(String, String)> {
+    let rest = endpoint.strip_prefix("http://")?;
+    let (addr, base) = match rest.find('/') {
+        Some(slash) => rest.split_at(slash),
+        None => (rest, ""),
+    };
+    let addr = if addr.contains(':') {
+        addr.to_string()
+    } else {
+        format!("{}:80", addr)
+    };
+    Some((addr, base.trim_end_matches('/').to_string()))
+}
+
+// Minimal HTTP/1.1 POST of a JSON body, Ok on any 2xx answer. Gives up at EXPORT_TIMEOUT.
+#[cfg(feature = "otlp")]
+fn post(addr: &str, path: &str, body: &Value) -> Result<(), String> {
+    use std::io::{ErrorKind, Read, Write};
+    use std::net::{TcpStream, ToSocketAddrs};
+
+    let deadline = Instant::now() + EXPORT_TIMEOUT;
+    let socket = addr
+        .to_socket_addrs()
+        .map_err(|e| e.to_string())?
+        .next()
+        .ok_or_else(|| format!("{} has no address", addr))?;
+    let mut stream =
+        TcpStream::connect_timeout(&socket, EXPORT_TIMEOUT).map_err(|e| e.to_string())?;
+    let left = || {
+        deadline
+            .checked_duration_since(Instant::now())
+            .filter(|left| !left.is_zero())
+            .ok_or_else(|| format!("no answer from {} in {:?}", addr, EXPORT_TIMEOUT))
+    };
+    stream
+        .set_write_timeout(Some(left()?))
+        .map_err(|e| e.to_string())?;
+    let body = body.to_string();
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        addr,
+        body.len(),
+        body
+    )
+    .map_err(|e| e.to_string())?;
+
+    // The status line is all we need, a slow or endless answer isn't waited for
+    let mut response = Vec::new();
+    let mut chunk = [0; 512];
+    while !response.windows(2).any(|w| w == b"\r\n") {
+        stream
+            .set_read_timeout(Some(left()?))
+            .map_err(|e| e.to_string())?;
+        match stream.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(read) => response.extend_from_slice(&chunk[..read]),
+            // The read timeout ran out with the deadline
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                left()?;
+            }
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    let response = String::from_utf8_lossy(&response);
+    let status = response.split_whitespace().nth(1).unwrap_or("");
+    if status.starts_with('2') {
+        Ok(())
+    } else {
+        Err(format!("{} answered {}", path, status))
+    }
+}
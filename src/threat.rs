@@ -0,0 +1,107 @@
+// How much each opponent gets to decide the outcome of the search. With three or four snakes
+// the search is paranoid: every opponent picks the reply that is worst for us, so a starving
+// snake on the far side of the board vetoes a plan just as hard as a long one next to our head.
+// Each opponent gets a weight from how much harm it can actually do, and its nodes back up
+// that share of the worst reply plus the rest of the average reply. The weights are taken once
+// at the root of the search, and in duels every opponent keeps the full weight.
+//
+// OPPONENT_PARANOIA sets the weight of opponents by name instead, in any game, e.g.
+// `Slippy:40,*:80`, where `*` stands for every opponent not listed. The average can be weighed
+// by how likely each reply is, see opponent_model.rs.
+
+use log::info;
+use std::env;
+use std::sync::OnceLock;
+
+use crate::logic::distance;
+use crate::Board;
+
+pub const PARANOIA_ENV: &str = "OPPONENT_PARANOIA";
+
+// Percent of the worst reply an opponent that can do full harm backs up
+pub const FULL_THREAT: i32 = 100;
+// Opponents whose head is at most this far from ours are fully in range
+const THREAT_RANGE: i32 = 3;
+// Share of its weight a shorter opponent keeps, it loses a head-to-head but still cuts us off
+const SHORTER: i32 = 75;
+// Even a harmless looking opponent keeps some veto
+const THREAT_FLOOR: i32 = 25;
+
+// OPPONENT_PARANOIA as (snake name, percent), entries that don't parse are left out
+fn configured() -> &'static [(String, i32)] {
+    static CONFIGURED: OnceLock<Vec<(String, i32)>> = OnceLock::new();
+    CONFIGURED.get_or_init(|| {
+        let mut weights = Vec::new();
+        for entry in env::var(PARANOIA_ENV).unwrap_or_default().split(',') {
+            let entry = entry.trim();
+            if entry.is_empty() {
+                continue;
+            }
+            match entry.rsplit_once(':').map(|(name, n)| (name, n.parse())) {
+                Some((name, Ok(percent))) => {
+                    weights.push((name.to_string(), FULL_THREAT.min(percent)))
+                }
+                _ => info!(
+                    "Ignoring '{}' in {}, expected name:percent",
+                    entry, PARANOIA_ENV
+                ),
+            }
+        }
+        weights
+    })
+}
+
+// The weight OPPONENT_PARANOIA gives the opponent called `name`, if any
+fn configured_weight(name: &str) -> Option<i32> {
+    let configured = configured();
+    configured
+        .iter()
+        .find(|(n, _)| n == name)
+        .or_else(|| configured.iter().find(|(n, _)| n == "*"))
+        .map(|&(_, percent)| percent.max(0))
+}
+
+// Fills `weights` with the threat of every snake of `board` to the one at `you_index`, in
+// percent. We and the dead snakes get the full weight, so do all opponents in duels unless
+// OPPONENT_PARANOIA says otherwise.
+pub fn weigh(board: &Board, you_index: usize, weights: &mut Vec<i32>) {
+    weights.clear();
+    weights.resize(board.snakes.len(), FULL_THREAT);
+    let alive = board.snakes.iter().filter(|s| !s.body.is_empty()).count();
+    let you = &board.snakes[you_index];
+    if you.body.is_empty() {
+        return;
+    }
+
+    for (index, snake) in board.snakes.iter().enumerate() {
+        if index == you_index || snake.body.is_empty() {
+            continue;
+        }
+        if let Some(weight) = configured_weight(&snake.name) {
+            weights[index] = weight;
+            continue;
+        }
+        if alive < 3 {
+            continue;
+        }
+        let d = distance(board, &snake.body[0], &you.body[0]).max(1);
+        let mut weight = FULL_THREAT * THREAT_RANGE.min(d) / d;
+        if snake.body.len() < you.body.len() {
+            weight = weight * SHORTER / 100;
+        }
+        // Starves before it gets to us
+        if snake.health < d {
+            weight = weight * snake.health.max(0) / d;
+        }
+        weights[index] = weight.max(THREAT_FLOOR);
+    }
+}
+
+// Score of an opponent node with threat `weight` from its worst reply for us and the sum of
+// the scores of its `replies`, each counted as often as its likelihood
+pub fn backup(worst: i32, sum: i64, replies: i64, weight: i32) -> i32 {
+    let mean = sum / replies.max(1);
+    let weight = weight as i64;
+    let score = (weight * worst as i64 + (FULL_THREAT as i64 - weight) * mean) / FULL_THREAT as i64;
+    score.clamp(i32::MIN as i64, i32::MAX as i64) as i32
+}
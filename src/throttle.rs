@@ -0,0 +1,177 @@
+// Watchdog on how much of the time budget the moves take. On a shared machine a noisy
+// neighbour can slow every search down for hours, and nobody is around mid-tournament to
+// pick a lighter preset. When most recent moves take more than 70% of the game's timeout,
+// later searches go less deep and fewer of them run at once; once the moves are fast again
+// they step back up. Every change is logged as THROTTLE and the state is in /metrics.
+//
+// A single late answer loses the game, and a move that usually takes 80% of the timeout can
+// take 110% when an allocator stall or a neighbour hits at the wrong moment. So the average
+// isn't enough: the searches of a move also leave a reserve of three standard deviations of
+// the recent move times, and after a move that came within 10% of the timeout (logged as
+// CLOSE CALL) a further 15% of the timeout for the next 10 moves.
+
+use log::info;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Moves the decision is based on
+const WINDOW: usize = 10;
+// Share of the budget a move may take before it counts as slow
+const SLOW: f64 = 0.7;
+// Slow moves out of the window that throttle down
+const SLOW_MOVES: usize = 8;
+// Share of the budget every move of the window has to stay under to step back up. Low,
+// since the step up makes the search several times slower again.
+const FAST: f64 = 0.15;
+const MAX_LEVEL: u32 = 3;
+// Plies less per level, and the least depth we throttle down to
+const DEPTH_STEP: i32 = 2;
+const MIN_DEPTH: i32 = 4;
+
+// Moves the spread of the move times is taken over
+const SPREAD_WINDOW: usize = 20;
+// Standard deviations of the move times kept in reserve
+const SPREAD_RESERVE: f64 = 3.0;
+// Share of the budget a move may take before it counts as a close call
+const CLOSE_CALL: f64 = 0.9;
+// Share of the budget kept in reserve after a close call, and for how many moves
+const CLOSE_CALL_RESERVE: f64 = 0.15;
+const CLOSE_CALL_MOVES: u32 = 10;
+// Most of the budget the reserve takes, the search still needs some time
+const MAX_RESERVE: f64 = 0.4;
+
+#[derive(Default)]
+struct ThrottleState {
+    // Share of the budget the last moves took, oldest first
+    recent: VecDeque<f64>,
+    level: u32,
+    // Share of the budget the last SPREAD_WINDOW moves took, kept across levels
+    spread: VecDeque<f64>,
+    // Moves left with the close call reserve
+    close_call: u32,
+}
+
+impl ThrottleState {
+    // Standard deviation of the shares in `spread`
+    fn deviation(&self) -> f64 {
+        let n = self.spread.len();
+        if n == 0 {
+            return 0.0;
+        }
+        let mean = self.spread.iter().sum::<f64>() / n as f64;
+        let variance = self.spread.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / n as f64;
+        variance.sqrt()
+    }
+
+    // Share of the budget the next move keeps in reserve
+    fn reserve(&self) -> f64 {
+        let deviation = self.deviation();
+        let close_call = if self.close_call > 0 {
+            CLOSE_CALL_RESERVE
+        } else {
+            0.0
+        };
+        (SPREAD_RESERVE * deviation + close_call).min(MAX_RESERVE)
+    }
+}
+
+#[derive(Default)]
+pub struct Throttle {
+    state: Mutex<ThrottleState>,
+}
+
+// Search depth at throttle `level` for the preset's `depth`
+fn depth_at(level: u32, depth: i32) -> i32 {
+    (depth - DEPTH_STEP * level as i32).max(MIN_DEPTH.min(depth))
+}
+
+// Search threads at throttle `level` out of `threads`, halved per level
+fn threads_at(level: u32, threads: usize) -> usize {
+    (threads >> level).max(1)
+}
+
+impl Throttle {
+    pub fn level(&self) -> u32 {
+        self.state.lock().unwrap().level
+    }
+
+    pub fn depth(&self, depth: i32) -> i32 {
+        depth_at(self.level(), depth)
+    }
+
+    pub fn threads(&self, threads: usize) -> usize {
+        threads_at(self.level(), threads)
+    }
+
+    // How much earlier than the timeout of `budget` the next move should be answered
+    pub fn reserve(&self, budget: Duration) -> Duration {
+        budget.mul_f64(self.state.lock().unwrap().reserve())
+    }
+
+    // Adds a move that took `used` out of `budget`. Returns the new level when it changed.
+    pub fn record(&self, used: Duration, budget: Duration) -> Option<u32> {
+        let mut state = self.state.lock().unwrap();
+        let share = used.as_secs_f64() / budget.as_secs_f64().max(0.001);
+        state.spread.push_back(share);
+        if state.spread.len() > SPREAD_WINDOW {
+            state.spread.pop_front();
+        }
+        if share > CLOSE_CALL {
+            state.close_call = CLOSE_CALL_MOVES;
+            info!(
+                "CLOSE CALL: a move took {:.0}% of the budget, keeping {:.0}% in reserve",
+                100.0 * share,
+                100.0 * state.reserve()
+            );
+        } else {
+            state.close_call = state.close_call.saturating_sub(1);
+        }
+        state.recent.push_back(share);
+        if state.recent.len() > WINDOW {
+            state.recent.pop_front();
+        }
+        if state.recent.len() < WINDOW {
+            return None;
+        }
+
+        let slow = state.recent.iter().filter(|&&s| s > SLOW).count();
+        let average = state.recent.iter().sum::<f64>() / WINDOW as f64;
+        let level = if slow >= SLOW_MOVES && state.level < MAX_LEVEL {
+            state.level + 1
+        } else if state.recent.iter().all(|&s| s < FAST) && state.level > 0 {
+            state.level - 1
+        } else {
+            return None;
+        };
+        info!(
+            "THROTTLE {} to level {}: moves took {:.0}% of the budget on average",
+            if level > state.level { "down" } else { "up" },
+            level,
+            100.0 * average
+        );
+        state.level = level;
+        // Judge the new level on its own moves
+        state.recent.clear();
+        Some(level)
+    }
+
+    pub fn to_json(&self, depth: i32, threads: usize) -> Value {
+        let state = self.state.lock().unwrap();
+        let average = match state.recent.len() {
+            0 => 0.0,
+            n => state.recent.iter().sum::<f64>() / n as f64,
+        };
+        let deviation = state.deviation();
+        json!({
+            "level": state.level,
+            "depth": depth_at(state.level, depth),
+            "threads": threads_at(state.level, threads),
+            "budget_used": average,
+            "budget_deviation": deviation,
+            "reserve": state.reserve(),
+            "close_call_moves": state.close_call,
+        })
+    }
+}
@@ -0,0 +1,209 @@
+// Tunes the evaluation weights by self-play with the cross-entropy method. Every weight gets
+// a normal distribution, starting at the preset's value. Each generation samples
+// `--population` weight sets from those distributions and plays each one `--games` duels
+// against the preset as it is (see selfplay.rs). The `--elite` best then give the
+// distributions their new means and spreads. The means are what the tuner converges on, so
+// they're written to `--out` after every generation as a JSON object of weights, ready for
+// SNAKE_EVAL_WEIGHTS or an arena `--engine` configuration.
+//
+// A few dozen duels tell weight sets apart only roughly, so the spreads shrink slowly (see
+// SMOOTHING) and no single lucky sample decides the result. Check the written weights with
+// the arena over a few hundred games before using them.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::{Map, Value};
+use std::f64::consts::PI;
+use std::fs;
+use std::thread;
+
+use crate::arena::{parse_size, set_ruleset, ArenaConfig};
+use crate::preset::{self, EvalWeights, Preset, DEFAULT_PRESET};
+use crate::selfplay::{play_series, Engine, SeriesResults};
+
+// Share of the old distribution kept every generation
+const SMOOTHING: f64 = 0.3;
+// Spread of the first generation, as a share of the starting weight...
+const START_SPREAD: f64 = 0.3;
+// ... and at least this much, so weights at 0 move too
+const MIN_SPREAD: f64 = 1.0;
+
+struct Options {
+    preset: &'static Preset,
+    generations: u32,
+    population: usize,
+    elite: usize,
+    games: u32,
+    depth: i32,
+    seed: u64,
+    threads: usize,
+    out: String,
+    config: ArenaConfig,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        preset: preset::find(DEFAULT_PRESET).unwrap(),
+        generations: 10,
+        population: 8,
+        elite: 2,
+        games: 20,
+        depth: 3,
+        seed: 1,
+        threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        out: "tuned-weights.json".to_string(),
+        config: ArenaConfig::default(),
+    };
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", flag))?;
+        let invalid = |_| format!("invalid value '{}' for {}", value, flag);
+        match flag.as_str() {
+            "--preset" => {
+                options.preset =
+                    preset::find(value).ok_or_else(|| format!("unknown preset '{}'", value))?
+            }
+            "--generations" => options.generations = value.parse().map_err(invalid)?,
+            "--population" => options.population = value.parse().map_err(invalid)?,
+            "--elite" => options.elite = value.parse().map_err(invalid)?,
+            "--games" => options.games = value.parse().map_err(invalid)?,
+            "--depth" => options.depth = value.parse().map_err(invalid)?,
+            "--seed" => options.seed = value.parse().map_err(invalid)?,
+            "--threads" => options.threads = value.parse().map_err(invalid)?,
+            "--out" => options.out = value.clone(),
+            "--ruleset" => set_ruleset(&mut options.config.rules, value)?,
+            "--size" => (options.config.width, options.config.height) = parse_size(value)?,
+            _ => return Err(format!("unknown option '{}'", flag)),
+        }
+    }
+    if options.elite == 0 || options.elite > options.population {
+        return Err(format!(
+            "--elite is 1 to --population ({}), not {}",
+            options.population, options.elite
+        ));
+    }
+    Ok(options)
+}
+
+// The weights by name, in the order EvalWeights serializes them
+fn to_map(weights: &EvalWeights) -> Map<String, Value> {
+    match serde_json::to_value(weights) {
+        Ok(Value::Object(map)) => map,
+        _ => unreachable!("evaluation weights serialize to an object"),
+    }
+}
+
+// Weights from their values in `names` order, rounded and never below 0
+fn from_values(base: &EvalWeights, names: &[String], values: &[f64]) -> EvalWeights {
+    let overrides: Map<String, Value> = names
+        .iter()
+        .zip(values)
+        .map(|(name, &value)| (name.clone(), Value::from(value.round().max(0.0) as i64)))
+        .collect();
+    preset::with_overrides(base, &overrides).unwrap()
+}
+
+// A draw from the standard normal distribution (Box-Muller)
+fn standard_normal(rng: &mut StdRng) -> f64 {
+    let u: f64 = rng.gen_range(f64::EPSILON..1.0);
+    let v: f64 = rng.gen();
+    (-2.0 * u.ln()).sqrt() * (2.0 * PI * v).cos()
+}
+
+// The candidate's share of the points of its duels, a draw counting half
+fn score(results: &SeriesResults) -> f64 {
+    let points: f64 = results
+        .iter()
+        .map(|(winner, _)| match winner {
+            Some(1) => 1.0,
+            None => 0.5,
+            Some(_) => 0.0,
+        })
+        .sum();
+    points / results.len().max(1) as f64
+}
+
+fn write_weights(path: &str, weights: &EvalWeights) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(weights).map_err(|e| e.to_string())?;
+    fs::write(path, json + "\n").map_err(|e| format!("can't write {}: {}", path, e))
+}
+
+// Entry point for `starter-snake-rust tune [--preset NAME] [--generations N] [--population N]
+// [--elite N] [--games N] [--depth D] [--seed S] [--threads T] [--out FILE]
+// [--ruleset standard|royale|wrapped|constrictor] [--size WxH]`
+pub fn run(args: &[String]) -> Result<(), String> {
+    let options = parse_options(args)?;
+    let base = options.preset;
+    let start = to_map(&base.eval);
+    let names: Vec<String> = start.keys().cloned().collect();
+    let mut mean: Vec<f64> = start.values().map(|v| v.as_f64().unwrap_or(0.0)).collect();
+    let mut spread: Vec<f64> = mean
+        .iter()
+        .map(|m| (m.abs() * START_SPREAD).max(MIN_SPREAD))
+        .collect();
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    println!(
+        "Tuning the weights of {} with {} duels per weight set at depth {}",
+        base.name, options.games, options.depth
+    );
+
+    for generation in 1..=options.generations {
+        let samples: Vec<Vec<f64>> = (0..options.population)
+            .map(|_| {
+                mean.iter()
+                    .zip(&spread)
+                    .map(|(m, s)| (m + s * standard_normal(&mut rng)).max(0.0))
+                    .collect()
+            })
+            .collect();
+        // Engine 0 is the preset as it is, every sample plays it in its own series
+        let mut engines = vec![Engine::new(base.name, options.depth, *base)];
+        for (i, values) in samples.iter().enumerate() {
+            let preset = Preset {
+                eval: from_values(&base.eval, &names, values),
+                ..*base
+            };
+            engines.push(Engine::new(&format!("sample-{}", i), options.depth, preset));
+        }
+        let series: Vec<Vec<usize>> = (1..engines.len()).map(|i| vec![0, i]).collect();
+        let seed = options.seed + generation as u64 * 7_919;
+        let results = play_series(
+            &engines,
+            &series,
+            options.games,
+            seed,
+            options.threads,
+            &options.config,
+        );
+
+        let mut ranked: Vec<(f64, &Vec<f64>)> = results.iter().map(score).zip(&samples).collect();
+        ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let elite = &ranked[..options.elite];
+        for (i, (m, s)) in mean.iter_mut().zip(spread.iter_mut()).enumerate() {
+            let elite_mean = elite.iter().map(|(_, v)| v[i]).sum::<f64>() / elite.len() as f64;
+            let elite_spread = (elite
+                .iter()
+                .map(|(_, v)| (v[i] - elite_mean).powi(2))
+                .sum::<f64>()
+                / elite.len() as f64)
+                .sqrt();
+            *m = SMOOTHING * *m + (1.0 - SMOOTHING) * elite_mean;
+            *s = (SMOOTHING * *s + (1.0 - SMOOTHING) * elite_spread).max(MIN_SPREAD);
+        }
+
+        let weights = from_values(&base.eval, &names, &mean);
+        write_weights(&options.out, &weights)?;
+        let elite_score = elite.iter().map(|(score, _)| score).sum::<f64>() / elite.len() as f64;
+        println!(
+            "generation {}: best sample scored {:.2}, elite {:.2}; mean weights written to {}",
+            generation, ranked[0].0, elite_score, options.out
+        );
+        println!(
+            "  {}",
+            serde_json::to_string(&weights).map_err(|e| e.to_string())?
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,30 @@
+// The watchdog of a move request. The search runs on a search thread (see scheduler.rs) and
+// should stop well before the answer is due, but a search stuck in the queue behind other
+// games or in an evaluation slower than the clock checks can't be allowed to lose the game
+// to a timeout. The request handler only waits for it until the answer is due, the game's
+// timeout less the room for the network (see network.rs). When the search hasn't answered
+// by then, the handler answers with the move of the deepest search of the turn that
+// finished, or with the safe move that leaves the most room when none did, and logs it as
+// WATCHDOG. The search is left to run out on its own.
+
+use std::sync::Mutex;
+
+use crate::direction::Direction;
+
+// The deepest search of a turn that finished so far
+#[derive(Default)]
+pub struct SearchProgress {
+    best: Mutex<Option<(i32, Direction)>>,
+}
+
+impl SearchProgress {
+    // The search of `depth` plies finished with `direction` as its best move
+    pub fn finished(&self, depth: i32, direction: Direction) {
+        *self.best.lock().unwrap() = Some((depth, direction));
+    }
+
+    // The depth and move of the deepest search that finished
+    pub fn best(&self) -> Option<(i32, Direction)> {
+        *self.best.lock().unwrap()
+    }
+}
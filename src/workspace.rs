@@ -0,0 +1,514 @@
+// Scratch buffers for the search. Every thread that searches owns one of these, created the
+// first time the thread searches and kept for its lifetime, and passes it down by `&mut` so
+// the flood fills and path searches done at every node reuse the same memory instead of
+// allocating.
+
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::deepening::CLOCK_INTERVAL;
+use crate::direction::Direction;
+use crate::metrics::EvalTotals;
+use crate::opponent_style::OpponentStyle;
+use crate::preset::{self, Preset};
+use crate::royale::ShrinkForecast;
+use crate::rules::RulesConfig;
+use crate::search_trace::SearchTrace;
+use crate::{Board, Coord};
+
+// Score a root move got in the last search. Moves searched after a better one only get an
+// upper bound, since alpha-beta stops as soon as they can't beat it.
+#[derive(Debug, Clone, Copy)]
+pub struct RootCandidate {
+    pub direction: Direction,
+    pub score: i32,
+    pub exact: bool,
+    // Opponents trapped by our body along the move's line, see `pv_kills`
+    pub kills: u32,
+}
+
+// How well alpha-beta prunes: the nodes with at least one legal move, the ones of them cut
+// off before every move was searched, and the ones cut off by the first move searched
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CutoffStats {
+    pub nodes: u64,
+    pub cutoffs: u64,
+    pub first_move: u64,
+}
+
+impl CutoffStats {
+    pub fn merge(&mut self, other: &CutoffStats) {
+        self.nodes += other.nodes;
+        self.cutoffs += other.cutoffs;
+        self.first_move += other.first_move;
+    }
+}
+
+// Killer moves kept per ply
+const KILLERS: usize = 2;
+
+// What a finished search leaves behind, to go back to when a deeper one is abandoned
+pub struct SearchSnapshot {
+    root_depth: i32,
+    root_candidates: Vec<RootCandidate>,
+    pv: Vec<(usize, Direction)>,
+    pv_kills: u32,
+    trace: Option<SearchTrace>,
+}
+
+// What a finished search leaves for another search of the same position to go on from, on
+// another thread: its results and what orders the moves of the next depth (see ponder.rs)
+pub struct SearchHandoff {
+    snapshot: SearchSnapshot,
+    refutations: Vec<(usize, i32, Direction)>,
+    killers: Vec<[Option<(usize, Direction)>; KILLERS]>,
+    history: Vec<i32>,
+}
+
+impl SearchHandoff {
+    pub fn depth(&self) -> i32 {
+        self.snapshot.root_depth
+    }
+}
+
+// Enough room for the largest standard board (25x25) so nothing grows during a search
+const PREALLOCATED_CELLS: usize = 25 * 25;
+
+pub struct SearchWorkspace {
+    // Per cell flags for breadth-first searches
+    pub visited: Vec<bool>,
+    // Per cell best known cost for path searches
+    pub costs: Vec<i32>,
+    pub queue: VecDeque<Coord>,
+    // Per cell index of the snake that gets there first, for territory searches
+    pub owners: Vec<usize>,
+    // Per cell turns until the body on it moves away, for reachable space searches
+    pub vacated: Vec<i32>,
+    pub heap: BinaryHeap<Reverse<(i32, i32, i32)>>,
+    // Per cell low link, cells of its block and room of its largest pocket, and the stack of
+    // the depth-first search, for dead end searches (see dead_ends.rs)
+    pub low: Vec<i32>,
+    pub block: Vec<i32>,
+    pub pocket: Vec<i32>,
+    pub dfs: Vec<(usize, usize)>,
+    // Evaluation terms of every leaf scored since the last `take_eval_totals`
+    pub eval_totals: EvalTotals,
+    // Remaining depth at the root of the current search
+    pub root_depth: i32,
+    pub root_candidates: Vec<RootCandidate>,
+    // Best line found below a node with the given remaining depth, as (snake index, move)
+    pub pv: Vec<Vec<(usize, Direction)>>,
+    // Opponents the line in `pv` at the same index traps with our body
+    pub pv_kills: Vec<u32>,
+    // When the current search has to be done by, None for searches without a time limit
+    pub deadline: Option<Instant>,
+    // The game of the current search is stalled
+    pub stalled: bool,
+    // Per snake index, whether the snake was alive at the root of the current search
+    pub root_alive: Vec<bool>,
+    // Score every leaf with the rich evaluation whatever the depth, for offline analysis
+    pub force_rich: bool,
+    // Recent opponent traffic per cell of the game being searched (see heat.rs), empty when
+    // the search has no history of the game
+    pub heat: Vec<i32>,
+    // How every snake of the game being searched has been playing, by index (see
+    // opponent_style.rs), empty when the search has no history of the game
+    pub styles: Vec<OpponentStyle>,
+    // Per snake index, the percent of its worst reply an opponent node backs up (see
+    // threat.rs), empty when every opponent plays its worst reply
+    pub threat: Vec<i32>,
+    // Node record of the current search, None unless it is traced (see search_trace.rs)
+    pub trace: Option<SearchTrace>,
+    // Moves picked so far in the turn being searched, played together once every snake has
+    // picked one (see `simulate_turn`)
+    pub pending: Vec<(usize, Direction)>,
+    // Where the moves of the turn being searched start in `pending`
+    pub turn_start: usize,
+    // Search settings and evaluation weights of the current search
+    pub preset: &'static Preset,
+    // The game of the current search is in a famine, see famine.rs
+    pub famine: bool,
+    // The rules of the game of the current search: hazard damage, constrictor growth
+    pub rules: RulesConfig,
+    // The next royale shrink when the current search can reach it (see royale.rs), the turns
+    // played so far in the line being searched, and where the shrink's cells start in the
+    // board's hazards once it's played
+    pub shrink: Option<ShrinkForecast>,
+    pub turns_played: i32,
+    pub hazards_before_shrink: usize,
+    // Opponent replies in the first turn that refuted one of our root moves, as (snake index,
+    // plies below the root, move). Kept across the deepening of a turn by `deepen_search`, and
+    // tried first below the other root moves.
+    pub refutations: Vec<(usize, i32, Direction)>,
+    // Per ply below the root, the last moves that cut a node off there, as (snake index,
+    // move), most recent first. Kept across the deepening of a turn like the refutations.
+    pub killers: Vec<[Option<(usize, Direction)>; KILLERS]>,
+    // Per snake index, head cell and move, the square of the remaining depth summed over the
+    // cutoffs the move caused this turn
+    pub history: Vec<i32>,
+    // When the current search has to be abandoned, see deepening.rs
+    pub stop_at: Option<Instant>,
+    // Set from another thread to abandon the current search, see ponder.rs
+    pub cancel: Option<Arc<AtomicBool>>,
+    pub aborted: bool,
+    // The one root move the current search looks at when the root is split between threads
+    // (see parallel.rs), None when it looks at all of them
+    pub root_move: Option<Direction>,
+    // Pruning of the searches since the last `take_cutoff_stats`
+    pub cutoff_stats: CutoffStats,
+    // Nodes since the clock was last looked at
+    nodes: u32,
+}
+
+impl SearchWorkspace {
+    pub fn new() -> SearchWorkspace {
+        SearchWorkspace {
+            visited: Vec::with_capacity(PREALLOCATED_CELLS),
+            costs: Vec::with_capacity(PREALLOCATED_CELLS),
+            queue: VecDeque::with_capacity(PREALLOCATED_CELLS),
+            owners: Vec::with_capacity(PREALLOCATED_CELLS),
+            vacated: Vec::with_capacity(PREALLOCATED_CELLS),
+            heap: BinaryHeap::with_capacity(4 * PREALLOCATED_CELLS),
+            low: Vec::with_capacity(PREALLOCATED_CELLS),
+            block: Vec::with_capacity(PREALLOCATED_CELLS),
+            pocket: Vec::with_capacity(PREALLOCATED_CELLS),
+            dfs: Vec::with_capacity(PREALLOCATED_CELLS),
+            eval_totals: EvalTotals::default(),
+            root_depth: 0,
+            root_candidates: Vec::with_capacity(4),
+            pv: Vec::new(),
+            pv_kills: Vec::new(),
+            deadline: None,
+            stalled: false,
+            root_alive: Vec::with_capacity(8),
+            force_rich: false,
+            heat: Vec::with_capacity(PREALLOCATED_CELLS),
+            styles: Vec::with_capacity(8),
+            threat: Vec::with_capacity(8),
+            trace: None,
+            pending: Vec::with_capacity(8),
+            turn_start: 0,
+            preset: preset::active(),
+            famine: false,
+            rules: RulesConfig::default(),
+            shrink: None,
+            turns_played: 0,
+            hazards_before_shrink: 0,
+            refutations: Vec::with_capacity(8),
+            killers: Vec::new(),
+            history: Vec::with_capacity(8 * 4 * PREALLOCATED_CELLS),
+            stop_at: None,
+            cancel: None,
+            aborted: false,
+            root_move: None,
+            cutoff_stats: CutoffStats::default(),
+            nodes: 0,
+        }
+    }
+
+    // Prepares the root candidates and principal variation buffers for a search of `depth`
+    // from `board` that has to finish by `deadline`
+    pub fn start_search(
+        &mut self,
+        board: &Board,
+        depth: i32,
+        deadline: Option<Instant>,
+        stalled: bool,
+    ) {
+        let plies = depth.max(0) as usize + 1;
+        self.root_depth = depth;
+        self.root_alive.clear();
+        self.root_alive
+            .extend(board.snakes.iter().map(|s| !s.body.is_empty()));
+        self.deadline = deadline;
+        self.stalled = stalled;
+        self.force_rich = false;
+        self.heat.clear();
+        self.styles.clear();
+        self.threat.clear();
+        self.trace = None;
+        self.pending.clear();
+        self.turn_start = 0;
+        self.preset = preset::active();
+        self.famine = false;
+        self.rules = RulesConfig::default();
+        self.shrink = None;
+        self.turns_played = 0;
+        self.refutations.clear();
+        self.killers.clear();
+        self.killers.resize(plies, [None; KILLERS]);
+        self.history.clear();
+        self.history.resize(
+            board.snakes.len() * 4 * board.width as usize * board.height as usize,
+            0,
+        );
+        self.stop_at = None;
+        self.cancel = None;
+        self.aborted = false;
+        self.root_move = None;
+        self.nodes = 0;
+        self.root_candidates.clear();
+        if self.pv.len() < plies {
+            self.pv.resize_with(plies, || Vec::with_capacity(plies));
+        }
+        for line in self.pv.iter_mut() {
+            line.clear();
+        }
+        self.pv_kills.clear();
+        self.pv_kills.resize(self.pv.len(), 0);
+    }
+
+    // Prepares a search of `depth` like `start_search`, one of several searches of the same
+    // turn that keep the refutations the ones before found
+    pub fn deepen_search(
+        &mut self,
+        board: &Board,
+        depth: i32,
+        deadline: Option<Instant>,
+        stalled: bool,
+    ) {
+        let refutations = std::mem::take(&mut self.refutations);
+        let mut killers = std::mem::take(&mut self.killers);
+        let history = std::mem::take(&mut self.history);
+        self.start_search(board, depth, deadline, stalled);
+        self.refutations = refutations;
+        killers.resize(depth.max(0) as usize + 1, [None; KILLERS]);
+        self.killers = killers;
+        self.history = history;
+    }
+
+    // Where the history of `direction` from `head` of the snake at `snake_index` is
+    fn history_slot(&self, board: &Board, snake_index: usize, head: &Coord, d: Direction) -> usize {
+        let cells = board.width as usize * board.height as usize;
+        let cell = (head.y * board.width + head.x) as usize;
+        let direction = Direction::ALL.iter().position(|&m| m == d).unwrap();
+        (snake_index * cells + cell) * 4 + direction
+    }
+
+    pub fn history(&self, board: &Board, snake_index: usize, direction: Direction) -> i32 {
+        let head = &board.snakes[snake_index].body[0];
+        let slot = self.history_slot(board, snake_index, head, direction);
+        self.history.get(slot).copied().unwrap_or(0)
+    }
+
+    // The killer rank of `direction` for the snake at `snake_index` `ply` plies below the
+    // root, 0 for the latest killer, KILLERS when it isn't one
+    pub fn killer_rank(&self, snake_index: usize, ply: i32, direction: Direction) -> usize {
+        self.killers
+            .get(ply as usize)
+            .and_then(|killers| {
+                killers
+                    .iter()
+                    .position(|&killer| killer == Some((snake_index, direction)))
+            })
+            .unwrap_or(KILLERS)
+    }
+
+    // `direction` cut off a node of the snake at `snake_index` with `depth` plies left
+    pub fn record_cutoff(
+        &mut self,
+        board: &Board,
+        snake_index: usize,
+        depth: i32,
+        direction: Direction,
+    ) {
+        let ply = (self.root_depth - depth) as usize;
+        if let Some(killers) = self.killers.get_mut(ply) {
+            if killers[0] != Some((snake_index, direction)) {
+                killers.rotate_right(1);
+                killers[0] = Some((snake_index, direction));
+            }
+        }
+        let head = &board.snakes[snake_index].body[0];
+        let slot = self.history_slot(board, snake_index, head, direction);
+        if let Some(history) = self.history.get_mut(slot) {
+            *history = history.saturating_add(depth * depth);
+        }
+    }
+
+    // Prepares the search of `root_move` alone, one branch of the search `parent` is set up
+    // for, with the same settings and what its shallower searches found
+    pub fn fork(&mut self, board: &Board, parent: &SearchWorkspace, root_move: Direction) {
+        self.start_search(board, parent.root_depth, parent.deadline, parent.stalled);
+        self.force_rich = parent.force_rich;
+        self.heat.extend_from_slice(&parent.heat);
+        self.styles.extend_from_slice(&parent.styles);
+        self.threat.extend_from_slice(&parent.threat);
+        self.preset = parent.preset;
+        self.famine = parent.famine;
+        self.rules = parent.rules.clone();
+        self.shrink = parent.shrink.clone();
+        self.refutations.extend_from_slice(&parent.refutations);
+        self.killers.clone_from(&parent.killers);
+        self.history.clone_from(&parent.history);
+        self.stop_at = parent.stop_at;
+        self.cancel.clone_from(&parent.cancel);
+        self.root_move = Some(root_move);
+    }
+
+    // Takes the result of a search whose root was split between threads: the candidates of
+    // every branch and the best line
+    pub fn join(
+        &mut self,
+        candidates: Vec<RootCandidate>,
+        pv: Vec<(usize, Direction)>,
+        pv_kills: u32,
+    ) {
+        let depth = self.root_depth.max(0) as usize;
+        self.root_candidates = candidates;
+        if let Some(line) = self.pv.get_mut(depth) {
+            *line = pv;
+            self.pv_kills[depth] = pv_kills;
+        }
+    }
+
+    // The reply of the snake at `snake_index`, `ply` plies below the root, that refuted a
+    // root move
+    pub fn refutation(&self, snake_index: usize, ply: i32) -> Option<Direction> {
+        self.refutations
+            .iter()
+            .find(|&&(index, at, _)| index == snake_index && at == ply)
+            .map(|&(_, _, direction)| direction)
+    }
+
+    pub fn record_refutation(&mut self, snake_index: usize, ply: i32, direction: Direction) {
+        match self
+            .refutations
+            .iter_mut()
+            .find(|(index, at, _)| *index == snake_index && *at == ply)
+        {
+            Some(entry) => entry.2 = direction,
+            None => self.refutations.push((snake_index, ply, direction)),
+        }
+    }
+
+    pub fn clear_pv(&mut self, depth: i32) {
+        if let Some(line) = self.pv.get_mut(depth as usize) {
+            line.clear();
+            self.pv_kills[depth as usize] = 0;
+        }
+    }
+
+    // The line below `depth` ends with an opponent that has no move left, `ours` when our
+    // body is what blocks it
+    pub fn end_pv_with_elimination(&mut self, depth: i32, ours: bool) {
+        self.clear_pv(depth);
+        if let Some(kills) = self.pv_kills.get_mut(depth as usize) {
+            *kills = ours as u32;
+        }
+    }
+
+    pub fn pv_kills(&self, depth: i32) -> u32 {
+        self.pv_kills.get(depth as usize).copied().unwrap_or(0)
+    }
+
+    // The best line below `depth` is now `direction` followed by the line found one ply down
+    pub fn update_pv(&mut self, depth: i32, snake_index: usize, direction: Direction) {
+        let depth = depth as usize;
+        if depth == 0 || depth >= self.pv.len() {
+            return;
+        }
+        let (below, rest) = self.pv.split_at_mut(depth);
+        let line = &mut rest[0];
+        line.clear();
+        line.push((snake_index, direction));
+        line.extend_from_slice(&below[depth - 1]);
+        self.pv_kills[depth] = self.pv_kills[depth - 1];
+    }
+
+    pub fn principal_variation(&self) -> &[(usize, Direction)] {
+        self.pv
+            .get(self.root_depth.max(0) as usize)
+            .map_or(&[], |line| line.as_slice())
+    }
+
+    // Clears everything for a search over a board with `cells` cells
+    pub fn reset(&mut self, cells: usize) {
+        self.visited.clear();
+        self.visited.resize(cells, false);
+        self.costs.clear();
+        self.costs.resize(cells, i32::MAX);
+        self.queue.clear();
+        self.heap.clear();
+    }
+
+    // Whether the current search ran out of time, only looks at the clock every so often
+    pub fn out_of_time(&mut self) -> bool {
+        if self.aborted {
+            return true;
+        }
+        self.nodes += 1;
+        if self.nodes >= CLOCK_INTERVAL {
+            self.nodes = 0;
+            self.aborted = self.stop_at.is_some_and(|stop| Instant::now() >= stop)
+                || self
+                    .cancel
+                    .as_ref()
+                    .is_some_and(|cancel| cancel.load(Ordering::Relaxed));
+        }
+        self.aborted
+    }
+
+    pub fn snapshot(&mut self) -> SearchSnapshot {
+        SearchSnapshot {
+            root_depth: self.root_depth,
+            root_candidates: self.root_candidates.clone(),
+            pv: self.principal_variation().to_vec(),
+            pv_kills: self.pv_kills(self.root_depth),
+            trace: self.trace.take(),
+        }
+    }
+
+    // Puts the results of a finished search back
+    pub fn restore(&mut self, snapshot: SearchSnapshot) {
+        let depth = snapshot.root_depth.max(0) as usize;
+        self.root_depth = snapshot.root_depth;
+        self.root_candidates = snapshot.root_candidates;
+        if let Some(line) = self.pv.get_mut(depth) {
+            *line = snapshot.pv;
+            self.pv_kills[depth] = snapshot.pv_kills;
+        }
+        self.trace = snapshot.trace;
+    }
+
+    // The finished search, to be taken over by a workspace on another thread
+    pub fn hand_off(&mut self) -> SearchHandoff {
+        SearchHandoff {
+            snapshot: self.snapshot(),
+            refutations: self.refutations.clone(),
+            killers: self.killers.clone(),
+            history: self.history.clone(),
+        }
+    }
+
+    // Goes on from the search of `board` that `handoff` comes from as if it had been done
+    // here, so the next depth is prepared with `deepen_search`
+    pub fn take_over(&mut self, board: &Board, handoff: SearchHandoff) {
+        self.start_search(board, handoff.snapshot.root_depth, None, false);
+        self.refutations = handoff.refutations;
+        self.killers = handoff.killers;
+        self.history = handoff.history;
+        self.restore(handoff.snapshot);
+    }
+
+    pub fn take_eval_totals(&mut self) -> EvalTotals {
+        std::mem::take(&mut self.eval_totals)
+    }
+
+    pub fn take_cutoff_stats(&mut self) -> CutoffStats {
+        std::mem::take(&mut self.cutoff_stats)
+    }
+}
+
+thread_local! {
+    static WORKSPACE: RefCell<SearchWorkspace> = RefCell::new(SearchWorkspace::new());
+}
+
+// Runs `f` with this thread's workspace
+pub fn with_workspace<R>(f: impl FnOnce(&mut SearchWorkspace) -> R) -> R {
+    WORKSPACE.with(|workspace| f(&mut workspace.borrow_mut()))
+}
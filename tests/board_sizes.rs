@@ -0,0 +1,65 @@
+// Whole games on boards of the sizes the engine meets, from the smallest standard map to
+// custom community boards past the 1024 cells bit sets hold (see src/bitboard.rs): the flood
+// fills and the Voronoi split on bit sets have to agree with the queues on every turn, and
+// the engine has to keep playing legal moves (see `invariants::check_board_size`).
+
+use starter_snake_rust::invariants::check_board_size;
+
+// Plies the engine searches, and turns played per game
+const DEPTH: i32 = 2;
+const TURNS: i32 = 60;
+
+fn check(width: i32, height: i32, wrapped: bool) {
+    for seed in 1..=2 {
+        if let Err(e) = check_board_size(width, height, wrapped, DEPTH, TURNS, seed) {
+            panic!("seed {}: {}", seed, e);
+        }
+    }
+}
+
+#[test]
+fn plays_7x7() {
+    check(7, 7, false);
+}
+
+#[test]
+fn plays_11x11() {
+    check(11, 11, false);
+}
+
+#[test]
+fn plays_11x11_wrapped() {
+    check(11, 11, true);
+}
+
+#[test]
+fn plays_19x19() {
+    check(19, 19, false);
+}
+
+#[test]
+fn plays_19x19_wrapped() {
+    check(19, 19, true);
+}
+
+#[test]
+fn plays_25x25() {
+    check(25, 25, false);
+}
+
+#[test]
+fn plays_32x32() {
+    check(32, 32, false);
+}
+
+// Past the largest bit sets, everything runs on the queues
+#[test]
+fn plays_41x41() {
+    check(41, 41, false);
+}
+
+// Not square, and with a row longer than a word of bit sets
+#[test]
+fn plays_70x9() {
+    check(70, 9, false);
+}
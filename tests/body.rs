@@ -0,0 +1,147 @@
+// Snake bodies (see src/body.rs): heads going in at the front through the free slots in front
+// of them and coming off again, tails going on and off the back, and moves taken back the way
+// the search undoes them, on short bodies that stay inline and long ones that spill.
+
+use serde_json::json;
+
+use starter_snake_rust::{Body, Coord};
+
+fn cell(x: i32, y: i32) -> Coord {
+    serde_json::from_value(json!({"x": x, "y": y})).unwrap()
+}
+
+// `length` segments in a column, head first
+fn column(length: i32) -> Vec<Coord> {
+    (0..length).map(|y| cell(0, y)).collect()
+}
+
+#[test]
+fn heads_go_in_at_the_front() {
+    for length in [0, 1, 3, 12, 13, 16, 17, 40] {
+        let mut body = Body::from(column(length));
+        let mut expected = column(length);
+        // Enough heads to use up the free slots a few times over
+        for i in 0..50 {
+            let head = cell(1, i);
+            body.push_front(head);
+            expected.insert(0, head);
+            assert_eq!(body, expected, "length {} after {} heads", length, i + 1);
+            assert_eq!(body.first(), Some(&head));
+        }
+        for _ in 0..50 {
+            assert_eq!(body.pop_front(), Some(expected.remove(0)));
+            assert_eq!(body, expected, "length {}", length);
+        }
+        assert_eq!(body, column(length));
+    }
+}
+
+#[test]
+fn empty_bodies_give_nothing_back() {
+    let mut body = Body::default();
+    assert_eq!(body.pop_front(), None);
+    assert_eq!(body.pop(), None);
+    body.push_front(cell(2, 2));
+    assert_eq!(body.pop(), Some(cell(2, 2)));
+    assert_eq!(body.pop_front(), None);
+    assert_eq!(body.pop(), None);
+    assert!(body.is_empty());
+}
+
+#[test]
+fn tails_go_on_and_off_the_back() {
+    let mut body = Body::from(column(3));
+    body.push_front(cell(1, 0));
+    body.push(cell(0, 3));
+    assert_eq!(
+        body,
+        vec![cell(1, 0), cell(0, 0), cell(0, 1), cell(0, 2), cell(0, 3)]
+    );
+    assert_eq!(body.pop(), Some(cell(0, 3)));
+    assert_eq!(body.pop(), Some(cell(0, 2)));
+    assert_eq!(body, vec![cell(1, 0), cell(0, 0), cell(0, 1)]);
+    // Popping the whole body from the back leaves no segment in front behind
+    assert_eq!(body.pop(), Some(cell(0, 1)));
+    assert_eq!(body.pop(), Some(cell(0, 0)));
+    assert_eq!(body.pop(), Some(cell(1, 0)));
+    assert_eq!(body.pop(), None);
+    assert!(body.is_empty());
+}
+
+// A move the way the search plays it: the tail comes off, the head goes in, and eating
+// doubles up the new tail. Returns the tail that came off.
+fn play(body: &mut Body, head: Coord, eats: bool) -> Coord {
+    let tail = body.pop().unwrap();
+    body.push_front(head);
+    if eats {
+        body.push(*body.last().unwrap());
+    }
+    tail
+}
+
+// And the way it takes the move back
+fn undo(body: &mut Body, tail: Coord, ate: bool) {
+    if ate {
+        body.pop();
+    }
+    body.pop_front();
+    body.push(tail);
+}
+
+#[test]
+fn moves_are_taken_back_exactly() {
+    for length in [3, 12, 14, 16, 30] {
+        let start = Body::from(column(length));
+        let mut body = start.clone();
+        let mut moves = Vec::new();
+        for i in 0..40 {
+            // Every third move eats
+            let eats = i % 3 == 0;
+            moves.push((play(&mut body, cell(1 + i % 5, i), eats), eats));
+            assert_eq!(
+                body.len(),
+                length as usize + moves.iter().filter(|m| m.1).count()
+            );
+        }
+        for (tail, ate) in moves.into_iter().rev() {
+            undo(&mut body, tail, ate);
+        }
+        assert_eq!(body, start, "length {}", length);
+    }
+}
+
+#[test]
+fn copies_and_requests_read_the_same() {
+    let mut body = Body::from(column(20));
+    body.push_front(cell(1, 0));
+    body.pop();
+    let copy = body.clone();
+    assert_eq!(copy, body);
+    let json = serde_json::to_value(&body).unwrap();
+    let read: Body = serde_json::from_value(json.clone()).unwrap();
+    assert_eq!(read, body);
+    assert_eq!(json.as_array().map(Vec::len), Some(20));
+    assert_eq!(json[0], json!({"x": 1, "y": 0}));
+}
+
+// Eating grows a body at the back while its head goes in at the front: up to 12 segments
+// it stays in its inline slots, past that it moves to the heap
+#[test]
+fn short_bodies_stay_inline() {
+    let mut body = Body::from(column(3));
+    for i in 0..200 {
+        // Eats until it's 12 long
+        let eats = body.len() < 12;
+        play(&mut body, cell(1, i), eats);
+        assert!(
+            !body.spilled(),
+            "{} segments after {} moves",
+            body.len(),
+            i + 1
+        );
+    }
+    for i in 0..20 {
+        play(&mut body, cell(2, i), true);
+    }
+    assert!(body.spilled());
+}
@@ -0,0 +1,69 @@
+// Requests for the integration tests, built as the JSON the platform sends. Every test file
+// takes what it needs with `mod common;`, so the ones it doesn't use aren't dead code.
+#![allow(dead_code)]
+
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+use serde_json::{json, Value};
+
+// Cells as a list of {x, y}
+pub fn cells(cells: &[(i32, i32)]) -> Value {
+    Value::Array(
+        cells
+            .iter()
+            .map(|&(x, y)| json!({"x": x, "y": y}))
+            .collect(),
+    )
+}
+
+// A snake with 90 health, named after its id, with `body` head first
+pub fn snake(id: &str, body: &[(i32, i32)]) -> Value {
+    json!({
+        "id": id,
+        "name": id,
+        "health": 90,
+        "body": cells(body),
+        "head": cells(&body[..1])[0],
+        "length": body.len(),
+        "latency": "0",
+        "shout": null,
+        "squad": null,
+    })
+}
+
+// A request of game `game_id` on an 11x11 board with `you` first and then `others`
+pub fn request(
+    game_id: &str,
+    turn: i32,
+    timeout: u32,
+    you: Value,
+    others: &[Value],
+    food: &[(i32, i32)],
+) -> Value {
+    let mut snakes = vec![you.clone()];
+    snakes.extend_from_slice(others);
+    json!({
+        "game": {"id": game_id, "timeout": timeout},
+        "turn": turn,
+        "board": {
+            "width": 11,
+            "height": 11,
+            "food": cells(food),
+            "snakes": snakes,
+            "hazards": [],
+        },
+        "you": you,
+    })
+}
+
+// Posts `body` to `path`, the answer has to be a 200
+pub async fn post(client: &Client, path: &str, body: String) -> Value {
+    let response = client
+        .post(path.to_string())
+        .header(ContentType::JSON)
+        .body(body)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    response.into_json().await.unwrap_or(Value::Null)
+}
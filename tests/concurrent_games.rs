@@ -0,0 +1,162 @@
+// Games played at the same time don't see each other's state: the per-game maps of the
+// monitors, the request ledger and the decision log, hammered from many threads and through
+// `/move`s of many games in flight at once.
+
+mod common;
+
+use rocket::local::asynchronous::Client;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread;
+
+use common::{post, snake};
+use starter_snake_rust::{Admission, GameMap, RequestLedger};
+
+const GAMES: usize = 8;
+const TURNS: i32 = 4;
+
+#[test]
+fn game_maps_keep_games_apart() {
+    let map: Arc<GameMap<Vec<usize>>> = Arc::new(GameMap::new("stress", None));
+    let writers: Vec<_> = (0..GAMES)
+        .map(|writer| {
+            let map = Arc::clone(&map);
+            thread::spawn(move || {
+                for step in 0..GAMES * 50 {
+                    // Every writer goes through every game 50 times, starting at its own
+                    let game = (writer + step) % GAMES;
+                    map.update(&format!("game-{}", game), |values| values.push(game));
+                }
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    for game in 0..GAMES {
+        let values = map.update(&format!("game-{}", game), |values| values.clone());
+        assert_eq!(values.len(), GAMES * 50, "game {}", game);
+        assert!(values.iter().all(|&value| value == game), "game {}", game);
+    }
+}
+
+#[test]
+fn ledgers_keep_games_apart() {
+    let ledger = Arc::new(RequestLedger::default());
+    let games: Vec<_> = (0..GAMES)
+        .map(|game| {
+            let ledger = Arc::clone(&ledger);
+            thread::spawn(move || {
+                let id = format!("game-{}", game);
+                for turn in 0..200 {
+                    let answer = json!({"game": game, "turn": turn});
+                    let Admission::Search(pending) = ledger.admit_move(&id, "you", turn) else {
+                        panic!("game {} turn {} isn't new", game, turn);
+                    };
+                    match ledger.admit_move(&id, "you", turn) {
+                        Admission::Pending(_) => {}
+                        _ => panic!("game {} turn {} isn't pending", game, turn),
+                    }
+                    pending.decided(&answer);
+                    match ledger.admit_move(&id, "you", turn) {
+                        Admission::Cached(cached) => assert_eq!(cached, answer),
+                        _ => panic!("game {} turn {} isn't cached", game, turn),
+                    }
+                }
+                assert!(ledger.end_game(&id, "you"));
+                assert!(matches!(
+                    ledger.admit_move(&id, "you", 200),
+                    Admission::Late
+                ));
+            })
+        })
+        .collect();
+    for game in games {
+        game.join().unwrap();
+    }
+}
+
+// Our snake's head in game `game`, a column of its own
+fn head(game: usize) -> (i32, i32) {
+    (game as i32 + 1, 2)
+}
+
+fn request(game: usize, turn: i32) -> String {
+    let (x, y) = head(game);
+    let you = snake(&format!("you-{}", game), &[(x, y), (x, y - 1), (x, y - 2)]);
+    let other = snake(&format!("other-{}", game), &[(x, 8), (x, 9), (x, 10)]);
+    let id = format!("game-{}", game);
+    common::request(&id, turn, 500, you, &[other], &[(10, 5)]).to_string()
+}
+
+// Every game sends every turn twice, the retry while the first may still be searched, and
+// all games at the same time. Every game gets one search and one decision log entry per
+// turn, about its own board.
+#[rocket::async_test]
+async fn parallel_moves_keep_games_apart() {
+    let log = std::env::temp_dir().join(format!("decisions-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&log);
+    std::env::set_var("DECISION_LOG", &log);
+    let client = Arc::new(Client::tracked(starter_snake_rust::rocket()).await.unwrap());
+    for game in 0..GAMES {
+        post(&client, "/start", request(game, 0)).await;
+    }
+
+    let games: Vec<_> = (0..GAMES)
+        .map(|game| {
+            let client = Arc::clone(&client);
+            rocket::tokio::spawn(async move {
+                for turn in 1..=TURNS {
+                    let (first, retry) = rocket::tokio::join!(
+                        post(&client, "/move", request(game, turn)),
+                        post(&client, "/move", request(game, turn)),
+                    );
+                    assert_eq!(first, retry, "the retry of game {} turn {}", game, turn);
+                    assert_ne!(first["move"], "down", "game {} turn {}", game, turn);
+                }
+            })
+        })
+        .collect();
+    for game in games {
+        game.await.unwrap();
+    }
+
+    let decisions = std::fs::read_to_string(&log).unwrap();
+    let _ = std::fs::remove_file(&log);
+    let mut logged: HashMap<(usize, i32), usize> = HashMap::new();
+    for line in decisions.lines() {
+        let decision: Value = serde_json::from_str(line).unwrap();
+        let game: usize = decision["game_id"].as_str().unwrap()["game-".len()..]
+            .parse()
+            .unwrap();
+        let turn = decision["turn"].as_i64().unwrap() as i32;
+        *logged.entry((game, turn)).or_default() += 1;
+        assert_eq!(decision["snake_id"], format!("you-{}", game));
+        let (x, y) = head(game);
+        let step = &decision["pv"][0];
+        assert_eq!(step["snake_id"], format!("you-{}", game));
+        let cell = (
+            step["cell"]["x"].as_i64().unwrap(),
+            step["cell"]["y"].as_i64().unwrap(),
+        );
+        let expected = match step["move"].as_str().unwrap() {
+            "up" => (x, y + 1),
+            "left" => (x - 1, y),
+            "right" => (x + 1, y),
+            other => panic!("game {} turn {} plays {}", game, turn, other),
+        };
+        assert_eq!(cell, (expected.0 as i64, expected.1 as i64));
+    }
+    for game in 0..GAMES {
+        for turn in 1..=TURNS {
+            assert_eq!(
+                logged.get(&(game, turn)),
+                Some(&1),
+                "game {} turn {}",
+                game,
+                turn
+            );
+        }
+    }
+}
@@ -0,0 +1,85 @@
+// The moves between cells, on plain boards and on wrapped ones where moving off one edge
+// comes back in at the opposite one.
+
+use serde_json::json;
+
+use starter_snake_rust::{Board, Coord, Direction};
+
+fn board(width: i32, height: i32, wrapped: bool) -> Board {
+    serde_json::from_value(json!({
+        "width": width,
+        "height": height,
+        "snakes": [],
+        "wrapped": wrapped,
+    }))
+    .unwrap()
+}
+
+fn cell(x: i32, y: i32) -> Coord {
+    serde_json::from_value(json!({"x": x, "y": y})).unwrap()
+}
+
+#[test]
+fn moves_between_neighbours() {
+    let board = board(11, 11, false);
+    let from = cell(5, 5);
+    assert_eq!(
+        Direction::between(&board, &from, &cell(5, 6)),
+        Some(Direction::Up)
+    );
+    assert_eq!(
+        Direction::between(&board, &from, &cell(5, 4)),
+        Some(Direction::Down)
+    );
+    assert_eq!(
+        Direction::between(&board, &from, &cell(4, 5)),
+        Some(Direction::Left)
+    );
+    assert_eq!(
+        Direction::between(&board, &from, &cell(6, 5)),
+        Some(Direction::Right)
+    );
+    assert_eq!(Direction::between(&board, &from, &cell(5, 5)), None);
+    assert_eq!(Direction::between(&board, &from, &cell(6, 6)), None);
+    assert_eq!(Direction::between(&board, &from, &cell(5, 7)), None);
+}
+
+#[test]
+fn moves_across_the_edges_of_wrapped_boards() {
+    let wrapped = board(11, 7, true);
+    let plain = board(11, 7, false);
+    for (from, to, direction) in [
+        (cell(0, 3), cell(10, 3), Direction::Left),
+        (cell(10, 3), cell(0, 3), Direction::Right),
+        (cell(4, 0), cell(4, 6), Direction::Down),
+        (cell(4, 6), cell(4, 0), Direction::Up),
+    ] {
+        assert_eq!(Direction::between(&wrapped, &from, &to), Some(direction));
+        assert_eq!(Direction::between(&plain, &from, &to), None);
+    }
+}
+
+#[test]
+fn opposite_moves_go_back() {
+    for direction in Direction::ALL {
+        let opposite = direction.opposite();
+        assert_ne!(opposite, direction);
+        assert_eq!(opposite.opposite(), direction);
+    }
+    for (width, height, wrapped) in [(11, 11, false), (11, 7, true)] {
+        let board = board(width, height, wrapped);
+        let cells: Vec<Coord> = (0..width)
+            .flat_map(|x| (0..height).map(move |y| cell(x, y)))
+            .collect();
+        for from in &cells {
+            for to in &cells {
+                if let Some(direction) = Direction::between(&board, from, to) {
+                    assert_eq!(
+                        Direction::between(&board, to, from),
+                        Some(direction.opposite())
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,68 @@
+// The cleanup of move requests in src/normalize.rs, on the payloads custom engines send.
+// States are compared through their JSON, like the requests they come from.
+
+mod common;
+
+use serde_json::{json, Value};
+
+use common::{cells, request, snake};
+use starter_snake_rust::{normalize_state, GameState};
+
+fn state(other: Value) -> GameState {
+    let you = snake("you", &[(2, 2), (2, 1), (2, 0)]);
+    serde_json::from_value(request("normalize", 3, 500, you, &[other], &[(5, 5)])).unwrap()
+}
+
+fn other(state: &GameState) -> Value {
+    serde_json::to_value(state).unwrap()["board"]["snakes"][1].clone()
+}
+
+#[test]
+fn well_formed_requests_stay_as_they_are() {
+    let mut normalized = state(snake("other", &[(8, 8), (8, 9), (8, 10)]));
+    let before = serde_json::to_value(&normalized).unwrap();
+    assert!(normalize_state(&mut normalized).is_empty());
+    assert_eq!(serde_json::to_value(&normalized).unwrap(), before);
+}
+
+#[test]
+fn drops_segments_off_the_board() {
+    for (body, left) in [
+        (&[(10, 10), (10, 11), (10, 12)][..], &[(10, 10)][..]),
+        (&[(0, 9), (-1, 9), (-2, 9)], &[(0, 9)]),
+        (&[(8, 1), (8, 0), (8, -1), (8, -1)], &[(8, 1), (8, 0)]),
+    ] {
+        let mut normalized = state(snake("other", body));
+        let fixes = normalize_state(&mut normalized);
+        assert!(fixes.contains(&"body off the board"), "{:?}", fixes);
+        let other = other(&normalized);
+        assert_eq!(other["body"], cells(left), "from {:?}", body);
+        assert_eq!(other["length"], left.len());
+    }
+}
+
+// With every segment off the board there's nothing left but the head, and the search needs
+// that on the board too
+#[test]
+fn keeps_a_head_on_the_board() {
+    let mut normalized = state(snake("other", &[(11, 4), (12, 4)]));
+    let fixes = normalize_state(&mut normalized);
+    assert!(fixes.contains(&"body off the board"), "{:?}", fixes);
+    let other = other(&normalized);
+    assert_eq!(other["body"], cells(&[(10, 4)]));
+    assert_eq!(other["head"], json!({"x": 10, "y": 4}));
+}
+
+// A request can't be fixed onto a board without cells, but it doesn't bring the server down
+#[test]
+fn leaves_boards_without_cells_alone() {
+    for (width, height) in [(0, 11), (11, 0), (-3, 11)] {
+        let you = snake("you", &[(2, 2), (2, 1), (2, 0)]);
+        let other = snake("other", &[(-1, 8), (8, 9), (8, 10)]);
+        let mut request = request("normalize", 3, 500, you, &[other], &[(5, 5)]);
+        request["board"]["width"] = json!(width);
+        request["board"]["height"] = json!(height);
+        let mut normalized: GameState = serde_json::from_value(request).unwrap();
+        assert_eq!(normalize_state(&mut normalized), ["board without cells"]);
+    }
+}
@@ -0,0 +1,33 @@
+// Replays every fixture in tests/positions through the engine and fails when it plays a move
+// that isn't one of the fixture's acceptable moves. All positions are played before failing,
+// so one run lists every blunder that came back.
+
+use starter_snake_rust::positions;
+
+#[test]
+fn engine_plays_an_acceptable_move_in_every_position() {
+    let positions = positions::load(None).unwrap();
+    assert!(!positions.is_empty(), "no fixtures in tests/positions");
+
+    let mut wrong = Vec::new();
+    for position in &positions {
+        let played = position.play();
+        if !played.is_some_and(|played| position.acceptable.contains(&played)) {
+            let acceptable: Vec<&str> = position.acceptable.iter().map(|d| d.as_str()).collect();
+            wrong.push(format!(
+                "{}: played {}, expected {} ({})",
+                position.name,
+                played.map_or("nothing", |d| d.as_str()),
+                acceptable.join(" or "),
+                position.notes
+            ));
+        }
+    }
+    assert!(
+        wrong.is_empty(),
+        "{} of {} positions failed:\n  {}",
+        wrong.len(),
+        positions.len(),
+        wrong.join("\n  ")
+    );
+}
@@ -0,0 +1,103 @@
+// The post-mortem of recorded games (see src/postmortem.rs): what it makes of the last frames
+// of a lost game under the game's own rules, and a results database that counts every game
+// once however often its recording is looked at.
+
+mod common;
+
+use serde_json::{json, Value};
+
+use common::{request, snake};
+use starter_snake_rust::{
+    classify_loss, load_results, record_result, GameResult, GameState, LossCause,
+};
+
+// The frame we were last alive in and the one after it, where we're gone
+fn lost(alive: Value) -> Vec<GameState> {
+    let mut dead = alive.clone();
+    dead["turn"] = json!(alive["turn"].as_i64().unwrap() + 1);
+    dead["board"]["snakes"]
+        .as_array_mut()
+        .unwrap()
+        .retain(|s| s["id"] != "you");
+    vec![alive, dead]
+        .into_iter()
+        .map(|frame| serde_json::from_value(frame).unwrap())
+        .collect()
+}
+
+// Our snake in the middle of the board next to a hazard, with 40 health
+fn next_to_a_hazard(ruleset: Value) -> Vec<GameState> {
+    let mut you = snake("you", &[(5, 5), (5, 4), (5, 3)]);
+    you["health"] = json!(40);
+    let other = snake("other", &[(9, 9), (9, 8), (9, 7)]);
+    let mut frame = request("hazard", 30, 500, you, &[other], &[]);
+    frame["game"]["ruleset"] = ruleset;
+    frame["board"]["hazards"] = json!([{"x": 6, "y": 5}]);
+    lost(frame)
+}
+
+#[test]
+fn a_hazard_kills_by_the_games_damage() {
+    let standard = next_to_a_hazard(json!({"name": "royale"}));
+    assert_eq!(classify_loss(&standard), Some(LossCause::Unknown));
+    let custom = next_to_a_hazard(json!({
+        "name": "royale",
+        "settings": {"hazardDamagePerTurn": 50},
+    }));
+    assert_eq!(classify_loss(&custom), Some(LossCause::HazardDeath));
+}
+
+// A 7x2 board: the opponent fills the top row, our snake the left of the bottom one with its
+// head against the left edge. The free cells are past that edge on a wrapped board.
+fn against_the_edge(wrapped: bool) -> Vec<GameState> {
+    let you = snake("you", &[(0, 0), (1, 0), (2, 0)]);
+    let row: Vec<(i32, i32)> = (0..7).rev().map(|x| (x, 1)).collect();
+    let other = snake("other", &row);
+    let mut frame = request("edge", 30, 500, you, &[other], &[]);
+    frame["board"]["width"] = json!(7);
+    frame["board"]["height"] = json!(2);
+    frame["board"]["wrapped"] = json!(wrapped);
+    lost(frame)
+}
+
+#[test]
+fn an_edge_walls_in_only_when_the_board_doesnt_wrap() {
+    assert_eq!(
+        classify_loss(&against_the_edge(false)),
+        Some(LossCause::SelfTrap)
+    );
+    assert_eq!(
+        classify_loss(&against_the_edge(true)),
+        Some(LossCause::Unknown)
+    );
+}
+
+fn result(game_id: &str, won: bool) -> GameResult {
+    GameResult {
+        game_id: game_id.to_string(),
+        version: "test".to_string(),
+        turns: 100,
+        won,
+        cause: (!won).then_some(LossCause::Starved),
+        stalled_at: None,
+        features: None,
+        pivot: None,
+    }
+}
+
+#[test]
+fn a_game_recorded_again_counts_once() {
+    let db = std::env::temp_dir().join(format!("results-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&db);
+    record_result(&db, &result("first", true)).unwrap();
+    record_result(&db, &result("second", false)).unwrap();
+    record_result(&db, &result("first", false)).unwrap();
+    record_result(&db, &result("second", false)).unwrap();
+    let results = load_results(&db).unwrap();
+    let _ = std::fs::remove_file(&db);
+    let games: Vec<(&str, bool)> = results
+        .iter()
+        .map(|r| (r.game_id.as_str(), r.won))
+        .collect();
+    assert_eq!(games, [("first", false), ("second", false)]);
+}
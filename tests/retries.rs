@@ -0,0 +1,71 @@
+// A retried `/move` whose turn is still being searched (see src/ledger.rs) is answered by its
+// own deadline, not whenever the search it waits for is done, and a search whose handler
+// went away doesn't keep its turn pending.
+
+mod common;
+
+use rocket::local::asynchronous::Client;
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+use common::snake;
+use starter_snake_rust::{Admission, RequestLedger};
+
+fn request(turn: i32, timeout: u32) -> String {
+    let you = snake("you", &[(2, 2), (2, 1), (2, 0)]);
+    let other = snake("other", &[(8, 8), (8, 9), (8, 10)]);
+    common::request("retried", turn, timeout, you, &[other], &[(5, 5)]).to_string()
+}
+
+async fn post(client: &Client, path: &str, body: String) -> (Value, Duration) {
+    let asked = Instant::now();
+    let answer = common::post(client, path, body).await;
+    (answer, asked.elapsed())
+}
+
+// The first copy is searched for most of its 1000 ms, the retry says it has 100 ms
+#[rocket::async_test]
+async fn a_retry_waits_for_the_search_only_until_its_deadline() {
+    let client = Client::tracked(starter_snake_rust::rocket()).await.unwrap();
+    post(&client, "/start", request(0, 1000)).await;
+    let ((searched, search_took), (retried, retry_took)) =
+        rocket::tokio::join!(post(&client, "/move", request(1, 1000)), async {
+            rocket::tokio::time::sleep(Duration::from_millis(20)).await;
+            post(&client, "/move", request(1, 100)).await
+        },);
+    assert!(
+        retry_took < Duration::from_millis(100),
+        "the retry took {:?}",
+        retry_took
+    );
+    assert!(
+        search_took > retry_took,
+        "the search took {:?}",
+        search_took
+    );
+    for answer in [&searched, &retried] {
+        let direction = answer["move"].as_str().unwrap();
+        assert!(["up", "left", "right"].contains(&direction), "{}", answer);
+    }
+}
+
+// A handler dropped before it answered, e.g. when its client hung up
+#[rocket::async_test]
+async fn a_dropped_search_leaves_its_turn() {
+    let ledger = RequestLedger::default();
+    let Admission::Search(pending) = ledger.admit_move("dropped", "you", 1) else {
+        panic!("turn 1 isn't new");
+    };
+    let Admission::Pending(mut search) = ledger.admit_move("dropped", "you", 1) else {
+        panic!("turn 1 isn't pending");
+    };
+    drop(pending);
+    // The retry hears right away that no answer is coming
+    assert!(search.changed().await.is_err());
+    assert!(search.borrow().is_none());
+    // and the turn is searched again instead of waited for
+    assert!(matches!(
+        ledger.admit_move("dropped", "you", 1),
+        Admission::Search(_)
+    ));
+}
@@ -0,0 +1,168 @@
+// Rule conformance of the move simulation: positions where eating, growing and tails are
+// easy to get wrong, played the way the search plays them (`invariants::play_turn`) and
+// compared with what the official rules give. The oracle's corpus of positions from baseline
+// games is checked the same way, every line it plays out from them.
+
+mod common;
+
+use serde_json::{json, Value};
+
+use common::cells;
+use starter_snake_rust::invariants::{check_oracle_corpus, check_rules, play_turn};
+use starter_snake_rust::{Board, Direction};
+
+// An 11x11 board with snakes of the given health and bodies, head first
+fn board(snakes: &[(i32, &[(i32, i32)])], food: &[(i32, i32)], hazards: &[(i32, i32)]) -> Board {
+    let snakes: Vec<Value> = snakes
+        .iter()
+        .enumerate()
+        .map(|(i, (health, body))| {
+            let mut snake = common::snake(&format!("snake-{}", i), body);
+            snake["health"] = json!(health);
+            snake
+        })
+        .collect();
+    serde_json::from_value(json!({
+        "width": 11,
+        "height": 11,
+        "food": cells(food),
+        "hazards": cells(hazards),
+        "snakes": snakes,
+    }))
+    .unwrap()
+}
+
+// The snake at `index` as it would be sent in a request
+fn snake(board: &Board, index: usize) -> Value {
+    serde_json::to_value(board).unwrap()["snakes"][index].clone()
+}
+
+fn body(board: &Board, index: usize) -> Vec<(i32, i32)> {
+    snake(board, index)["body"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| {
+            (
+                c["x"].as_i64().unwrap() as i32,
+                c["y"].as_i64().unwrap() as i32,
+            )
+        })
+        .collect()
+}
+
+fn health(board: &Board, index: usize) -> i64 {
+    snake(board, index)["health"].as_i64().unwrap()
+}
+
+fn length(board: &Board, index: usize) -> i64 {
+    snake(board, index)["length"].as_i64().unwrap()
+}
+
+// Plays the turn like the search, after checking that the official rules agree
+fn turn(board: &Board, moves: &[Direction], constrictor: bool) -> Board {
+    check_rules(board, moves, constrictor).unwrap();
+    let indexed: Vec<(usize, Direction)> = moves.iter().copied().enumerate().collect();
+    play_turn(board, &indexed, constrictor)
+}
+
+#[test]
+fn eating_doubles_up_the_tail_for_one_turn() {
+    let start = board(&[(50, &[(5, 5), (4, 5), (3, 5)])], &[(6, 5)], &[]);
+
+    let ate = turn(&start, &[Direction::Right], false);
+    assert_eq!(body(&ate, 0), [(6, 5), (5, 5), (4, 5), (4, 5)]);
+    assert_eq!(length(&ate, 0), 4);
+    assert_eq!(health(&ate, 0), 100);
+
+    // The doubled-up tail stays put, the snake is at its new length from here on
+    let grown = turn(&ate, &[Direction::Right], false);
+    assert_eq!(body(&grown, 0), [(7, 5), (6, 5), (5, 5), (4, 5)]);
+    assert_eq!(health(&grown, 0), 99);
+
+    let moved = turn(&grown, &[Direction::Right], false);
+    assert_eq!(body(&moved, 0), [(8, 5), (7, 5), (6, 5), (5, 5)]);
+    assert_eq!(length(&moved, 0), 4);
+}
+
+#[test]
+fn a_snake_eating_on_its_first_move_stays_stacked() {
+    // Snakes start with their whole body on one cell
+    let start = board(&[(100, &[(5, 5), (5, 5), (5, 5)])], &[(5, 6)], &[]);
+
+    let ate = turn(&start, &[Direction::Up], false);
+    assert_eq!(body(&ate, 0), [(5, 6), (5, 5), (5, 5), (5, 5)]);
+
+    let moved = turn(&ate, &[Direction::Up], false);
+    assert_eq!(body(&moved, 0), [(5, 7), (5, 6), (5, 5), (5, 5)]);
+}
+
+#[test]
+fn following_a_tail_is_safe_unless_its_snake_just_ate() {
+    // snake-1's head follows snake-0's tail at (3, 5)
+    let follower: &[(i32, i32)] = &[(3, 6), (3, 7), (3, 8)];
+    let moves = [Direction::Right, Direction::Down];
+
+    let start = board(&[(50, &[(5, 5), (4, 5), (3, 5)]), (50, follower)], &[], &[]);
+    let played = turn(&start, &moves, false);
+    assert_eq!(body(&played, 1), [(3, 5), (3, 6), (3, 7)]);
+
+    let stacked = board(
+        &[(50, &[(5, 5), (4, 5), (3, 5), (3, 5)]), (50, follower)],
+        &[],
+        &[],
+    );
+    let played = turn(&stacked, &moves, false);
+    assert!(body(&played, 1).is_empty());
+    assert_eq!(health(&played, 1), 0);
+}
+
+#[test]
+fn both_snakes_eat_food_they_reach_together() {
+    let start = board(
+        &[
+            (50, &[(4, 5), (3, 5), (2, 5)]),
+            (50, &[(6, 5), (7, 5), (8, 5), (9, 5)]),
+        ],
+        &[(5, 5)],
+        &[],
+    );
+    let played = turn(&start, &[Direction::Right, Direction::Left], false);
+    // The longer snake wins the head-to-head after both grew
+    assert!(body(&played, 0).is_empty());
+    assert_eq!(body(&played, 1), [(5, 5), (6, 5), (7, 5), (8, 5), (8, 5)]);
+}
+
+#[test]
+fn food_in_a_hazard_heals_without_damage() {
+    let start = board(
+        &[(20, &[(5, 5), (4, 5), (3, 5)])],
+        &[(6, 5)],
+        &[(6, 5), (7, 5)],
+    );
+    let ate = turn(&start, &[Direction::Right], false);
+    assert_eq!(health(&ate, 0), 100);
+
+    let hurt = turn(&ate, &[Direction::Right], false);
+    assert_eq!(health(&hurt, 0), 100 - 1 - 14);
+}
+
+#[test]
+fn constrictor_snakes_grow_every_turn() {
+    let start = board(&[(50, &[(5, 5), (4, 5), (4, 5)])], &[], &[]);
+
+    let played = turn(&start, &[Direction::Right], true);
+    assert_eq!(body(&played, 0), [(6, 5), (5, 5), (4, 5), (4, 5)]);
+    assert_eq!(health(&played, 0), 100);
+
+    let played = turn(&played, &[Direction::Right], true);
+    assert_eq!(body(&played, 0), [(7, 5), (6, 5), (5, 5), (4, 5), (4, 5)]);
+}
+
+// The oracle's lines from positions of real games: two turns of every combination of moves,
+// about 120,000 turns over the 40 positions
+#[test]
+fn oracle_lines_follow_the_rules() {
+    let checked = check_oracle_corpus(40, 1).unwrap_or_else(|e| panic!("{}", e));
+    assert!(checked > 1000, "only {} turns checked", checked);
+}
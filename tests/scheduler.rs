@@ -0,0 +1,81 @@
+// Background work on the search threads (see src/scheduler.rs): it waits behind the searches
+// and gives its thread up to a search that finds none free.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use starter_snake_rust::{SearchScheduler, Urgency};
+
+// Background work that keeps its thread until it's cancelled, or for 5 s
+fn hold(scheduler: &SearchScheduler, personality: &'static str) -> Arc<AtomicBool> {
+    let cancel = Arc::new(AtomicBool::new(false));
+    let (started, running) = mpsc::channel();
+    let flag = Arc::clone(&cancel);
+    scheduler.execute_background(personality, Arc::clone(&cancel), move || {
+        started.send(()).unwrap();
+        let until = Instant::now() + Duration::from_secs(5);
+        while !flag.load(Ordering::Relaxed) && Instant::now() < until {
+            thread::sleep(Duration::from_millis(1));
+        }
+    });
+    running.recv_timeout(Duration::from_secs(1)).unwrap();
+    cancel
+}
+
+fn urgency() -> Urgency {
+    Urgency {
+        deadline: Instant::now() + Duration::from_millis(400),
+        criticality: 0,
+    }
+}
+
+#[test]
+fn a_search_cancels_the_background_work_on_its_thread() {
+    let scheduler = SearchScheduler::new(1);
+    let cancel = hold(&scheduler, "standard");
+    let asked = Instant::now();
+    let answer = scheduler.submit("standard", urgency(), || 7);
+    assert_eq!(answer.blocking_recv(), Ok(7));
+    assert!(cancel.load(Ordering::Relaxed));
+    let took = asked.elapsed();
+    assert!(took < Duration::from_millis(100), "waited {:?}", took);
+}
+
+#[test]
+fn searches_go_before_waiting_background_work() {
+    let scheduler = SearchScheduler::new(1);
+    let first = hold(&scheduler, "standard");
+    let (done, order) = mpsc::channel();
+    let background = done.clone();
+    scheduler.execute_background("standard", Arc::new(AtomicBool::new(false)), move || {
+        background.send("background").unwrap();
+    });
+    let answer = scheduler.submit("standard", urgency(), move || {
+        done.send("search").unwrap();
+    });
+    answer.blocking_recv().unwrap();
+    assert!(first.load(Ordering::Relaxed));
+    assert_eq!(order.recv_timeout(Duration::from_secs(1)), Ok("search"));
+    assert_eq!(order.recv_timeout(Duration::from_secs(1)), Ok("background"));
+}
+
+#[test]
+fn background_work_cancelled_while_waiting_never_starts() {
+    let scheduler = SearchScheduler::new(1);
+    let first = hold(&scheduler, "standard");
+    let (done, ran) = mpsc::channel();
+    let stale = Arc::new(AtomicBool::new(false));
+    scheduler.execute_background("standard", Arc::clone(&stale), move || {
+        done.send(()).unwrap();
+    });
+    stale.store(true, Ordering::Relaxed);
+    first.store(true, Ordering::Relaxed);
+    scheduler
+        .submit("standard", urgency(), || ())
+        .blocking_recv()
+        .unwrap();
+    assert!(ran.recv_timeout(Duration::from_millis(200)).is_err());
+}
@@ -0,0 +1,159 @@
+// The Redis side of the shared store (see src/shared_store.rs): the replies of the protocol
+// as the store reads them, and a server whose Redis stopped answering, or answers a byte at a
+// time, still answering its moves in time.
+
+mod common;
+
+use rocket::http::{ContentType, Status};
+use rocket::local::blocking::Client;
+use std::io::{Cursor, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::snake;
+use starter_snake_rust::{read_reply, Reply};
+
+fn parse(bytes: &[u8]) -> Result<Reply, String> {
+    read_reply(&mut Cursor::new(bytes))
+}
+
+#[test]
+fn reads_simple_replies() {
+    assert_eq!(parse(b"+OK\r\n"), Ok(Reply::Status("OK".to_string())));
+    assert_eq!(parse(b":42\r\n"), Ok(Reply::Integer(42)));
+    assert_eq!(parse(b":-3\r\n"), Ok(Reply::Integer(-3)));
+    assert_eq!(
+        parse(b"-ERR unknown command\r\n"),
+        Err("ERR unknown command".to_string())
+    );
+}
+
+#[test]
+fn reads_bulk_replies() {
+    assert_eq!(parse(b"$-1\r\n"), Ok(Reply::Nil));
+    assert_eq!(parse(b"$0\r\n\r\n"), Ok(Reply::Bulk(Vec::new())));
+    assert_eq!(
+        parse(b"$5\r\nhello\r\n"),
+        Ok(Reply::Bulk(b"hello".to_vec()))
+    );
+    // The length counts, not the line breaks
+    assert_eq!(
+        parse(b"$6\r\nab\r\ncd\r\n"),
+        Ok(Reply::Bulk(b"ab\r\ncd".to_vec()))
+    );
+}
+
+#[test]
+fn reads_array_replies() {
+    assert_eq!(parse(b"*-1\r\n"), Ok(Reply::Nil));
+    assert_eq!(parse(b"*0\r\n"), Ok(Reply::Array(Vec::new())));
+    assert_eq!(
+        parse(b"*3\r\n$1\r\na\r\n$-1\r\n*1\r\n:7\r\n"),
+        Ok(Reply::Array(vec![
+            Reply::Bulk(b"a".to_vec()),
+            Reply::Nil,
+            Reply::Array(vec![Reply::Integer(7)]),
+        ]))
+    );
+}
+
+#[test]
+fn reads_replies_one_at_a_time() {
+    let mut stream = Cursor::new(&b"+OK\r\n$2\r\nhi\r\n:1\r\n"[..]);
+    assert_eq!(read_reply(&mut stream), Ok(Reply::Status("OK".to_string())));
+    assert_eq!(read_reply(&mut stream), Ok(Reply::Bulk(b"hi".to_vec())));
+    assert_eq!(read_reply(&mut stream), Ok(Reply::Integer(1)));
+}
+
+#[test]
+fn rejects_broken_replies() {
+    for broken in [
+        &b""[..],
+        b"+OK",
+        b"$5\r\nhel",
+        b"$5\r\nhelloXY",
+        b"$-2\r\n",
+        b"*2\r\n:1\r\n",
+        b"*-5\r\n",
+        b":many\r\n",
+        b"?what\r\n",
+    ] {
+        assert!(
+            parse(broken).is_err(),
+            "read '{}'",
+            String::from_utf8_lossy(broken)
+        );
+    }
+}
+
+// GAME_STORE is read when the server is built, one test at a time sets it
+static STORE_ENV: Mutex<()> = Mutex::new(());
+
+// A server with its store at a Redis that leaves every connection to `serve`
+fn with_redis(serve: fn(TcpStream)) -> Client {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            thread::spawn(move || serve(stream));
+        }
+    });
+    let _env = STORE_ENV
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    std::env::set_var("GAME_STORE", format!("redis://{}", addr));
+    Client::tracked(starter_snake_rust::rocket()).unwrap()
+}
+
+fn request(turn: i32) -> String {
+    let you = snake("you", &[(2, 2), (2, 1), (2, 0)]);
+    let other = snake("other", &[(8, 8), (8, 9), (8, 10)]);
+    common::request("stalled-store", turn, 500, you, &[other], &[(5, 5)]).to_string()
+}
+
+// A Redis that takes connections and never answers used to hold every store call of a turn
+// for its whole timeout, one after the other, and the move came after the game's timeout
+// The start of a game and its first moves, each answered in time
+fn answers_in_time(client: Client) {
+    for turn in 0..=3 {
+        let path = if turn == 0 { "/start" } else { "/move" };
+        let asked = Instant::now();
+        let answer = client
+            .post(path)
+            .header(ContentType::JSON)
+            .body(request(turn))
+            .dispatch();
+        let took = asked.elapsed();
+        assert_eq!(answer.status(), Status::Ok);
+        assert!(
+            took < Duration::from_millis(500),
+            "turn {} took {:?}",
+            turn,
+            took
+        );
+    }
+}
+
+#[test]
+fn answers_in_time_while_the_store_hangs() {
+    answers_in_time(with_redis(|stream| {
+        thread::sleep(Duration::from_secs(60));
+        drop(stream);
+    }));
+}
+
+// A Redis that answers every call with a long value a byte at a time used to keep each read
+// within its timeout, and the first store call of a turn went on for seconds
+#[test]
+fn answers_in_time_while_the_store_trickles() {
+    answers_in_time(with_redis(|mut stream| {
+        if stream.write_all(b"$1000\r\n").is_err() {
+            return;
+        }
+        while stream.write_all(b"x").is_ok() {
+            thread::sleep(Duration::from_millis(5));
+        }
+    }));
+}
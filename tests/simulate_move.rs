@@ -0,0 +1,190 @@
+// Property tests of the move simulation the search plays on (see src/invariants.rs for the
+// invariants): random legal boards of up to 11x11 with up to four snakes, some wrapped, some
+// with food and hazards, and a random move for every snake. The turns are also played with
+// the official rules of rules.rs and have to end the same. The flood fills on the bit sets
+// of bitboard.rs are checked on the same boards.
+
+mod common;
+
+use quickcheck::{Arbitrary, Gen, QuickCheck, TestResult};
+use serde_json::json;
+
+use common::{cells, snake};
+
+use starter_snake_rust::invariants::{
+    check_eliminated_skipped, check_flood_fill, check_move, check_partition_forecast, check_rules,
+    check_turn,
+};
+use starter_snake_rust::{Board, Direction};
+
+// Cases per property unless QUICKCHECK_TESTS says otherwise
+const CASES: u64 = 2000;
+
+#[derive(Clone, Debug)]
+struct Position {
+    board: Board,
+    moves: Vec<(usize, Direction)>,
+    famine: bool,
+    constrictor: bool,
+}
+
+// A number in `low..=high`
+fn between(g: &mut Gen, low: i32, high: i32) -> i32 {
+    low + (u32::arbitrary(g) % (high - low + 1) as u32) as i32
+}
+
+fn free_cell(g: &mut Gen, width: i32, height: i32, taken: &[(i32, i32)]) -> Option<(i32, i32)> {
+    (0..50)
+        .map(|_| (between(g, 0, width - 1), between(g, 0, height - 1)))
+        .find(|cell| !taken.contains(cell))
+}
+
+// A random walk of 1 to 6 segments from a free cell, head first, that stays put where it
+// can't go on, so some bodies are stacked. Sometimes the tail is doubled up too, like a
+// snake that just ate.
+fn body(g: &mut Gen, width: i32, height: i32, taken: &[(i32, i32)]) -> Vec<(i32, i32)> {
+    let Some(head) = free_cell(g, width, height, taken) else {
+        return Vec::new();
+    };
+    let length = between(g, 1, 6) as usize;
+    let mut body = vec![head];
+    while body.len() < length {
+        let (x, y) = *body.last().unwrap();
+        let (dx, dy) = [(0, 1), (0, -1), (1, 0), (-1, 0)][between(g, 0, 3) as usize];
+        let next = (x + dx, y + dy);
+        let inside = next.0 >= 0 && next.0 < width && next.1 >= 0 && next.1 < height;
+        if inside && !taken.contains(&next) && !body.contains(&next) {
+            body.push(next);
+        } else {
+            body.push((x, y));
+        }
+    }
+    if bool::arbitrary(g) && bool::arbitrary(g) {
+        body.push(*body.last().unwrap());
+    }
+    body
+}
+
+impl Arbitrary for Position {
+    fn arbitrary(g: &mut Gen) -> Position {
+        let (width, height) = (between(g, 3, 11), between(g, 3, 11));
+        let mut taken = Vec::new();
+        let mut snakes = Vec::new();
+        for i in 0..between(g, 1, 4) {
+            let body = body(g, width, height, &taken);
+            if body.is_empty() {
+                break;
+            }
+            taken.extend_from_slice(&body);
+            let mut snake = snake(&format!("snake-{}", i), &body);
+            snake["health"] = json!(between(g, 1, 100));
+            snakes.push(snake);
+        }
+        let food: Vec<(i32, i32)> = (0..between(g, 0, 3))
+            .filter_map(|_| free_cell(g, width, height, &taken))
+            .collect();
+        let hazards: Vec<(i32, i32)> = (0..between(g, 0, 6))
+            .map(|_| (between(g, 0, width - 1), between(g, 0, height - 1)))
+            .collect();
+        let board: Board = serde_json::from_value(json!({
+            "width": width,
+            "height": height,
+            "food": cells(&food),
+            "hazards": cells(&hazards),
+            "snakes": snakes,
+            "wrapped": between(g, 0, 3) == 0,
+        }))
+        .unwrap();
+        let moves = (0..snakes.len())
+            .map(|i| (i, Direction::ALL[between(g, 0, 3) as usize]))
+            .collect();
+        Position {
+            board,
+            moves,
+            famine: bool::arbitrary(g),
+            constrictor: between(g, 0, 3) == 0,
+        }
+    }
+}
+
+fn verdict(checked: Result<(), String>) -> TestResult {
+    match checked {
+        Ok(()) => TestResult::passed(),
+        Err(e) => TestResult::error(e),
+    }
+}
+
+fn move_invariants(position: Position) -> TestResult {
+    let checked = position.moves.iter().try_for_each(|&(snake, direction)| {
+        check_move(&position.board, snake, direction, position.constrictor)
+    });
+    verdict(checked)
+}
+
+fn turn_invariants(position: Position) -> TestResult {
+    verdict(check_turn(
+        &position.board,
+        &position.moves,
+        position.famine,
+        position.constrictor,
+    ))
+}
+
+fn rules_conformance(position: Position) -> TestResult {
+    let moves: Vec<Direction> = position.moves.iter().map(|&(_, d)| d).collect();
+    verdict(check_rules(&position.board, &moves, position.constrictor))
+}
+
+fn flood_fill_agreement(position: Position) -> TestResult {
+    verdict(check_flood_fill(&position.board))
+}
+
+fn forecast_restores(position: Position) -> TestResult {
+    verdict(check_partition_forecast(
+        &position.board,
+        position.constrictor,
+    ))
+}
+
+// Two turns of a duel, one of a game of four
+fn eliminated_skipped(position: Position) -> TestResult {
+    verdict(check_eliminated_skipped(&position.board, 0, 4))
+}
+
+fn quickcheck() -> QuickCheck {
+    let cases = std::env::var("QUICKCHECK_TESTS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(CASES);
+    QuickCheck::new().tests(cases)
+}
+
+#[test]
+fn simulate_move_keeps_its_invariants() {
+    quickcheck().quickcheck(move_invariants as fn(Position) -> TestResult);
+}
+
+#[test]
+fn simulate_turn_keeps_its_invariants() {
+    quickcheck().quickcheck(turn_invariants as fn(Position) -> TestResult);
+}
+
+#[test]
+fn simulate_turn_follows_the_rules() {
+    quickcheck().quickcheck(rules_conformance as fn(Position) -> TestResult);
+}
+
+#[test]
+fn bit_sets_fill_like_the_queue() {
+    quickcheck().quickcheck(flood_fill_agreement as fn(Position) -> TestResult);
+}
+
+#[test]
+fn partition_forecasts_leave_the_board_alone() {
+    quickcheck().quickcheck(forecast_restores as fn(Position) -> TestResult);
+}
+
+#[test]
+fn eliminated_snakes_take_no_plies() {
+    quickcheck().quickcheck(eliminated_skipped as fn(Position) -> TestResult);
+}